@@ -1,18 +1,18 @@
 use core::fmt;
-use std::{collections::HashMap, sync::{atomic::{self, AtomicU16}, Arc}, thread, time::Duration};
+use std::{collections::HashMap, sync::{atomic::{self, AtomicI64}, Arc}, thread, time::Duration};
 
 use anyhow::{anyhow, Result};
 
 
 #[derive(Debug, Clone)]
 pub struct AmapMetrics {
-    data: Arc<HashMap<String, atomic::AtomicU16>>,
+    data: Arc<HashMap<String, atomic::AtomicI64>>,
 }
 
 impl AmapMetrics {
     pub fn new(metri_names: &[&'static str]) -> Self {
         let map = metri_names.iter().map(|name| {
-            (name.to_string(), AtomicU16::new(0))
+            (name.to_string(), AtomicI64::new(0))
         }).collect();
         Self { data: Arc::new(map) }
     }
@@ -28,6 +28,16 @@ impl AmapMetrics {
         tom.fetch_sub(1, atomic::Ordering::Relaxed);
         Ok(())
     }
+
+    pub fn add(&self, key: &str, delta: i64) -> Result<()> {
+        let tom = self.data.get(key).ok_or_else(|| anyhow!("key not found"))?;
+        tom.fetch_add(delta, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<i64> {
+        self.data.get(key).map(|v| v.load(atomic::Ordering::Relaxed))
+    }
 }
 
 impl fmt::Display for AmapMetrics {
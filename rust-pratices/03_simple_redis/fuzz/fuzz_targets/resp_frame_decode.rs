@@ -0,0 +1,34 @@
+#![no_main]
+
+// pull only the resp module in by path, the fuzz build doesn't need the whole server
+#[allow(dead_code)]
+#[path = "../../src/resp/mod.rs"]
+mod resp;
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use resp::{RespDecode, RespError, RespFrame, RespLimits};
+
+// run with: cargo fuzz run resp_frame_decode
+fuzz_target!(|data: &[u8]| {
+    let _ = RespFrame::decode(BytesMut::from(data));
+
+    // small limits so the fuzzer reaches every limit branch quickly
+    let limits = RespLimits::new()
+        .max_bulk_len(1024)
+        .max_array_len(64)
+        .max_nesting_depth(8)
+        .max_pending_buf(4096);
+    let mut buf = BytesMut::from(data);
+    loop {
+        let before = buf.len();
+        match RespFrame::decode_with(&mut buf, &limits) {
+            Ok(_) => assert!(buf.len() < before, "a decoded frame must consume bytes"),
+            Err(RespError::NotComplete) => break,
+            Err(e) => {
+                assert!(e.is_fatal());
+                break;
+            }
+        }
+    }
+});
@@ -0,0 +1,428 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cmd::{all_commands, command_categories, lookup, CommandSpec, WRITE},
+    utils::glob_match,
+};
+
+// the categories ACL rules can name with +@ / -@
+pub const CATEGORIES: &[&str] = &[
+    "keyspace", "read", "write", "set", "list", "hash", "string", "admin", "dangerous", "fast", "slow",
+    "connection", "transaction", "scripting", "pubsub", "stream", "bitmap", "hyperloglog", "sortedset", "geo",
+];
+
+pub const DEFAULT_USER: &str = "default";
+
+// passwords are only kept as their SHA256, like redis
+pub fn hash_password(password: &[u8]) -> String {
+    hex::encode(Sha256::digest(password))
+}
+
+// why a command was refused
+#[derive(Debug, PartialEq)]
+pub enum Denied {
+    Command,
+    Key,
+    Channel,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: Vec<u8>,
+    read: bool,
+    write: bool,
+}
+
+// An ACL user. The command rules are applied in order, like redis, into the set of
+// allowed commands; the normalized rules are kept to describe the user.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    passwords: BTreeSet<String>,
+    command_rules: Vec<String>,
+    allowed: HashSet<&'static str>,
+    // single subcommands allowed with +cmd|sub
+    allowed_subcommands: HashSet<String>,
+    keys: Vec<KeyPattern>,
+    channels: Vec<Vec<u8>>,
+}
+
+impl User {
+    // a new user can do nothing until rules are given
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            command_rules: Vec::new(),
+            allowed: HashSet::new(),
+            allowed_subcommands: HashSet::new(),
+            keys: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_nopass(&self) -> bool {
+        self.nopass
+    }
+
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash_password(password)))
+    }
+
+    // apply one ACL SETUSER rule
+    pub fn apply(&mut self, rule: &str) -> Result<(), String> {
+        let syntax = || format!("Error in ACL SETUSER modifier '{}': Syntax error", rule);
+        let lower = rule.to_ascii_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => return self.apply("~*"),
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => return self.apply("&*"),
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => return self.apply("+@all"),
+            "nocommands" => return self.apply("-@all"),
+            "reset" => {
+                *self = User::new(&self.name);
+                self.apply("resetchannels")?;
+            }
+            _ => {
+                let Some((prefix, rest)) = rule.split_at_checked(1) else {
+                    return Err(syntax());
+                };
+                match prefix {
+                    ">" => {
+                        self.passwords.insert(hash_password(rest.as_bytes()));
+                        self.nopass = false;
+                    }
+                    "<" => {
+                        if !self.passwords.remove(&hash_password(rest.as_bytes())) {
+                            return Err(format!("Error in ACL SETUSER modifier '{}': no such password", rule));
+                        }
+                    }
+                    "#" => {
+                        if rest.len() != 64 || !rest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                            return Err(format!(
+                                "Error in ACL SETUSER modifier '{}': The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters",
+                                rule
+                            ));
+                        }
+                        self.passwords.insert(rest.to_ascii_lowercase());
+                        self.nopass = false;
+                    }
+                    "!" => {
+                        self.passwords.remove(&rest.to_ascii_lowercase());
+                    }
+                    "~" => self.add_key_pattern(rest, true, true),
+                    "%" => {
+                        let (perms, pattern) = rest.split_once('~').ok_or_else(syntax)?;
+                        let perms = perms.to_ascii_uppercase();
+                        if perms.is_empty() || !perms.chars().all(|c| c == 'R' || c == 'W') {
+                            return Err(syntax());
+                        }
+                        self.add_key_pattern(pattern, perms.contains('R'), perms.contains('W'));
+                    }
+                    "&" => {
+                        if !self.channels.iter().any(|c| c == rest.as_bytes()) {
+                            self.channels.push(rest.as_bytes().to_vec());
+                        }
+                    }
+                    "+" | "-" => self.apply_command_rule(prefix == "+", &lower[1..]).map_err(|_| syntax())?,
+                    _ => return Err(syntax()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, pattern: &str, read: bool, write: bool) {
+        let pattern = pattern.as_bytes().to_vec();
+        match self.keys.iter_mut().find(|k| k.pattern == pattern) {
+            Some(k) => {
+                k.read |= read;
+                k.write |= write;
+            }
+            None => self.keys.push(KeyPattern { pattern, read, write }),
+        }
+    }
+
+    // +@category, +command, +command|subcommand, and the same with -
+    fn apply_command_rule(&mut self, allow: bool, target: &str) -> Result<(), ()> {
+        let sign = if allow { "+" } else { "-" };
+        if let Some(category) = target.strip_prefix('@') {
+            if category == "all" {
+                self.command_rules.clear();
+                self.allowed_subcommands.clear();
+                self.allowed = if allow { all_commands().map(|spec| spec.name).collect() } else { HashSet::new() };
+                if allow {
+                    self.command_rules.push("+@all".into());
+                }
+                return Ok(());
+            }
+            if !CATEGORIES.contains(&category) {
+                return Err(());
+            }
+            for spec in all_commands().filter(|spec| command_categories(spec).contains(&category)) {
+                self.allow(spec.name, allow);
+            }
+        } else if let Some((name, sub)) = target.split_once('|') {
+            let spec = lookup(name.as_bytes()).ok_or(())?;
+            let full = format!("{}|{}", spec.name, sub);
+            if allow {
+                self.allowed_subcommands.insert(full);
+            } else {
+                self.allowed_subcommands.remove(&full);
+            }
+        } else {
+            let spec = lookup(target.as_bytes()).ok_or(())?;
+            self.allow(spec.name, allow);
+            self.allowed_subcommands.retain(|s| !s.starts_with(&format!("{}|", spec.name)));
+        }
+        self.command_rules.push(format!("{}{}", sign, target));
+        Ok(())
+    }
+
+    fn allow(&mut self, name: &'static str, allow: bool) {
+        if allow {
+            self.allowed.insert(name);
+        } else {
+            self.allowed.remove(name);
+        }
+    }
+
+    pub fn can_run(&self, spec: &CommandSpec, argv: &[Vec<u8>]) -> bool {
+        if self.allowed.contains(spec.name) {
+            return true;
+        }
+        argv.get(1).is_some_and(|sub| {
+            let sub = String::from_utf8_lossy(sub).to_ascii_lowercase();
+            self.allowed_subcommands.contains(&format!("{}|{}", spec.name, sub))
+        })
+    }
+
+    pub fn can_access_key(&self, key: &[u8], write: bool) -> bool {
+        self.keys.iter().any(|k| (if write { k.write } else { k.read }) && glob_match(&k.pattern, key))
+    }
+
+    pub fn can_access_channel(&self, channel: &[u8]) -> bool {
+        self.channels.iter().any(|pattern| glob_match(pattern, channel))
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    pub fn passwords(&self) -> impl Iterator<Item = &String> {
+        self.passwords.iter()
+    }
+
+    pub fn commands_description(&self) -> String {
+        let mut rules = self.command_rules.clone();
+        if rules.first().map(String::as_str) != Some("+@all") {
+            rules.insert(0, "-@all".into());
+        }
+        rules.join(" ")
+    }
+
+    pub fn keys_description(&self) -> String {
+        let keys = self.keys.iter().map(|k| {
+            let pattern = String::from_utf8_lossy(&k.pattern);
+            match (k.read, k.write) {
+                (true, true) => format!("~{}", pattern),
+                (true, false) => format!("%R~{}", pattern),
+                _ => format!("%W~{}", pattern),
+            }
+        });
+        keys.collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn channels_description(&self) -> String {
+        let channels = self.channels.iter().map(|c| format!("&{}", String::from_utf8_lossy(c)));
+        channels.collect::<Vec<_>>().join(" ")
+    }
+
+    // one line of ACL LIST, rules that would recreate the user
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().iter().map(|f| f.to_string()));
+        parts.extend(self.passwords.iter().map(|p| format!("#{}", p)));
+        parts.push(if self.keys.is_empty() { "resetkeys".into() } else { self.keys_description() });
+        parts.push(if self.channels.is_empty() { "resetchannels".into() } else { self.channels_description() });
+        parts.push(self.commands_description());
+        parts.join(" ")
+    }
+}
+
+// The users of the server. `default` always exists: it's the user of new connections,
+// without a password unless requirepass is set.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+}
+
+impl Acl {
+    pub fn new(requirepass: Option<&str>) -> Self {
+        let mut default = User::new(DEFAULT_USER);
+        let password = requirepass.map(|p| format!(">{}", p)).unwrap_or_else(|| "nopass".into());
+        for rule in ["on", password.as_str(), "~*", "&*", "+@all"] {
+            default.apply(rule).expect("valid default user rules");
+        }
+        Self { users: RwLock::new(BTreeMap::from([(DEFAULT_USER.to_string(), default)])) }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<String, User>> {
+        self.users.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, User>> {
+        self.users.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // whether new connections start authenticated as the default user
+    pub fn default_needs_auth(&self) -> bool {
+        !self.read().get(DEFAULT_USER).is_some_and(|u| u.enabled && u.nopass)
+    }
+
+    pub fn authenticate(&self, name: &str, password: &[u8]) -> bool {
+        self.read().get(name).is_some_and(|u| u.check_password(password))
+    }
+
+    // create or change a user, all rules apply or none
+    pub fn set_user(&self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut users = self.write();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply(rule)?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<User> {
+        self.read().get(name).cloned()
+    }
+
+    pub fn del_user(&self, name: &str) -> bool {
+        self.write().remove(name).is_some()
+    }
+
+    pub fn users(&self) -> Vec<User> {
+        self.read().values().cloned().collect()
+    }
+
+    pub fn check(&self, name: &str, spec: &CommandSpec, argv: &[Vec<u8>]) -> Result<(), Denied> {
+        let users = self.read();
+        let user = users.get(name).filter(|u| u.enabled).ok_or(Denied::Command)?;
+        if !user.can_run(spec, argv) {
+            return Err(Denied::Command);
+        }
+        let channels = match spec.name {
+            "publish" => &argv[1..2],
+            "subscribe" | "psubscribe" => &argv[1..],
+            _ => &[],
+        };
+        if channels.iter().any(|channel| !user.can_access_channel(channel)) {
+            return Err(Denied::Channel);
+        }
+        let write = spec.has_flag(WRITE);
+        if spec.keys(argv).iter().any(|key| !user.can_access_key(key, write)) {
+            return Err(Denied::Key);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::lookup;
+
+    use super::{hash_password, Acl, Denied, User};
+
+    fn argv(line: &str) -> Vec<Vec<u8>> {
+        line.split_whitespace().map(|s| s.as_bytes().to_vec()).collect()
+    }
+
+    fn rules(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_user_rules() {
+        let mut user = User::new("alice");
+        for rule in ["on", ">secret", "~app:*", "%R~shared:*", "&news", "+@read", "-hgetall", "+client|id"] {
+            user.apply(rule).unwrap();
+        }
+        assert!(user.check_password(b"secret"));
+        assert!(!user.check_password(b"other"));
+        let get = lookup(b"get").unwrap();
+        assert!(user.can_run(get, &argv("get a")));
+        assert!(!user.can_run(lookup(b"set").unwrap(), &argv("set a 1")));
+        assert!(!user.can_run(lookup(b"hgetall").unwrap(), &argv("hgetall h")));
+        assert!(user.can_run(lookup(b"client").unwrap(), &argv("client id")));
+        assert!(!user.can_run(lookup(b"client").unwrap(), &argv("client kill 1.2.3.4:5")));
+        assert!(user.can_access_key(b"app:1", true));
+        assert!(user.can_access_key(b"shared:1", false));
+        assert!(!user.can_access_key(b"shared:1", true));
+        assert!(user.can_access_channel(b"news"));
+        assert_eq!(
+            user.describe(),
+            format!("user alice on #{} ~app:* %R~shared:* &news -@all +@read -hgetall +client|id", hash_password(b"secret"))
+        );
+
+        assert!(user.apply("+@nope").is_err());
+        assert!(user.apply("<wrong").is_err());
+        user.apply("reset").unwrap();
+        assert!(!user.is_enabled());
+        assert_eq!(user.describe(), "user alice off resetkeys resetchannels -@all");
+    }
+
+    #[test]
+    fn test_acl_check() {
+        let acl = Acl::new(None);
+        assert!(!acl.default_needs_auth());
+        acl.set_user("bob", &rules("on nopass ~bob:* +@all -flushall")).unwrap();
+        let set = lookup(b"set").unwrap();
+        assert_eq!(acl.check("bob", set, &argv("set bob:1 x")), Ok(()));
+        assert_eq!(acl.check("bob", set, &argv("set alice:1 x")), Err(Denied::Key));
+        assert_eq!(acl.check("bob", lookup(b"flushall").unwrap(), &argv("flushall")), Err(Denied::Command));
+        assert_eq!(acl.check("nobody", set, &argv("set a 1")), Err(Denied::Command));
+        acl.set_user("carol", &rules("on nopass resetchannels &news:* +publish")).unwrap();
+        let publish = lookup(b"publish").unwrap();
+        assert_eq!(acl.check("carol", publish, &argv("publish news:1 hi")), Ok(()));
+        assert_eq!(acl.check("carol", publish, &argv("publish other hi")), Err(Denied::Channel));
+
+        // a failing rule leaves the user as it was
+        assert!(acl.set_user("bob", &rules("off +@nope")).is_err());
+        assert!(acl.get_user("bob").unwrap().is_enabled());
+
+        let acl = Acl::new(Some("pw"));
+        assert!(acl.default_needs_auth());
+        assert!(acl.authenticate("default", b"pw"));
+        assert!(!acl.authenticate("default", b"nope"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::cmd::Argv;
+
+type Waiters = HashMap<(usize, Vec<u8>), HashMap<u64, Arc<Notify>>>;
+
+// The clients blocked on keys, e.g. by XREAD BLOCK. A write to one of the keys
+// wakes them up to run their command again.
+#[derive(Debug, Default)]
+pub struct Blocking {
+    waiters: Mutex<Waiters>,
+    next_id: AtomicU64,
+}
+
+// what a command that found nothing asks its connection to wait for
+#[derive(Debug, Clone)]
+pub struct Blocked {
+    pub keys: Vec<Vec<u8>>,
+    // None blocks forever
+    pub timeout: Option<Duration>,
+    // the command to run again, with e.g. `$` already resolved
+    pub argv: Argv,
+}
+
+impl Blocking {
+    fn waiters(&self) -> MutexGuard<'_, Waiters> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Start watching the keys. A write after this wakes the waiter even when it
+    // happens before `Waiter::woken` is awaited.
+    pub fn watch(self: &Arc<Self>, db: usize, keys: &[Vec<u8>]) -> Waiter {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let notify = Arc::new(Notify::new());
+        let mut waiters = self.waiters();
+        for key in keys {
+            waiters.entry((db, key.clone())).or_default().insert(id, notify.clone());
+        }
+        Waiter { blocking: self.clone(), id, db, keys: keys.to_vec(), notify }
+    }
+
+    // wake everyone waiting on one of the keys
+    pub fn signal(&self, db: usize, keys: &[&[u8]]) {
+        let waiters = self.waiters();
+        if waiters.is_empty() {
+            return;
+        }
+        for key in keys {
+            for notify in waiters.get(&(db, key.to_vec())).into_iter().flat_map(|w| w.values()) {
+                notify.notify_one();
+            }
+        }
+    }
+
+    // the blocked_clients of INFO
+    pub fn blocked_clients(&self) -> usize {
+        let waiters = self.waiters();
+        let mut ids: Vec<u64> = waiters.values().flat_map(|w| w.keys().copied()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.len()
+    }
+}
+
+// one blocked client, stops watching when dropped
+#[derive(Debug)]
+pub struct Waiter {
+    blocking: Arc<Blocking>,
+    id: u64,
+    db: usize,
+    keys: Vec<Vec<u8>>,
+    notify: Arc<Notify>,
+}
+
+impl Waiter {
+    pub async fn woken(&self) {
+        self.notify.notified().await
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut waiters = self.blocking.waiters();
+        for key in &self.keys {
+            let k = (self.db, key.clone());
+            if let Some(w) = waiters.get_mut(&k) {
+                w.remove(&self.id);
+                if w.is_empty() {
+                    waiters.remove(&k);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::Blocking;
+
+    #[tokio::test]
+    async fn test_signal_wakes_watchers() {
+        let blocking = Arc::new(Blocking::default());
+        let waiter = blocking.watch(0, &[b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(blocking.blocked_clients(), 1);
+        // another db, nothing happens
+        blocking.signal(1, &[b"a"]);
+        assert!(tokio::time::timeout(Duration::from_millis(10), waiter.woken()).await.is_err());
+        // a signal before awaiting isn't lost
+        blocking.signal(0, &[b"b"]);
+        tokio::time::timeout(Duration::from_millis(100), waiter.woken()).await.unwrap();
+        drop(waiter);
+        assert_eq!(blocking.blocked_clients(), 0);
+    }
+}
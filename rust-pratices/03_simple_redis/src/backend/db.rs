@@ -0,0 +1,375 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Deref,
+    panic,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use rand::Rng;
+
+use crate::config::MaxmemoryPolicy;
+
+use super::{Shard, Value};
+
+// One logical database, split into shards picked by key hash. Every shard has its
+// own lock, so commands on keys of different shards run in parallel. A command
+// locks the shards of its keys in ascending order, two of them can't wait on
+// each other; commands without keys (KEYS, FLUSHDB, snapshots...) lock them all.
+#[derive(Debug)]
+pub struct Keyspace {
+    shards: Vec<RwLock<Shard>>,
+}
+
+impl Keyspace {
+    pub fn new(shards: usize) -> Self {
+        Self { shards: (0..shards.max(1)).map(|_| RwLock::default()).collect() }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    // the shards of the keys, sorted and deduplicated: the order they're locked in
+    pub fn shards_of<K: AsRef<[u8]>>(&self, keys: &[K]) -> Vec<usize> {
+        let mut shards: Vec<usize> = keys.iter().map(|k| self.shard_of(k.as_ref())).collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+    }
+
+    // a panic while holding a lock doesn't make the data unusable, keep serving it
+    pub fn read(&self, shards: Option<&[usize]>) -> DbReadGuard<'_> {
+        DbReadGuard(self.lock(shards, |s| Locked::Read(s.read().unwrap_or_else(|e| e.into_inner()))))
+    }
+
+    pub fn write(&self, shards: Option<&[usize]>) -> Db<'_> {
+        self.lock(shards, |s| Locked::Write(s.write().unwrap_or_else(|e| e.into_inner())))
+    }
+
+    // `shards` must be ascending, None locks every shard
+    fn lock<'a>(&'a self, shards: Option<&[usize]>, lock: impl Fn(&'a RwLock<Shard>) -> Locked<'a>) -> Db<'a> {
+        let mut locked: Vec<Option<Locked<'a>>> = (0..self.shards.len()).map(|_| None).collect();
+        match shards {
+            None => locked.iter_mut().zip(&self.shards).for_each(|(slot, shard)| *slot = Some(lock(shard))),
+            Some(shards) => {
+                debug_assert!(shards.is_sorted(), "shards must be locked in ascending order");
+                shards.iter().for_each(|&i| locked[i] = Some(lock(&self.shards[i])));
+            }
+        }
+        Db { keyspace: self, locked }
+    }
+}
+
+enum Locked<'a> {
+    Read(RwLockReadGuard<'a, Shard>),
+    Write(RwLockWriteGuard<'a, Shard>),
+}
+
+impl Deref for Locked<'_> {
+    type Target = Shard;
+
+    fn deref(&self) -> &Shard {
+        match self {
+            Locked::Read(guard) => guard,
+            Locked::Write(guard) => guard,
+        }
+    }
+}
+
+impl Locked<'_> {
+    fn get_mut(&mut self) -> &mut Shard {
+        match self {
+            Locked::Write(guard) => guard,
+            Locked::Read(_) => unreachable!("shard is only locked for reading"),
+        }
+    }
+}
+
+// The locked shards of a database. Key operations go to the shard of the key, which
+// must be one of the locked ones; whole database operations cover the locked shards.
+pub struct Db<'a> {
+    keyspace: &'a Keyspace,
+    locked: Vec<Option<Locked<'a>>>,
+}
+
+// The panic payload of a command that used a key its spec doesn't declare, cmd::call
+// turns it into an error reply.
+#[derive(Debug)]
+pub struct UndeclaredKey;
+
+// Locking the shard of such a key now would be out of order and could deadlock with
+// another command. Debug builds stop right here to get the key spec fixed.
+fn undeclared_key() -> ! {
+    debug_assert!(false, "the shard of the key is not locked, the command's key spec misses it");
+    panic::panic_any(UndeclaredKey)
+}
+
+// Read access to a database, only the shared methods of Db are reachable.
+pub struct DbReadGuard<'a>(Db<'a>);
+
+impl<'a> Deref for DbReadGuard<'a> {
+    type Target = Db<'a>;
+
+    fn deref(&self) -> &Db<'a> {
+        &self.0
+    }
+}
+
+impl<'a> Db<'a> {
+    fn shard(&self, key: &[u8]) -> &Shard {
+        match self.locked[self.keyspace.shard_of(key)].as_deref() {
+            Some(shard) => shard,
+            None => undeclared_key(),
+        }
+    }
+
+    fn shard_mut(&mut self, key: &[u8]) -> &mut Shard {
+        let index = self.keyspace.shard_of(key);
+        match self.locked[index].as_mut() {
+            Some(shard) => shard.get_mut(),
+            None => undeclared_key(),
+        }
+    }
+
+    fn shards(&self) -> impl Iterator<Item = &Shard> {
+        self.locked.iter().flatten().map(|s| s.deref())
+    }
+
+    fn shards_mut(&mut self) -> impl Iterator<Item = &mut Shard> + use<'_, 'a> {
+        self.locked.iter_mut().flatten().map(|s| s.get_mut())
+    }
+
+    // number of keys, expired keys not purged yet are counted too like redis DBSIZE
+    pub fn len(&self) -> usize {
+        self.shards().map(|s| s.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards().all(|s| s.is_empty())
+    }
+
+    pub fn expires_len(&self) -> usize {
+        self.shards().map(|s| s.expires_len()).sum()
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.shards().map(|s| s.used_memory()).sum()
+    }
+
+    pub fn settle(&mut self) {
+        self.shards_mut().for_each(|s| s.settle());
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Value> {
+        self.shard(key).get(key)
+    }
+
+    pub fn peek(&self, key: &[u8]) -> Option<(&Value, u64, u8)> {
+        self.shard(key).peek(key)
+    }
+
+    pub fn key_memory(&self, key: &[u8], samples: usize) -> Option<usize> {
+        self.shard(key).key_memory(key, samples)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.shard_mut(key).get_mut(key)
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.shard(key).contains(key)
+    }
+
+    pub fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        self.shard_mut(key).expire_if_needed(key)
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Value) {
+        self.shard_mut(&key).set(key, value)
+    }
+
+    pub fn set_keep_ttl(&mut self, key: Vec<u8>, value: Value) {
+        self.shard_mut(&key).set_keep_ttl(key, value)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.shard_mut(key).remove(key)
+    }
+
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        self.shard_mut(key).remove_if_empty(key)
+    }
+
+    pub fn expire_at(&self, key: &[u8]) -> Option<u64> {
+        self.shard(key).expire_at(key)
+    }
+
+    pub fn set_expire(&mut self, key: &[u8], at: u64) -> bool {
+        self.shard_mut(key).set_expire(key, at)
+    }
+
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.shard_mut(key).persist(key)
+    }
+
+    pub fn set_access(&mut self, key: &[u8], idle_ms: Option<u64>, counter: Option<u8>) {
+        self.shard_mut(key).set_access(key, idle_ms, counter)
+    }
+
+    pub fn clear(&mut self) {
+        self.shards_mut().for_each(|s| s.clear());
+    }
+
+    // exchange the contents with another database locked the same way, SWAPDB and loading
+    pub fn swap(&mut self, other: &mut Db) {
+        for (a, b) in self.locked.iter_mut().zip(other.locked.iter_mut()) {
+            if let (Some(a), Some(b)) = (a, b) {
+                std::mem::swap(a.get_mut(), b.get_mut());
+            }
+        }
+    }
+
+    // a copy of the locked shards, for saving in the background
+    pub fn to_keyspace(&self) -> Keyspace {
+        let shards = self.locked.iter().map(|s| RwLock::new(s.as_deref().cloned().unwrap_or_default()));
+        Keyspace { shards: shards.collect() }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.shards().flat_map(|s| s.keys())
+    }
+
+    // live entries with their deadline, used by snapshots
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Value, Option<u64>)> {
+        self.shards().flat_map(|s| s.iter())
+    }
+
+    // a random shard, picked by its share of `size`, then a random key of it
+    pub fn random_key(&self) -> Option<&Vec<u8>> {
+        self.pick_shard(|s| s.len())?.random_key()
+    }
+
+    fn pick_shard(&self, size: impl Fn(&Shard) -> usize) -> Option<&Shard> {
+        let total: usize = self.shards().map(&size).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut n = rand::thread_rng().gen_range(0..total);
+        self.shards().find(|s| {
+            let found = n < size(s);
+            n = n.saturating_sub(size(s));
+            found
+        })
+    }
+
+    // walk the shards one after the other, the cursor is a position in all of them
+    pub fn scan(&self, cursor: usize, count: usize) -> (usize, Vec<&Vec<u8>>) {
+        let (mut start, mut keys) = (0, vec![]);
+        let mut budget = count;
+        for shard in self.shards() {
+            let len = shard.len();
+            if cursor < start + len && budget > 0 {
+                let pos = cursor.max(start) - start;
+                let (next, found) = shard.scan(pos, budget);
+                keys.extend(found);
+                if next != 0 {
+                    return (start + next, keys);
+                }
+                budget -= len - pos;
+            }
+            start += len;
+            if budget == 0 {
+                break;
+            }
+        }
+        let total = self.len();
+        (if start >= total { 0 } else { start }, keys)
+    }
+
+    pub fn set_keep_expired(&mut self, keep: bool) {
+        self.shards_mut().for_each(|s| s.set_keep_expired(keep));
+    }
+
+    pub fn take_expired(&mut self) -> Vec<Vec<u8>> {
+        self.shards_mut().flat_map(|s| s.take_expired()).collect()
+    }
+
+    // `samples` spread over the shards by how many keys with a TTL they have
+    pub fn active_expire(&mut self, samples: usize) -> usize {
+        let total = self.expires_len();
+        if total == 0 {
+            return 0;
+        }
+        self.shards_mut()
+            .map(|s| {
+                let share = (samples * s.expires_len()).div_ceil(total);
+                s.active_expire(share)
+            })
+            .sum()
+    }
+
+    // sample one shard, picked by its share of the keys the policy may evict
+    pub fn eviction_candidate(&self, policy: MaxmemoryPolicy, samples: usize) -> Option<(Vec<u8>, u64)> {
+        use MaxmemoryPolicy::*;
+
+        let volatile = matches!(policy, VolatileLru | VolatileLfu | VolatileRandom | VolatileTtl);
+        let shard = self.pick_shard(|s| if volatile { s.expires_len() } else { s.len() })?;
+        shard.eviction_candidate(policy, samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::Value;
+
+    use super::Keyspace;
+
+    fn value() -> Value {
+        Value::String(vec![].into())
+    }
+
+    #[test]
+    fn test_keyspace_locks_key_shards() {
+        let keyspace = Keyspace::new(8);
+        let shards = keyspace.shards_of(&["a", "b", "a"]);
+        assert!(shards.len() <= 2 && shards.is_sorted());
+        let mut db = keyspace.write(Some(&shards));
+        db.set(b"a".to_vec(), value());
+        db.set(b"b".to_vec(), value());
+        // another command on keys of other shards isn't blocked
+        let others: Vec<usize> = (0..8).filter(|i| !shards.contains(i)).collect();
+        assert_eq!(keyspace.read(Some(&others)).len(), 0);
+        drop(db);
+        assert_eq!(keyspace.read(None).len(), 2);
+    }
+
+    #[test]
+    fn test_keyspace_scan() {
+        let keyspace = Keyspace::new(4);
+        let mut db = keyspace.write(None);
+        for i in 0..25 {
+            db.set(format!("k{}", i).into_bytes(), value());
+        }
+        let mut cursor = 0;
+        let mut seen = 0;
+        loop {
+            let (next, keys) = db.scan(cursor, 10);
+            seen += keys.len();
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(seen, 25);
+        assert!(db.random_key().is_some());
+    }
+}
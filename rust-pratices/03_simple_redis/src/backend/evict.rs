@@ -0,0 +1,110 @@
+use std::{sync::atomic::Ordering, time::Instant};
+
+use crate::{config::MaxmemoryPolicy, pubsub};
+
+use super::Backend;
+
+impl Backend {
+    // Evict keys until the used memory fits maxmemory again, like redis performEvictions:
+    // every round samples each db and removes the best candidate of all of them.
+    // Returns false when still over the limit because the policy has nothing to evict.
+    // A replica doesn't evict by itself, it gets the DEL of its master's evictions.
+    pub fn perform_evictions(&self) -> bool {
+        let maxmemory = self.config().maxmemory as usize;
+        if maxmemory == 0 || self.repl.is_replica() || self.used_memory() <= maxmemory {
+            return true;
+        }
+        let start = Instant::now();
+        let fits = self.evict_to(maxmemory);
+        self.latency.record("eviction-cycle", start.elapsed());
+        fits
+    }
+
+    fn evict_to(&self, maxmemory: usize) -> bool {
+        let policy = self.config().maxmemory_policy;
+        let samples = self.config().maxmemory_samples as usize;
+        while self.used_memory() > maxmemory {
+            if policy == MaxmemoryPolicy::Noeviction {
+                return false;
+            }
+            let best = (0..self.db_count())
+                .filter_map(|i| {
+                    let (key, score) = self.read(i).eviction_candidate(policy, samples)?;
+                    Some((i, key, score))
+                })
+                .max_by_key(|(_, _, score)| *score);
+            let Some((index, key, _)) = best else {
+                return false;
+            };
+            let _order = self.repl.is_enabled().then(|| self.repl.order());
+            if self.write(index).remove(&key).is_some() {
+                self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                self.pubsub.notify_keyspace_event(pubsub::EVICTED, "evicted", &key, index);
+                self.tracking.invalidate(&[&key], None);
+                self.repl.propagate(index, &[vec![b"del".to_vec(), key]]);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, Value},
+        config::{Config, MaxmemoryPolicy},
+        utils::now_ms,
+    };
+
+    fn backend(policy: MaxmemoryPolicy) -> Backend {
+        Backend::new(Config {
+            databases: 2,
+            maxmemory: 10_000,
+            maxmemory_policy: policy,
+            ..Config::default()
+        })
+    }
+
+    fn fill(backend: &Backend, db: usize, prefix: &str, n: usize) {
+        for i in 0..n {
+            let key = format!("{}{}", prefix, i).into_bytes();
+            backend.write(db).set(key, Value::String(vec![0; 100].into()));
+        }
+    }
+
+    #[test]
+    fn test_allkeys_eviction() {
+        let backend = backend(MaxmemoryPolicy::AllkeysLru);
+        fill(&backend, 0, "a", 100);
+        fill(&backend, 1, "b", 100);
+        assert!(backend.used_memory() > 10_000);
+
+        assert!(backend.perform_evictions());
+        assert!(backend.used_memory() <= 10_000);
+        let keys = backend.read(0).len() + backend.read(1).len();
+        assert!(keys < 200);
+        assert_eq!(backend.evicted_keys.load(std::sync::atomic::Ordering::Relaxed) as usize, 200 - keys);
+    }
+
+    #[test]
+    fn test_noeviction() {
+        let backend = backend(MaxmemoryPolicy::Noeviction);
+        fill(&backend, 0, "a", 100);
+        assert!(!backend.perform_evictions());
+        assert_eq!(backend.read(0).len(), 100);
+    }
+
+    #[test]
+    fn test_volatile_only_evicts_keys_with_ttl() {
+        let backend = backend(MaxmemoryPolicy::VolatileTtl);
+        fill(&backend, 0, "a", 100);
+        for i in 0..10 {
+            let key = format!("a{}", i).into_bytes();
+            backend.write(0).set_expire(&key, now_ms() + 10_000);
+        }
+        // the volatile keys alone can't bring memory under the limit
+        assert!(!backend.perform_evictions());
+        assert_eq!(backend.read(0).len(), 90);
+        assert_eq!(backend.read(0).expires_len(), 0);
+    }
+}
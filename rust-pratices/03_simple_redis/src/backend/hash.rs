@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use super::{
+    listpack::ListPack,
+    value::{sampled_size, EncodingLimits, ELEMENT_OVERHEAD},
+};
+
+// A hash value: a listpack of field, value pairs while it has at most
+// hash-max-listpack-entries fields none longer than hash-max-listpack-value,
+// a hash table from then on.
+#[derive(Debug, Clone)]
+pub enum Hash {
+    ListPack(ListPack),
+    Table(HashMap<Vec<u8>, Vec<u8>>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash::ListPack(ListPack::new())
+    }
+}
+
+// the index of the field entry in a listpack of pairs
+fn find(lp: &ListPack, field: &[u8]) -> Option<usize> {
+    lp.iter().step_by(2).position(|f| f == field).map(|i| i * 2)
+}
+
+impl Hash {
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>, limits: &EncodingLimits) -> Self {
+        let mut hash = Hash::default();
+        for (field, value) in pairs {
+            hash.insert(&field, &value, limits);
+        }
+        hash
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::ListPack(lp) => lp.len() / 2,
+            Hash::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        match self {
+            Hash::ListPack(lp) => find(lp, field).and_then(|i| lp.get(i + 1)),
+            Hash::Table(table) => table.get(field).map(Vec::as_slice),
+        }
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    // true when the field is new
+    pub fn insert(&mut self, field: &[u8], value: &[u8], limits: &EncodingLimits) -> bool {
+        if let Hash::ListPack(lp) = self {
+            let max_value = limits.hash_max_listpack_value;
+            if field.len() <= max_value && value.len() <= max_value {
+                match find(lp, field) {
+                    Some(i) => {
+                        lp.replace(i + 1, value);
+                        return false;
+                    }
+                    None if lp.len() / 2 < limits.hash_max_listpack_entries => {
+                        lp.push_back(field);
+                        lp.push_back(value);
+                        return true;
+                    }
+                    None => {}
+                }
+            }
+            self.convert();
+        }
+        match self {
+            Hash::Table(table) => table.insert(field.to_vec(), value.to_vec()).is_none(),
+            Hash::ListPack(_) => unreachable!("converted above"),
+        }
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            Hash::ListPack(lp) => match find(lp, field) {
+                Some(i) => {
+                    lp.remove(i);
+                    lp.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Hash::Table(table) => table.remove(field).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], &[u8])> + '_> {
+        match self {
+            Hash::ListPack(lp) => {
+                let mut entries = lp.iter();
+                Box::new(std::iter::from_fn(move || Some((entries.next()?, entries.next()?))))
+            }
+            Hash::Table(table) => Box::new(table.iter().map(|(k, v)| (k.as_slice(), v.as_slice()))),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Hash::ListPack(_) = self {
+            let table = self.iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect();
+            *self = Hash::Table(table);
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Hash::ListPack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Hash::ListPack(lp) => lp.bytes(),
+            Hash::Table(table) => {
+                sampled_size(table.len(), table.iter().map(|(k, v)| k.len() + v.len() + ELEMENT_OVERHEAD), samples)
+            }
+        }
+    }
+}
+
+// the same fields and values, whatever the encodings
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hash;
+    use crate::backend::value::EncodingLimits;
+
+    #[test]
+    fn test_conversion() {
+        let limits = EncodingLimits { hash_max_listpack_entries: 2, hash_max_listpack_value: 4, ..EncodingLimits::default() };
+        let mut hash = Hash::default();
+        assert!(hash.insert(b"a", b"1", &limits));
+        assert!(!hash.insert(b"a", b"2", &limits));
+        assert!(hash.insert(b"b", b"3", &limits));
+        assert_eq!((hash.encoding(), hash.len(), hash.get(b"a")), ("listpack", 2, Some(&b"2"[..])));
+        assert!(hash.remove(b"b") && !hash.remove(b"b"));
+
+        // a long value converts it
+        let mut long = hash.clone();
+        assert!(!long.insert(b"a", b"12345", &limits));
+        assert_eq!((long.encoding(), long.get(b"a")), ("hashtable", Some(&b"12345"[..])));
+        // and so does a third field
+        hash.insert(b"b", b"3", &limits);
+        hash.insert(b"c", b"4", &limits);
+        assert_eq!((hash.encoding(), hash.len()), ("hashtable", 3));
+        let pairs = [(b"a".to_vec(), b"2".to_vec()), (b"b".to_vec(), b"3".to_vec()), (b"c".to_vec(), b"4".to_vec())];
+        assert_eq!(Hash::from_pairs(pairs, &EncodingLimits::default()), hash);
+    }
+}
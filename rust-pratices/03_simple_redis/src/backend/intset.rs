@@ -0,0 +1,128 @@
+// A sorted array of integers stored with the smallest width that fits all of
+// them (2, 4 or 8 bytes), like a redis intset. Sets of integers use it until
+// they pass set-max-intset-entries.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntSet {
+    width: usize,
+    data: Vec<u8>,
+}
+
+impl Default for IntSet {
+    fn default() -> Self {
+        Self { width: 2, data: Vec::new() }
+    }
+}
+
+fn width_of(v: i64) -> usize {
+    if i16::try_from(v).is_ok() {
+        2
+    } else if i32::try_from(v).is_ok() {
+        4
+    } else {
+        8
+    }
+}
+
+fn read(data: &[u8], width: usize, index: usize) -> i64 {
+    let bytes = &data[index * width..(index + 1) * width];
+    match width {
+        2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+        4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+        _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+fn encode(v: i64, width: usize) -> Vec<u8> {
+    v.to_le_bytes()[..width].to_vec()
+}
+
+impl IntSet {
+    pub fn len(&self) -> usize {
+        self.data.len() / self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get(&self, index: usize) -> i64 {
+        read(&self.data, self.width, index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    fn search(&self, v: i64) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.get(mid).cmp(&v) {
+                std::cmp::Ordering::Equal => return Ok(mid),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        Err(lo)
+    }
+
+    pub fn contains(&self, v: i64) -> bool {
+        self.search(v).is_ok()
+    }
+
+    pub fn insert(&mut self, v: i64) -> bool {
+        let width = width_of(v);
+        if width > self.width {
+            // re-encode everything with the wider width
+            let data = self.iter().flat_map(|n| encode(n, width)).collect();
+            self.data = data;
+            self.width = width;
+        }
+        match self.search(v) {
+            Ok(_) => false,
+            Err(i) => {
+                let pos = i * self.width;
+                self.data.splice(pos..pos, encode(v, self.width));
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, v: i64) -> bool {
+        match self.search(v) {
+            Ok(i) => {
+                self.data.drain(i * self.width..(i + 1) * self.width);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntSet;
+
+    #[test]
+    fn test_intset() {
+        let mut set = IntSet::default();
+        assert!(set.insert(5));
+        assert!(set.insert(-3));
+        assert!(!set.insert(5));
+        assert_eq!(set.bytes(), 4);
+        // a wider member upgrades all of them, the order is kept
+        assert!(set.insert(100_000));
+        assert_eq!(set.bytes(), 12);
+        assert!(set.insert(i64::MIN));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![i64::MIN, -3, 5, 100_000]);
+        assert!(set.contains(-3) && !set.contains(4));
+        assert!(set.remove(5));
+        assert!(!set.remove(5));
+        assert_eq!(set.len(), 3);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::utils::now_ms;
+
+// samples kept per event, like redis
+const HISTORY_LEN: usize = 160;
+
+// the spikes of one event, one sample per second
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyEvent {
+    // (unix seconds, milliseconds), oldest first
+    pub history: VecDeque<(u64, u64)>,
+    pub max: u64,
+}
+
+impl LatencyEvent {
+    pub fn latest(&self) -> (u64, u64) {
+        self.history.back().copied().unwrap_or_default()
+    }
+}
+
+// The LATENCY monitor: events that took at least latency-monitor-threshold
+// milliseconds, 0 turns it off
+#[derive(Debug)]
+pub struct Latency {
+    events: Mutex<BTreeMap<&'static str, LatencyEvent>>,
+    threshold: AtomicU64,
+}
+
+impl Latency {
+    pub fn new(threshold: u64) -> Self {
+        Self { events: Mutex::new(BTreeMap::new()), threshold: AtomicU64::new(threshold) }
+    }
+
+    fn events(&self) -> MutexGuard<'_, BTreeMap<&'static str, LatencyEvent>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold(&self, ms: u64) {
+        self.threshold.store(ms, Ordering::Relaxed);
+    }
+
+    // a sample for the event when it was slow enough, spikes in the same second
+    // keep the worst
+    pub fn record(&self, event: &'static str, elapsed: Duration) {
+        let threshold = self.threshold();
+        let ms = elapsed.as_millis() as u64;
+        if threshold == 0 || ms < threshold {
+            return;
+        }
+        let now = now_ms() / 1000;
+        let mut events = self.events();
+        let event = events.entry(event).or_default();
+        event.max = event.max.max(ms);
+        match event.history.back_mut() {
+            Some((time, worst)) if *time == now => *worst = (*worst).max(ms),
+            _ => {
+                event.history.push_back((now, ms));
+                if event.history.len() > HISTORY_LEN {
+                    event.history.pop_front();
+                }
+            }
+        }
+    }
+
+    pub fn latest(&self) -> Vec<(&'static str, LatencyEvent)> {
+        self.events().iter().map(|(name, event)| (*name, event.clone())).collect()
+    }
+
+    pub fn history(&self, event: &str) -> Option<LatencyEvent> {
+        self.events().get(event).cloned()
+    }
+
+    // forget the given events, or every one, returns how many were dropped
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut all = self.events();
+        if events.is_empty() {
+            let n = all.len();
+            all.clear();
+            return n;
+        }
+        events.iter().filter(|name| all.remove(name.as_str()).is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Latency;
+
+    #[test]
+    fn test_latency() {
+        let latency = Latency::new(0);
+        latency.record("command", Duration::from_secs(1));
+        assert!(latency.latest().is_empty());
+
+        latency.set_threshold(10);
+        latency.record("command", Duration::from_millis(9));
+        latency.record("command", Duration::from_millis(15));
+        latency.record("command", Duration::from_millis(12));
+        latency.record("expire-cycle", Duration::from_millis(10));
+        let latest = latency.latest();
+        assert_eq!(latest.iter().map(|(name, _)| *name).collect::<Vec<_>>(), vec!["command", "expire-cycle"]);
+        // both spikes were in the same second
+        let command = latency.history("command").unwrap();
+        assert_eq!(command.history.len(), 1);
+        assert_eq!(command.latest().1, 15);
+        assert_eq!(command.max, 15);
+
+        assert_eq!(latency.reset(&["command".into(), "nope".into()]), 1);
+        assert_eq!(latency.reset(&[]), 1);
+        assert!(latency.history("expire-cycle").is_none());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use super::{
+    listpack::ListPack,
+    value::{sampled_size, EncodingLimits},
+};
+
+// A list value: a listpack while it fits list-max-listpack-size, a deque from
+// then on. The deque is reported as quicklist, the name redis tools expect.
+#[derive(Debug, Clone)]
+pub enum List {
+    ListPack(ListPack),
+    Deque(VecDeque<Vec<u8>>),
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List::ListPack(ListPack::new())
+    }
+}
+
+impl List {
+    pub fn from_items(items: impl IntoIterator<Item = Vec<u8>>, limits: &EncodingLimits) -> Self {
+        let mut list = List::default();
+        for item in items {
+            list.push_back(item, limits);
+        }
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            List::ListPack(lp) => lp.len(),
+            List::Deque(deque) => deque.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // a listpack that can't take `entries` more entries and `added` more bytes becomes a deque
+    fn reserve(&mut self, entries: usize, added: usize, limits: &EncodingLimits) {
+        if let List::ListPack(lp) = self {
+            // the entry header is a byte or two
+            if !limits.list_fits(lp.len() + entries, lp.bytes() + added + 2) {
+                *self = List::Deque(lp.iter().map(<[u8]>::to_vec).collect());
+            }
+        }
+    }
+
+    pub fn push_front(&mut self, item: Vec<u8>, limits: &EncodingLimits) {
+        self.reserve(1, item.len(), limits);
+        match self {
+            List::ListPack(lp) => lp.insert(0, &item),
+            List::Deque(deque) => deque.push_front(item),
+        }
+    }
+
+    pub fn push_back(&mut self, item: Vec<u8>, limits: &EncodingLimits) {
+        self.reserve(1, item.len(), limits);
+        match self {
+            List::ListPack(lp) => lp.push_back(&item),
+            List::Deque(deque) => deque.push_back(item),
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        match self {
+            List::ListPack(lp) if lp.is_empty() => None,
+            List::ListPack(lp) => Some(lp.remove(0)),
+            List::Deque(deque) => deque.pop_front(),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<Vec<u8>> {
+        match self {
+            List::ListPack(lp) if lp.is_empty() => None,
+            List::ListPack(lp) => Some(lp.remove(lp.len() - 1)),
+            List::Deque(deque) => deque.pop_back(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            List::ListPack(lp) => lp.get(index),
+            List::Deque(deque) => deque.get(index).map(Vec::as_slice),
+        }
+    }
+
+    // replace the item at an index known to be in range
+    pub fn set(&mut self, index: usize, item: Vec<u8>, limits: &EncodingLimits) {
+        self.reserve(0, item.len(), limits);
+        match self {
+            List::ListPack(lp) => lp.replace(index, &item),
+            List::Deque(deque) => deque[index] = item,
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        match self {
+            List::ListPack(lp) => {
+                lp.remove(index);
+            }
+            List::Deque(deque) => {
+                deque.remove(index);
+            }
+        }
+    }
+
+    // keep the items start..=end, both in range
+    pub fn trim(&mut self, start: usize, end: usize) {
+        match self {
+            List::ListPack(lp) => {
+                lp.truncate(end + 1);
+                lp.remove_front(start);
+            }
+            List::Deque(deque) => {
+                deque.truncate(end + 1);
+                deque.drain(..start);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = List::default();
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        match self {
+            List::ListPack(lp) => Box::new(lp.iter()),
+            List::Deque(deque) => Box::new(deque.iter().map(Vec::as_slice)),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            List::ListPack(_) => "listpack",
+            List::Deque(_) => "quicklist",
+        }
+    }
+
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            List::ListPack(lp) => lp.bytes(),
+            List::Deque(deque) => sampled_size(deque.len(), deque.iter().map(|v| v.len()), samples),
+        }
+    }
+}
+
+// the same items, whatever the encodings
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::List;
+    use crate::backend::value::EncodingLimits;
+
+    #[test]
+    fn test_conversion() {
+        let limits = EncodingLimits { list_max_listpack_size: 3, ..EncodingLimits::default() };
+        let mut list = List::from_items([b"a".to_vec(), b"b".to_vec()], &limits);
+        list.push_front(b"z".to_vec(), &limits);
+        assert_eq!((list.encoding(), list.get(0)), ("listpack", Some(&b"z"[..])));
+        let mut deque = list.clone();
+        deque.push_back(b"c".to_vec(), &limits);
+        assert_eq!((deque.encoding(), deque.len()), ("quicklist", 4));
+
+        for list in [&mut list, &mut deque] {
+            assert_eq!(list.pop_front(), Some(b"z".to_vec()));
+            list.set(1, b"B".to_vec(), &limits);
+            list.trim(1, list.len() - 1);
+            assert_eq!(list.iter().next(), Some(&b"B"[..]));
+        }
+        assert_eq!(deque.pop_back(), Some(b"c".to_vec()));
+        assert_eq!(list, deque);
+
+        // a negative size limits the bytes, -1 is 4kb
+        let limits = EncodingLimits { list_max_listpack_size: -1, ..EncodingLimits::default() };
+        let mut list = List::default();
+        list.push_back(vec![0; 3000], &limits);
+        assert_eq!(list.encoding(), "listpack");
+        list.push_back(vec![0; 3000], &limits);
+        assert_eq!(list.encoding(), "quicklist");
+    }
+}
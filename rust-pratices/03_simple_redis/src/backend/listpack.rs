@@ -0,0 +1,166 @@
+// A flat byte array of entries, each one a LEB128 length followed by its bytes,
+// like a redis listpack. Small lists, hashes (field, value, field, value, ...)
+// and sets live in one allocation instead of one per element; every operation
+// scans from the start, which is cheap below the *-max-listpack-* limits.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListPack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+pub struct Iter<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+        let (entry, next) = read_entry(self.buf, self.pos);
+        self.pos = next;
+        Some(entry)
+    }
+}
+
+fn read_entry(buf: &[u8], pos: usize) -> (&[u8], usize) {
+    let (mut len, mut shift, mut pos) = (0usize, 0, pos);
+    loop {
+        let byte = buf[pos];
+        pos += 1;
+        len |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    (&buf[pos..pos + len], pos + len)
+}
+
+fn encode_entry(entry: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(entry.len() + 2);
+    let mut len = entry.len();
+    while len >= 0x80 {
+        out.push((len & 0x7f) as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(entry);
+    out
+}
+
+impl ListPack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the encoded size, what the listpack costs in memory
+    pub fn bytes(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { buf: &self.buf, pos: 0 }
+    }
+
+    // the byte offset of entry `index`, the end for `len`
+    fn offset(&self, index: usize) -> usize {
+        let mut pos = 0;
+        for _ in 0..index {
+            pos = read_entry(&self.buf, pos).1;
+        }
+        pos
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.iter().nth(index)
+    }
+
+    pub fn position(&self, entry: &[u8]) -> Option<usize> {
+        self.iter().position(|e| e == entry)
+    }
+
+    pub fn push_back(&mut self, entry: &[u8]) {
+        self.buf.extend_from_slice(&encode_entry(entry));
+        self.len += 1;
+    }
+
+    pub fn insert(&mut self, index: usize, entry: &[u8]) {
+        let pos = self.offset(index.min(self.len));
+        self.buf.splice(pos..pos, encode_entry(entry));
+        self.len += 1;
+    }
+
+    pub fn replace(&mut self, index: usize, entry: &[u8]) {
+        let pos = self.offset(index);
+        let next = read_entry(&self.buf, pos).1;
+        self.buf.splice(pos..next, encode_entry(entry));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Vec<u8> {
+        let pos = self.offset(index);
+        let (entry, next) = read_entry(&self.buf, pos);
+        let entry = entry.to_vec();
+        self.buf.drain(pos..next);
+        self.len -= 1;
+        entry
+    }
+
+    // keep the first `len` entries
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let pos = self.offset(len);
+            self.buf.truncate(pos);
+            self.len = len;
+        }
+    }
+
+    // drop the first `n` entries
+    pub fn remove_front(&mut self, n: usize) {
+        let n = n.min(self.len);
+        let pos = self.offset(n);
+        self.buf.drain(..pos);
+        self.len -= n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListPack;
+
+    #[test]
+    fn test_listpack() {
+        let mut lp = ListPack::new();
+        let long = vec![b'x'; 300];
+        lp.push_back(b"b");
+        lp.push_back(&long);
+        lp.insert(0, b"a");
+        lp.insert(3, b"");
+        assert_eq!(lp.len(), 4);
+        assert_eq!(lp.iter().collect::<Vec<_>>(), vec![&b"a"[..], b"b", &long, b""]);
+        // two bytes of length for the long entry
+        assert_eq!(lp.bytes(), 2 + 2 + 302 + 1);
+        assert_eq!(lp.position(b""), Some(3));
+
+        lp.replace(1, b"bb");
+        assert_eq!(lp.get(1), Some(&b"bb"[..]));
+        assert_eq!(lp.remove(2), long);
+        assert_eq!(lp.get(2), Some(&b""[..]));
+        lp.remove_front(1);
+        assert_eq!(lp.iter().collect::<Vec<_>>(), vec![&b"bb"[..], b""]);
+        lp.truncate(1);
+        assert_eq!((lp.len(), lp.get(1)), (1, None));
+    }
+}
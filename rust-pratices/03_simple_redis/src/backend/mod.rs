@@ -0,0 +1,329 @@
+pub mod blocking;
+pub mod db;
+pub mod evict;
+pub mod hash;
+pub mod hyperloglog;
+pub mod intset;
+pub mod latency;
+pub mod list;
+pub mod listpack;
+pub mod monitor;
+pub mod set;
+pub mod shard;
+pub mod slowlog;
+pub mod snapshot;
+pub mod stats;
+pub mod stream;
+pub mod string;
+pub mod value;
+pub mod zset;
+
+pub use blocking::Blocking;
+pub use db::{Db, DbReadGuard, Keyspace, UndeclaredKey};
+pub use hash::Hash;
+pub use latency::Latency;
+pub use list::List;
+pub use monitor::Monitors;
+pub use set::Set;
+pub use shard::Shard;
+pub use slowlog::SlowLog;
+pub use stats::Stats;
+pub use string::Str;
+pub use value::{EncodingLimits, Value};
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::Instant,
+};
+
+use crate::{
+    acl::Acl, cluster::Cluster, pubsub::{self, PubSub}, config::Config, connections::Connections, replication::Replication,
+    scripting::ScriptCache, shutdown::Shutdown, tracking::Tracking, utils::now_ms,
+};
+
+// The shared keyspace: `databases` independent databases, each one split into
+// `keyspace-shards` shards behind their own locks.
+// Cheap to clone, every connection holds one.
+#[derive(Debug, Clone)]
+pub struct Backend(Arc<BackendInner>);
+
+#[derive(Debug)]
+pub struct BackendInner {
+    // the startup settings, the ones CONFIG SET changes are updated in place
+    config: RwLock<Config>,
+    dbs: Vec<Keyspace>,
+    // writes since the last successful save
+    pub dirty: AtomicU64,
+    // unix seconds of the last successful save
+    pub last_save: AtomicU64,
+    pub bgsave_in_progress: AtomicBool,
+    // estimated bytes of every db, kept up to date by DbWriteGuard
+    used_memory: AtomicUsize,
+    pub evicted_keys: AtomicU64,
+    pub expired_keys: AtomicU64,
+    pub stats: Stats,
+    pub repl: Replication,
+    // the slot layout, only in cluster mode
+    pub cluster: Option<Cluster>,
+    pub scripts: ScriptCache,
+    pub connections: Connections,
+    pub acl: Acl,
+    pub pubsub: PubSub,
+    pub tracking: Tracking,
+    pub blocking: Arc<Blocking>,
+    pub monitors: Arc<Monitors>,
+    pub slowlog: SlowLog,
+    pub latency: Latency,
+    pub shutdown: Shutdown,
+    // every command holds it shared, EXEC holds it exclusive to run atomically
+    cmd_lock: RwLock<()>,
+}
+
+impl Deref for Backend {
+    type Target = BackendInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl Backend {
+    pub fn new(config: Config) -> Self {
+        Self::build(config, None)
+    }
+
+    pub fn with_cluster(config: Config, cluster: Cluster) -> Self {
+        Self::build(config, Some(cluster))
+    }
+
+    fn build(config: Config, cluster: Option<Cluster>) -> Self {
+        let dbs = (0..config.databases).map(|_| Keyspace::new(config.keyspace_shards as usize)).collect();
+        let repl = Replication::new(config.repl_backlog_size as usize, config.replica_output_buffer_limit as usize);
+        let acl = Acl::new(config.requirepass.as_deref());
+        let config_notify = config.notify_keyspace_events;
+        let slowlog = SlowLog::new(config.slowlog_log_slower_than, config.slowlog_max_len as usize);
+        let latency = Latency::new(config.latency_monitor_threshold);
+        Self(Arc::new(BackendInner {
+            config: RwLock::new(config),
+            dbs,
+            dirty: AtomicU64::new(0),
+            last_save: AtomicU64::new(now_ms() / 1000),
+            bgsave_in_progress: AtomicBool::new(false),
+            used_memory: AtomicUsize::new(0),
+            evicted_keys: AtomicU64::new(0),
+            expired_keys: AtomicU64::new(0),
+            stats: Stats::default(),
+            repl,
+            cluster,
+            scripts: ScriptCache::default(),
+            connections: Connections::default(),
+            acl,
+            pubsub: PubSub::new(config_notify),
+            tracking: Tracking::default(),
+            blocking: Arc::default(),
+            monitors: Arc::default(),
+            slowlog,
+            latency,
+            shutdown: Shutdown::default(),
+            cmd_lock: RwLock::new(()),
+        }))
+    }
+
+    pub fn config(&self) -> RwLockReadGuard<'_, Config> {
+        self.config.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn update_config(&self, f: impl FnOnce(&mut Config)) {
+        f(&mut self.config.write().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    // read before locking a db, the limits are copied out of the config
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        self.config().encoding_limits()
+    }
+
+    pub fn db_count(&self) -> usize {
+        self.dbs.len()
+    }
+
+    // the whole db, for the commands and tasks that don't work on given keys
+    pub fn read(&self, index: usize) -> DbReadGuard<'_> {
+        self.read_shards(index, None)
+    }
+
+    pub fn write(&self, index: usize) -> DbWriteGuard<'_> {
+        self.write_shards(index, None)
+    }
+
+    // the shards `keys` live in, the same in every db
+    pub fn shards_of<K: AsRef<[u8]>>(&self, keys: &[K]) -> Vec<usize> {
+        self.dbs[0].shards_of(keys)
+    }
+
+    // only the given shards of a db, ascending like `shards_of` returns them; None is all
+    pub fn read_shards(&self, index: usize, shards: Option<&[usize]>) -> DbReadGuard<'_> {
+        self.dbs[index].read(shards)
+    }
+
+    pub fn write_shards(&self, index: usize, shards: Option<&[usize]>) -> DbWriteGuard<'_> {
+        let mut db = self.dbs[index].write(shards);
+        // a replica doesn't expire keys by itself, it applies the DEL its master propagates
+        let keep_expired = self.repl.is_replica();
+        if keep_expired {
+            db.set_keep_expired(true);
+        }
+        let before = db.used_memory();
+        DbWriteGuard { db, index, backend: &self.0, before, keep_expired }
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn shared(&self) -> RwLockReadGuard<'_, ()> {
+        self.cmd_lock.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn exclusive(&self) -> RwLockWriteGuard<'_, ()> {
+        self.cmd_lock.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // lock the same shards of two different dbs for writing, always in index order so
+    // that concurrent MOVE / SWAPDB can't deadlock each other
+    pub fn write_pair(
+        &self,
+        a: usize,
+        b: usize,
+        shards: Option<&[usize]>,
+    ) -> (DbWriteGuard<'_>, DbWriteGuard<'_>) {
+        assert_ne!(a, b, "write_pair needs two different dbs");
+        if a < b {
+            let ga = self.write_shards(a, shards);
+            let gb = self.write_shards(b, shards);
+            (ga, gb)
+        } else {
+            let gb = self.write_shards(b, shards);
+            let ga = self.write_shards(a, shards);
+            (ga, gb)
+        }
+    }
+
+    // read-lock every db in index order, a consistent view for snapshots
+    pub fn read_all(&self) -> Vec<DbReadGuard<'_>> {
+        (0..self.dbs.len()).map(|i| self.read(i)).collect()
+    }
+
+    pub fn swap_db(&self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let (mut ga, mut gb) = self.write_pair(a, b, None);
+        ga.swap(&mut gb);
+        self.tracking.invalidate_all();
+    }
+
+    pub fn flush_all(&self) {
+        for i in 0..self.dbs.len() {
+            self.write(i).clear();
+        }
+        self.tracking.invalidate_all();
+    }
+
+    pub fn add_dirty(&self, n: u64) {
+        self.dirty.fetch_add(n, Ordering::Relaxed);
+    }
+
+    // one round of active expiration over every db, a replica waits for its master's DEL
+    pub fn active_expire(&self, samples: usize) -> usize {
+        if self.repl.is_replica() {
+            return 0;
+        }
+        let start = Instant::now();
+        // the expirations go to the replication stream as DEL, in order with the writes
+        let _guard = self.shared();
+        let _order = self.repl.is_enabled().then(|| self.repl.order());
+        let expired = (0..self.dbs.len()).map(|i| self.write(i).active_expire(samples)).sum();
+        self.latency.record("expire-cycle", start.elapsed());
+        expired
+    }
+}
+
+// Write access to the locked shards of one db. On drop the sizes of values changed in place are
+// settled and the difference is folded into the backend's used memory, the keys
+// that expired meanwhile are counted, notified and propagated as DEL.
+pub struct DbWriteGuard<'a> {
+    db: Db<'a>,
+    index: usize,
+    backend: &'a BackendInner,
+    before: usize,
+    keep_expired: bool,
+}
+
+impl<'a> Deref for DbWriteGuard<'a> {
+    type Target = Db<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl DerefMut for DbWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.db
+    }
+}
+
+impl Drop for DbWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.db.settle();
+        let after = self.db.used_memory();
+        let used_memory = &self.backend.used_memory;
+        if after >= self.before {
+            used_memory.fetch_add(after - self.before, Ordering::Relaxed);
+        } else {
+            used_memory.fetch_sub(self.before - after, Ordering::Relaxed);
+        }
+        let expired = self.db.take_expired();
+        self.backend.expired_keys.fetch_add(expired.len() as u64, Ordering::Relaxed);
+        for key in &expired {
+            self.backend.pubsub.notify_keyspace_event(pubsub::EXPIRED, "expired", key, self.index);
+        }
+        self.backend.tracking.invalidate(&expired, None);
+        if !expired.is_empty() {
+            let dels: Vec<_> = expired.into_iter().map(|key| vec![b"del".to_vec(), key]).collect();
+            self.backend.repl.propagate(self.index, &dels);
+        }
+        if self.keep_expired {
+            self.db.set_keep_expired(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    use super::{Backend, Value};
+
+    #[test]
+    fn test_swap_db() {
+        let backend = Backend::new(Config { databases: 4, ..Config::default() });
+        backend.write(0).set(b"a".to_vec(), Value::String(b"0".to_vec().into()));
+        backend.write(3).set(b"b".to_vec(), Value::String(b"3".to_vec().into()));
+
+        backend.swap_db(3, 0);
+        assert!(backend.read(0).contains(b"b"));
+        assert!(backend.read(3).contains(b"a"));
+        assert_eq!(backend.read(1).len(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::{cmd::simple, resp::RespFrame};
+
+// the connections that ran MONITOR, each one gets every executed command. Every
+// command looks at the list, so it's behind a lock that readers share.
+#[derive(Debug, Default)]
+pub struct Monitors {
+    senders: RwLock<HashMap<u64, UnboundedSender<RespFrame>>>,
+    next_id: AtomicU64,
+}
+
+impl Monitors {
+    fn senders(&self) -> RwLockReadGuard<'_, HashMap<u64, UnboundedSender<RespFrame>>> {
+        self.senders.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn senders_mut(&self) -> RwLockWriteGuard<'_, HashMap<u64, UnboundedSender<RespFrame>>> {
+        self.senders.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders().is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.senders().len()
+    }
+
+    // `+1339518083.107412 [0 127.0.0.1:60866] "keys" "*"`, like redis
+    pub fn feed(&self, db: usize, addr: &str, argv: &[Vec<u8>]) {
+        let senders = self.senders();
+        if senders.is_empty() {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!("{}.{:06} [{} {}]", now.as_secs(), now.subsec_micros(), db, addr);
+        for arg in argv {
+            line.push(' ');
+            line.push_str(&quote(arg));
+        }
+        let frame = simple(line);
+        for tx in senders.values() {
+            let _ = tx.send(frame.clone());
+        }
+    }
+}
+
+// an argument in double quotes with the bytes that aren't printable escaped
+fn quote(arg: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in arg {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// The receiving end of one monitoring connection, dropping it stops the feed
+#[derive(Debug)]
+pub struct Monitor {
+    id: u64,
+    monitors: Arc<Monitors>,
+    rx: UnboundedReceiver<RespFrame>,
+}
+
+impl Monitor {
+    pub fn new(monitors: &Arc<Monitors>) -> Self {
+        let (tx, rx) = unbounded_channel();
+        let id = monitors.next_id.fetch_add(1, Ordering::Relaxed);
+        monitors.senders_mut().insert(id, tx);
+        Self { id, monitors: monitors.clone(), rx }
+    }
+
+    pub fn try_recv(&mut self) -> Option<RespFrame> {
+        self.rx.try_recv().ok()
+    }
+
+    pub async fn recv(&mut self) -> Option<RespFrame> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.monitors.senders_mut().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::resp::RespFrame;
+
+    use super::{Monitor, Monitors};
+
+    #[test]
+    fn test_monitor_feed() {
+        let monitors = Arc::new(Monitors::default());
+        monitors.feed(0, "127.0.0.1:1", &[b"get".to_vec()]);
+        let mut monitor = Monitor::new(&monitors);
+        monitors.feed(3, "127.0.0.1:1", &[b"set".to_vec(), b"k".to_vec(), b"a \"b\"\n\x01".to_vec()]);
+        let Some(RespFrame::SimpleString(line)) = monitor.try_recv() else { panic!("expect: simple string") };
+        assert!(line.as_str().ends_with(r#" [3 127.0.0.1:1] "set" "k" "a \"b\"\n\x01""#), "{}", line.as_str());
+        assert_eq!(monitor.try_recv(), None);
+        drop(monitor);
+        assert!(monitors.is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use crate::utils::parse_canonical_i64;
+
+use super::{
+    intset::IntSet,
+    listpack::ListPack,
+    value::{sampled_size, EncodingLimits},
+};
+
+// A set value: an intset while every member is an integer and there are at most
+// set-max-intset-entries of them, else a listpack while it's within the
+// set-max-listpack-* limits, a hash set from then on.
+#[derive(Debug, Clone)]
+pub enum Set {
+    IntSet(IntSet),
+    ListPack(ListPack),
+    Table(HashSet<Vec<u8>>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(IntSet::default())
+    }
+}
+
+impl Set {
+    pub fn from_members<T: AsRef<[u8]>>(members: impl IntoIterator<Item = T>, limits: &EncodingLimits) -> Self {
+        let mut set = Set::default();
+        for member in members {
+            set.insert(member.as_ref(), limits);
+        }
+        set
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(set) => set.len(),
+            Set::ListPack(lp) => lp.len(),
+            Set::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => parse_canonical_i64(member).is_some_and(|n| set.contains(n)),
+            Set::ListPack(lp) => lp.position(member).is_some(),
+            Set::Table(table) => table.contains(member),
+        }
+    }
+
+    // true when the member is new
+    pub fn insert(&mut self, member: &[u8], limits: &EncodingLimits) -> bool {
+        if self.contains(member) {
+            return false;
+        }
+        let int = parse_canonical_i64(member);
+        let fits = match self {
+            Set::IntSet(set) => int.is_some() && set.len() < limits.set_max_intset_entries,
+            Set::ListPack(lp) => {
+                lp.len() < limits.set_max_listpack_entries && member.len() <= limits.set_max_listpack_value
+            }
+            Set::Table(_) => true,
+        };
+        if !fits {
+            self.convert(member, limits);
+        }
+        match (self, int) {
+            (Set::IntSet(set), Some(n)) => set.insert(n),
+            (Set::ListPack(lp), _) => {
+                lp.push_back(member);
+                true
+            }
+            (Set::Table(table), _) => table.insert(member.to_vec()),
+            (Set::IntSet(_), None) => unreachable!("converted above"),
+        }
+    }
+
+    // an intset that can't take `member` becomes a listpack if the result stays
+    // within its limits, anything else a hash set
+    fn convert(&mut self, member: &[u8], limits: &EncodingLimits) {
+        let max_value = limits.set_max_listpack_value;
+        let to_listpack = matches!(self, Set::IntSet(_))
+            && self.len() < limits.set_max_listpack_entries
+            && member.len() <= max_value
+            && self.iter().all(|m| m.len() <= max_value);
+        *self = if to_listpack {
+            let mut lp = ListPack::new();
+            self.iter().for_each(|m| lp.push_back(&m));
+            Set::ListPack(lp)
+        } else {
+            Set::Table(self.iter().map(Cow::into_owned).collect())
+        };
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => parse_canonical_i64(member).is_some_and(|n| set.remove(n)),
+            Set::ListPack(lp) => match lp.position(member) {
+                Some(i) => {
+                    lp.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Set::Table(table) => table.remove(member),
+        }
+    }
+
+    // integers are formatted on the fly, hence the Cow
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, [u8]>> + '_> {
+        match self {
+            Set::IntSet(set) => Box::new(set.iter().map(|n| Cow::Owned(n.to_string().into_bytes()))),
+            Set::ListPack(lp) => Box::new(lp.iter().map(Cow::Borrowed)),
+            Set::Table(table) => Box::new(table.iter().map(|m| Cow::Borrowed(m.as_slice()))),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::IntSet(_) => "intset",
+            Set::ListPack(_) => "listpack",
+            Set::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Set::IntSet(set) => set.bytes(),
+            Set::ListPack(lp) => lp.bytes(),
+            Set::Table(table) => sampled_size(table.len(), table.iter().map(|m| m.len()), samples),
+        }
+    }
+}
+
+// the same members, whatever the encodings
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|m| other.contains(&m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Set;
+    use crate::backend::value::EncodingLimits;
+
+    #[test]
+    fn test_conversion() {
+        let limits = EncodingLimits {
+            set_max_intset_entries: 3,
+            set_max_listpack_entries: 4,
+            set_max_listpack_value: 5,
+            ..EncodingLimits::default()
+        };
+        let mut set = Set::from_members(["3", "-1", "2"], &limits);
+        assert_eq!((set.encoding(), set.len()), ("intset", 3));
+        assert!(set.contains(b"-1") && !set.contains(b"03"));
+        assert!(!set.insert(b"2", &limits));
+
+        // a fourth integer is past the intset limit
+        let mut ints = set.clone();
+        assert!(ints.insert(b"4", &limits));
+        assert_eq!(ints.encoding(), "listpack");
+        // so is a string member, the set still fits a listpack
+        assert!(set.insert(b"a", &limits));
+        assert_eq!(set.encoding(), "listpack");
+        assert!(set.remove(b"3") && set.contains(b"a"));
+        assert!(set.insert(b"toolong", &limits));
+        assert_eq!((set.encoding(), set.len()), ("hashtable", 4));
+        assert_eq!(Set::from_members(["-1", "2", "a", "toolong"], &limits), set);
+    }
+}
@@ -0,0 +1,459 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::{config::MaxmemoryPolicy, utils::now_ms};
+
+use super::{value::MEMORY_SAMPLES, Value};
+
+// rough cost of a key slot in the keyspace, on top of the key and value bytes
+const ENTRY_OVERHEAD: usize = 64;
+
+// the LFU counter works like in redis: new keys start at LFU_INIT_VAL, the counter
+// grows logarithmically and is decremented once per LFU_DECAY_MINUTES of idle time
+pub const LFU_INIT_VAL: u8 = 5;
+const LFU_LOG_FACTOR: f64 = 10.0;
+const LFU_DECAY_MINUTES: u64 = 1;
+
+// A value with the metadata eviction needs. The access fields are atomics
+// so that reads under a shared lock can still record them.
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    // estimated bytes held by the key and the value
+    size: usize,
+    // unix ms of the last access, drives LRU
+    access: AtomicU64,
+    // logarithmic access counter, drives LFU
+    counter: AtomicU8,
+}
+
+impl Clone for Entry {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            size: self.size,
+            access: AtomicU64::new(self.access.load(Ordering::Relaxed)),
+            counter: AtomicU8::new(self.counter.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Entry {
+    fn new(key: &[u8], value: Value) -> Self {
+        let size = entry_size(key, &value);
+        Self { value, size, access: AtomicU64::new(now_ms()), counter: AtomicU8::new(LFU_INIT_VAL) }
+    }
+
+    fn idle_ms(&self, now: u64) -> u64 {
+        now.saturating_sub(self.access.load(Ordering::Relaxed))
+    }
+
+    // the counter after applying the decay for the time since the last access
+    fn lfu_counter(&self, now: u64) -> u8 {
+        let periods = self.idle_ms(now) / 60_000 / LFU_DECAY_MINUTES;
+        let counter = self.counter.load(Ordering::Relaxed) as u64;
+        counter.saturating_sub(periods) as u8
+    }
+
+    fn touch(&self) {
+        let now = now_ms();
+        let mut counter = self.lfu_counter(now);
+        if counter < u8::MAX {
+            let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+            let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+            if rand::thread_rng().gen::<f64>() < p {
+                counter += 1;
+            }
+        }
+        self.counter.store(counter, Ordering::Relaxed);
+        self.access.store(now, Ordering::Relaxed);
+    }
+}
+
+fn entry_size(key: &[u8], value: &Value) -> usize {
+    ENTRY_OVERHEAD + key.len() + value.memory_usage(MEMORY_SAMPLES)
+}
+
+// The keys of one shard of a database. Keys live in `entries`, keys with a TTL also
+// have their unix-ms deadline in `expires`. IndexMap gives O(1) random access for sampling.
+#[derive(Debug, Default, Clone)]
+pub struct Shard {
+    entries: IndexMap<Vec<u8>, Entry>,
+    expires: IndexMap<Vec<u8>, u64>,
+    // sum of the entry sizes
+    used_memory: usize,
+    // keys handed out by get_mut, their size is recomputed by `settle`
+    touched: Vec<Vec<u8>>,
+    // keys removed because their TTL passed, collected by `take_expired`
+    expired: Vec<Vec<u8>>,
+    // set while a replica writes to the shard: keys past their deadline stay until the
+    // master's DEL comes, see `Backend::write_shards`
+    keep_expired: bool,
+}
+
+impl Shard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // number of keys, expired keys not purged yet are counted too like redis DBSIZE
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn expires_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    // estimated bytes used by the keys and values, exact after `settle`
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    // recompute the size of the values modified in place since the last call
+    pub fn settle(&mut self) {
+        for key in std::mem::take(&mut self.touched) {
+            if let Some(entry) = self.entries.get_mut(&key) {
+                let size = entry_size(&key, &entry.value);
+                self.used_memory = self.used_memory - entry.size + size;
+                entry.size = size;
+            }
+        }
+    }
+
+    fn is_expired(&self, key: &[u8], now: u64) -> bool {
+        !self.keep_expired && matches!(self.expires.get(key), Some(&at) if at <= now)
+    }
+
+    pub fn set_keep_expired(&mut self, keep: bool) {
+        self.keep_expired = keep;
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Value> {
+        if self.is_expired(key, now_ms()) {
+            return None;
+        }
+        let entry = self.entries.get(key)?;
+        entry.touch();
+        Some(&entry.value)
+    }
+
+    // the value with its idle ms and LFU counter, not counted as an access (OBJECT)
+    pub fn peek(&self, key: &[u8]) -> Option<(&Value, u64, u8)> {
+        let now = now_ms();
+        if self.is_expired(key, now) {
+            return None;
+        }
+        let entry = self.entries.get(key)?;
+        Some((&entry.value, entry.idle_ms(now), entry.lfu_counter(now)))
+    }
+
+    // estimated bytes of a key and its value, sampling `samples` elements (MEMORY USAGE)
+    pub fn key_memory(&self, key: &[u8], samples: usize) -> Option<usize> {
+        let (value, _, _) = self.peek(key)?;
+        Some(ENTRY_OVERHEAD + key.len() + value.memory_usage(samples))
+    }
+
+    // the lazy-expire path: an expired key is removed before it's handed out
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.expire_if_needed(key);
+        let entry = self.entries.get_mut(key)?;
+        entry.touch();
+        self.touched.push(key.to_vec());
+        Some(&mut entry.value)
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    // returns true when the key was expired and removed
+    pub fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        if self.is_expired(key, now_ms()) {
+            self.remove(key);
+            self.expired.push(key.to_vec());
+            return true;
+        }
+        false
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Value) {
+        let entry = Entry::new(&key, value);
+        self.used_memory += entry.size;
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.used_memory -= old.size;
+        }
+    }
+
+    // set a value and drop any previous TTL, like SET
+    pub fn set(&mut self, key: Vec<u8>, value: Value) {
+        self.expires.swap_remove(&key);
+        self.insert(key, value);
+    }
+
+    // overwrite the value but keep the TTL, used by commands modifying a value in place
+    pub fn set_keep_ttl(&mut self, key: Vec<u8>, value: Value) {
+        self.expire_if_needed(&key);
+        self.insert(key, value);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.expires.swap_remove(key);
+        let entry = self.entries.swap_remove(key)?;
+        self.used_memory -= entry.size;
+        Some(entry.value)
+    }
+
+    // remove the key if its aggregate value became empty
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if matches!(self.entries.get(key), Some(e) if e.value.is_empty_aggregate()) {
+            self.remove(key);
+        }
+    }
+
+    pub fn expire_at(&self, key: &[u8]) -> Option<u64> {
+        self.expires.get(key).copied()
+    }
+
+    pub fn set_expire(&mut self, key: &[u8], at: u64) -> bool {
+        if !self.entries.contains_key(key) {
+            return false;
+        }
+        self.expires.insert(key.to_vec(), at);
+        true
+    }
+
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expires.swap_remove(key).is_some()
+    }
+
+    // overwrite the eviction metadata of a key, used by RESTORE IDLETIME / FREQ
+    pub fn set_access(&mut self, key: &[u8], idle_ms: Option<u64>, counter: Option<u8>) {
+        let Some(entry) = self.entries.get(key) else {
+            return;
+        };
+        if let Some(idle_ms) = idle_ms {
+            entry.access.store(now_ms().saturating_sub(idle_ms), Ordering::Relaxed);
+        }
+        if let Some(counter) = counter {
+            entry.counter.store(counter, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.expires.clear();
+        self.touched.clear();
+        self.used_memory = 0;
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        let now = now_ms();
+        self.entries.keys().filter(move |k| !self.is_expired(k, now))
+    }
+
+    // live entries with their deadline, used by snapshots
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Value, Option<u64>)> {
+        let now = now_ms();
+        self.entries
+            .iter()
+            .filter(move |(k, _)| !self.is_expired(k, now))
+            .map(|(k, e)| (k, &e.value, self.expires.get(k).copied()))
+    }
+
+    pub fn random_key(&self) -> Option<&Vec<u8>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        // a few tries so a shard full of expired keys doesn't loop forever
+        for _ in 0..100 {
+            let (key, _) = self.entries.get_index(rng.gen_range(0..self.entries.len()))?;
+            if !self.is_expired(key, now_ms()) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    // walk the keyspace by position, `cursor` is the index to start from, 0 when finished
+    pub fn scan(&self, cursor: usize, count: usize) -> (usize, Vec<&Vec<u8>>) {
+        let now = now_ms();
+        let end = (cursor + count).min(self.entries.len());
+        let keys = (cursor..end)
+            .filter_map(|i| self.entries.get_index(i))
+            .filter(|(k, _)| !self.is_expired(k, now))
+            .map(|(k, _)| k)
+            .collect();
+        let next = if end >= self.entries.len() { 0 } else { end };
+        (next, keys)
+    }
+
+    pub fn take_expired(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.expired)
+    }
+
+    // sample keys with a TTL and remove the expired ones, returns how many were removed
+    pub fn active_expire(&mut self, samples: usize) -> usize {
+        let now = now_ms();
+        let mut rng = rand::thread_rng();
+        let mut removed = 0;
+        for _ in 0..samples.min(self.expires.len()) {
+            let idx = rng.gen_range(0..self.expires.len());
+            let Some((key, &at)) = self.expires.get_index(idx) else { break };
+            if at <= now {
+                let key = key.clone();
+                self.remove(&key);
+                self.expired.push(key);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    // sample up to `samples` keys, from every key or only the ones with a TTL depending on
+    // the policy, and return the best one to evict with its score (higher is better)
+    pub fn eviction_candidate(&self, policy: MaxmemoryPolicy, samples: usize) -> Option<(Vec<u8>, u64)> {
+        use MaxmemoryPolicy::*;
+
+        let volatile = matches!(policy, VolatileLru | VolatileLfu | VolatileRandom | VolatileTtl);
+        let pool = if volatile { self.expires.len() } else { self.entries.len() };
+        if pool == 0 {
+            return None;
+        }
+        let now = now_ms();
+        let mut rng = rand::thread_rng();
+        let mut best: Option<(&Vec<u8>, u64)> = None;
+        for _ in 0..samples.min(pool) {
+            let idx = rng.gen_range(0..pool);
+            let (key, entry) = if volatile {
+                let (key, _) = self.expires.get_index(idx)?;
+                (key, self.entries.get(key)?)
+            } else {
+                self.entries.get_index(idx)?
+            };
+            let score = match policy {
+                AllkeysLru | VolatileLru => entry.idle_ms(now),
+                AllkeysLfu | VolatileLfu => (u8::MAX - entry.lfu_counter(now)) as u64,
+                // the sooner it expires the better
+                VolatileTtl => u64::MAX - self.expires.get(key).copied().unwrap_or(u64::MAX),
+                AllkeysRandom | VolatileRandom | Noeviction => rng.gen(),
+            };
+            if best.is_none_or(|(_, s)| score > s) {
+                best = Some((key, score));
+            }
+        }
+        best.map(|(key, score)| (key.clone(), score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{backend::Value, config::MaxmemoryPolicy, utils::now_ms};
+
+    use super::Shard;
+
+    #[test]
+    fn test_shard_expire() {
+        let mut shard = Shard::new();
+        shard.set(b"a".to_vec(), Value::String(b"1".to_vec().into()));
+        shard.set(b"b".to_vec(), Value::String(b"2".to_vec().into()));
+        assert!(shard.set_expire(b"a", now_ms() - 1));
+        assert!(!shard.set_expire(b"missing", now_ms()));
+
+        assert_eq!(shard.get(b"a"), None);
+        assert_eq!(shard.keys().count(), 1);
+        assert_eq!(shard.len(), 2);
+
+        assert!(shard.get_mut(b"a").is_none());
+        assert_eq!(shard.len(), 1);
+    }
+
+    #[test]
+    fn test_shard_set_clears_ttl() {
+        let mut shard = Shard::new();
+        shard.set(b"a".to_vec(), Value::String(b"1".to_vec().into()));
+        shard.set_expire(b"a", now_ms() + 10_000);
+        shard.set_keep_ttl(b"a".to_vec(), Value::String(b"2".to_vec().into()));
+        assert!(shard.expire_at(b"a").is_some());
+
+        shard.set(b"a".to_vec(), Value::String(b"3".to_vec().into()));
+        assert!(shard.expire_at(b"a").is_none());
+    }
+
+    #[test]
+    fn test_shard_scan() {
+        let mut shard = Shard::new();
+        for i in 0..25 {
+            shard.set(format!("k{}", i).into_bytes(), Value::String(vec![].into()));
+        }
+        let mut cursor = 0;
+        let mut seen = 0;
+        loop {
+            let (next, keys) = shard.scan(cursor, 10);
+            seen += keys.len();
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(seen, 25);
+    }
+
+    #[test]
+    fn test_shard_active_expire() {
+        let mut shard = Shard::new();
+        for i in 0..10 {
+            let key = format!("k{}", i).into_bytes();
+            shard.set(key.clone(), Value::String(vec![].into()));
+            shard.set_expire(&key, now_ms() - 1);
+        }
+        let mut removed = 0;
+        while shard.expires_len() > 0 {
+            removed += shard.active_expire(20);
+        }
+        assert_eq!(removed, 10);
+        assert!(shard.is_empty());
+    }
+
+    #[test]
+    fn test_shard_used_memory() {
+        let mut shard = Shard::new();
+        shard.set(b"a".to_vec(), Value::String(vec![0; 100].into()));
+        let used = shard.used_memory();
+        assert!(used > 100);
+
+        if let Some(Value::String(s)) = shard.get_mut(b"a") {
+            s.make_raw().extend_from_slice(&[0; 1000]);
+        }
+        shard.settle();
+        assert_eq!(shard.used_memory(), used + 1000);
+
+        shard.set(b"a".to_vec(), Value::String(vec![].into()));
+        assert_eq!(shard.used_memory(), used - 100);
+        shard.remove(b"a");
+        assert_eq!(shard.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_shard_eviction_candidate() {
+        let mut shard = Shard::new();
+        assert!(shard.eviction_candidate(MaxmemoryPolicy::AllkeysLru, 5).is_none());
+
+        shard.set(b"a".to_vec(), Value::String(vec![].into()));
+        shard.set(b"b".to_vec(), Value::String(vec![].into()));
+        assert!(shard.eviction_candidate(MaxmemoryPolicy::VolatileLru, 5).is_none());
+
+        shard.set_expire(b"b", now_ms() + 10_000);
+        for _ in 0..10 {
+            let (key, _) = shard.eviction_candidate(MaxmemoryPolicy::VolatileTtl, 5).unwrap();
+            assert_eq!(key, b"b");
+        }
+    }
+}
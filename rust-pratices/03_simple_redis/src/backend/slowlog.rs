@@ -0,0 +1,161 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::utils::now_ms;
+
+// like redis, long commands are shortened before they are kept
+const MAX_ARGC: usize = 32;
+const MAX_ARG_LEN: usize = 128;
+
+// one command slower than slowlog-log-slower-than
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowLogEntry {
+    pub id: u64,
+    // unix seconds when it ran
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub argv: Vec<Vec<u8>>,
+    pub addr: String,
+    pub name: String,
+}
+
+// The SLOWLOG ring buffer, newest entry first. A negative threshold disables
+// it, 0 logs every command.
+#[derive(Debug)]
+pub struct SlowLog {
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+    next_id: AtomicU64,
+    // microseconds
+    threshold: AtomicI64,
+    max_len: AtomicUsize,
+}
+
+impl SlowLog {
+    pub fn new(threshold: i64, max_len: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+            threshold: AtomicI64::new(threshold),
+            max_len: AtomicUsize::new(max_len),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, VecDeque<SlowLogEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn threshold(&self) -> i64 {
+        self.threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold(&self, usec: i64) {
+        self.threshold.store(usec, Ordering::Relaxed);
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+        self.entries().truncate(max_len);
+    }
+
+    pub fn is_slow(&self, duration: Duration) -> bool {
+        let threshold = self.threshold();
+        threshold >= 0 && duration.as_micros() >= threshold as u128
+    }
+
+    pub fn record(&self, argv: &[Vec<u8>], duration: Duration, addr: &str, name: &str) {
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: now_ms() / 1000,
+            duration,
+            argv: shorten(argv),
+            addr: addr.into(),
+            name: name.into(),
+        };
+        let max_len = self.max_len();
+        let mut entries = self.entries();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    // the newest `count` entries, all of them with None
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let entries = self.entries();
+        entries.iter().take(count.unwrap_or(usize::MAX)).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    pub fn reset(&self) {
+        self.entries().clear();
+    }
+}
+
+fn shorten(argv: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let kept = if argv.len() > MAX_ARGC { MAX_ARGC - 1 } else { argv.len() };
+    let mut out: Vec<Vec<u8>> = argv[..kept]
+        .iter()
+        .map(|arg| {
+            if arg.len() <= MAX_ARG_LEN {
+                return arg.clone();
+            }
+            let mut short = arg[..MAX_ARG_LEN].to_vec();
+            short.extend_from_slice(format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes());
+            short
+        })
+        .collect();
+    if kept < argv.len() {
+        out.push(format!("... ({} more arguments)", argv.len() - kept).into_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SlowLog;
+
+    #[test]
+    fn test_slowlog() {
+        let slowlog = SlowLog::new(100, 2);
+        let argv = |s: &str| s.split_whitespace().map(|a| a.as_bytes().to_vec()).collect::<Vec<_>>();
+        assert!(!slowlog.is_slow(Duration::from_micros(99)));
+        assert!(slowlog.is_slow(Duration::from_micros(100)));
+        slowlog.record(&argv("get a"), Duration::from_micros(100), "127.0.0.1:1", "");
+        slowlog.record(&argv("get b"), Duration::from_micros(300), "127.0.0.1:1", "");
+        slowlog.record(&argv("get c"), Duration::from_millis(1), "127.0.0.1:1", "app");
+        let entries = slowlog.get(None);
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(entries[0].argv, argv("get c"));
+        assert_eq!(entries[0].name, "app");
+        assert_eq!(slowlog.get(Some(1)).len(), 1);
+
+        let long = vec![vec![b'x'; 200]; 40];
+        slowlog.record(&long, Duration::from_secs(1), "", "");
+        let entry = &slowlog.get(Some(1))[0];
+        assert_eq!(entry.argv.len(), 32);
+        assert!(entry.argv[0].ends_with(b"... (72 more bytes)"));
+        assert_eq!(entry.argv[31], b"... (9 more arguments)");
+
+        slowlog.set_threshold(-1);
+        assert!(!slowlog.is_slow(Duration::from_secs(1)));
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+    }
+}
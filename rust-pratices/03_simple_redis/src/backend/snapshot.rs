@@ -0,0 +1,308 @@
+use std::{fs, sync::atomic::Ordering};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+use tracing::{info, warn};
+
+use crate::{
+    resp::{RespEncode, RespFrame, RespLimits, SimpleString},
+    utils::{crc64, now_ms, parse_f64},
+};
+
+use super::{stream::Stream, zset::ZSet, Backend, DbReadGuard, EncodingLimits, Hash, Keyspace, List, Set, Value};
+
+// A snapshot is a stream of RESP frames, so it can be inspected with any RESP tool:
+//   +SRDB <version>
+//   *4 :<db> $<key> :<expire-at-ms | -1> *<value>      (one per key)
+//   +EOF
+// a value is encoded as an array whose first element is the type name.
+const SNAPSHOT_MAGIC: &str = "SRDB";
+const SNAPSHOT_VERSION: u32 = 1;
+
+pub fn value_to_frame(value: &Value) -> RespFrame {
+    let bulk = |v: &[u8]| RespFrame::BulkString(v.to_vec());
+    let mut items = vec![RespFrame::BulkString(value.type_name().as_bytes().to_vec())];
+    match value {
+        Value::String(s) => items.push(bulk(s)),
+        Value::List(list) => items.extend(list.iter().map(bulk)),
+        Value::Hash(hash) => {
+            for (k, v) in hash.iter() {
+                items.push(bulk(k));
+                items.push(bulk(v));
+            }
+        }
+        Value::Set(set) => items.extend(set.iter().map(|m| RespFrame::BulkString(m.into_owned()))),
+        Value::ZSet(zset) => {
+            for (member, score) in zset.iter() {
+                items.push(RespFrame::BulkString(member.to_vec()));
+                items.push(RespFrame::BulkString(score.to_string().into_bytes()));
+            }
+        }
+        Value::Stream(stream) => items.extend(stream.to_items().into_iter().map(RespFrame::BulkString)),
+    }
+    RespFrame::Array(items)
+}
+
+// values are rebuilt in the encodings the current limits pick
+pub fn frame_to_value(frame: RespFrame, limits: &EncodingLimits) -> Result<Value> {
+    let RespFrame::Array(items) = frame else {
+        bail!("snapshot value must be an array");
+    };
+    let mut items = items.into_iter().map(|f| match f {
+        RespFrame::BulkString(b) => Ok(b),
+        _ => Err(anyhow!("snapshot value item must be a bulk string")),
+    });
+    let type_name = items.next().ok_or_else(|| anyhow!("empty snapshot value"))??;
+    let items = items.collect::<Result<Vec<_>>>()?;
+
+    let value = match type_name.as_slice() {
+        b"string" => {
+            let [s]: [Vec<u8>; 1] =
+                items.try_into().map_err(|_| anyhow!("string value needs one item"))?;
+            Value::String(s.into())
+        }
+        b"list" => Value::List(List::from_items(items, limits)),
+        b"hash" => {
+            if items.len() % 2 != 0 {
+                bail!("hash value needs field/value pairs");
+            }
+            let mut iter = items.into_iter();
+            let pairs = std::iter::from_fn(|| Some((iter.next()?, iter.next()?)));
+            Value::Hash(Hash::from_pairs(pairs, limits))
+        }
+        b"set" => Value::Set(Set::from_members(items, limits)),
+        b"zset" => {
+            if items.len() % 2 != 0 {
+                bail!("zset value needs member/score pairs");
+            }
+            let mut zset = ZSet::default();
+            for pair in items.chunks(2) {
+                let score = parse_f64(&pair[1]).ok_or_else(|| anyhow!("invalid zset score"))?;
+                zset.insert(&pair[0], score);
+            }
+            Value::ZSet(zset)
+        }
+        b"stream" => Value::Stream(Box::new(Stream::from_items(items).ok_or_else(|| anyhow!("invalid stream value"))?)),
+        other => bail!("unknown value type: {}", String::from_utf8_lossy(other)),
+    };
+    Ok(value)
+}
+
+// A DUMP payload: one value as in a snapshot, then the snapshot version (u16 LE)
+// and the CRC64 of everything before it (u64 LE), like the redis layout.
+pub fn dump_value(value: &Value) -> Vec<u8> {
+    let mut payload = value_to_frame(value).encode();
+    payload.extend_from_slice(&(SNAPSHOT_VERSION as u16).to_le_bytes());
+    payload.extend_from_slice(&crc64(&payload).to_le_bytes());
+    payload
+}
+
+// the value of a DUMP payload, the errors are the ones RESTORE replies with
+pub fn restore_value(payload: &[u8], limits: &EncodingLimits) -> Result<Value> {
+    let damaged = || anyhow!("DUMP payload version or checksum are wrong");
+    let frame_len = payload.len().checked_sub(10).ok_or_else(damaged)?;
+    let (body, crc) = payload.split_at(frame_len + 2);
+    let (frame, version) = body.split_at(frame_len);
+    if crc64(body).to_le_bytes() != crc || u16::from_le_bytes([version[0], version[1]]) as u32 > SNAPSHOT_VERSION {
+        return Err(damaged());
+    }
+    // the payload comes from a client, no element can be longer than the payload
+    let resp_limits = RespLimits::new().max_bulk_len(frame.len()).max_array_len(frame.len());
+    let mut buf = BytesMut::from(frame);
+    match RespFrame::decode_with(&mut buf, &resp_limits) {
+        Ok(frame) if buf.is_empty() => frame_to_value(frame, limits).map_err(|_| anyhow!("Bad data format")),
+        _ => bail!("Bad data format"),
+    }
+}
+
+// encode every db into snapshot bytes
+pub fn dump(dbs: &[DbReadGuard]) -> Vec<u8> {
+    let mut buf = SimpleString::new(format!("{} {}", SNAPSHOT_MAGIC, SNAPSHOT_VERSION)).encode();
+    for (index, db) in dbs.iter().enumerate() {
+        for (key, value, expire_at) in db.iter() {
+            let entry = RespFrame::Array(vec![
+                RespFrame::Integer(index as i64),
+                RespFrame::BulkString(key.clone()),
+                RespFrame::Integer(expire_at.map(|at| at as i64).unwrap_or(-1)),
+                value_to_frame(value),
+            ]);
+            buf.extend_from_slice(&entry.encode());
+        }
+    }
+    buf.extend_from_slice(&SimpleString::new("EOF").encode());
+    buf
+}
+
+// load snapshot bytes into the backend, replacing what it holds, returns the number of keys
+pub fn restore(backend: &Backend, data: &[u8]) -> Result<usize> {
+    // the snapshot is trusted local data, only the nesting needs a bound
+    let limits = RespLimits::new()
+        .max_bulk_len(usize::MAX)
+        .max_array_len(usize::MAX)
+        .max_pending_buf(usize::MAX);
+    let mut buf = BytesMut::from(data);
+
+    let header = RespFrame::decode_with(&mut buf, &limits)?;
+    let expected = RespFrame::SimpleString(SimpleString::new(format!(
+        "{} {}",
+        SNAPSHOT_MAGIC, SNAPSHOT_VERSION
+    )));
+    if header != expected {
+        bail!("invalid snapshot header: {:?}", header);
+    }
+
+    // loaded aside, then swapped in
+    let shards = backend.config().keyspace_shards as usize;
+    let staged: Vec<Keyspace> = (0..backend.db_count()).map(|_| Keyspace::new(shards)).collect();
+    let mut dbs: Vec<_> = staged.iter().map(|k| k.write(None)).collect();
+    let encoding = backend.encoding_limits();
+    let now = now_ms();
+    let mut count = 0;
+    loop {
+        let frame = RespFrame::decode_with(&mut buf, &limits)?;
+        let items = match frame {
+            RespFrame::SimpleString(s) if s == SimpleString::new("EOF") => break,
+            RespFrame::Array(items) if items.len() == 4 => items,
+            other => bail!("invalid snapshot entry: {:?}", other),
+        };
+        let mut items = items.into_iter();
+        let (Some(RespFrame::Integer(index)), Some(RespFrame::BulkString(key)), Some(RespFrame::Integer(expire_at)), Some(value)) =
+            (items.next(), items.next(), items.next(), items.next())
+        else {
+            bail!("invalid snapshot entry layout");
+        };
+
+        let db = dbs.get_mut(index as usize).ok_or_else(|| {
+            anyhow!("snapshot uses db {} but only {} databases are configured", index, backend.db_count())
+        })?;
+        // keys already expired while the server was down are skipped
+        if expire_at >= 0 && (expire_at as u64) <= now {
+            continue;
+        }
+        db.set(key.clone(), frame_to_value(value, &encoding)?);
+        if expire_at >= 0 {
+            db.set_expire(&key, expire_at as u64);
+        }
+        count += 1;
+    }
+
+    for (index, db) in dbs.iter_mut().enumerate() {
+        backend.write(index).swap(db);
+    }
+    Ok(count)
+}
+
+fn write_file(backend: &Backend, data: &[u8]) -> Result<()> {
+    let path = backend.config().snapshot_path();
+    // write aside then rename, a crash never leaves a half written snapshot
+    let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+pub fn save(backend: &Backend) -> Result<()> {
+    let data = {
+        let dbs = backend.read_all();
+        dump(&dbs)
+    };
+    let dirty = backend.dirty.load(Ordering::Relaxed);
+    write_file(backend, &data)?;
+    backend.dirty.fetch_sub(dirty, Ordering::Relaxed);
+    backend.last_save.store(now_ms() / 1000, Ordering::Relaxed);
+    info!("DB saved on disk: {}", backend.config().snapshot_path().display());
+    Ok(())
+}
+
+// copy the keyspace under the locks, encode and write it in another thread
+pub fn bgsave(backend: &Backend) -> Result<()> {
+    if backend.bgsave_in_progress.swap(true, Ordering::AcqRel) {
+        bail!("Background save already in progress");
+    }
+    let copies: Vec<Keyspace> = backend.read_all().iter().map(|db| db.to_keyspace()).collect();
+    let dirty = backend.dirty.load(Ordering::Relaxed);
+
+    let backend = backend.clone();
+    std::thread::spawn(move || {
+        let dbs: Vec<_> = copies.iter().map(|k| k.read(None)).collect();
+        let data = dump(&dbs);
+        match write_file(&backend, &data) {
+            Ok(()) => {
+                backend.dirty.fetch_sub(dirty, Ordering::Relaxed);
+                backend.last_save.store(now_ms() / 1000, Ordering::Relaxed);
+                info!("Background saving terminated with success");
+            }
+            Err(e) => warn!("Background saving error: {}", e),
+        }
+        backend.bgsave_in_progress.store(false, Ordering::Release);
+    });
+    Ok(())
+}
+
+// whether one of the save points is reached, checked every second like the redis cron
+pub fn save_due(backend: &Backend) -> bool {
+    if backend.bgsave_in_progress.load(Ordering::Acquire) {
+        return false;
+    }
+    let dirty = backend.dirty.load(Ordering::Relaxed);
+    let elapsed = (now_ms() / 1000).saturating_sub(backend.last_save.load(Ordering::Relaxed));
+    backend.config().save.0.iter().any(|&(seconds, changes)| dirty >= changes && elapsed >= seconds)
+}
+
+// load the snapshot file at startup if there is one
+pub fn load(backend: &Backend) -> Result<usize> {
+    let path = backend.config().snapshot_path();
+    if !path.exists() {
+        return Ok(0);
+    }
+    let data = fs::read(&path)?;
+    let count = restore(backend, &data)?;
+    info!("DB loaded from disk: {} keys", count);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, EncodingLimits, Hash, List, Set, Value},
+        config::Config,
+        utils::now_ms,
+    };
+
+    use super::{dump, restore};
+
+    #[test]
+    fn test_snapshot_roundtrip_keeps_db() {
+        let backend = Backend::new(Config { databases: 4, ..Config::default() });
+        let limits = EncodingLimits::default();
+        backend.write(0).set(b"s".to_vec(), Value::String(b"hello".to_vec().into()));
+        backend
+            .write(2)
+            .set(b"l".to_vec(), Value::List(List::from_items([b"a".to_vec(), b"b".to_vec()], &limits)));
+        backend
+            .write(3)
+            .set(b"h".to_vec(), Value::Hash(Hash::from_pairs([(b"f".to_vec(), b"v".to_vec())], &limits)));
+        backend.write(3).set(b"set".to_vec(), Value::Set(Set::from_members(["m"], &limits)));
+        backend.write(3).set_expire(b"set", now_ms() + 100_000);
+
+        let data = dump(&backend.read_all());
+
+        let other = Backend::new(Config { databases: 4, ..Config::default() });
+        assert_eq!(restore(&other, &data).unwrap(), 4);
+        assert_eq!(other.read(0).get(b"s"), Some(&Value::String(b"hello".to_vec().into())));
+        assert!(other.read(2).contains(b"l"));
+        assert!(!other.read(0).contains(b"l"));
+        assert!(other.read(3).contains(b"h"));
+        assert!(other.read(3).expire_at(b"set").is_some());
+    }
+
+    #[test]
+    fn test_snapshot_db_out_of_range() {
+        let backend = Backend::new(Config { databases: 4, ..Config::default() });
+        backend.write(3).set(b"s".to_vec(), Value::String(b"hello".to_vec().into()));
+        let data = dump(&backend.read_all());
+
+        let other = Backend::new(Config { databases: 2, ..Config::default() });
+        assert!(restore(&other, &data).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use concurrency::metrics::AmapMetrics;
+
+use crate::cmd::all_commands;
+
+// Server counters for INFO. Per command stats live in AmapMetrics keyed by the
+// command name: the names are all known up front, so counting is lock free.
+#[derive(Debug)]
+pub struct Stats {
+    pub started: Instant,
+    pub total_commands: AtomicU64,
+    pub net_input_bytes: AtomicU64,
+    pub net_output_bytes: AtomicU64,
+    calls: AmapMetrics,
+    usec: AmapMetrics,
+    failed: AmapMetrics,
+}
+
+// what INFO commandstats shows for one command
+#[derive(Debug, PartialEq)]
+pub struct CommandStat {
+    pub name: &'static str,
+    pub calls: u64,
+    pub usec: u64,
+    pub failed: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        let names: Vec<&'static str> = all_commands().map(|spec| spec.name).collect();
+        Self {
+            started: Instant::now(),
+            total_commands: AtomicU64::new(0),
+            net_input_bytes: AtomicU64::new(0),
+            net_output_bytes: AtomicU64::new(0),
+            calls: AmapMetrics::new(&names),
+            usec: AmapMetrics::new(&names),
+            failed: AmapMetrics::new(&names),
+        }
+    }
+}
+
+impl Stats {
+    pub fn record(&self, name: &str, elapsed: Duration, failed: bool) {
+        self.total_commands.fetch_add(1, Ordering::Relaxed);
+        // every name comes from the command table, the keys always exist
+        let _ = self.calls.inc(name);
+        let _ = self.usec.add(name, elapsed.as_micros() as i64);
+        if failed {
+            let _ = self.failed.inc(name);
+        }
+    }
+
+    pub fn add_net_input(&self, n: usize) {
+        self.net_input_bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_net_output(&self, n: usize) {
+        self.net_output_bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    // commands called at least once, by name
+    pub fn command_stats(&self) -> Vec<CommandStat> {
+        let count = |m: &AmapMetrics, name| m.get(name).unwrap_or(0).max(0) as u64;
+        let mut stats: Vec<CommandStat> = all_commands()
+            .map(|spec| CommandStat {
+                name: spec.name,
+                calls: count(&self.calls, spec.name),
+                usec: count(&self.usec, spec.name),
+                failed: count(&self.failed, spec.name),
+            })
+            .filter(|s| s.calls > 0)
+            .collect();
+        stats.sort_by_key(|s| s.name);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CommandStat, Stats};
+
+    #[test]
+    fn test_command_stats() {
+        let stats = Stats::default();
+        stats.record("get", Duration::from_micros(10), false);
+        stats.record("get", Duration::from_micros(5), true);
+        stats.record("set", Duration::from_micros(1), false);
+        assert_eq!(
+            stats.command_stats(),
+            vec![
+                CommandStat { name: "get", calls: 2, usec: 15, failed: 1 },
+                CommandStat { name: "set", calls: 1, usec: 1, failed: 0 },
+            ]
+        );
+    }
+}
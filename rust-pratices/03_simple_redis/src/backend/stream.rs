@@ -0,0 +1,470 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::utils::parse_i64;
+
+// An entry ID: milliseconds of the unix time it was added at, and a sequence
+// number telling apart the entries of the same millisecond
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    // `ms-seq`, or `ms` alone with `default_seq`
+    pub fn parse(s: &[u8], default_seq: u64) -> Option<StreamId> {
+        let s = std::str::from_utf8(s).ok()?;
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(s.parse().ok()?, default_seq)),
+        }
+    }
+
+    pub fn next(self) -> Option<StreamId> {
+        match (self.seq.checked_add(1), self.ms.checked_add(1)) {
+            (Some(seq), _) => Some(StreamId::new(self.ms, seq)),
+            (None, Some(ms)) => Some(StreamId::new(ms, 0)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn prev(self) -> Option<StreamId> {
+        match (self.seq.checked_sub(1), self.ms.checked_sub(1)) {
+            (Some(seq), _) => Some(StreamId::new(self.ms, seq)),
+            (None, Some(ms)) => Some(StreamId::new(ms, u64::MAX)),
+            (None, None) => None,
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// the fields of an entry, flattened as field value field value ...
+pub type Fields = Vec<Vec<u8>>;
+
+// how XADD / XTRIM shrink a stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trim {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+// An entry delivered to a consumer and not acknowledged yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    // unix ms of the last delivery
+    pub delivered_at: u64,
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    // the pending entries list (PEL) of the whole group
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    // consumer name -> unix ms it was last seen at
+    pub consumers: BTreeMap<Vec<u8>, u64>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Self { last_delivered, ..Self::default() }
+    }
+
+    // true when the consumer is new
+    pub fn touch_consumer(&mut self, name: &[u8], now: u64) -> bool {
+        self.consumers.insert(name.to_vec(), now).is_none()
+    }
+
+    // removes the consumer with its pending entries, returns how many there were
+    pub fn delete_consumer(&mut self, name: &[u8]) -> usize {
+        if self.consumers.remove(name).is_none() {
+            return 0;
+        }
+        let before = self.pending.len();
+        self.pending.retain(|_, p| p.consumer != name);
+        before - self.pending.len()
+    }
+
+    pub fn consumer_pending(&self, name: &[u8]) -> impl Iterator<Item = (&StreamId, &PendingEntry)> + '_ {
+        let name = name.to_vec();
+        self.pending.iter().filter(move |(_, p)| p.consumer == name)
+    }
+
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+}
+
+// XCLAIM options
+#[derive(Debug, Clone, Default)]
+pub struct Claim {
+    pub min_idle: u64,
+    // the delivery time to set, now by default
+    pub delivered_at: Option<u64>,
+    pub deliveries: Option<u64>,
+    // claim ids which aren't pending as long as the entry exists
+    pub force: bool,
+    // don't count a delivery, the caller only wants the ids
+    pub just_id: bool,
+}
+
+// An append-only log of entries ordered by ID, with consumer groups
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    // the ID of the last entry ever added, deleted or not
+    last_id: StreamId,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    // the ID `*` stands for: the current millisecond, or the next sequence
+    // number when the clock is behind the last entry
+    pub fn next_id(&self, now: u64) -> Option<StreamId> {
+        if now > self.last_id.ms {
+            Some(StreamId::new(now, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    // false when the ID isn't greater than the last one, so never for 0-0
+    pub fn add(&mut self, id: StreamId, fields: Fields) -> bool {
+        if id <= self.last_id {
+            return false;
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        true
+    }
+
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        self.entries.remove(&id).is_some()
+    }
+
+    // removes the oldest entries, returns how many
+    pub fn trim(&mut self, trim: Trim) -> usize {
+        let before = self.entries.len();
+        match trim {
+            Trim::MaxLen(max) => {
+                while self.entries.len() > max {
+                    self.entries.pop_first();
+                }
+            }
+            Trim::MinId(min) => self.entries = self.entries.split_off(&min),
+        }
+        before - self.entries.len()
+    }
+
+    // the entries from start to end inclusive, the newest first when reversed
+    pub fn range(&self, start: StreamId, end: StreamId, count: Option<usize>, rev: bool) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return vec![];
+        }
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+        let entries: Box<dyn Iterator<Item = _>> = if rev { Box::new(range.rev()) } else { Box::new(range) };
+        entries.take(count).map(|(id, fields)| (*id, fields.clone())).collect()
+    }
+
+    // the entries with an ID greater than `after`
+    pub fn read_after(&self, after: StreamId, count: Option<usize>) -> Vec<(StreamId, Fields)> {
+        match after.next() {
+            Some(start) => self.range(start, StreamId::MAX, count, false),
+            None => vec![],
+        }
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Vec<u8>, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    // false when the group already exists
+    pub fn create_group(&mut self, name: &[u8], last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.to_vec(), ConsumerGroup::new(last_delivered));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    // Deliver entries to a consumer of the group: with `after` None the ones never
+    // delivered to the group, which become pending unless `no_ack`; otherwise the
+    // consumer's own pending entries after that ID, with None fields for the ones
+    // deleted meanwhile. None when the group doesn't exist.
+    #[allow(clippy::type_complexity)]
+    pub fn read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        after: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let g = self.groups.get_mut(group)?;
+        g.touch_consumer(consumer, now);
+        let Some(after) = after else {
+            let entries = match g.last_delivered.next() {
+                Some(start) => {
+                    let count = count.unwrap_or(usize::MAX);
+                    self.entries.range(start..).take(count).map(|(id, f)| (*id, f.clone())).collect::<Vec<_>>()
+                }
+                None => vec![],
+            };
+            if let Some((last, _)) = entries.last() {
+                g.last_delivered = *last;
+            }
+            if !no_ack {
+                for (id, _) in &entries {
+                    let pending = PendingEntry { consumer: consumer.to_vec(), delivered_at: now, deliveries: 1 };
+                    g.pending.insert(*id, pending);
+                }
+            }
+            return Some(entries.into_iter().map(|(id, f)| (id, Some(f))).collect());
+        };
+        let ids: Vec<StreamId> = g
+            .consumer_pending(consumer)
+            .filter(|(id, _)| **id > after)
+            .map(|(id, _)| *id)
+            .take(count.unwrap_or(usize::MAX))
+            .collect();
+        Some(ids.into_iter().map(|id| (id, self.entries.get(&id).cloned())).collect())
+    }
+
+    // Give pending entries idle for at least `min_idle` ms to another consumer,
+    // returns the claimed ones. Entries deleted from the stream leave the PEL.
+    // None when the group doesn't exist.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        ids: &[StreamId],
+        claim: &Claim,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let g = self.groups.get_mut(group)?;
+        g.touch_consumer(consumer, now);
+        let mut claimed = Vec::new();
+        for id in ids {
+            let Some(fields) = self.entries.get(id) else {
+                g.pending.remove(id);
+                continue;
+            };
+            if claim.force && !g.pending.contains_key(id) {
+                let pending = PendingEntry { consumer: consumer.to_vec(), delivered_at: now, deliveries: 0 };
+                g.pending.insert(*id, pending);
+            }
+            let Some(pending) = g.pending.get_mut(id) else {
+                continue;
+            };
+            if now.saturating_sub(pending.delivered_at) < claim.min_idle {
+                continue;
+            }
+            pending.consumer = consumer.to_vec();
+            pending.delivered_at = claim.delivered_at.unwrap_or(now);
+            if let Some(deliveries) = claim.deliveries {
+                pending.deliveries = deliveries;
+            } else if !claim.just_id {
+                pending.deliveries += 1;
+            }
+            claimed.push((*id, fields.clone()));
+        }
+        Some(claimed)
+    }
+
+    // estimated bytes, like Value::memory_usage
+    pub fn sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        let entries = self.entries.values().map(|fields| 16 + fields.iter().map(|f| f.len()).sum::<usize>());
+        let groups = self.groups.iter().map(|(name, g)| name.len() + g.pending.len() * 48 + g.consumers.len() * 32);
+        entries.chain(groups)
+    }
+
+    // The stream flattened into strings for snapshots:
+    //   last-id #entries (id #fields field value ...)... #groups
+    //   (name last-delivered #consumers (name seen)... #pending (id consumer delivered-at deliveries)...)...
+    pub fn to_items(&self) -> Vec<Vec<u8>> {
+        let mut items = vec![self.last_id.to_string().into_bytes(), self.entries.len().to_string().into_bytes()];
+        for (id, fields) in &self.entries {
+            items.push(id.to_string().into_bytes());
+            items.push(fields.len().to_string().into_bytes());
+            items.extend(fields.iter().cloned());
+        }
+        items.push(self.groups.len().to_string().into_bytes());
+        for (name, g) in &self.groups {
+            items.push(name.clone());
+            items.push(g.last_delivered.to_string().into_bytes());
+            items.push(g.consumers.len().to_string().into_bytes());
+            for (consumer, seen) in &g.consumers {
+                items.push(consumer.clone());
+                items.push(seen.to_string().into_bytes());
+            }
+            items.push(g.pending.len().to_string().into_bytes());
+            for (id, p) in &g.pending {
+                items.push(id.to_string().into_bytes());
+                items.push(p.consumer.clone());
+                items.push(p.delivered_at.to_string().into_bytes());
+                items.push(p.deliveries.to_string().into_bytes());
+            }
+        }
+        items
+    }
+
+    pub fn from_items(items: Vec<Vec<u8>>) -> Option<Stream> {
+        let mut items = items.into_iter();
+        let mut next = || items.next();
+        let id = |v: Vec<u8>| StreamId::parse(&v, 0);
+        let num = |v: Vec<u8>| parse_i64(&v).and_then(|n| u64::try_from(n).ok());
+
+        let mut stream = Stream { last_id: id(next()?)?, ..Stream::default() };
+        for _ in 0..num(next()?)? {
+            let entry = id(next()?)?;
+            let fields = (0..num(next()?)?).map(|_| next()).collect::<Option<Vec<_>>>()?;
+            stream.entries.insert(entry, fields);
+        }
+        for _ in 0..num(next()?)? {
+            let name = next()?;
+            let mut group = ConsumerGroup::new(id(next()?)?);
+            for _ in 0..num(next()?)? {
+                let consumer = next()?;
+                group.consumers.insert(consumer, num(next()?)?);
+            }
+            for _ in 0..num(next()?)? {
+                let entry = id(next()?)?;
+                let pending = PendingEntry { consumer: next()?, delivered_at: num(next()?)?, deliveries: num(next()?)? };
+                group.pending.insert(entry, pending);
+            }
+            stream.groups.insert(name, group);
+        }
+        next().is_none().then_some(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Claim, Stream, StreamId, Trim};
+
+    fn fields(s: &str) -> Vec<Vec<u8>> {
+        s.split_whitespace().map(|f| f.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_stream_id() {
+        assert_eq!(StreamId::parse(b"5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(StreamId::parse(b"5", u64::MAX), Some(StreamId::new(5, u64::MAX)));
+        assert_eq!(StreamId::parse(b"5-x", 0), None);
+        assert_eq!(StreamId::new(5, u64::MAX).next(), Some(StreamId::new(6, 0)));
+        assert_eq!(StreamId::new(5, 0).prev(), Some(StreamId::new(4, u64::MAX)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::new(1, 2).to_string(), "1-2");
+    }
+
+    #[test]
+    fn test_add_range_trim() {
+        let mut stream = Stream::default();
+        assert_eq!(stream.next_id(10), Some(StreamId::new(10, 0)));
+        assert!(stream.add(StreamId::new(10, 0), fields("a 1")));
+        // the clock went back
+        assert_eq!(stream.next_id(9), Some(StreamId::new(10, 1)));
+        assert!(!stream.add(StreamId::new(10, 0), fields("a 2")));
+        assert!(stream.add(StreamId::new(10, 1), fields("a 2")));
+        assert!(stream.add(StreamId::new(12, 0), fields("a 3")));
+
+        let all = stream.range(StreamId::MIN, StreamId::MAX, None, false);
+        assert_eq!(all.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>(), ["10-0", "10-1", "12-0"]);
+        let rev = stream.range(StreamId::MIN, StreamId::MAX, Some(1), true);
+        assert_eq!(rev, vec![(StreamId::new(12, 0), fields("a 3"))]);
+        assert_eq!(stream.read_after(StreamId::new(10, 1), None).len(), 1);
+
+        assert_eq!(stream.trim(Trim::MaxLen(2)), 1);
+        assert_eq!(stream.trim(Trim::MinId(StreamId::new(11, 0))), 1);
+        assert_eq!(stream.len(), 1);
+        assert_eq!(stream.last_id(), StreamId::new(12, 0));
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut stream = Stream::default();
+        for ms in 1..=3 {
+            stream.add(StreamId::new(ms, 0), fields("f v"));
+        }
+        assert!(stream.create_group(b"g", StreamId::MIN));
+        assert!(!stream.create_group(b"g", StreamId::MIN));
+
+        let read = stream.read_group(b"g", b"alice", None, Some(2), false, 100).unwrap();
+        assert_eq!(read.len(), 2);
+        let read = stream.read_group(b"g", b"bob", None, None, false, 100).unwrap();
+        assert_eq!(read, vec![(StreamId::new(3, 0), Some(fields("f v")))]);
+        assert_eq!(stream.read_group(b"g", b"bob", None, None, false, 100).unwrap(), vec![]);
+
+        // alice's history, then her first entry acknowledged
+        let history = stream.read_group(b"g", b"alice", Some(StreamId::MIN), None, false, 100).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(stream.group_mut(b"g").unwrap().ack(StreamId::new(1, 0)));
+        stream.delete(StreamId::new(2, 0));
+        let history = stream.read_group(b"g", b"alice", Some(StreamId::MIN), None, false, 100).unwrap();
+        assert_eq!(history, vec![(StreamId::new(2, 0), None)]);
+
+        // bob's entry isn't idle long enough at first
+        let claim = Claim { min_idle: 50, ..Claim::default() };
+        let ids = [StreamId::new(2, 0), StreamId::new(3, 0)];
+        assert_eq!(stream.claim(b"g", b"carol", &ids, &claim, 120).unwrap(), vec![]);
+        let claimed = stream.claim(b"g", b"carol", &ids, &claim, 200).unwrap();
+        assert_eq!(claimed, vec![(StreamId::new(3, 0), fields("f v"))]);
+        let group = stream.group(b"g").unwrap();
+        // the deleted entry left the PEL
+        assert_eq!(group.pending.len(), 1);
+        assert_eq!(group.pending[&StreamId::new(3, 0)].deliveries, 2);
+        assert_eq!(stream.group_mut(b"g").unwrap().delete_consumer(b"carol"), 1);
+    }
+
+    #[test]
+    fn test_items_roundtrip() {
+        let mut stream = Stream::default();
+        stream.add(StreamId::new(1, 0), fields("a 1 b 2"));
+        stream.add(StreamId::new(2, 5), fields("c 3"));
+        stream.create_group(b"g", StreamId::MIN);
+        stream.read_group(b"g", b"c1", None, Some(1), false, 7);
+        stream.delete(StreamId::new(2, 5));
+        let items = stream.to_items();
+        assert_eq!(Stream::from_items(items.clone()), Some(stream));
+        assert_eq!(Stream::from_items(items[..items.len() - 1].to_vec()), None);
+    }
+}
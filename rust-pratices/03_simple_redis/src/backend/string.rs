@@ -0,0 +1,130 @@
+use std::{fmt, ops::Deref};
+
+use crate::utils::parse_canonical_i64;
+
+// raw strings up to this length are reported as embstr, like redis
+const EMBSTR_MAX: usize = 44;
+// the longest i64 in decimal, "-9223372036854775808"
+const INT_DIGITS: usize = 20;
+
+// A string value. Integers in their canonical form are kept inline, without a
+// heap allocation, the others as plain bytes. It reads as bytes either way.
+#[derive(Clone)]
+pub enum Str {
+    Int { len: u8, digits: [u8; INT_DIGITS] },
+    Raw(Vec<u8>),
+}
+
+impl Str {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        match parse_canonical_i64(&bytes) {
+            Some(n) => Self::from_int(n),
+            None => Str::Raw(bytes),
+        }
+    }
+
+    pub fn from_int(n: i64) -> Self {
+        let text = n.to_string();
+        let mut digits = [0; INT_DIGITS];
+        digits[..text.len()].copy_from_slice(text.as_bytes());
+        Str::Int { len: text.len() as u8, digits }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Str::Int { len, digits } => &digits[..*len as usize],
+            Str::Raw(bytes) => bytes,
+        }
+    }
+
+    // the bytes to edit in place (APPEND, SETRANGE, SETBIT), an integer becomes raw
+    pub fn make_raw(&mut self) -> &mut Vec<u8> {
+        if let Str::Int { .. } = self {
+            *self = Str::Raw(self.as_bytes().to_vec());
+        }
+        match self {
+            Str::Raw(bytes) => bytes,
+            Str::Int { .. } => unreachable!("converted above"),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            Str::Raw(bytes) => bytes,
+            int => int.as_bytes().to_vec(),
+        }
+    }
+
+    // the name replied by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Str::Int { .. } => "int",
+            Str::Raw(bytes) if bytes.len() <= EMBSTR_MAX => "embstr",
+            Str::Raw(_) => "raw",
+        }
+    }
+
+    // heap bytes, an inline integer has none
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Str::Int { .. } => 0,
+            Str::Raw(bytes) => bytes.len(),
+        }
+    }
+}
+
+impl Deref for Str {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl From<Vec<u8>> for Str {
+    fn from(bytes: Vec<u8>) -> Self {
+        Str::new(bytes)
+    }
+}
+
+impl From<Str> for Vec<u8> {
+    fn from(s: Str) -> Self {
+        s.into_vec()
+    }
+}
+
+impl From<&Str> for Vec<u8> {
+    fn from(s: &Str) -> Self {
+        s.as_bytes().to_vec()
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}({:?})", self.encoding(), String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Str;
+
+    #[test]
+    fn test_encodings() {
+        let n = Str::from(b"-12345".to_vec());
+        assert_eq!((n.encoding(), &n[..], n.memory_usage()), ("int", &b"-12345"[..], 0));
+        assert_eq!(Str::from(b"012".to_vec()).encoding(), "embstr");
+        assert_eq!(Str::from(vec![b'a'; 45]).encoding(), "raw");
+        assert_eq!(Str::from_int(i64::MIN), Str::from(b"-9223372036854775808".to_vec()));
+
+        let mut s = Str::from(b"10".to_vec());
+        s.make_raw().push(b'0');
+        assert_eq!((s.encoding(), s.into_vec()), ("embstr", b"100".to_vec()));
+    }
+}
@@ -0,0 +1,114 @@
+use super::{hash::Hash, list::List, set::Set, stream::Stream, string::Str, zset::ZSet};
+
+// elements sampled to estimate the size of an aggregate, like redis MEMORY USAGE
+pub const MEMORY_SAMPLES: usize = 5;
+// rough heap cost of one element besides its bytes
+pub const ELEMENT_OVERHEAD: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Str),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    Stream(Box<Stream>),
+    ZSet(ZSet),
+}
+
+// When small aggregates leave their compact encoding, from the *-max-listpack-*
+// and set-max-intset-entries settings. The defaults are the ones of redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    // entries when positive, -1 to -5 for 4kb to 64kb of encoded bytes
+    pub list_max_listpack_size: i64,
+}
+
+impl Default for EncodingLimits {
+    fn default() -> Self {
+        Self {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+        }
+    }
+}
+
+impl EncodingLimits {
+    pub fn list_fits(&self, len: usize, bytes: usize) -> bool {
+        match self.list_max_listpack_size {
+            n if n > 0 => len <= n as usize,
+            n => bytes <= 4096 << (n.unsigned_abs().clamp(1, 5) - 1),
+        }
+    }
+}
+
+impl Value {
+    // the name replied by TYPE
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::Stream(_) => "stream",
+            Value::ZSet(_) => "zset",
+        }
+    }
+
+    // the name replied by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(s) => s.encoding(),
+            Value::List(l) => l.encoding(),
+            Value::Hash(h) => h.encoding(),
+            Value::Set(s) => s.encoding(),
+            Value::Stream(_) => "stream",
+            Value::ZSet(_) => "skiplist",
+        }
+    }
+
+    // aggregates are removed from the keyspace once they become empty, streams
+    // stay with their last ID and groups
+    pub fn is_empty_aggregate(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(l) => l.is_empty(),
+            Value::Hash(h) => h.is_empty(),
+            Value::Set(s) => s.is_empty(),
+            Value::ZSet(z) => z.is_empty(),
+        }
+    }
+
+    // estimated bytes held by the value, aggregates average `samples` elements
+    // and scale by their length, 0 samples means looking at every element
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Value::String(s) => s.memory_usage(),
+            Value::List(l) => l.memory_usage(samples),
+            Value::Hash(h) => h.memory_usage(samples),
+            Value::Set(s) => s.memory_usage(samples),
+            Value::ZSet(z) => sampled_size(z.len(), z.iter().map(|(m, _)| m.len() + 8 + ELEMENT_OVERHEAD), samples),
+            Value::Stream(s) => {
+                let parts = s.len() + s.groups().count();
+                sampled_size(parts, s.sizes(), samples)
+            }
+        }
+    }
+}
+
+pub fn sampled_size(len: usize, sizes: impl Iterator<Item = usize>, samples: usize) -> usize {
+    let n = if samples == 0 { len } else { samples.min(len) };
+    if n == 0 {
+        return 0;
+    }
+    let sum: usize = sizes.take(n).map(|size| size + ELEMENT_OVERHEAD).sum();
+    sum * len / n
+}
@@ -0,0 +1,92 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+};
+
+// a score ordered with total_cmp, scores are never NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// A sorted set: members ordered by score, then by member bytes like redis
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ZSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    // true when the member is new
+    pub fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        let old = self.scores.insert(member.to_vec(), score);
+        if let Some(old) = old {
+            self.ordered.remove(&(Score(old), member.to_vec()));
+        }
+        self.ordered.insert((Score(score), member.to_vec()));
+        old.is_none()
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.ordered.remove(&(Score(score), member.to_vec())),
+            None => false,
+        }
+    }
+
+    // the 0-based position by ascending score
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.ordered.range(..(Score(score), member.to_vec())).count())
+    }
+
+    // members with their scores, lowest score first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], f64)> {
+        self.ordered.iter().map(|(score, member)| (member.as_slice(), score.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZSet;
+
+    #[test]
+    fn test_zset_order() {
+        let mut zset = ZSet::default();
+        assert!(zset.insert(b"b", 1.0));
+        assert!(zset.insert(b"a", 1.0));
+        assert!(zset.insert(b"c", -2.5));
+        assert!(!zset.insert(b"c", 3.0));
+        let members: Vec<_> = zset.iter().map(|(m, s)| (m.to_vec(), s)).collect();
+        assert_eq!(members, vec![(b"a".to_vec(), 1.0), (b"b".to_vec(), 1.0), (b"c".to_vec(), 3.0)]);
+        assert_eq!(zset.rank(b"c"), Some(2));
+        assert!(zset.remove(b"a"));
+        assert!(!zset.remove(b"a"));
+        assert_eq!(zset.rank(b"b"), Some(0));
+        assert_eq!(zset.len(), 2);
+    }
+}
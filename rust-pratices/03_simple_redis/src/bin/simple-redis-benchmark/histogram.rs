@@ -0,0 +1,133 @@
+// A log-linear latency histogram in microseconds: exact below 32us, then every
+// power of two split into 16 buckets, so a percentile is off by at most ~6%.
+// Histograms of different connections merge by adding the buckets.
+
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const EXACT: u64 = 1 << (SUB_BITS + 1);
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self { buckets: vec![0; BUCKETS], count: 0, sum: 0, min: u64::MAX, max: 0 }
+    }
+}
+
+fn index(v: u64) -> usize {
+    if v < EXACT {
+        return v as usize;
+    }
+    let exp = 63 - v.leading_zeros();
+    ((exp - SUB_BITS) as usize) * SUB_BUCKETS + (v >> (exp - SUB_BITS)) as usize
+}
+
+// the highest value that falls in the bucket
+fn upper_bound(i: usize) -> u64 {
+    if (i as u64) < EXACT {
+        return i as u64;
+    }
+    let exp = (i / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+    let mantissa = (i % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    let width = 1u64 << (exp - SUB_BITS);
+    (mantissa << (exp - SUB_BITS)) + width - 1
+}
+
+impl Histogram {
+    pub fn record(&mut self, usec: u64) {
+        self.buckets[index(usec)] += 1;
+        self.count += 1;
+        self.sum += usec;
+        self.min = self.min.min(usec);
+        self.max = self.max.max(usec);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.buckets.iter_mut().zip(&other.buckets) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> u64 {
+        if self.count == 0 { 0 } else { self.min }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.sum as f64 / self.count as f64 }
+    }
+
+    // the latency `percent` of the samples are at or below, 0..=100
+    pub fn percentile(&self, percent: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let target = ((percent / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= target {
+                return upper_bound(i).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index, upper_bound, Histogram};
+
+    #[test]
+    fn test_buckets() {
+        for v in [0, 1, 31, 32, 33, 63, 64, 100, 1000, 123_456, u64::MAX / 3] {
+            let i = index(v);
+            assert!(upper_bound(i) >= v, "{}", v);
+            assert!(i == 0 || upper_bound(i - 1) < v, "{}", v);
+        }
+        assert_eq!(index(32), 32);
+        assert_eq!(upper_bound(32), 33);
+        assert_eq!(index(u64::MAX), super::BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut a = Histogram::default();
+        let mut b = Histogram::default();
+        for v in 1..=500 {
+            a.record(v);
+        }
+        for v in 501..=1000 {
+            b.record(v);
+        }
+        a.merge(&b);
+        assert_eq!(a.count(), 1000);
+        assert_eq!((a.min(), a.max()), (1, 1000));
+        assert_eq!(a.mean(), 500.5);
+        let p50 = a.percentile(50.0);
+        assert!((500..=532).contains(&p50), "{}", p50);
+        let p99 = a.percentile(99.0);
+        assert!((990..=1000).contains(&p99), "{}", p99);
+        assert_eq!(a.percentile(100.0), 1000);
+        assert_eq!(Histogram::default().percentile(50.0), 0);
+    }
+}
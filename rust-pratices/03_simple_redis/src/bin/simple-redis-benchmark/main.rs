@@ -0,0 +1,442 @@
+// A load generator in the spirit of redis-benchmark, it works against
+// simple-redis and real redis alike, or against the engine in process.
+
+mod histogram;
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use clap::Parser;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use histogram::Histogram;
+use simple_redis::{
+    config::Config,
+    resp::{RespDecoder, RespError, RespFrame, RespLimits},
+    Store,
+};
+
+// the commands a test can send, each one touches a random key when -r is given
+const TESTS: &[&str] = &["ping", "set", "get", "incr", "lpush", "rpush", "lpop", "rpop", "sadd", "spop", "hset", "zadd", "mset"];
+
+#[derive(Debug, Parser)]
+#[command(name = "simple-redis-benchmark", version, about = "Benchmark a redis server", disable_help_flag = true)]
+struct Opts {
+    /// server hostname
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+
+    #[arg(short, long, default_value_t = 6379)]
+    port: u16,
+
+    /// password, sent with AUTH on every connection
+    #[arg(short = 'a', long)]
+    password: Option<String>,
+
+    /// number of parallel connections
+    #[arg(short, long, default_value_t = 50)]
+    clients: usize,
+
+    /// total number of requests of each test
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    requests: u64,
+
+    /// requests sent at once by a connection before reading the replies
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pipeline: u64,
+
+    /// random keys out of this many, 0 uses a single key
+    #[arg(short = 'r', long, default_value_t = 0)]
+    keyspace: u64,
+
+    /// bytes of SET / LPUSH ... values
+    #[arg(short = 'd', long, default_value_t = 3)]
+    data_size: usize,
+
+    /// the tests to run one after the other, comma separated
+    #[arg(short = 't', long, value_delimiter = ',', default_values_t = ["set".to_string(), "get".to_string()])]
+    tests: Vec<String>,
+
+    /// one test mixing commands by weight instead, e.g. get=9,set=1
+    #[arg(long, value_delimiter = ',', conflicts_with = "tests")]
+    mix: Vec<String>,
+
+    /// only the throughput and p50 of each test
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// run the commands on a Store in this process, a thread per client, instead of a server
+    #[arg(long)]
+    in_process: bool,
+
+    /// shards of each database with --in-process, 1 is a single lock per database
+    #[arg(long, default_value_t = 16, requires = "in_process")]
+    keyspace_shards: u32,
+
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+}
+
+// a test: the commands with their weights
+#[derive(Debug, Clone)]
+struct Workload {
+    name: String,
+    commands: Vec<(String, u32)>,
+}
+
+impl Workload {
+    fn pick(&self, rng: &mut StdRng) -> &str {
+        let total: u32 = self.commands.iter().map(|(_, w)| w).sum();
+        let mut n = rng.gen_range(0..total);
+        for (command, weight) in &self.commands {
+            if n < *weight {
+                return command;
+            }
+            n -= weight;
+        }
+        &self.commands[0].0
+    }
+}
+
+fn check_test(name: &str) -> Result<String> {
+    let name = name.trim().to_ascii_lowercase();
+    if !TESTS.contains(&name.as_str()) {
+        bail!("unknown test '{}', the tests are: {}", name, TESTS.join(","));
+    }
+    Ok(name)
+}
+
+fn workloads(opts: &Opts) -> Result<Vec<Workload>> {
+    if opts.mix.is_empty() {
+        return opts
+            .tests
+            .iter()
+            .map(|t| Ok(Workload { name: t.to_ascii_uppercase(), commands: vec![(check_test(t)?, 1)] }))
+            .collect();
+    }
+    let mut commands = Vec::new();
+    for entry in &opts.mix {
+        let (name, weight) = entry.split_once('=').unwrap_or((entry, "1"));
+        let weight: u32 = weight.parse().map_err(|_| anyhow::anyhow!("invalid weight in '{}'", entry))?;
+        if weight > 0 {
+            commands.push((check_test(name)?, weight));
+        }
+    }
+    if commands.is_empty() {
+        bail!("--mix needs at least one command with a weight above 0");
+    }
+    Ok(vec![Workload { name: format!("MIX {}", opts.mix.join(",")), commands }])
+}
+
+// `prefix:__rand_int__` like redis-benchmark without -r, a random one of the keyspace with it
+fn random_key(prefix: &str, keyspace: u64, rng: &mut StdRng) -> Vec<u8> {
+    if keyspace == 0 {
+        format!("{}:__rand_int__", prefix).into_bytes()
+    } else {
+        format!("{}:{:012}", prefix, rng.gen_range(0..keyspace)).into_bytes()
+    }
+}
+
+// the arguments of one request
+fn request(command: &str, opts: &Opts, value: &[u8], rng: &mut StdRng) -> Vec<Vec<u8>> {
+    let key = |prefix: &str, rng: &mut StdRng| random_key(prefix, opts.keyspace, rng);
+    match command {
+        "ping" => vec![b"PING".to_vec()],
+        "set" => vec![b"SET".to_vec(), key("key", rng), value.to_vec()],
+        "get" => vec![b"GET".to_vec(), key("key", rng)],
+        "incr" => vec![b"INCR".to_vec(), key("counter", rng)],
+        "lpush" => vec![b"LPUSH".to_vec(), b"mylist".to_vec(), value.to_vec()],
+        "rpush" => vec![b"RPUSH".to_vec(), b"mylist".to_vec(), value.to_vec()],
+        "lpop" => vec![b"LPOP".to_vec(), b"mylist".to_vec()],
+        "rpop" => vec![b"RPOP".to_vec(), b"mylist".to_vec()],
+        "sadd" => vec![b"SADD".to_vec(), b"myset".to_vec(), key("element", rng)],
+        "spop" => vec![b"SPOP".to_vec(), b"myset".to_vec()],
+        "hset" => vec![b"HSET".to_vec(), b"myhash".to_vec(), key("element", rng), value.to_vec()],
+        "zadd" => {
+            let score = rng.gen_range(0..1_000_000).to_string().into_bytes();
+            vec![b"ZADD".to_vec(), b"myzset".to_vec(), score, key("element", rng)]
+        }
+        "mset" => {
+            let mut args = vec![b"MSET".to_vec()];
+            for _ in 0..10 {
+                args.push(key("key", rng));
+                args.push(value.to_vec());
+            }
+            args
+        }
+        _ => unreachable!("checked by check_test"),
+    }
+}
+
+fn encode_request(args: &[Vec<u8>], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+struct Conn {
+    stream: TcpStream,
+    buf: BytesMut,
+    decoder: RespDecoder,
+    limits: RespLimits,
+}
+
+impl Conn {
+    async fn connect(opts: &Opts) -> Result<Self> {
+        let stream = TcpStream::connect((opts.host.as_str(), opts.port)).await?;
+        stream.set_nodelay(true)?;
+        let mut conn = Self {
+            stream,
+            buf: BytesMut::with_capacity(16 * 1024),
+            decoder: RespDecoder::new(),
+            limits: RespLimits::default(),
+        };
+        if let Some(password) = &opts.password {
+            let mut out = Vec::new();
+            encode_request(&[b"AUTH".to_vec(), password.as_bytes().to_vec()], &mut out);
+            conn.stream.write_all(&out).await?;
+            if let RespFrame::Error(e) = conn.read_frame().await? {
+                bail!("AUTH failed: {}", e.as_str());
+            }
+        }
+        Ok(conn)
+    }
+
+    async fn read_frame(&mut self) -> Result<RespFrame> {
+        loop {
+            match self.decoder.decode(&mut self.buf, &self.limits) {
+                Ok(frame) => return Ok(frame),
+                Err(RespError::NotComplete) => {}
+                Err(e) => return Err(e.into()),
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                bail!("connection closed by server");
+            }
+        }
+    }
+}
+
+// what one connection measured
+#[derive(Default)]
+struct ConnResult {
+    latency: Histogram,
+    errors: u64,
+}
+
+// Send requests until the shared budget runs out. Every request of a pipeline
+// gets the latency of the whole round trip, like redis-benchmark.
+async fn run_conn(mut conn: Conn, opts: Arc<Opts>, workload: Arc<Workload>, issued: Arc<AtomicU64>) -> Result<ConnResult> {
+    let mut rng = StdRng::from_entropy();
+    let value = vec![b'x'; opts.data_size];
+    let mut result = ConnResult::default();
+    let mut out = Vec::new();
+    loop {
+        let first = issued.fetch_add(opts.pipeline, Ordering::Relaxed);
+        if first >= opts.requests {
+            return Ok(result);
+        }
+        let batch = opts.pipeline.min(opts.requests - first);
+        out.clear();
+        for _ in 0..batch {
+            let command = workload.pick(&mut rng);
+            encode_request(&request(command, &opts, &value, &mut rng), &mut out);
+        }
+        let start = Instant::now();
+        conn.stream.write_all(&out).await?;
+        for _ in 0..batch {
+            if let RespFrame::Error(_) = conn.read_frame().await? {
+                result.errors += 1;
+            }
+        }
+        let usec = start.elapsed().as_micros() as u64;
+        for _ in 0..batch {
+            result.latency.record(usec);
+        }
+    }
+}
+
+async fn run_workload(opts: &Arc<Opts>, workload: Workload) -> Result<()> {
+    let workload = Arc::new(workload);
+    // connect everyone before the clock starts
+    let mut conns = Vec::with_capacity(opts.clients);
+    for _ in 0..opts.clients {
+        conns.push(Conn::connect(opts).await?);
+    }
+    let issued = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+    let tasks: Vec<_> = conns
+        .into_iter()
+        .map(|conn| tokio::spawn(run_conn(conn, opts.clone(), workload.clone(), issued.clone())))
+        .collect();
+    let mut latency = Histogram::default();
+    let mut errors = 0;
+    for task in tasks {
+        let result = task.await??;
+        latency.merge(&result.latency);
+        errors += result.errors;
+    }
+    report(opts, &workload.name, start.elapsed(), &latency, errors);
+    Ok(())
+}
+
+// --in-process: no networking, what's measured is the command engine and its locks, e.g.
+// how it scales with --clients for a --keyspace-shards. A pipeline is run back to back and,
+// like over the network, every request of it gets the latency of the whole batch.
+fn run_in_process(opts: &Opts, workload: &Workload) {
+    let store = Store::with_config(Config { keyspace_shards: opts.keyspace_shards, ..Config::default() });
+    let issued = AtomicU64::new(0);
+    let start = Instant::now();
+    let results: Vec<ConnResult> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..opts.clients)
+            .map(|_| {
+                let (mut store, issued) = (store.session(), &issued);
+                scope.spawn(move || {
+                    let mut rng = StdRng::from_entropy();
+                    let value = vec![b'x'; opts.data_size];
+                    let mut result = ConnResult::default();
+                    let mut batch = Vec::new();
+                    loop {
+                        let first = issued.fetch_add(opts.pipeline, Ordering::Relaxed);
+                        if first >= opts.requests {
+                            return result;
+                        }
+                        let count = opts.pipeline.min(opts.requests - first);
+                        batch.clear();
+                        batch.extend((0..count).map(|_| request(workload.pick(&mut rng), opts, &value, &mut rng)));
+                        let start = Instant::now();
+                        for args in &batch {
+                            if let RespFrame::Error(_) = store.execute(args) {
+                                result.errors += 1;
+                            }
+                        }
+                        let usec = start.elapsed().as_micros() as u64;
+                        for _ in 0..count {
+                            result.latency.record(usec);
+                        }
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().expect("benchmark thread panicked")).collect()
+    });
+    let mut latency = Histogram::default();
+    let mut errors = 0;
+    for result in results {
+        latency.merge(&result.latency);
+        errors += result.errors;
+    }
+    report(opts, &workload.name, start.elapsed(), &latency, errors);
+}
+
+fn msec(usec: u64) -> f64 {
+    usec as f64 / 1000.0
+}
+
+fn report(opts: &Opts, name: &str, elapsed: Duration, latency: &Histogram, errors: u64) {
+    let rps = latency.count() as f64 / elapsed.as_secs_f64();
+    if opts.quiet {
+        println!("{}: {:.2} requests per second, p50={:.3} msec", name, rps, msec(latency.percentile(50.0)));
+        return;
+    }
+    println!("====== {} ======", name);
+    println!("  {} requests completed in {:.2} seconds", latency.count(), elapsed.as_secs_f64());
+    println!("  {} parallel clients", opts.clients);
+    println!("  {} bytes payload", opts.data_size);
+    println!("  pipeline depth {}", opts.pipeline);
+    if errors > 0 {
+        println!("  {} error replies", errors);
+    }
+    println!();
+    println!("Latency by percentile distribution:");
+    for percent in [0.0, 50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 99.99, 100.0] {
+        println!("{:>8.3}% <= {:.3} milliseconds", percent, msec(latency.percentile(percent)));
+    }
+    println!();
+    println!("Summary:");
+    println!("  throughput summary: {:.2} requests per second", rps);
+    println!("  latency summary (msec):");
+    println!("{:>13}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}", "avg", "min", "p50", "p95", "p99", "p999", "max");
+    println!(
+        "{:>13.3}{:>10.3}{:>10.3}{:>10.3}{:>10.3}{:>10.3}{:>10.3}",
+        latency.mean() / 1000.0,
+        msec(latency.min()),
+        msec(latency.percentile(50.0)),
+        msec(latency.percentile(95.0)),
+        msec(latency.percentile(99.0)),
+        msec(latency.percentile(99.9)),
+        msec(latency.max()),
+    );
+    println!();
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+    if opts.clients == 0 || opts.pipeline == 0 {
+        bail!("--clients and --pipeline must be at least 1");
+    }
+    let workloads = workloads(&opts)?;
+    let opts = Arc::new(opts);
+    for workload in workloads {
+        if opts.in_process {
+            run_in_process(&opts, &workload);
+        } else {
+            run_workload(&opts, workload).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{encode_request, request, workloads, Opts};
+
+    #[test]
+    fn test_workloads() {
+        let opts = Opts::parse_from(["bench", "-t", "set,GET,incr"]);
+        let names: Vec<String> = workloads(&opts).unwrap().into_iter().map(|w| w.name).collect();
+        assert_eq!(names, ["SET", "GET", "INCR"]);
+        assert!(workloads(&Opts::parse_from(["bench", "-t", "nope"])).is_err());
+
+        let mix = workloads(&Opts::parse_from(["bench", "--mix", "get=9,set=1,incr=0"])).unwrap();
+        assert_eq!(mix.len(), 1);
+        assert_eq!(mix[0].commands, [("get".to_string(), 9), ("set".to_string(), 1)]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let gets = (0..1000).filter(|_| mix[0].pick(&mut rng) == "get").count();
+        assert!((850..950).contains(&gets), "{}", gets);
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let opts = Opts::parse_from(["bench", "-r", "100"]);
+        let args = request("set", &opts, b"xyz", &mut rng);
+        assert_eq!(args[0], b"SET");
+        assert!(args[1].starts_with(b"key:000000000"));
+        assert_eq!(request("mset", &opts, b"v", &mut rng).len(), 21);
+
+        let single = Opts::parse_from(["bench"]);
+        assert_eq!(request("get", &single, b"", &mut rng)[1], b"key:__rand_int__");
+
+        let mut out = Vec::new();
+        encode_request(&[b"GET".to_vec(), b"k".to_vec()], &mut out);
+        assert_eq!(out, b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+};
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    cluster::{key_hash_slot, SLOTS},
+    cmd::{lookup, Argv},
+    resp::{RespDecoder, RespEncode, RespError, RespFrame, RespLimits},
+    tls::{self, Stream},
+};
+
+// how many MOVED / ASK redirections one command may follow
+const MAX_REDIRECTS: usize = 5;
+
+// A minimal async RESP client: commands go out as arrays of bulk strings,
+// replies are decoded frame by frame. RESP3 push messages met while waiting for
+// a reply are put aside.
+#[derive(Debug)]
+pub struct Client {
+    stream: Stream,
+    buf: BytesMut,
+    decoder: RespDecoder,
+    limits: RespLimits,
+    pushes: Vec<RespFrame>,
+}
+
+pub fn command_frame<T: AsRef<[u8]>>(args: &[T]) -> RespFrame {
+    RespFrame::Array(args.iter().map(|a| RespFrame::BulkString(a.as_ref().to_vec())).collect())
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::with_stream(stream.into()))
+    }
+
+    // connect over TLS, the server certificate must be valid for `server_name`
+    pub async fn connect_tls(addr: impl ToSocketAddrs, connector: &TlsConnector, server_name: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::with_stream(tls::connect_tls(stream, connector, server_name).await?))
+    }
+
+    fn with_stream(stream: Stream) -> Self {
+        Self {
+            stream,
+            buf: BytesMut::with_capacity(4096),
+            decoder: RespDecoder::new(),
+            limits: RespLimits::default(),
+            pushes: Vec::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: RespLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub async fn send<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<()> {
+        self.stream.write_all(&command_frame(args).encode()).await?;
+        Ok(())
+    }
+
+    pub async fn read_frame(&mut self) -> Result<RespFrame> {
+        loop {
+            match self.decoder.decode(&mut self.buf, &self.limits) {
+                Ok(frame) => return Ok(frame),
+                Err(RespError::NotComplete) => {}
+                Err(e) => return Err(e.into()),
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                bail!("connection closed by server");
+            }
+        }
+    }
+
+    pub async fn command<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<RespFrame> {
+        self.send(args).await?;
+        loop {
+            match self.read_frame().await? {
+                RespFrame::Push(push) => self.pushes.push(RespFrame::Push(push)),
+                reply => return Ok(reply),
+            }
+        }
+    }
+
+    // the push messages received so far
+    pub fn take_pushes(&mut self) -> Vec<RespFrame> {
+        std::mem::take(&mut self.pushes)
+    }
+
+    // take the push messages the server already sent without waiting for more,
+    // only while no reply is expected
+    pub async fn poll_pushes(&mut self) -> Result<()> {
+        loop {
+            match self.decoder.decode(&mut self.buf, &self.limits) {
+                Ok(RespFrame::Push(push)) => self.pushes.push(RespFrame::Push(push)),
+                Ok(frame) => bail!("unexpected frame without a command: {:?}", frame),
+                Err(RespError::NotComplete) => {
+                    // reading is cancel safe, a timeout loses nothing
+                    match tokio::time::timeout(Duration::ZERO, self.stream.read_buf(&mut self.buf)).await {
+                        Ok(Ok(0)) => bail!("connection closed by server"),
+                        Ok(read) => read?,
+                        Err(_) => return Ok(()),
+                    };
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // the socket and whatever was read past the last frame
+    pub fn into_parts(self) -> (Stream, BytesMut) {
+        (self.stream, self.buf)
+    }
+}
+
+// A client with a local cache of read replies (client side caching). The server
+// tracks the keys the connection reads and pushes an invalidation when one changes,
+// which drops the replies that read it. Only the commands passed to `cached` are
+// cached: the caller knows which of its reads are worth it and deterministic.
+#[derive(Debug)]
+pub struct CachingClient {
+    client: Client,
+    replies: HashMap<Argv, RespFrame>,
+    // the cached commands that read each key
+    readers: HashMap<Vec<u8>, HashSet<Argv>>,
+}
+
+impl CachingClient {
+    // switch the connection to RESP3 and turn tracking on
+    pub async fn new(mut client: Client) -> Result<Self> {
+        for command in [&["hello", "3"][..], &["client", "tracking", "on"]] {
+            if let RespFrame::Error(e) = client.command(command).await? {
+                bail!("{}", e.as_str());
+            }
+        }
+        Ok(Self { client, replies: HashMap::new(), readers: HashMap::new() })
+    }
+
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        Self::new(Client::connect(addr).await?).await
+    }
+
+    // any command, never cached
+    pub async fn command<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<RespFrame> {
+        let reply = self.client.command(args).await?;
+        self.invalidate();
+        Ok(reply)
+    }
+
+    // a read only command with keys, served from the cache until one of them changes
+    pub async fn cached<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<RespFrame> {
+        let argv: Argv = args.iter().map(|a| a.as_ref().to_vec()).collect();
+        self.client.poll_pushes().await?;
+        self.invalidate();
+        if let Some(reply) = self.replies.get(&argv) {
+            return Ok(reply.clone());
+        }
+        let keys: Vec<Vec<u8>> = match argv.first().and_then(|name| lookup(name)) {
+            Some(spec) => spec.keys(&argv).into_iter().map(|k| k.to_vec()).collect(),
+            None => vec![],
+        };
+        let reply = self.client.command(&argv).await?;
+        // errors aren't cached, nor commands the server can't track
+        if !keys.is_empty() && !matches!(reply, RespFrame::Error(_)) {
+            for key in keys {
+                self.readers.entry(key).or_default().insert(argv.clone());
+            }
+            self.replies.insert(argv, reply.clone());
+        }
+        // an invalidation that came with the reply may be for it
+        self.invalidate();
+        Ok(reply)
+    }
+
+    pub async fn get(&mut self, key: impl AsRef<[u8]>) -> Result<RespFrame> {
+        self.cached(&[b"get".as_ref(), key.as_ref()]).await
+    }
+
+    // number of cached replies
+    pub fn len(&self) -> usize {
+        self.replies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+    }
+
+    // apply the invalidations received so far, a nil key list means every key changed
+    fn invalidate(&mut self) {
+        for push in self.client.take_pushes() {
+            let RespFrame::Push(items) = push else { continue };
+            let [RespFrame::BulkString(kind), keys] = &items[..] else { continue };
+            if kind != b"invalidate" {
+                continue;
+            }
+            match keys {
+                RespFrame::Array(keys) => {
+                    for key in keys {
+                        let RespFrame::BulkString(key) = key else { continue };
+                        for argv in self.readers.remove(key).unwrap_or_default() {
+                            self.replies.remove(&argv);
+                        }
+                    }
+                }
+                _ => {
+                    self.replies.clear();
+                    self.readers.clear();
+                }
+            }
+        }
+    }
+}
+
+// A client for a cluster: commands are sent to the node serving the slot of their
+// first key. The slot map comes from CLUSTER SLOTS and is fixed up by MOVED replies,
+// ASK replies are followed once with ASKING.
+#[derive(Debug)]
+pub struct ClusterClient {
+    seeds: Vec<String>,
+    nodes: HashMap<String, Client>,
+    // the address serving each slot
+    slots: Vec<Option<String>>,
+}
+
+// `MOVED 3999 127.0.0.1:6381` -> (slot, address)
+fn parse_redirect(message: &str, kind: &str) -> Option<(u16, String)> {
+    let mut parts = message.strip_prefix(kind)?.split_whitespace();
+    let slot = parts.next()?.parse().ok()?;
+    Some((slot, parts.next()?.to_string()))
+}
+
+impl ClusterClient {
+    pub async fn connect(seeds: &[&str]) -> Result<Self> {
+        let mut client = Self {
+            seeds: seeds.iter().map(|s| s.to_string()).collect(),
+            nodes: HashMap::new(),
+            slots: vec![None; SLOTS],
+        };
+        client.refresh_slots().await?;
+        Ok(client)
+    }
+
+    async fn node(&mut self, addr: &str) -> Result<&mut Client> {
+        if !self.nodes.contains_key(addr) {
+            self.nodes.insert(addr.to_string(), Client::connect(addr).await?);
+        }
+        Ok(self.nodes.get_mut(addr).expect("node connected above"))
+    }
+
+    // load the slot map from the first seed or known node that answers CLUSTER SLOTS
+    pub async fn refresh_slots(&mut self) -> Result<()> {
+        let mut candidates: Vec<String> = self.nodes.keys().cloned().collect();
+        candidates.extend(self.seeds.iter().cloned());
+        for addr in candidates {
+            let Ok(node) = self.node(&addr).await else { continue };
+            let Ok(RespFrame::Array(ranges)) = node.command(&["cluster", "slots"]).await else { continue };
+            self.slots = vec![None; SLOTS];
+            for range in ranges {
+                let RespFrame::Array(range) = range else { continue };
+                let [RespFrame::Integer(start), RespFrame::Integer(end), RespFrame::Array(master), ..] = &range[..] else {
+                    continue;
+                };
+                let [RespFrame::BulkString(host), RespFrame::Integer(port), ..] = &master[..] else { continue };
+                let addr = format!("{}:{}", String::from_utf8_lossy(host), port);
+                for slot in *start..=*end {
+                    self.slots[slot as usize] = Some(addr.clone());
+                }
+            }
+            return Ok(());
+        }
+        bail!("no cluster node answered CLUSTER SLOTS")
+    }
+
+    // the node for the first key of the command, any node for keyless ones
+    fn route<T: AsRef<[u8]>>(&self, args: &[T]) -> Option<String> {
+        let argv: Vec<Vec<u8>> = args.iter().map(|a| a.as_ref().to_vec()).collect();
+        let slot = argv.first().and_then(|name| lookup(name)).and_then(|spec| {
+            spec.keys(&argv).first().map(|key| key_hash_slot(key))
+        });
+        match slot {
+            Some(slot) => self.slots[slot as usize].clone(),
+            None => self.slots.iter().flatten().next().cloned(),
+        }
+        .or_else(|| self.seeds.first().cloned())
+    }
+
+    pub async fn command<T: AsRef<[u8]>>(&mut self, args: &[T]) -> Result<RespFrame> {
+        let mut addr = self.route(args).ok_or_else(|| anyhow!("no cluster node known"))?;
+        let mut asking = false;
+        for _ in 0..=MAX_REDIRECTS {
+            let node = self.node(&addr).await?;
+            if asking {
+                node.command(&["asking"]).await?;
+            }
+            let reply = node.command(args).await?;
+            let RespFrame::Error(e) = &reply else { return Ok(reply) };
+            if let Some((slot, target)) = parse_redirect(e.as_str(), "MOVED ") {
+                self.slots[slot as usize] = Some(target.clone());
+                (addr, asking) = (target, false);
+            } else if let Some((_, target)) = parse_redirect(e.as_str(), "ASK ") {
+                (addr, asking) = (target, true);
+            } else {
+                return Ok(reply);
+            }
+        }
+        bail!("too many cluster redirections")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::TlsConnector;
+
+    use crate::{
+        backend::Backend,
+        cluster::Cluster,
+        cmd::{bulk, ok},
+        config::Config,
+        network,
+        resp::RespFrame,
+        tls::{self, tests::TestPki, TlsAuthClients},
+    };
+
+    use super::{CachingClient, Client, ClusterClient};
+
+    #[tokio::test]
+    async fn test_tls_client() {
+        let pki = TestPki::generate("client");
+        let config = Config {
+            tls_port: Some(0),
+            tls_cert_file: Some(pki.path("server.crt")),
+            tls_key_file: Some(pki.path("server.key")),
+            tls_ca_cert_file: Some(pki.path("ca.crt")),
+            tls_auth_clients: TlsAuthClients::Yes,
+            ..Config::default()
+        };
+        let acceptor = tls::acceptor(&config).unwrap().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(network::serve_tls(listener, acceptor, Backend::new(config)));
+
+        let identity = (pki.path("client.crt"), pki.path("client.key"));
+        let client_config = tls::client_config(&pki.path("ca.crt"), Some((&identity.0, &identity.1))).unwrap();
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let mut client = Client::connect_tls(addr, &connector, "localhost").await.unwrap();
+        assert_eq!(client.command(&["set", "a", "1"]).await.unwrap(), ok());
+        assert_eq!(client.command(&["get", "a"]).await.unwrap(), bulk("1"));
+
+        // the server certificate isn't valid for another name
+        assert!(Client::connect_tls(addr, &connector, "example.com").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_limits() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(network::serve(listener, Backend::default()));
+
+        let mut client = Client::connect(addr).await.unwrap();
+        let reply = client.command(&["config", "set", "proto-max-bulk-len", "1mb", "client-query-buffer-limit", "1mb"]);
+        assert_eq!(reply.await.unwrap(), ok());
+        let big = "x".repeat(2 << 20);
+        let reply = client.command(&["set", "a", &big]).await.unwrap();
+        assert!(matches!(reply, RespFrame::Error(e) if e.as_str().starts_with("ERR Protocol error")));
+        assert!(client.command(&["ping"]).await.is_err());
+
+        // small elements of a frame that never completes still count against the buffer
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut request = b"*1000000\r\n".to_vec();
+        for _ in 0..(2 << 20) / 9 {
+            request.extend_from_slice(b"$3\r\nabc\r\n");
+        }
+        let _ = stream.write_all(&request).await;
+        let mut out = Vec::new();
+        let closed = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut out)).await;
+        assert!(closed.is_ok(), "the connection should be closed");
+    }
+
+    #[tokio::test]
+    async fn test_cluster_client_follows_redirects() {
+        let listeners = [TcpListener::bind("127.0.0.1:0").await.unwrap(), TcpListener::bind("127.0.0.1:0").await.unwrap()];
+        let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+        let layout = format!("a 127.0.0.1:{} 0-8191\nb 127.0.0.1:{} 8192-16383", ports[0], ports[1]);
+        let mut backends = Vec::new();
+        for (listener, port) in listeners.into_iter().zip(&ports) {
+            let backend = Backend::with_cluster(Config::default(), Cluster::parse(&layout, *port).unwrap());
+            tokio::spawn(network::serve(listener, backend.clone()));
+            backends.push(backend);
+        }
+
+        let seed = format!("127.0.0.1:{}", ports[0]);
+        let mut client = ClusterClient::connect(&[&seed]).await.unwrap();
+        // foo is served by b, bar by a
+        client.command(&["set", "foo", "1"]).await.unwrap();
+        client.command(&["set", "bar", "2"]).await.unwrap();
+        assert_eq!(backends[1].read(0).keys().count(), 1);
+        assert_eq!(client.command(&["get", "foo"]).await.unwrap(), bulk("1"));
+
+        // move foo's slot 12182 to a behind the client's back: MOVED
+        for backend in &backends {
+            backend.cluster.as_ref().unwrap().set_slot(12182, crate::cluster::SlotAction::Node("a".into())).unwrap();
+        }
+        client.command(&["set", "foo", "3"]).await.unwrap();
+        assert_eq!(backends[0].read(0).keys().count(), 2);
+
+        // bar's slot 5061 migrating from a to b, the missing key is asked on b
+        backends[0].cluster.as_ref().unwrap().set_slot(5061, crate::cluster::SlotAction::Migrating("b".into())).unwrap();
+        backends[1].cluster.as_ref().unwrap().set_slot(5061, crate::cluster::SlotAction::Importing("a".into())).unwrap();
+        client.command(&["del", "bar"]).await.unwrap();
+        client.command(&["set", "bar", "4"]).await.unwrap();
+        assert_eq!(backends[1].read(0).keys().count(), 2);
+        assert_eq!(client.command(&["get", "bar"]).await.unwrap(), bulk("4"));
+    }
+
+    #[tokio::test]
+    async fn test_caching_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(network::serve(listener, Backend::default()));
+
+        let mut cache = CachingClient::connect(addr).await.unwrap();
+        let mut other = Client::connect(addr).await.unwrap();
+        other.command(&["set", "a", "1"]).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), bulk("1"));
+        assert_eq!(cache.cached(&["strlen", "a"]).await.unwrap(), crate::cmd::int(1));
+        assert_eq!(cache.len(), 2);
+
+        other.command(&["set", "a", "22"]).await.unwrap();
+        // the invalidation is on its way, wait for it
+        while !cache.is_empty() {
+            cache.command(&["ping"]).await.unwrap();
+        }
+        assert_eq!(cache.get("a").await.unwrap(), bulk("22"));
+
+        // our own writes invalidate too
+        cache.command(&["set", "a", "3"]).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), bulk("3"));
+        other.command(&["flushall"]).await.unwrap();
+        while !cache.is_empty() {
+            cache.command(&["ping"]).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,312 @@
+pub mod slot;
+
+pub use slot::{key_hash_slot, SLOTS};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{anyhow, bail, Result};
+use indexmap::IndexMap;
+
+use crate::replication::new_replid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub id: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Node {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+// Slot layout of the cluster as this node knows it. There is no gossip: every node
+// loads the same layout file and finds itself in it by port, changes made with
+// CLUSTER ADDSLOTS / DELSLOTS / SETSLOT have to be applied on each node.
+// The set of nodes is static, there is no CLUSTER MEET / FORGET: a node is added
+// by listing it in the layout file of every node and restarting them.
+#[derive(Debug)]
+pub struct Cluster {
+    myself: String,
+    state: RwLock<ClusterState>,
+}
+
+#[derive(Debug)]
+struct ClusterState {
+    nodes: IndexMap<String, Node>,
+    // the owner node id of each slot
+    slots: Vec<Option<String>>,
+    // slots being moved away to / in from another node, for ASK redirections
+    migrating: HashMap<u16, String>,
+    importing: HashMap<u16, String>,
+}
+
+// what CLUSTER SETSLOT does
+#[derive(Debug, PartialEq)]
+pub enum SlotAction {
+    Migrating(String),
+    Importing(String),
+    Node(String),
+    Stable,
+}
+
+// parse `1000` or `0-5460`
+fn parse_range(s: &str) -> Result<(u16, u16)> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end): (u16, u16) = (start.parse()?, end.parse()?);
+    if start > end || end as usize >= SLOTS {
+        bail!("invalid slot range: {}", s);
+    }
+    Ok((start, end))
+}
+
+impl Cluster {
+    // a node alone in its cluster, without slots
+    pub fn new(host: String, port: u16) -> Self {
+        let myself = Node { id: new_replid(), host, port };
+        Self::with_nodes(myself.id.clone(), vec![(myself, vec![])])
+    }
+
+    fn with_nodes(myself: String, nodes: Vec<(Node, Vec<(u16, u16)>)>) -> Self {
+        let mut state = ClusterState {
+            nodes: IndexMap::new(),
+            slots: vec![None; SLOTS],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+        for (node, ranges) in nodes {
+            for (start, end) in ranges {
+                for slot in start..=end {
+                    state.slots[slot as usize] = Some(node.id.clone());
+                }
+            }
+            state.nodes.insert(node.id.clone(), node);
+        }
+        Self { myself, state: RwLock::new(state) }
+    }
+
+    // The layout file has one line per node: `<id> <host>:<port> [<slot>|<start>-<end>]...`,
+    // `#` starts a comment. The node listening on `port` is this one.
+    pub fn parse(text: &str, port: u16) -> Result<Self> {
+        let mut nodes = Vec::new();
+        let mut myself = None;
+        for line in text.lines().map(|l| l.split('#').next().unwrap_or("").trim()) {
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (Some(id), Some(addr)) = (parts.next(), parts.next()) else {
+                bail!("invalid cluster node line: {}", line);
+            };
+            let (host, node_port) = addr.rsplit_once(':').ok_or_else(|| anyhow!("invalid node address: {}", addr))?;
+            let node = Node { id: id.to_string(), host: host.to_string(), port: node_port.parse()? };
+            let ranges = parts.map(parse_range).collect::<Result<Vec<_>>>()?;
+            if node.port == port {
+                if myself.is_some() {
+                    bail!("more than one cluster node uses port {}", port);
+                }
+                myself = Some(node.id.clone());
+            }
+            nodes.push((node, ranges));
+        }
+        let myself = myself.ok_or_else(|| anyhow!("no cluster node uses port {}", port))?;
+        Ok(Self::with_nodes(myself, nodes))
+    }
+
+    // load the layout file, or start alone when it doesn't exist yet
+    pub fn load(path: &Path, host: &str, port: u16) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text, port),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::new(host.to_string(), port)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut out = String::new();
+        for node in self.nodes() {
+            out.push_str(&format!("{} {}", node.id, node.addr()));
+            for (start, end, owner) in self.slot_ranges() {
+                if owner.id == node.id {
+                    out.push_str(&if start == end { format!(" {}", start) } else { format!(" {}-{}", start, end) });
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_config())?;
+        Ok(())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, ClusterState> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, ClusterState> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn myself(&self) -> Node {
+        self.read().nodes[&self.myself].clone()
+    }
+
+    pub fn nodes(&self) -> Vec<Node> {
+        self.read().nodes.values().cloned().collect()
+    }
+
+    pub fn node(&self, id: &str) -> Option<Node> {
+        self.read().nodes.get(id).cloned()
+    }
+
+    pub fn owner(&self, slot: u16) -> Option<Node> {
+        let state = self.read();
+        let id = state.slots[slot as usize].as_ref()?;
+        state.nodes.get(id).cloned()
+    }
+
+    pub fn migrating(&self, slot: u16) -> Option<Node> {
+        let state = self.read();
+        state.migrating.get(&slot).and_then(|id| state.nodes.get(id)).cloned()
+    }
+
+    pub fn importing(&self, slot: u16) -> Option<Node> {
+        let state = self.read();
+        state.importing.get(&slot).and_then(|id| state.nodes.get(id)).cloned()
+    }
+
+    // contiguous (start, end, owner) ranges in slot order
+    pub fn slot_ranges(&self) -> Vec<(u16, u16, Node)> {
+        let state = self.read();
+        let mut ranges: Vec<(u16, u16, Node)> = Vec::new();
+        for (slot, owner) in state.slots.iter().enumerate() {
+            let Some(node) = owner.as_ref().and_then(|id| state.nodes.get(id)) else {
+                continue;
+            };
+            match ranges.last_mut() {
+                Some((_, end, last)) if *end as usize + 1 == slot && last.id == node.id => *end = slot as u16,
+                _ => ranges.push((slot as u16, slot as u16, node.clone())),
+            }
+        }
+        ranges
+    }
+
+    pub fn assigned_slots(&self) -> usize {
+        self.read().slots.iter().filter(|s| s.is_some()).count()
+    }
+
+    pub fn add_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut state = self.write();
+        if let Some(slot) = slots.iter().find(|&&s| state.slots[s as usize].is_some()) {
+            return Err(format!("Slot {} is already busy", slot));
+        }
+        for &slot in slots {
+            state.slots[slot as usize] = Some(self.myself.clone());
+            state.importing.remove(&slot);
+        }
+        Ok(())
+    }
+
+    pub fn del_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut state = self.write();
+        if let Some(slot) = slots.iter().find(|&&s| state.slots[s as usize].is_none()) {
+            return Err(format!("Slot {} is already unassigned", slot));
+        }
+        for &slot in slots {
+            state.slots[slot as usize] = None;
+            state.migrating.remove(&slot);
+            state.importing.remove(&slot);
+        }
+        Ok(())
+    }
+
+    pub fn set_slot(&self, slot: u16, action: SlotAction) -> Result<(), String> {
+        let mut state = self.write();
+        if let SlotAction::Migrating(id) | SlotAction::Importing(id) | SlotAction::Node(id) = &action {
+            if !state.nodes.contains_key(id) {
+                return Err(format!("Unknown node {}", id));
+            }
+        }
+        let owner = state.slots[slot as usize].clone();
+        match action {
+            SlotAction::Migrating(id) => {
+                if owner.as_deref() != Some(self.myself.as_str()) {
+                    return Err(format!("I'm not the owner of hash slot {}", slot));
+                }
+                state.migrating.insert(slot, id);
+            }
+            SlotAction::Importing(id) => {
+                if owner.as_deref() == Some(self.myself.as_str()) {
+                    return Err(format!("I'm already the owner of hash slot {}", slot));
+                }
+                state.importing.insert(slot, id);
+            }
+            SlotAction::Node(id) => {
+                state.slots[slot as usize] = Some(id);
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+            SlotAction::Stable => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cluster, SlotAction};
+
+    const LAYOUT: &str = "\
+# three local nodes
+a 127.0.0.1:7000 0-5460
+b 127.0.0.1:7001 5461-10922
+c 127.0.0.1:7002 10923-16383
+";
+
+    #[test]
+    fn test_parse_layout() {
+        let cluster = Cluster::parse(LAYOUT, 7001).unwrap();
+        assert_eq!(cluster.myself().id, "b");
+        assert_eq!(cluster.owner(0).unwrap().port, 7000);
+        assert_eq!(cluster.owner(16383).unwrap().id, "c");
+        assert_eq!(cluster.assigned_slots(), 16384);
+        assert_eq!(cluster.slot_ranges().len(), 3);
+        assert_eq!(cluster.to_config(), LAYOUT.lines().skip(1).map(|l| format!("{}\n", l)).collect::<String>());
+
+        assert!(Cluster::parse(LAYOUT, 7005).is_err());
+        assert!(Cluster::parse("a 127.0.0.1:7000 0-16384", 7000).is_err());
+    }
+
+    #[test]
+    fn test_slot_changes() {
+        let cluster = Cluster::parse("a 127.0.0.1:7000\nb 127.0.0.1:7001 100", 7000).unwrap();
+        cluster.add_slots(&[1, 2, 3]).unwrap();
+        assert!(cluster.add_slots(&[3, 4]).is_err());
+        assert_eq!(cluster.slot_ranges()[0].0, 1);
+        assert_eq!(cluster.slot_ranges()[0].1, 3);
+
+        cluster.set_slot(1, SlotAction::Migrating("b".into())).unwrap();
+        assert_eq!(cluster.migrating(1).unwrap().id, "b");
+        assert!(cluster.set_slot(100, SlotAction::Migrating("b".into())).is_err());
+        cluster.set_slot(100, SlotAction::Importing("b".into())).unwrap();
+        cluster.set_slot(1, SlotAction::Node("b".into())).unwrap();
+        assert!(cluster.migrating(1).is_none());
+        assert_eq!(cluster.owner(1).unwrap().id, "b");
+
+        cluster.del_slots(&[2, 3]).unwrap();
+        assert!(cluster.del_slots(&[2]).is_err());
+        assert_eq!(cluster.assigned_slots(), 2);
+    }
+}
@@ -0,0 +1,40 @@
+// the keyspace of a cluster is split in 16384 hash slots
+pub const SLOTS: usize = 16384;
+
+// The slot of a key: CRC16 (XMODEM) of the key modulo 16384, like redis. When the key
+// has a non empty `{...}` part only that part is hashed, so related keys can be
+// forced into one slot, e.g. `{user1000}.following` and `{user1000}.followers`.
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = match key.iter().position(|&b| b == b'{') {
+        Some(start) => match key[start + 1..].iter().position(|&b| b == b'}') {
+            Some(len) if len > 0 => &key[start + 1..start + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16::State::<crc16::XMODEM>::calculate(hashed) % SLOTS as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key_hash_slot;
+
+    #[test]
+    fn test_key_hash_slot() {
+        // values from redis CLUSTER KEYSLOT
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"bar"), 5061);
+        assert_eq!(key_hash_slot(b"123456789"), 12739);
+        assert_eq!(key_hash_slot(b""), 0);
+    }
+
+    #[test]
+    fn test_hashtag() {
+        assert_eq!(key_hash_slot(b"{user1000}.following"), key_hash_slot(b"user1000"));
+        assert_eq!(key_hash_slot(b"foo{bar}{zap}"), key_hash_slot(b"bar"));
+        // an empty tag hashes the whole key
+        assert_eq!(key_hash_slot(b"foo{}{bar}"), crc16::State::<crc16::XMODEM>::calculate(b"foo{}{bar}") % 16384);
+        assert_eq!(key_hash_slot(b"foo{bar"), key_hash_slot(b"foo{bar"));
+        assert_ne!(key_hash_slot(b"foo{bar"), key_hash_slot(b"bar"));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    acl::{CATEGORIES, DEFAULT_USER},
+    connections::KillFilter,
+};
+
+use super::{
+    all_commands, array, bulk, command_categories, help_reply, int, nil, ok, CmdResult, CommandError, CommandSpec, Context,
+    ADMIN, NOSCRIPT, NO_KEYS,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[CommandSpec::new("acl", -2, ADMIN | NOSCRIPT, NO_KEYS, acl)];
+
+const ACL_HELP: &[&str] = &[
+    "CAT [<category>]",
+    "    List all commands that belong to <category>, or all command categories",
+    "    when no category is specified.",
+    "DELUSER <username> [<username> ...]",
+    "    Delete a list of users.",
+    "GETUSER <username>",
+    "    Get the user's details.",
+    "LIST",
+    "    Show users details in config file format.",
+    "SETUSER <username> <attribute> [<attribute> ...]",
+    "    Create or modify a user with the specified attributes.",
+    "USERS",
+    "    List all the registered usernames.",
+    "WHOAMI",
+    "    Return the current connection username.",
+];
+
+// ACL SETUSER | GETUSER | DELUSER | LIST | USERS | WHOAMI | CAT
+fn acl(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = String::from_utf8_lossy(&argv[1]).to_ascii_lowercase();
+    let args: Vec<String> = argv[2..].iter().map(|a| String::from_utf8_lossy(a).into_owned()).collect();
+    let acl = &ctx.backend.acl;
+    match (sub.as_str(), args.as_slice()) {
+        ("setuser", [name, rules @ ..]) => {
+            acl.set_user(name, rules).map_err(CommandError::Other)?;
+            Ok(ok())
+        }
+        ("getuser", [name]) => Ok(match acl.get_user(name) {
+            Some(user) => array([
+                bulk("flags"),
+                array(user.flags().into_iter().map(bulk)),
+                bulk("passwords"),
+                array(user.passwords().map(|p| bulk(p.as_str()))),
+                bulk("commands"),
+                bulk(user.commands_description()),
+                bulk("keys"),
+                bulk(user.keys_description()),
+                bulk("channels"),
+                bulk(user.channels_description()),
+            ]),
+            None => nil(),
+        }),
+        ("deluser", names) if !names.is_empty() => {
+            if names.iter().any(|n| n == DEFAULT_USER) {
+                return Err(CommandError::Other("The 'default' user cannot be removed".into()));
+            }
+            let mut deleted = 0;
+            for name in names {
+                if acl.del_user(name) {
+                    deleted += 1;
+                    // connections of a removed user go away with it
+                    let filter = KillFilter { user: Some(name.clone()), ..KillFilter::default() };
+                    ctx.backend.connections.kill(&filter);
+                }
+            }
+            Ok(int(deleted))
+        }
+        ("list", []) => Ok(array(acl.users().iter().map(|u| bulk(u.describe())))),
+        ("users", []) => Ok(array(acl.users().into_iter().map(|u| bulk(u.name)))),
+        ("whoami", []) => Ok(bulk(ctx.session.user.as_deref().unwrap_or(DEFAULT_USER))),
+        ("help", []) => Ok(help_reply("ACL", ACL_HELP)),
+        ("cat", []) => Ok(array(CATEGORIES.iter().map(|c| bulk(*c)))),
+        ("cat", [category]) => {
+            let category = category.to_ascii_lowercase();
+            if !CATEGORIES.contains(&category.as_str()) {
+                return Err(CommandError::Other(format!("Unknown category '{}'", category)));
+            }
+            let mut names: Vec<&str> = all_commands()
+                .filter(|spec| command_categories(spec).contains(&category.as_str()))
+                .map(|spec| spec.name)
+                .collect();
+            names.sort_unstable();
+            Ok(array(names.into_iter().map(bulk)))
+        }
+        ("setuser" | "getuser" | "deluser" | "list" | "users" | "whoami" | "cat", _) => {
+            Err(CommandError::WrongArity(format!("acl|{}", sub)))
+        }
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try ACL HELP.", sub))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cmd::{
+            array, bulk, error_frame, int, ok, simple,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        config::Config,
+        resp::RespFrame,
+    };
+
+    fn noperm(msg: &str) -> RespFrame {
+        error_frame(CommandError::NoPerm(msg.into()))
+    }
+
+    // a connection of the default user, not authenticated yet
+    fn client() -> Session {
+        Session { user: Some("default".into()), ..Session::default() }
+    }
+
+    #[test]
+    fn test_acl_users() {
+        let backend = test_backend();
+        let mut admin = Session { authenticated: true, ..client() };
+        assert_eq!(run(&backend, &mut admin, "acl setuser alice on >pw ~cache:* +@read +set"), ok());
+        assert_eq!(run(&backend, &mut admin, "acl users"), array([bulk("alice"), bulk("default")]));
+        assert!(matches!(run(&backend, &mut admin, "acl setuser bob +@nope"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut admin, "acl help"), RespFrame::Array(help) if help.contains(&simple("WHOAMI"))));
+
+        let mut alice = client();
+        assert_eq!(run(&backend, &mut alice, "get cache:1"), error_frame(CommandError::NoAuth));
+        assert_eq!(run(&backend, &mut alice, "auth alice nope"), error_frame(CommandError::WrongPass));
+        assert_eq!(run(&backend, &mut alice, "auth alice pw"), ok());
+        assert_eq!(run(&backend, &mut alice, "acl whoami"), noperm("User alice has no permissions to run the 'acl' command"));
+        assert_eq!(run(&backend, &mut alice, "set cache:1 x"), ok());
+        assert_eq!(run(&backend, &mut alice, "get other"), noperm("No permissions to access a key"));
+        assert_eq!(run(&backend, &mut alice, "del cache:1"), noperm("User alice has no permissions to run the 'del' command"));
+
+        assert_eq!(run(&backend, &mut admin, "acl deluser alice nobody"), int(1));
+        assert!(matches!(run(&backend, &mut admin, "acl deluser default"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut alice, "get cache:1"), noperm("User alice has no permissions to run the 'get' command"));
+    }
+
+    #[test]
+    fn test_requirepass() {
+        let backend = Backend::new(Config { requirepass: Some("secret".into()), ..Config::default() });
+        let mut session = client();
+        assert_eq!(run(&backend, &mut session, "ping"), error_frame(CommandError::NoAuth));
+        assert_eq!(run(&backend, &mut session, "auth wrong"), error_frame(CommandError::WrongPass));
+        assert_eq!(run(&backend, &mut session, "auth secret"), ok());
+        assert_eq!(run(&backend, &mut session, "ping"), simple("PONG"));
+        assert_eq!(run(&backend, &mut session, "acl whoami"), bulk("default"));
+
+        // without requirepass the default user needs no AUTH
+        let backend = test_backend();
+        assert!(matches!(run(&backend, &mut client(), "auth secret"), RespFrame::Error(_)));
+    }
+}
@@ -0,0 +1,489 @@
+use crate::backend::{Db, Value};
+
+use super::{
+    arg_i64, arg_str, array, eq_ignore_case, int, nil, string::get_string, string::normalize_range,
+    string::MAX_STRING_LEN, CmdResult, CommandError, CommandSpec, Context, DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("setbit", 4, WRITE | DENYOOM, KEY1, setbit),
+    CommandSpec::new("getbit", 3, READONLY | FAST, KEY1, getbit),
+    CommandSpec::new("bitcount", -2, READONLY, KEY1, bitcount),
+    CommandSpec::new("bitpos", -3, READONLY, KEY1, bitpos),
+    CommandSpec::new("bitop", -4, WRITE | DENYOOM, (2, -1, 1), bitop),
+    CommandSpec::new("bitfield", -2, WRITE | DENYOOM, KEY1, bitfield),
+];
+
+// the string of a key to modify in place, created empty when missing
+fn string_mut<'a>(db: &'a mut Db, key: &[u8]) -> Result<&'a mut Vec<u8>, CommandError> {
+    if db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::String(Vec::new().into()));
+    }
+    match db.get_mut(key) {
+        Some(Value::String(s)) => Ok(s.make_raw()),
+        _ => Err(CommandError::WrongType),
+    }
+}
+
+// bit offsets go up to the last bit of a 512MB string
+fn arg_bit_offset(v: &[u8]) -> Result<u64, CommandError> {
+    let offset = arg_i64(v).map_err(|_| bit_offset_error())?;
+    if offset < 0 || offset as u64 >= MAX_STRING_LEN as u64 * 8 {
+        return Err(bit_offset_error());
+    }
+    Ok(offset as u64)
+}
+
+fn bit_offset_error() -> CommandError {
+    CommandError::Other("bit offset is not an integer or out of range".into())
+}
+
+// bits are numbered from the most significant bit of the first byte, like redis
+fn get_bit(s: &[u8], offset: u64) -> u8 {
+    let byte = (offset / 8) as usize;
+    s.get(byte).map_or(0, |b| (b >> (7 - offset % 8)) & 1)
+}
+
+fn set_bit(s: &mut Vec<u8>, offset: u64, bit: u8) {
+    let byte = (offset / 8) as usize;
+    if s.len() <= byte {
+        s.resize(byte + 1, 0);
+    }
+    let mask = 1 << (7 - offset % 8);
+    if bit == 1 {
+        s[byte] |= mask;
+    } else {
+        s[byte] &= !mask;
+    }
+}
+
+// SETBIT key offset 0|1, replies the previous bit
+fn setbit(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let offset = arg_bit_offset(&argv[2])?;
+    let bit = match argv[3].as_slice() {
+        b"0" => 0,
+        b"1" => 1,
+        _ => return Err(CommandError::Other("bit is not an integer or out of range".into())),
+    };
+    let mut db = ctx.write();
+    let s = string_mut(&mut db, &argv[1])?;
+    let old = get_bit(s, offset);
+    set_bit(s, offset, bit);
+    Ok(int(old))
+}
+
+fn getbit(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let offset = arg_bit_offset(&argv[2])?;
+    let db = ctx.read();
+    Ok(int(get_string(&db, &argv[1])?.map_or(0, |s| get_bit(s, offset))))
+}
+
+// A start / end range of BITCOUNT and BITPOS as inclusive bit offsets, in bytes
+// by default or in bits with BIT. None when the range is empty.
+fn bit_range(s: &[u8], start: i64, end: i64, unit: Option<&Vec<u8>>) -> Result<Option<(u64, u64)>, CommandError> {
+    let bits = match unit {
+        None => false,
+        Some(u) if eq_ignore_case(u, "byte") => false,
+        Some(u) if eq_ignore_case(u, "bit") => true,
+        Some(_) => return Err(CommandError::Syntax),
+    };
+    if bits {
+        return Ok(normalize_range(start, end, s.len() * 8).map(|(a, b)| (a as u64, b as u64)));
+    }
+    Ok(normalize_range(start, end, s.len()).map(|(a, b)| (a as u64 * 8, b as u64 * 8 + 7)))
+}
+
+fn count_ones(s: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    if first == last {
+        return (start..=end).map(|i| get_bit(s, i) as u64).sum();
+    }
+    let head: u64 = (start..(first as u64 + 1) * 8).map(|i| get_bit(s, i) as u64).sum();
+    let middle: u64 = s[first + 1..last].iter().map(|b| b.count_ones() as u64).sum();
+    let tail: u64 = (last as u64 * 8..=end).map(|i| get_bit(s, i) as u64).sum();
+    head + middle + tail
+}
+
+// BITCOUNT key [start end [BYTE|BIT]]
+fn bitcount(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let range = match &argv[2..] {
+        [] => None,
+        [start, end] => Some((arg_i64(start)?, arg_i64(end)?, None)),
+        [start, end, unit] => Some((arg_i64(start)?, arg_i64(end)?, Some(unit))),
+        _ => return Err(CommandError::Syntax),
+    };
+    let db = ctx.read();
+    let Some(s) = get_string(&db, &argv[1])? else {
+        return Ok(int(0));
+    };
+    let range = match range {
+        None => (!s.is_empty()).then(|| (0, s.len() as u64 * 8 - 1)),
+        Some((start, end, unit)) => bit_range(s, start, end, unit)?,
+    };
+    Ok(int(range.map_or(0, |(start, end)| count_ones(s, start, end))))
+}
+
+// BITPOS key 0|1 [start [end [BYTE|BIT]]]
+// Looking for a clear bit without an end finds the one right after the string
+// when every bit is set, like redis.
+fn bitpos(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let bit = match argv[2].as_slice() {
+        b"0" => 0,
+        b"1" => 1,
+        _ => return Err(CommandError::Other("The bit argument must be 1 or 0.".into())),
+    };
+    let (start, end, unit) = match &argv[3..] {
+        [] => (0, -1, None),
+        [start] => (arg_i64(start)?, -1, None),
+        [start, end] => (arg_i64(start)?, arg_i64(end)?, None),
+        [start, end, unit] => (arg_i64(start)?, arg_i64(end)?, Some(unit)),
+        _ => return Err(CommandError::Syntax),
+    };
+    let end_given = argv.len() > 4;
+    let db = ctx.read();
+    let s = get_string(&db, &argv[1])?.map_or(&[][..], |s| s.as_bytes());
+    if s.is_empty() {
+        return Ok(int(if bit == 0 { 0 } else { -1 }));
+    }
+    let Some((start, end)) = bit_range(s, start, end, unit)? else {
+        return Ok(int(-1));
+    };
+    match (start..=end).find(|i| get_bit(s, *i) == bit) {
+        Some(pos) => Ok(int(pos as i64)),
+        None if bit == 0 && !end_given => Ok(int(end as i64 + 1)),
+        None => Ok(int(-1)),
+    }
+}
+
+// BITOP AND|OR|XOR|NOT destkey key [key ...], replies the length of the result.
+// Missing keys are strings of zeros, shorter strings are padded with zeros.
+fn bitop(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let op = arg_str(&argv[1]);
+    let sources = &argv[3..];
+    if !matches!(op.as_str(), "and" | "or" | "xor" | "not") {
+        return Err(CommandError::Syntax);
+    }
+    if op == "not" && sources.len() != 1 {
+        return Err(CommandError::Other("BITOP NOT must be called with a single source key.".into()));
+    }
+
+    let mut db = ctx.write();
+    let values = sources
+        .iter()
+        .map(|key| get_string(&db, key).map(|s| s.map(|s| s.to_vec()).unwrap_or_default()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = values.iter().map(|v| v.len()).max().unwrap_or(0);
+    let byte = |v: &Vec<u8>, i: usize| v.get(i).copied().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut bytes = values.iter().map(|v| byte(v, i));
+            let first = bytes.next().unwrap_or(0);
+            match op.as_str() {
+                "and" => bytes.fold(first, |acc, b| acc & b),
+                "or" => bytes.fold(first, |acc, b| acc | b),
+                "xor" => bytes.fold(first, |acc, b| acc ^ b),
+                _ => !first,
+            }
+        })
+        .collect();
+    if result.is_empty() {
+        db.remove(&argv[2]);
+    } else {
+        db.set(argv[2].clone(), Value::String(result.into()));
+    }
+    Ok(int(len))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+// a BITFIELD integer type: i1..i64 or u1..u63
+#[derive(Debug, Clone, Copy)]
+struct FieldType {
+    signed: bool,
+    bits: u32,
+}
+
+impl FieldType {
+    fn parse(v: &[u8]) -> Result<Self, CommandError> {
+        let invalid = || {
+            CommandError::Other(
+                "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into(),
+            )
+        };
+        let (signed, bits) = match v {
+            [b'i' | b'I', bits @ ..] => (true, bits),
+            [b'u' | b'U', bits @ ..] => (false, bits),
+            _ => return Err(invalid()),
+        };
+        let bits: u32 = std::str::from_utf8(bits).ok().and_then(|b| b.parse().ok()).ok_or_else(invalid)?;
+        let max = if signed { 64 } else { 63 };
+        if bits == 0 || bits > max {
+            return Err(invalid());
+        }
+        Ok(Self { signed, bits })
+    }
+
+    fn min(&self) -> i128 {
+        if self.signed {
+            -(1i128 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed {
+            (1i128 << (self.bits - 1)) - 1
+        } else {
+            (1i128 << self.bits) - 1
+        }
+    }
+
+    // the value to store for `v` under the overflow mode, None when FAIL refuses it
+    fn fit(&self, v: i128, overflow: Overflow) -> Option<i64> {
+        if (self.min()..=self.max()).contains(&v) {
+            return Some(v as i64);
+        }
+        match overflow {
+            Overflow::Wrap => {
+                let span = 1i128 << self.bits;
+                Some(((v - self.min()).rem_euclid(span) + self.min()) as i64)
+            }
+            Overflow::Sat => Some(v.clamp(self.min(), self.max()) as i64),
+            Overflow::Fail => None,
+        }
+    }
+
+    fn read(&self, s: &[u8], offset: u64) -> i64 {
+        let raw = (0..self.bits as u64).fold(0u64, |acc, i| (acc << 1) | get_bit(s, offset + i) as u64);
+        if self.signed && self.bits < 64 && raw >> (self.bits - 1) & 1 == 1 {
+            // sign extend
+            (raw | (u64::MAX << self.bits)) as i64
+        } else {
+            raw as i64
+        }
+    }
+
+    fn write(&self, s: &mut Vec<u8>, offset: u64, v: i64) {
+        let raw = v as u64;
+        for i in 0..self.bits as u64 {
+            set_bit(s, offset + i, ((raw >> (self.bits as u64 - 1 - i)) & 1) as u8);
+        }
+    }
+}
+
+enum FieldOp {
+    Get(FieldType, u64),
+    Set(FieldType, u64, i64),
+    IncrBy(FieldType, u64, i64),
+    Overflow(Overflow),
+}
+
+// a BITFIELD offset: bits, or `#n` for the n-th field of the type's width
+fn field_offset(v: &[u8], ty: FieldType) -> Result<u64, CommandError> {
+    match v {
+        [b'#', n @ ..] => {
+            let n = arg_i64(n).map_err(|_| bit_offset_error())?;
+            let offset = (n as u64).checked_mul(ty.bits as u64).filter(|_| n >= 0).ok_or_else(bit_offset_error)?;
+            arg_bit_offset(offset.to_string().as_bytes())
+        }
+        _ => arg_bit_offset(v),
+    }
+}
+
+// BITFIELD key [GET type offset] [SET type offset value] [INCRBY type offset increment]
+//               [OVERFLOW WRAP|SAT|FAIL] ...
+fn bitfield(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut ops = Vec::new();
+    let mut i = 2;
+    while i < argv.len() {
+        let op = arg_str(&argv[i]);
+        let args = &argv[i + 1..];
+        match (op.as_str(), args) {
+            ("get", [ty, offset, ..]) => {
+                let ty = FieldType::parse(ty)?;
+                ops.push(FieldOp::Get(ty, field_offset(offset, ty)?));
+                i += 3;
+            }
+            ("set" | "incrby", [ty, offset, value, ..]) => {
+                let ty = FieldType::parse(ty)?;
+                let offset = field_offset(offset, ty)?;
+                let value = arg_i64(value)?;
+                ops.push(if op == "set" { FieldOp::Set(ty, offset, value) } else { FieldOp::IncrBy(ty, offset, value) });
+                i += 4;
+            }
+            ("overflow", [mode, ..]) => {
+                let mode = match arg_str(mode).as_str() {
+                    "wrap" => Overflow::Wrap,
+                    "sat" => Overflow::Sat,
+                    "fail" => Overflow::Fail,
+                    _ => return Err(CommandError::Other("Invalid OVERFLOW type specified".into())),
+                };
+                ops.push(FieldOp::Overflow(mode));
+                i += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let mut db = ctx.write();
+    let writes = ops.iter().any(|op| matches!(op, FieldOp::Set(..) | FieldOp::IncrBy(..)));
+    let mut empty = Vec::new();
+    let s = if writes {
+        string_mut(&mut db, &argv[1])?
+    } else {
+        match db.get_mut(&argv[1]) {
+            None => &mut empty,
+            Some(Value::String(s)) => s.make_raw(),
+            Some(_) => return Err(CommandError::WrongType),
+        }
+    };
+    let mut overflow = Overflow::Wrap;
+    let mut replies = Vec::new();
+    for op in ops {
+        match op {
+            FieldOp::Overflow(mode) => overflow = mode,
+            FieldOp::Get(ty, offset) => replies.push(int(ty.read(s, offset))),
+            FieldOp::Set(ty, offset, value) => {
+                let old = ty.read(s, offset);
+                match ty.fit(value as i128, overflow) {
+                    Some(v) => {
+                        ty.write(s, offset, v);
+                        replies.push(int(old));
+                    }
+                    None => replies.push(nil()),
+                }
+            }
+            FieldOp::IncrBy(ty, offset, incr) => {
+                let old = ty.read(s, offset);
+                match ty.fit(old as i128 + incr as i128, overflow) {
+                    Some(v) => {
+                        ty.write(s, offset, v);
+                        replies.push(int(v));
+                    }
+                    None => replies.push(nil()),
+                }
+            }
+        }
+    }
+    Ok(array(replies))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            array, bulk, error_frame, int, nil,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        resp::RespFrame,
+    };
+
+    #[test]
+    fn test_setbit_getbit_bitcount() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "setbit k 7 1"), int(0));
+        assert_eq!(run(&backend, &mut session, "setbit k 7 1"), int(1));
+        assert_eq!(run(&backend, &mut session, "get k"), bulk("\x01"));
+        // grows with zeros
+        assert_eq!(run(&backend, &mut session, "setbit k 17 1"), int(0));
+        assert_eq!(run(&backend, &mut session, "strlen k"), int(3));
+        assert_eq!(run(&backend, &mut session, "getbit k 17"), int(1));
+        assert_eq!(run(&backend, &mut session, "getbit k 100"), int(0));
+        assert!(matches!(run(&backend, &mut session, "setbit k -1 1"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "setbit k 1 2"), RespFrame::Error(_)));
+
+        run(&backend, &mut session, "set s foobar");
+        assert_eq!(run(&backend, &mut session, "bitcount s"), int(26));
+        assert_eq!(run(&backend, &mut session, "bitcount s 0 0"), int(4));
+        assert_eq!(run(&backend, &mut session, "bitcount s 1 1"), int(6));
+        assert_eq!(run(&backend, &mut session, "bitcount s 1 1 byte"), int(6));
+        assert_eq!(run(&backend, &mut session, "bitcount s 5 30 bit"), int(17));
+        assert_eq!(run(&backend, &mut session, "bitcount s -2 -1"), int(7));
+        assert_eq!(run(&backend, &mut session, "bitcount nope"), int(0));
+        assert_eq!(run(&backend, &mut session, "bitcount s 0 1 bits"), error_frame(CommandError::Syntax));
+    }
+
+    #[test]
+    fn test_bitpos() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        // \xff\xf0\x00
+        for i in 0..12 {
+            run(&backend, &mut session, &format!("setbit b {} 1", i));
+        }
+        run(&backend, &mut session, "setbit b 23 0");
+        assert_eq!(run(&backend, &mut session, "bitpos b 0"), int(12));
+        assert_eq!(run(&backend, &mut session, "bitpos b 1 2"), int(-1));
+        assert_eq!(run(&backend, &mut session, "bitpos b 1 1"), int(8));
+        assert_eq!(run(&backend, &mut session, "bitpos b 0 7 15 bit"), int(12));
+        assert_eq!(run(&backend, &mut session, "bitpos b 2"), error_frame(CommandError::Other("The bit argument must be 1 or 0.".into())));
+        assert_eq!(run(&backend, &mut session, "bitpos nope 0"), int(0));
+        assert_eq!(run(&backend, &mut session, "bitpos nope 1"), int(-1));
+    }
+
+    #[test]
+    fn test_bitpos_all_set() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        for i in 0..16 {
+            run(&backend, &mut session, &format!("setbit k {} 1", i));
+        }
+        // past the end without an end argument, -1 with one
+        assert_eq!(run(&backend, &mut session, "bitpos k 0"), int(16));
+        assert_eq!(run(&backend, &mut session, "bitpos k 0 0 -1"), int(-1));
+    }
+
+    #[test]
+    fn test_bitop() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a abc");
+        run(&backend, &mut session, "set b a");
+        assert_eq!(run(&backend, &mut session, "bitop and dest a b"), int(3));
+        assert_eq!(run(&backend, &mut session, "get dest"), bulk(b"a\x00\x00".to_vec()));
+        assert_eq!(run(&backend, &mut session, "bitop or dest a b nope"), int(3));
+        assert_eq!(run(&backend, &mut session, "get dest"), bulk("abc"));
+        assert_eq!(run(&backend, &mut session, "bitop xor dest a a"), int(3));
+        assert_eq!(run(&backend, &mut session, "get dest"), bulk(vec![0u8; 3]));
+        assert_eq!(run(&backend, &mut session, "bitop not dest b"), int(1));
+        assert_eq!(run(&backend, &mut session, "get dest"), bulk(vec![!b'a']));
+        assert!(matches!(run(&backend, &mut session, "bitop not dest a b"), RespFrame::Error(_)));
+        // nothing to combine, the destination goes away
+        assert_eq!(run(&backend, &mut session, "bitop or dest nope"), int(0));
+        assert_eq!(run(&backend, &mut session, "exists dest"), int(0));
+    }
+
+    #[test]
+    fn test_bitfield() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "bitfield k get u8 0"), array([int(0)]));
+        assert_eq!(run(&backend, &mut session, "exists k"), int(0));
+        assert_eq!(
+            run(&backend, &mut session, "bitfield k set i8 0 -100 get u8 0 get i4 #1"),
+            array([int(0), int(156), int(-4)])
+        );
+        assert_eq!(run(&backend, &mut session, "bitfield k incrby i8 0 -100"), array([int(56)]));
+        assert_eq!(
+            run(&backend, &mut session, "bitfield k overflow sat incrby i8 0 -200 overflow fail incrby i8 0 -1 incrby u2 100 1"),
+            array([int(-128), nil(), int(1)])
+        );
+        assert_eq!(run(&backend, &mut session, "bitfield k overflow sat set u4 #0 99"), array([int(8)]));
+        assert_eq!(run(&backend, &mut session, "bitfield k get u4 0"), array([int(15)]));
+        assert_eq!(
+            run(&backend, &mut session, "bitfield k set i64 0 -1 get i64 0 get u63 1"),
+            array([int(-(1i64 << 60)), int(-1), int(i64::MAX)])
+        );
+        assert!(matches!(run(&backend, &mut session, "bitfield k get u64 0"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "bitfield k overflow nope"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut session, "bitfield k get i8"), error_frame(CommandError::Syntax));
+    }
+}
@@ -0,0 +1,345 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    connections::{Conn, ConnFlags, KillFilter},
+    tracking::TrackingOptions,
+};
+
+use super::{
+    arg_i64, bulk, bulk_array, eq_ignore_case, int, map_reply, nil, ok, CmdResult, CommandError,
+    CommandSpec, Context, NOSCRIPT, NO_KEYS,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[CommandSpec::new("client", -2, NOSCRIPT, NO_KEYS, client)];
+
+fn this_conn(ctx: &Context) -> Result<Arc<Conn>, CommandError> {
+    ctx.session.conn.clone().ok_or_else(|| CommandError::Other("no client connection".into()))
+}
+
+fn list_reply(conns: impl IntoIterator<Item = Arc<Conn>>) -> super::RespFrame {
+    bulk(conns.into_iter().map(|c| c.describe() + "\n").collect::<String>())
+}
+
+// CLIENT ID | INFO | LIST | SETNAME | GETNAME | KILL | PAUSE | UNPAUSE | TRACKING | CACHING |
+// GETREDIR | TRACKINGINFO
+fn client(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = String::from_utf8_lossy(&argv[1]).to_ascii_lowercase();
+    let args = &argv[2..];
+    let connections = &ctx.backend.connections;
+    match (sub.as_str(), args) {
+        ("id", []) => Ok(int(this_conn(ctx)?.id)),
+        ("info", []) => Ok(bulk(this_conn(ctx)?.describe() + "\n")),
+        ("getname", []) => Ok(this_conn(ctx)?.state().name.clone().map(bulk).unwrap_or_else(nil)),
+        ("setname", [name]) => set_name(ctx, name),
+        ("list", args) => client_list(ctx, args),
+        ("kill", args) => client_kill(ctx, args),
+        // CLIENT PAUSE timeout [WRITE | ALL]
+        ("pause", [timeout, mode @ ..]) => {
+            let timeout = arg_i64(timeout)
+                .ok()
+                .filter(|t| *t >= 0)
+                .ok_or_else(|| CommandError::Other("timeout is not an integer or out of range".into()))?;
+            let writes_only = match mode {
+                [] => false,
+                [m] if eq_ignore_case(m, "all") => false,
+                [m] if eq_ignore_case(m, "write") => true,
+                _ => return Err(CommandError::Syntax),
+            };
+            connections.pause(Duration::from_millis(timeout as u64), writes_only);
+            Ok(ok())
+        }
+        ("unpause", []) => {
+            connections.unpause();
+            Ok(ok())
+        }
+        ("tracking", [switch, options @ ..]) => client_tracking(ctx, switch, options),
+        // CLIENT CACHING yes | no
+        ("caching", [value]) => {
+            let value = match String::from_utf8_lossy(value).to_ascii_lowercase().as_str() {
+                "yes" => true,
+                "no" => false,
+                _ => return Err(CommandError::Syntax),
+            };
+            match &ctx.session.tracking {
+                Some(t) if t.optin && value || t.optout && !value => {
+                    ctx.session.caching = Some(value);
+                    Ok(ok())
+                }
+                Some(t) if t.optin || t.optout => Err(CommandError::Other(format!(
+                    "CLIENT CACHING {} is only valid when tracking is enabled in {} mode.",
+                    if value { "YES" } else { "NO" },
+                    if value { "OPTIN" } else { "OPTOUT" },
+                ))),
+                _ => Err(CommandError::Other(
+                    "CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled".into(),
+                )),
+            }
+        }
+        // -1 without tracking, 0 without a redirection
+        ("getredir", []) => Ok(int(match &ctx.session.tracking {
+            Some(t) => t.redirect.map_or(0, |id| id as i64),
+            None => -1,
+        })),
+        ("trackinginfo", []) => {
+            let tracking = ctx.session.tracking.clone();
+            let flags = match &tracking {
+                None => vec!["off"],
+                Some(t) => [(true, "on"), (t.bcast, "bcast"), (t.optin, "optin"), (t.optout, "optout"), (t.noloop, "noloop")]
+                    .into_iter()
+                    .filter_map(|(set, flag)| set.then_some(flag))
+                    .collect(),
+            };
+            let redirect = tracking.as_ref().map_or(-1, |t| t.redirect.map_or(0, |id| id as i64));
+            let prefixes = tracking.map(|t| t.prefixes).unwrap_or_default();
+            Ok(map_reply(
+                ctx.session,
+                [("flags", bulk_array(flags)), ("redirect", int(redirect)), ("prefixes", bulk_array(prefixes))],
+            ))
+        }
+        ("id" | "info" | "getname" | "setname" | "pause" | "unpause" | "tracking" | "caching" | "getredir"
+        | "trackinginfo", _) => {
+            Err(CommandError::WrongArity(format!("client|{}", sub)))
+        }
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'", sub))),
+    }
+}
+
+// CLIENT SETNAME and HELLO SETNAME, an empty name removes it
+pub fn set_name(ctx: &Context, name: &[u8]) -> CmdResult {
+    if name.iter().any(|&b| b <= b' ' || b > b'~') {
+        return Err(CommandError::Other("Client names cannot contain spaces, newlines or special characters.".into()));
+    }
+    let name = (!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned());
+    this_conn(ctx)?.state().name = name;
+    Ok(ok())
+}
+
+// CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT] [NOLOOP]
+fn client_tracking(ctx: &mut Context, switch: &[u8], args: &[Vec<u8>]) -> CmdResult {
+    let id = this_conn(ctx)?.id;
+    let tracking = &ctx.backend.tracking;
+    if eq_ignore_case(switch, "off") {
+        if !args.is_empty() {
+            return Err(CommandError::Syntax);
+        }
+        tracking.disable(id);
+        ctx.session.tracking = None;
+        ctx.session.caching = None;
+        return Ok(ok());
+    }
+    if !eq_ignore_case(switch, "on") {
+        return Err(CommandError::Syntax);
+    }
+    let mut options = TrackingOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match String::from_utf8_lossy(arg).to_ascii_lowercase().as_str() {
+            "redirect" => {
+                let target = args.next().ok_or(CommandError::Syntax)?;
+                let target = arg_i64(target).ok().filter(|t| *t > 0).map(|t| t as u64);
+                match target {
+                    Some(target) if tracking.is_connected(target) => options.redirect = Some(target),
+                    _ => return Err(CommandError::Other("The client ID you want redirect to does not exist".into())),
+                }
+            }
+            "prefix" => options.prefixes.push(args.next().ok_or(CommandError::Syntax)?.clone()),
+            "bcast" => options.bcast = true,
+            "optin" => options.optin = true,
+            "optout" => options.optout = true,
+            "noloop" => options.noloop = true,
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    if !options.bcast && !options.prefixes.is_empty() {
+        return Err(CommandError::Other("PREFIX option requires BCAST mode to be enabled".into()));
+    }
+    if options.optin && options.optout {
+        return Err(CommandError::Other("You can't use both OPTIN and OPTOUT".into()));
+    }
+    if options.bcast && (options.optin || options.optout) {
+        return Err(CommandError::Other("OPTIN and OPTOUT are not compatible with BCAST".into()));
+    }
+    tracking.enable(id, options.clone());
+    ctx.session.tracking = Some(options);
+    Ok(ok())
+}
+
+// CLIENT LIST [TYPE normal|master|replica] [ID id [id ...]]
+fn client_list(ctx: &Context, args: &[Vec<u8>]) -> CmdResult {
+    let mut conns = ctx.backend.connections.list();
+    match args {
+        [] => {}
+        [opt, kind] if eq_ignore_case(opt, "type") => {
+            let flags = match String::from_utf8_lossy(kind).to_ascii_lowercase().as_str() {
+                "normal" => ConnFlags::Normal,
+                "master" => ConnFlags::Master,
+                "replica" | "slave" => ConnFlags::Replica,
+                kind => return Err(CommandError::Other(format!("Unknown client type '{}'", kind))),
+            };
+            conns.retain(|c| c.state().flags == flags);
+        }
+        [opt, ids @ ..] if eq_ignore_case(opt, "id") && !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|id| arg_i64(id).ok().filter(|id| *id > 0).map(|id| id as u64))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| CommandError::Other("Invalid client ID".into()))?;
+            conns.retain(|c| ids.contains(&c.id));
+        }
+        _ => return Err(CommandError::Syntax),
+    }
+    Ok(list_reply(conns))
+}
+
+// CLIENT KILL addr, or CLIENT KILL <filter> <value> ... with ID, ADDR, LADDR, USER and SKIPME
+fn client_kill(ctx: &Context, args: &[Vec<u8>]) -> CmdResult {
+    let connections = &ctx.backend.connections;
+    let me = ctx.session.conn.as_ref().map(|c| c.id);
+    if let [addr] = args {
+        let filter = KillFilter { addr: Some(String::from_utf8_lossy(addr).into_owned()), ..KillFilter::default() };
+        return match connections.kill(&filter) {
+            0 => Err(CommandError::Other("No such client".into())),
+            _ => Ok(ok()),
+        };
+    }
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    let mut filter = KillFilter { skip: me, ..KillFilter::default() };
+    for pair in args.chunks(2) {
+        let value = String::from_utf8_lossy(&pair[1]).into_owned();
+        match String::from_utf8_lossy(&pair[0]).to_ascii_lowercase().as_str() {
+            "id" => {
+                let id = arg_i64(&pair[1]).ok().filter(|id| *id > 0);
+                filter.id = Some(id.ok_or_else(|| CommandError::Other("client-id should be greater than 0".into()))? as u64);
+            }
+            "addr" => filter.addr = Some(value),
+            "laddr" => filter.laddr = Some(value),
+            "user" => filter.user = Some(value),
+            "skipme" => {
+                filter.skip = match value.to_ascii_lowercase().as_str() {
+                    "yes" => me,
+                    "no" => None,
+                    _ => return Err(CommandError::Syntax),
+                }
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    Ok(int(connections.kill(&filter)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cmd::{
+            array, bulk, bulk_array, error_frame, int, nil, ok,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        connections::ClientAddr,
+        pubsub::Subscriber,
+        resp::RespFrame,
+        tracking::{Invalidation, Invalidations},
+    };
+
+    fn connect(backend: &Backend, port: u16) -> Session {
+        let addr = ClientAddr::Tcp(format!("127.0.0.1:{}", port).parse().unwrap());
+        let conn = backend.connections.register(addr.clone(), None);
+        let invalidations = Some(Invalidations::new(backend, conn.id));
+        Session { addr: Some(addr), conn: Some(conn), invalidations, ..Session::default() }
+    }
+
+    fn list(backend: &Backend, session: &mut Session, line: &str) -> Vec<String> {
+        let RespFrame::BulkString(text) = run(backend, session, line) else { panic!("expect: bulk") };
+        String::from_utf8(text).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_client_commands() {
+        let backend = test_backend();
+        let mut a = connect(&backend, 5001);
+        let mut b = connect(&backend, 5002);
+        let a_id = a.conn.as_ref().unwrap().id;
+        let b_id = b.conn.as_ref().unwrap().id;
+
+        assert_eq!(run(&backend, &mut a, "client id"), int(a_id));
+        assert_eq!(run(&backend, &mut a, "client getname"), nil());
+        assert_eq!(run(&backend, &mut a, "client setname worker"), ok());
+        assert_eq!(run(&backend, &mut a, "client getname"), bulk("worker"));
+        assert!(matches!(run(&backend, &mut a, "client setname"), RespFrame::Error(_)));
+
+        let lines = list(&backend, &mut a, "client list");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("addr=127.0.0.1:5001") && lines[0].contains("name=worker"));
+        assert_eq!(list(&backend, &mut a, &format!("client list id {}", b_id)).len(), 1);
+        assert!(list(&backend, &mut a, "client info")[0].starts_with(&format!("id={} ", a_id)));
+
+        // the caller is skipped by default
+        assert_eq!(run(&backend, &mut a, "client kill user default"), int(1));
+        assert!(b.conn.as_ref().unwrap().is_killed());
+        assert_eq!(run(&backend, &mut b, "client kill 127.0.0.1:5001"), ok());
+        assert!(a.conn.as_ref().unwrap().is_killed());
+        assert_eq!(
+            run(&backend, &mut b, "client kill 127.0.0.1:9"),
+            error_frame(CommandError::Other("No such client".into()))
+        );
+        assert_eq!(run(&backend, &mut b, &format!("client kill id {} skipme no", b_id)), int(1));
+
+        assert_eq!(run(&backend, &mut a, "client pause 10 write"), ok());
+        assert_eq!(run(&backend, &mut a, "client unpause"), ok());
+        assert_eq!(run(&backend, &mut a, "client pause 10 some"), error_frame(CommandError::Syntax));
+    }
+
+    fn invalidated(session: &mut Session) -> Option<RespFrame> {
+        let invalidation: Invalidation = session.invalidations.as_mut()?.try_recv()?;
+        invalidation.frame(session)
+    }
+
+    #[test]
+    fn test_client_tracking() {
+        let backend = test_backend();
+        let mut a = connect(&backend, 5001);
+        let mut b = connect(&backend, 5002);
+
+        assert_eq!(run(&backend, &mut a, "client getredir"), int(-1));
+        assert!(matches!(run(&backend, &mut a, "client tracking on prefix x"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut a, "client tracking on redirect 999"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut a, "hello 3"), run(&backend, &mut a, "hello"));
+        assert_eq!(run(&backend, &mut a, "client tracking on"), ok());
+        assert_eq!(run(&backend, &mut a, "client getredir"), int(0));
+
+        run(&backend, &mut a, "mget k j");
+        run(&backend, &mut b, "set k 1");
+        let push = |keys: &[&str]| RespFrame::Push(vec![bulk("invalidate"), bulk_array(keys)]);
+        assert_eq!(invalidated(&mut a), Some(push(&["k"])));
+        run(&backend, &mut b, "set k 2");
+        assert_eq!(invalidated(&mut a), None);
+
+        // OPTIN only tracks the reads after CLIENT CACHING yes
+        assert_eq!(run(&backend, &mut a, "client tracking on optin"), ok());
+        run(&backend, &mut a, "get k");
+        run(&backend, &mut b, "del k");
+        assert_eq!(invalidated(&mut a), None);
+        assert_eq!(run(&backend, &mut a, "client caching yes"), ok());
+        run(&backend, &mut a, "get k");
+        run(&backend, &mut b, "set k 3");
+        assert_eq!(invalidated(&mut a), Some(push(&["k"])));
+
+        // a RESP2 connection gets them on __redis__:invalidate of another one
+        let mut subscriber = Subscriber::new(&backend);
+        subscriber.subscribe(b"__redis__:invalidate");
+        b.subscriber = Some(subscriber);
+        let b_id = b.conn.as_ref().unwrap().id;
+        assert_eq!(run(&backend, &mut a, &format!("client tracking on bcast prefix user: redirect {}", b_id)), ok());
+        run(&backend, &mut a, "set user:1 x");
+        b.subscriber.as_mut().unwrap().try_recv();
+        assert_eq!(
+            invalidated(&mut b),
+            Some(array([bulk("message"), bulk("__redis__:invalidate"), bulk_array(["user:1"])]))
+        );
+        assert_eq!(run(&backend, &mut a, "client tracking off"), ok());
+        assert_eq!(run(&backend, &mut a, "client getredir"), int(-1));
+    }
+}
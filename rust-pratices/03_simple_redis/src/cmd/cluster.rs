@@ -0,0 +1,375 @@
+use crate::{
+    backend::Backend,
+    cluster::{key_hash_slot, Cluster, Node, SlotAction, SLOTS},
+};
+
+use super::{
+    arg_i64, arg_usize, array, bulk, bulk_array, int, lookup, ok, CmdResult, CommandError,
+    CommandSpec, Context, Session, ADMIN, FAST, NOSCRIPT, NO_KEYS,
+};
+
+// the subcommands that change the slot layout are admin ones, like CONFIG
+const SLOT_ADMIN: u32 = ADMIN | NOSCRIPT;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("cluster", -2, 0, NO_KEYS, cluster).with_subcommand_flags(&[
+        ("addslots", SLOT_ADMIN),
+        ("addslotsrange", SLOT_ADMIN),
+        ("delslots", SLOT_ADMIN),
+        ("delslotsrange", SLOT_ADMIN),
+        ("setslot", SLOT_ADMIN),
+        ("saveconfig", SLOT_ADMIN),
+    ]),
+    CommandSpec::new("asking", 1, FAST, NO_KEYS, asking),
+];
+
+// Where a command has to run, like redis getNodeByQuery: all its keys must hash to one
+// slot, and that slot must be served here, or be imported here after ASKING.
+// EXEC is checked with the keys of every queued command.
+pub fn check_redirect(
+    backend: &Backend,
+    session: &Session,
+    spec: &CommandSpec,
+    argv: &[Vec<u8>],
+    asking: bool,
+) -> Result<(), CommandError> {
+    let Some(cluster) = backend.cluster.as_ref() else {
+        return Ok(());
+    };
+    // the replication stream is applied as it comes
+    if session.is_master {
+        return Ok(());
+    }
+    let keys: Vec<&[u8]> = if spec.name == "exec" {
+        let queued = session.multi.iter().flatten();
+        queued.filter_map(|argv| Some(lookup(&argv[0])?.keys(argv))).flatten().collect()
+    } else {
+        spec.keys(argv)
+    };
+    let Some(first) = keys.first() else {
+        return Ok(());
+    };
+    let slot = key_hash_slot(first);
+    if keys.iter().any(|k| key_hash_slot(k) != slot) {
+        return Err(CommandError::CrossSlot);
+    }
+
+    let owner = cluster.owner(slot).ok_or(CommandError::ClusterDown)?;
+    if owner.id != cluster.myself().id {
+        if asking && cluster.importing(slot).is_some() {
+            return Ok(());
+        }
+        return Err(CommandError::Moved(slot, owner.addr()));
+    }
+    // while a slot moves away, keys not here anymore are answered by the target
+    if let Some(target) = cluster.migrating(slot) {
+        let db = backend.read_shards(session.db, Some(&backend.shards_of(&keys)));
+        let present = keys.iter().filter(|k| db.contains(k)).count();
+        if present == 0 {
+            return Err(CommandError::Ask(slot, target.addr()));
+        }
+        if present < keys.len() {
+            return Err(CommandError::TryAgain);
+        }
+    }
+    Ok(())
+}
+
+fn asking(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    if ctx.backend.cluster.is_none() {
+        return Err(CommandError::Other("This instance has cluster support disabled".into()));
+    }
+    ctx.session.asking = true;
+    Ok(ok())
+}
+
+fn arg_slot(v: &[u8]) -> Result<u16, CommandError> {
+    match arg_i64(v) {
+        Ok(slot) if (0..SLOTS as i64).contains(&slot) => Ok(slot as u16),
+        _ => Err(CommandError::Other("Invalid or out of range slot".into())),
+    }
+}
+
+// ADDSLOTSRANGE / DELSLOTSRANGE arguments: start end [start end ...]
+fn arg_slot_ranges(args: &[Vec<u8>]) -> Result<Vec<u16>, CommandError> {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    let mut slots = Vec::new();
+    for pair in args.chunks(2) {
+        let (start, end) = (arg_slot(&pair[0])?, arg_slot(&pair[1])?);
+        if start > end {
+            return Err(CommandError::Other(format!("start slot number {} is greater than end slot number {}", start, end)));
+        }
+        slots.extend(start..=end);
+    }
+    Ok(slots)
+}
+
+fn node_frame(node: &Node) -> super::RespFrame {
+    array([bulk(node.host.clone()), int(node.port), bulk(node.id.clone())])
+}
+
+// CLUSTER <subcommand> [args...]
+fn cluster(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = String::from_utf8_lossy(&argv[1]).to_ascii_lowercase();
+    // KEYSLOT is plain arithmetic, it's answered with cluster support disabled too
+    if sub == "keyslot" {
+        let [_, _, key] = argv else { return Err(CommandError::WrongArity("cluster|keyslot".into())) };
+        return Ok(int(key_hash_slot(key)));
+    }
+    let Some(cluster) = ctx.backend.cluster.as_ref() else {
+        return Err(CommandError::Other("This instance has cluster support disabled".into()));
+    };
+    let args = &argv[2..];
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(CommandError::WrongArity(format!("cluster|{}", sub)))
+        }
+    };
+    let to_err = CommandError::Other;
+
+    match sub.as_str() {
+        "myid" => {
+            arity(0)?;
+            Ok(bulk(cluster.myself().id))
+        }
+        "info" => {
+            arity(0)?;
+            Ok(bulk(cluster_info(cluster)))
+        }
+        "nodes" => {
+            arity(0)?;
+            Ok(bulk(cluster_nodes(cluster)))
+        }
+        "slots" => {
+            arity(0)?;
+            Ok(array(cluster.slot_ranges().iter().map(|(start, end, node)| {
+                array([int(*start), int(*end), node_frame(node)])
+            })))
+        }
+        "shards" => {
+            arity(0)?;
+            Ok(cluster_shards(cluster))
+        }
+        "countkeysinslot" => {
+            arity(1)?;
+            let slot = arg_slot(&args[0])?;
+            let db = ctx.backend.read(0);
+            Ok(int(db.keys().filter(|k| key_hash_slot(k) == slot).count()))
+        }
+        "getkeysinslot" => {
+            arity(2)?;
+            let (slot, count) = (arg_slot(&args[0])?, arg_usize(&args[1])?);
+            let db = ctx.backend.read(0);
+            Ok(bulk_array(db.keys().filter(|k| key_hash_slot(k) == slot).take(count)))
+        }
+        "addslots" | "delslots" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongArity(format!("cluster|{}", sub)));
+            }
+            let slots = args.iter().map(|s| arg_slot(s)).collect::<Result<Vec<_>, _>>()?;
+            if sub == "addslots" { cluster.add_slots(&slots) } else { cluster.del_slots(&slots) }.map_err(to_err)?;
+            Ok(ok())
+        }
+        "addslotsrange" | "delslotsrange" => {
+            let slots = arg_slot_ranges(args)?;
+            if sub == "addslotsrange" { cluster.add_slots(&slots) } else { cluster.del_slots(&slots) }.map_err(to_err)?;
+            Ok(ok())
+        }
+        // SETSLOT slot MIGRATING|IMPORTING|NODE node-id | STABLE
+        "setslot" => {
+            let slot = arg_slot(args.first().ok_or(CommandError::Syntax)?)?;
+            let id = || {
+                args.get(2).map(|id| String::from_utf8_lossy(id).into_owned()).ok_or(CommandError::Syntax)
+            };
+            let action = match args.get(1).map(|a| String::from_utf8_lossy(a).to_ascii_lowercase()).as_deref() {
+                Some("migrating") => SlotAction::Migrating(id()?),
+                Some("importing") => SlotAction::Importing(id()?),
+                Some("node") => SlotAction::Node(id()?),
+                Some("stable") => SlotAction::Stable,
+                _ => return Err(CommandError::Syntax),
+            };
+            cluster.set_slot(slot, action).map_err(to_err)?;
+            Ok(ok())
+        }
+        "saveconfig" => {
+            arity(0)?;
+            let path = ctx.backend.config().cluster_config_path().ok_or_else(|| to_err("no cluster config file".into()))?;
+            cluster.save(&path).map_err(|e| to_err(e.to_string()))?;
+            Ok(ok())
+        }
+        // the nodes are the ones of the layout file, there is no handshake to add one
+        "meet" | "forget" => Err(to_err(format!(
+            "CLUSTER {} is not supported, the cluster nodes are listed in the cluster config file",
+            sub.to_ascii_uppercase()
+        ))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'", sub))),
+    }
+}
+
+fn cluster_info(cluster: &Cluster) -> String {
+    let assigned = cluster.assigned_slots();
+    let ranges = cluster.slot_ranges();
+    let mut owners: Vec<&str> = ranges.iter().map(|(_, _, node)| node.id.as_str()).collect();
+    owners.sort();
+    owners.dedup();
+    let state = if assigned == SLOTS { "ok" } else { "fail" };
+    [
+        "cluster_enabled:1".to_string(),
+        format!("cluster_state:{}", state),
+        format!("cluster_slots_assigned:{}", assigned),
+        format!("cluster_slots_ok:{}", assigned),
+        format!("cluster_known_nodes:{}", cluster.nodes().len()),
+        format!("cluster_size:{}", owners.len()),
+    ]
+    .iter()
+    .map(|l| format!("{}\r\n", l))
+    .collect()
+}
+
+// one line per node like redis CLUSTER NODES:
+// <id> <ip:port@cport> <flags> <master> <ping-sent> <pong-recv> <epoch> <link-state> <slot>...
+fn cluster_nodes(cluster: &Cluster) -> String {
+    let myself = cluster.myself();
+    let ranges = cluster.slot_ranges();
+    let mut out = String::new();
+    for node in cluster.nodes() {
+        let flags = if node.id == myself.id { "myself,master" } else { "master" };
+        out.push_str(&format!(
+            "{} {}@{} {} - 0 0 0 connected",
+            node.id,
+            node.addr(),
+            node.port as u32 + 10000,
+            flags
+        ));
+        for (start, end, _) in ranges.iter().filter(|(_, _, owner)| owner.id == node.id) {
+            out.push_str(&if start == end { format!(" {}", start) } else { format!(" {}-{}", start, end) });
+        }
+        for (slot, target) in (0..SLOTS as u16).filter_map(|s| Some((s, cluster.migrating(s)?))) {
+            if node.id == myself.id {
+                out.push_str(&format!(" [{}->-{}]", slot, target.id));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// CLUSTER SHARDS in its RESP2 shape: a list of flat key / value arrays
+fn cluster_shards(cluster: &Cluster) -> super::RespFrame {
+    let ranges = cluster.slot_ranges();
+    array(cluster.nodes().into_iter().map(|node| {
+        let slots = ranges
+            .iter()
+            .filter(|(_, _, owner)| owner.id == node.id)
+            .flat_map(|(start, end, _)| [int(*start), int(*end)]);
+        array([
+            bulk("slots"),
+            array(slots),
+            bulk("nodes"),
+            array([array([
+                bulk("id"),
+                bulk(node.id.clone()),
+                bulk("port"),
+                int(node.port),
+                bulk("ip"),
+                bulk(node.host.clone()),
+                bulk("endpoint"),
+                bulk(node.host.clone()),
+                bulk("role"),
+                bulk("master"),
+                bulk("replication-offset"),
+                int(0),
+                bulk("health"),
+                bulk("online"),
+            ])]),
+        ])
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cluster::Cluster,
+        cmd::{bulk, error_frame, execute_argv, int, nil, ok, tests::run, CommandError, Session},
+        config::Config,
+        resp::{RespFrame, SimpleError},
+    };
+
+    fn cluster_backend() -> Backend {
+        let layout = "a 127.0.0.1:7000 0-8191\nb 127.0.0.1:7001 8192-16383";
+        Backend::with_cluster(Config::default(), Cluster::parse(layout, 7000).unwrap())
+    }
+
+    #[test]
+    fn test_redirects() {
+        let backend = cluster_backend();
+        let mut session = Session::new();
+        // foo is in slot 12182, bar in 5061
+        assert_eq!(run(&backend, &mut session, "set bar 1"), ok());
+        assert_eq!(run(&backend, &mut session, "get foo"), error_frame(CommandError::Moved(12182, "127.0.0.1:7001".into())));
+        assert_eq!(run(&backend, &mut session, "mget foo bar"), error_frame(CommandError::CrossSlot));
+        assert_eq!(run(&backend, &mut session, "mget {bar}a {bar}b"), RespFrame::Array(vec![nil(), nil()]));
+        assert_eq!(run(&backend, &mut session, "select 1"), error_frame(CommandError::Other("SELECT is not allowed in cluster mode".into())));
+
+        // moving slot 5061 to b: missing keys are asked there, existing ones stay here
+        assert_eq!(run(&backend, &mut session, "cluster setslot 5061 migrating b"), ok());
+        assert_eq!(run(&backend, &mut session, "get bar"), bulk("1"));
+        assert_eq!(run(&backend, &mut session, "del bar"), int(1));
+        assert_eq!(run(&backend, &mut session, "get bar"), error_frame(CommandError::Ask(5061, "127.0.0.1:7001".into())));
+
+        // b owns foo's slot but a imports it: only after ASKING
+        assert_eq!(run(&backend, &mut session, "cluster setslot 12182 importing b"), ok());
+        assert_eq!(run(&backend, &mut session, "asking"), ok());
+        assert_eq!(run(&backend, &mut session, "get foo"), nil());
+        assert!(matches!(run(&backend, &mut session, "get foo"), RespFrame::Error(_)));
+    }
+
+    #[test]
+    fn test_cluster_commands() {
+        let backend = cluster_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "cluster keyslot foo"), int(12182));
+        assert_eq!(run(&backend, &mut session, "cluster myid"), bulk("a"));
+        assert_eq!(
+            run(&backend, &mut session, "cluster slots"),
+            RespFrame::Array(vec![
+                RespFrame::Array(vec![int(0), int(8191), RespFrame::Array(vec![bulk("127.0.0.1"), int(7000), bulk("a")])]),
+                RespFrame::Array(vec![int(8192), int(16383), RespFrame::Array(vec![bulk("127.0.0.1"), int(7001), bulk("b")])]),
+            ])
+        );
+        run(&backend, &mut session, "set bar 1");
+        assert_eq!(run(&backend, &mut session, "cluster countkeysinslot 5061"), int(1));
+        assert_eq!(run(&backend, &mut session, "cluster delslotsrange 0 99"), ok());
+        assert_eq!(run(&backend, &mut session, "cluster addslots 5"), ok());
+        assert!(matches!(run(&backend, &mut session, "cluster addslots 5"), RespFrame::Error(_)));
+        let RespFrame::BulkString(info) = run(&backend, &mut session, "cluster info") else { panic!("expect: bulk") };
+        assert!(String::from_utf8(info).unwrap().contains("cluster_slots_assigned:16285"));
+
+        assert!(matches!(run(&backend, &mut session, "cluster meet 127.0.0.1 7002"), RespFrame::Error(_)));
+
+        let plain = crate::cmd::tests::test_backend();
+        assert_eq!(run(&plain, &mut session, "cluster keyslot foo"), int(12182));
+        assert!(matches!(run(&plain, &mut session, "cluster info"), RespFrame::Error(_)));
+    }
+
+    #[test]
+    fn test_slot_changes_not_from_script() {
+        let backend = cluster_backend();
+        let mut session = Session::new();
+        let eval = |session: &mut Session, script: &str| {
+            execute_argv(&backend, session, vec![b"eval".to_vec(), script.as_bytes().to_vec(), b"0".to_vec()])
+        };
+        let denied = RespFrame::Error(SimpleError::new("ERR This Redis command is not allowed from script"));
+        assert_eq!(eval(&mut session, "return redis.call('cluster', 'delslots', 5)"), denied);
+        assert_eq!(eval(&mut session, "return redis.call('CLUSTER', 'SetSlot', 5, 'node', 'b')"), denied);
+        assert_eq!(eval(&mut session, "return redis.call('cluster', 'saveconfig')"), denied);
+        assert_eq!(eval(&mut session, "return redis.call('cluster', 'myid')"), bulk("a"));
+        let RespFrame::BulkString(info) = run(&backend, &mut session, "cluster info") else { panic!("expect: bulk") };
+        assert!(String::from_utf8(info).unwrap().contains("cluster_slots_assigned:16384"));
+    }
+}
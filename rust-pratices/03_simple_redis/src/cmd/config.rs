@@ -0,0 +1,461 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use clap::ValueEnum;
+
+use crate::{
+    acl::DEFAULT_USER,
+    backend::Backend,
+    config::{parse_save, quote_value, Config},
+    logging,
+    pubsub::{format_events, parse_events},
+    utils::{glob_match_nocase, parse_memory},
+};
+
+use super::{
+    arg_str, bulk, eq_ignore_case, help_reply, ok, CmdResult, CommandError, CommandSpec, Context, RespFrame, ADMIN, NOSCRIPT,
+    NO_KEYS,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[CommandSpec::new("config", -2, ADMIN | NOSCRIPT, NO_KEYS, config)];
+
+type Setter = fn(&Backend, &str) -> Result<(), String>;
+
+// A parameter CONFIG GET shows, the ones with a setter can be changed at runtime.
+// The names are the command line / config file options.
+struct Param {
+    name: &'static str,
+    get: fn(&Backend) -> String,
+    set: Option<Setter>,
+}
+
+// the name of a setting value as CONFIG GET, INFO and the config file show it
+pub(crate) fn enum_name(v: impl ValueEnum) -> String {
+    v.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
+
+fn yes_no(v: bool) -> String {
+    if v { "yes" } else { "no" }.into()
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| "argument couldn't be parsed into an integer".into())
+}
+
+const PARAMS: &[Param] = &[
+    Param { name: "bind", get: |b| b.config().bind.clone(), set: None },
+    Param { name: "port", get: |b| b.config().port.to_string(), set: None },
+    Param {
+        name: "unixsocket",
+        get: |b| b.config().unixsocket.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+        set: None,
+    },
+    Param {
+        name: "unixsocketperm",
+        get: |b| b.config().unixsocketperm.map(|perm| format!("{:o}", perm)).unwrap_or_else(|| "0".into()),
+        set: None,
+    },
+    Param { name: "databases", get: |b| b.config().databases.to_string(), set: None },
+    Param { name: "keyspace-shards", get: |b| b.config().keyspace_shards.to_string(), set: None },
+    Param {
+        name: "dir",
+        get: |b| b.config().dir.display().to_string(),
+        set: Some(|b, value| {
+            if !Path::new(value).is_dir() {
+                return Err(format!("No such directory: {}", value));
+            }
+            b.update_config(|c| c.dir = value.into());
+            Ok(())
+        }),
+    },
+    Param {
+        name: "dbfilename",
+        get: |b| b.config().dbfilename.clone(),
+        set: Some(|b, value| {
+            if value.is_empty() || value.contains('/') {
+                return Err("dbfilename can't be a path, just a filename".into());
+            }
+            b.update_config(|c| c.dbfilename = value.into());
+            Ok(())
+        }),
+    },
+    Param {
+        name: "save",
+        get: |b| b.config().save.to_string(),
+        set: Some(|b, value| {
+            let save = parse_save(value)?;
+            b.update_config(|c| c.save = save);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory",
+        get: |b| b.config().maxmemory.to_string(),
+        set: Some(|b, value| {
+            let maxmemory = parse_memory(value)?;
+            b.update_config(|c| c.maxmemory = maxmemory);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory-policy",
+        get: |b| enum_name(b.config().maxmemory_policy),
+        set: Some(|b, value| {
+            let policy = ValueEnum::from_str(value, true)?;
+            b.update_config(|c| c.maxmemory_policy = policy);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory-samples",
+        get: |b| b.config().maxmemory_samples.to_string(),
+        set: Some(|b, value| {
+            let samples = parse_number(value)?;
+            if samples == 0 {
+                return Err("argument must be between 1 and 4294967295 inclusive".into());
+            }
+            b.update_config(|c| c.maxmemory_samples = samples);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        get: |b| b.config().proto_max_bulk_len.to_string(),
+        set: Some(|b, value| {
+            let len = parse_memory(value)?;
+            if len < 1024 * 1024 {
+                return Err("argument must be a memory value of at least 1mb".into());
+            }
+            b.update_config(|c| c.proto_max_bulk_len = len);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        get: |b| b.config().client_query_buffer_limit.to_string(),
+        set: Some(|b, value| {
+            let limit = parse_memory(value)?;
+            if limit < 1024 * 1024 {
+                return Err("argument must be a memory value of at least 1mb".into());
+            }
+            b.update_config(|c| c.client_query_buffer_limit = limit);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "replica-read-only",
+        get: |b| yes_no(b.config().replica_read_only),
+        set: Some(|b, value| {
+            let read_only = parse_yes_no(value)?;
+            b.update_config(|c| c.replica_read_only = read_only);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "replica-output-buffer-limit",
+        get: |b| b.repl.output_limit().to_string(),
+        set: Some(|b, value| {
+            b.repl.set_output_limit(parse_memory(value)? as usize);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "requirepass",
+        get: |b| b.config().requirepass.clone().unwrap_or_default(),
+        set: Some(|b, value| {
+            let password = if value.is_empty() { "nopass".into() } else { format!(">{}", value) };
+            b.acl.set_user(DEFAULT_USER, &["resetpass".into(), password])?;
+            b.update_config(|c| c.requirepass = (!value.is_empty()).then(|| value.into()));
+            Ok(())
+        }),
+    },
+    Param {
+        name: "masterauth",
+        get: |b| b.config().masterauth.clone().unwrap_or_default(),
+        set: Some(|b, value| {
+            b.update_config(|c| c.masterauth = (!value.is_empty()).then(|| value.into()));
+            Ok(())
+        }),
+    },
+    Param {
+        name: "masteruser",
+        get: |b| b.config().masteruser.clone().unwrap_or_default(),
+        set: Some(|b, value| {
+            b.update_config(|c| c.masteruser = (!value.is_empty()).then(|| value.into()));
+            Ok(())
+        }),
+    },
+    Param {
+        name: "loglevel",
+        get: |b| enum_name(b.config().loglevel),
+        set: Some(|b, value| {
+            let level = ValueEnum::from_str(value, true)?;
+            logging::set_level(level)?;
+            b.update_config(|c| c.loglevel = level);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "notify-keyspace-events",
+        get: |b| format_events(b.pubsub.notify_flags()),
+        set: Some(|b, value| {
+            let flags = parse_events(value).ok_or("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")?;
+            b.pubsub.set_notify_flags(flags);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        get: |b| b.slowlog.threshold().to_string(),
+        set: Some(|b, value| {
+            b.slowlog.set_threshold(parse_number(value)?);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "slowlog-max-len",
+        get: |b| b.slowlog.max_len().to_string(),
+        set: Some(|b, value| {
+            b.slowlog.set_max_len(parse_number(value)?);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        get: |b| b.latency.threshold().to_string(),
+        set: Some(|b, value| {
+            b.latency.set_threshold(parse_number(value)?);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "hash-max-listpack-entries",
+        get: |b| b.config().hash_max_listpack_entries.to_string(),
+        set: Some(|b, value| {
+            let n = parse_number(value)?;
+            b.update_config(|c| c.hash_max_listpack_entries = n);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "hash-max-listpack-value",
+        get: |b| b.config().hash_max_listpack_value.to_string(),
+        set: Some(|b, value| {
+            let n = parse_number(value)?;
+            b.update_config(|c| c.hash_max_listpack_value = n);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "set-max-intset-entries",
+        get: |b| b.config().set_max_intset_entries.to_string(),
+        set: Some(|b, value| {
+            let n = parse_number(value)?;
+            b.update_config(|c| c.set_max_intset_entries = n);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "set-max-listpack-entries",
+        get: |b| b.config().set_max_listpack_entries.to_string(),
+        set: Some(|b, value| {
+            let n = parse_number(value)?;
+            b.update_config(|c| c.set_max_listpack_entries = n);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "set-max-listpack-value",
+        get: |b| b.config().set_max_listpack_value.to_string(),
+        set: Some(|b, value| {
+            let n = parse_number(value)?;
+            b.update_config(|c| c.set_max_listpack_value = n);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "list-max-listpack-size",
+        get: |b| b.config().list_max_listpack_size.to_string(),
+        set: Some(|b, value| {
+            let size = parse_number(value)?;
+            if size < -5 {
+                return Err("argument must be between -5 and 9223372036854775807 inclusive".into());
+            }
+            b.update_config(|c| c.list_max_listpack_size = size);
+            Ok(())
+        }),
+    },
+];
+
+// Write the current settings back to the config file: the lines of known
+// parameters get their current value, the rest is kept as is, and the parameters
+// that differ from the defaults and weren't in the file are appended.
+fn rewrite(backend: &Backend) -> Result<(), String> {
+    let path = backend.config().config_file.clone().ok_or("The server is running without a config file")?;
+    let old = fs::read_to_string(&path).unwrap_or_default();
+    let line = |param: &Param| format!("{} {}", param.name, quote_value(&(param.get)(backend)));
+
+    let mut written = HashSet::new();
+    let mut lines = Vec::new();
+    for old_line in old.lines() {
+        let name = old_line.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        match PARAMS.iter().find(|p| p.name == name) {
+            // a parameter given several times is written once
+            Some(param) => {
+                if written.insert(param.name) {
+                    lines.push(line(param));
+                }
+            }
+            None => lines.push(old_line.to_string()),
+        }
+    }
+    let defaults = Backend::new(Config::default());
+    for param in PARAMS.iter().filter(|p| !written.contains(p.name)) {
+        if (param.get)(backend) != (param.get)(&defaults) {
+            lines.push(line(param));
+        }
+    }
+
+    let tmp = path.with_extension("tmp");
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(&tmp, text).and_then(|_| fs::rename(&tmp, &path)).map_err(|e| e.to_string())
+}
+
+fn set_failed(name: &str, reason: &str) -> CommandError {
+    CommandError::Other(format!("CONFIG SET failed (possibly related to argument '{}') - {}", name, reason))
+}
+
+const CONFIG_HELP: &[&str] = &[
+    "GET <pattern>",
+    "    Return parameters matching the glob-like <pattern> and their values.",
+    "SET <directive> <value>",
+    "    Set the configuration <directive> to <value>.",
+    "REWRITE",
+    "    Rewrite the configuration file.",
+];
+
+// CONFIG GET pattern [pattern ...] | SET parameter value [parameter value ...] | REWRITE
+fn config(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    let args = &argv[2..];
+    match sub.as_str() {
+        "get" if !args.is_empty() => {
+            let params = PARAMS.iter().filter(|p| args.iter().any(|pattern| glob_match_nocase(pattern, p.name.as_bytes())));
+            let pairs = params.flat_map(|p| [bulk(p.name), bulk((p.get)(ctx.backend))]);
+            Ok(RespFrame::Array(pairs.collect()))
+        }
+        "set" if !args.is_empty() && args.len().is_multiple_of(2) => {
+            // every parameter is checked before any is changed
+            let mut changes = Vec::new();
+            for pair in args.chunks(2) {
+                let name = String::from_utf8_lossy(&pair[0]).to_ascii_lowercase();
+                let param = PARAMS
+                    .iter()
+                    .find(|p| eq_ignore_case(&pair[0], p.name))
+                    .ok_or_else(|| set_failed(&name, "Unknown option or number of arguments"))?;
+                let set = param.set.ok_or_else(|| set_failed(&name, "can't set immutable config"))?;
+                changes.push((set, String::from_utf8_lossy(&pair[1]).into_owned(), name));
+            }
+            for (set, value, name) in changes {
+                set(ctx.backend, &value).map_err(|reason| set_failed(&name, &reason))?;
+            }
+            Ok(ok())
+        }
+        "rewrite" if args.is_empty() => {
+            rewrite(ctx.backend).map_err(|e| CommandError::Other(format!("Rewriting config file: {}", e)))?;
+            Ok(ok())
+        }
+        "help" if args.is_empty() => Ok(help_reply("CONFIG", CONFIG_HELP)),
+        "get" | "set" | "rewrite" => Err(CommandError::WrongArity(format!("config|{}", sub))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try CONFIG HELP.", sub))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cmd::{array, bulk, execute_argv, ok, simple, tests::run, Session},
+        config::{Config, MaxmemoryPolicy},
+        resp::RespFrame,
+    };
+
+    #[test]
+    fn test_config_get_set() {
+        let backend = Backend::default();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "config get port"), array([bulk("port"), bulk("6379")]));
+        assert_eq!(
+            run(&backend, &mut session, "config get maxmemory*"),
+            array([
+                bulk("maxmemory"),
+                bulk("0"),
+                bulk("maxmemory-policy"),
+                bulk("noeviction"),
+                bulk("maxmemory-samples"),
+                bulk("5"),
+            ])
+        );
+        assert_eq!(run(&backend, &mut session, "config set notify-keyspace-events Kx"), ok());
+        assert_eq!(run(&backend, &mut session, "config get notify*"), array([bulk("notify-keyspace-events"), bulk("xK")]));
+        assert!(matches!(run(&backend, &mut session, "config set notify-keyspace-events Kq"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "config set port 1"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut session, "config set slowlog-log-slower-than -1 slowlog-max-len 16"), ok());
+        assert_eq!(
+            run(&backend, &mut session, "config get slowlog*"),
+            array([bulk("slowlog-log-slower-than"), bulk("-1"), bulk("slowlog-max-len"), bulk("16")])
+        );
+        assert!(matches!(run(&backend, &mut session, "config set latency-monitor-threshold -5"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "config set nope 1"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "config help"), RespFrame::Array(help) if help.contains(&simple("REWRITE"))));
+    }
+
+    #[test]
+    fn test_config_set_live() {
+        let backend = Backend::default();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "config set maxmemory 1mb maxmemory-policy allkeys-lru"), ok());
+        assert_eq!(backend.config().maxmemory, 1 << 20);
+        assert_eq!(backend.config().maxmemory_policy, MaxmemoryPolicy::AllkeysLru);
+        assert!(matches!(run(&backend, &mut session, "config set maxmemory-policy nope"), RespFrame::Error(_)));
+        assert!(matches!(run(&backend, &mut session, "config set dbfilename a/b.srdb"), RespFrame::Error(_)));
+        let argv = ["config", "set", "save", "3600 1 60 1000"].map(|a| a.as_bytes().to_vec()).to_vec();
+        assert_eq!(execute_argv(&backend, &mut session, argv), ok());
+        assert_eq!(backend.config().save.0, vec![(3600, 1), (60, 1000)]);
+        assert_eq!(run(&backend, &mut session, "config set proto-max-bulk-len 2mb"), ok());
+        assert_eq!(backend.config().resp_limits().max_bulk_len, 2 << 20);
+        assert!(matches!(run(&backend, &mut session, "config set client-query-buffer-limit 1kb"), RespFrame::Error(_)));
+
+        assert!(!backend.acl.default_needs_auth());
+        assert_eq!(run(&backend, &mut session, "config set requirepass secret"), ok());
+        assert!(backend.acl.default_needs_auth());
+        assert!(backend.acl.authenticate("default", b"secret"));
+        assert_eq!(run(&backend, &mut session, "config get requirepass"), array([bulk("requirepass"), bulk("secret")]));
+    }
+
+    #[test]
+    fn test_config_rewrite() {
+        let path = std::env::temp_dir().join(format!("simple-redis-rewrite-{}.conf", std::process::id()));
+        std::fs::write(&path, "# my server\nport 7000\nmaxmemory 1mb\nmaxmemory 2mb\ntls-port 7001\n").unwrap();
+        let backend = Backend::new(Config { config_file: Some(path.clone()), port: 7000, ..Config::default() });
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "config set maxmemory 3mb notify-keyspace-events Ex"), ok());
+        assert_eq!(run(&backend, &mut session, "config rewrite"), ok());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text,
+            "# my server\nport 7000\nmaxmemory 3145728\ntls-port 7001\nnotify-keyspace-events xE\n"
+        );
+
+        assert!(matches!(run(&Backend::default(), &mut session, "config rewrite"), RespFrame::Error(_)));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::acl::DEFAULT_USER;
+
+use super::{
+    all_commands, arg_i64, array, bulk, client::set_name, eq_ignore_case, int, map_reply, ok, simple, CmdResult,
+    CommandError, CommandSpec, Context, ADMIN, DENYOOM, FAST, NOSCRIPT, NO_AUTH, NO_KEYS, NO_MULTI, READONLY,
+    WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("ping", -1, FAST, NO_KEYS, ping),
+    CommandSpec::new("echo", 2, FAST, NO_KEYS, echo),
+    CommandSpec::new("select", 2, FAST, NO_KEYS, select),
+    CommandSpec::new("quit", -1, FAST | NO_MULTI | NOSCRIPT | NO_AUTH, NO_KEYS, quit),
+    CommandSpec::new("auth", -2, FAST | NOSCRIPT | NO_AUTH, NO_KEYS, auth),
+    CommandSpec::new("hello", -1, FAST | NOSCRIPT | NO_AUTH, NO_KEYS, hello),
+    CommandSpec::new("command", -1, 0, NO_KEYS, command),
+];
+
+fn ping(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    // a subscribed connection gets pong like a message
+    if ctx.session.is_subscribed() {
+        let message = argv.get(1).cloned().unwrap_or_default();
+        return Ok(array([bulk("pong"), bulk(message)]));
+    }
+    match argv.len() {
+        1 => Ok(simple("PONG")),
+        2 => Ok(bulk(argv[1].clone())),
+        _ => Err(CommandError::WrongArity("ping".into())),
+    }
+}
+
+fn echo(_ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(bulk(argv[1].clone()))
+}
+
+fn select(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let index = arg_i64(&argv[1]).map_err(|_| CommandError::Other("invalid DB index".into()))?;
+    if index < 0 || index as usize >= ctx.backend.db_count() {
+        return Err(CommandError::DbIndexOutOfRange);
+    }
+    if index != 0 && ctx.backend.cluster.is_some() {
+        return Err(CommandError::Other("SELECT is not allowed in cluster mode".into()));
+    }
+    ctx.session.db = index as usize;
+    Ok(ok())
+}
+
+fn quit(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    ctx.session.closing = true;
+    Ok(ok())
+}
+
+// AUTH password | AUTH username password
+fn auth(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let acl = &ctx.backend.acl;
+    let (user, password) = match &argv[1..] {
+        [password] => {
+            if !acl.default_needs_auth() {
+                return Err(CommandError::Other(
+                    "AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into(),
+                ));
+            }
+            (DEFAULT_USER.to_string(), password)
+        }
+        [user, password] => (String::from_utf8_lossy(user).into_owned(), password),
+        _ => return Err(CommandError::Syntax),
+    };
+    if !acl.authenticate(&user, password) {
+        return Err(CommandError::WrongPass);
+    }
+    if let Some(conn) = &ctx.session.conn {
+        conn.state().user = user.clone();
+    }
+    ctx.session.user = Some(user);
+    ctx.session.authenticated = true;
+    Ok(ok())
+}
+
+// HELLO [protover [AUTH username password] [SETNAME name]]: picks RESP2 or RESP3 for the
+// connection and tells about the server
+fn hello(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let resp3 = match argv.get(1).map(|v| arg_i64(v)) {
+        None => ctx.session.resp3,
+        Some(Ok(2)) => false,
+        Some(Ok(3)) => true,
+        Some(Ok(_)) => return Err(CommandError::Raw("NOPROTO unsupported protocol version".into())),
+        Some(Err(_)) => return Err(CommandError::Other("Protocol version is not an integer or out of range".into())),
+    };
+    let (mut credentials, mut name) = (None, None);
+    let mut options = argv.iter().skip(2);
+    while let Some(option) = options.next() {
+        if eq_ignore_case(option, "auth") {
+            let user = options.next().ok_or(CommandError::Syntax)?;
+            credentials = Some((user, options.next().ok_or(CommandError::Syntax)?));
+        } else if eq_ignore_case(option, "setname") {
+            name = Some(options.next().ok_or(CommandError::Syntax)?);
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    if let Some((user, password)) = credentials {
+        auth(ctx, &[b"auth".to_vec(), user.clone(), password.clone()])?;
+    }
+    if ctx.session.user.is_some() && !ctx.session.authenticated {
+        return Err(CommandError::Raw(
+            "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into(),
+        ));
+    }
+    if let Some(name) = name {
+        set_name(ctx, name)?;
+    }
+    ctx.session.resp3 = resp3;
+    let backend = ctx.backend;
+    Ok(map_reply(
+        ctx.session,
+        [
+            ("server", bulk("redis")),
+            ("version", bulk(env!("CARGO_PKG_VERSION"))),
+            ("proto", int(if resp3 { 3 } else { 2 })),
+            ("id", int(ctx.session.client_id().unwrap_or(0))),
+            ("mode", bulk(if backend.cluster.is_some() { "cluster" } else { "standalone" })),
+            ("role", bulk(if backend.repl.is_replica() { "replica" } else { "master" })),
+            ("modules", array([])),
+        ],
+    ))
+}
+
+fn flag_names(spec: &CommandSpec) -> Vec<super::RespFrame> {
+    [
+        (WRITE, "write"),
+        (READONLY, "readonly"),
+        (DENYOOM, "denyoom"),
+        (ADMIN, "admin"),
+        (FAST, "fast"),
+        (NOSCRIPT, "noscript"),
+        (NO_AUTH, "no_auth"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| spec.has_flag(*flag))
+    .map(|(_, name)| simple(name))
+    .collect()
+}
+
+fn command_info(spec: &CommandSpec) -> super::RespFrame {
+    array([
+        bulk(spec.name),
+        int(spec.arity),
+        array(flag_names(spec)),
+        int(spec.first_key),
+        int(spec.last_key),
+        int(spec.step),
+    ])
+}
+
+// COMMAND [COUNT | INFO name... | DOCS]
+fn command(_ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = argv.get(1).map(|s| String::from_utf8_lossy(s).to_ascii_lowercase());
+    match sub.as_deref() {
+        None => Ok(array(all_commands().map(command_info))),
+        Some("count") => Ok(int(all_commands().count())),
+        Some("info") => Ok(array(argv[2..].iter().map(|name| match super::lookup(name) {
+            Some(spec) => command_info(spec),
+            None => super::nil_array(),
+        }))),
+        // redis-cli asks for docs at startup, it's fine to have none
+        Some("docs") => Ok(array([])),
+        Some(_) => Err(CommandError::Syntax),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        bulk, error_frame, ok, simple,
+        tests::{run, test_backend},
+        CommandError, Session,
+    };
+
+    #[test]
+    fn test_ping_echo() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "ping"), simple("PONG"));
+        assert_eq!(run(&backend, &mut session, "ping hi"), bulk("hi"));
+        assert_eq!(run(&backend, &mut session, "echo hey"), bulk("hey"));
+    }
+
+    #[test]
+    fn test_select() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "select 15"), ok());
+        assert_eq!(session.db, 15);
+        assert_eq!(run(&backend, &mut session, "select 16"), error_frame(CommandError::DbIndexOutOfRange));
+        assert_eq!(session.db, 15);
+
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "select 0");
+        assert_eq!(run(&backend, &mut session, "exists a"), crate::cmd::int(0));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::backend::monitor::Monitor;
+
+use super::{
+    arg_i64, arg_str, array, bulk, bulk_array, help_reply, int, ok, CmdResult, CommandError, CommandSpec, Context, ADMIN,
+    NOSCRIPT, NO_KEYS,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("monitor", 1, ADMIN | NOSCRIPT, NO_KEYS, monitor),
+    CommandSpec::new("slowlog", -2, ADMIN, NO_KEYS, slowlog),
+    CommandSpec::new("latency", -2, ADMIN | NOSCRIPT, NO_KEYS, latency),
+];
+
+// from now on the connection gets a line for every command, until it closes
+fn monitor(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    if ctx.session.conn.is_none() {
+        return Err(CommandError::Other("MONITOR needs a client connection".into()));
+    }
+    if ctx.session.monitor.is_none() {
+        ctx.session.monitor = Some(Monitor::new(&ctx.backend.monitors));
+    }
+    Ok(ok())
+}
+
+const SLOWLOG_HELP: &[&str] = &[
+    "GET [<count>]",
+    "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+    "    Entries are made of:",
+    "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+    "    client name",
+    "LEN",
+    "    Return the length of the slowlog.",
+    "RESET",
+    "    Reset the slowlog.",
+];
+
+const LATENCY_HELP: &[&str] = &[
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+];
+
+// SLOWLOG GET [count] | LEN | RESET
+fn slowlog(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    let slowlog = &ctx.backend.slowlog;
+    match (sub.as_str(), &argv[2..]) {
+        ("get", args @ ([] | [_])) => {
+            let count = match args.first().map(|c| arg_i64(c)).transpose()? {
+                None => Some(10),
+                Some(-1) => None,
+                Some(n) if n >= 0 => Some(n as usize),
+                Some(_) => return Err(CommandError::Other("count should be greater than or equal to -1".into())),
+            };
+            Ok(array(slowlog.get(count).into_iter().map(|entry| {
+                array([
+                    int(entry.id),
+                    int(entry.timestamp),
+                    int(entry.duration.as_micros() as i64),
+                    bulk_array(entry.argv),
+                    bulk(entry.addr),
+                    bulk(entry.name),
+                ])
+            })))
+        }
+        ("len", []) => Ok(int(slowlog.len())),
+        ("help", []) => Ok(help_reply("SLOWLOG", SLOWLOG_HELP)),
+        ("reset", []) => {
+            slowlog.reset();
+            Ok(ok())
+        }
+        ("get" | "len" | "reset", _) => Err(CommandError::WrongArity(format!("slowlog|{}", sub))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try SLOWLOG HELP.", sub))),
+    }
+}
+
+// LATENCY LATEST | HISTORY event | RESET [event ...]
+fn latency(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    let latency = &ctx.backend.latency;
+    match (sub.as_str(), &argv[2..]) {
+        ("latest", []) => Ok(array(latency.latest().into_iter().map(|(name, event)| {
+            let (time, ms) = event.latest();
+            array([bulk(name), int(time), int(ms), int(event.max)])
+        }))),
+        ("history", [event]) => {
+            let history = latency.history(&String::from_utf8_lossy(event)).map(|e| e.history).unwrap_or_default();
+            Ok(array(history.into_iter().map(|(time, ms)| array([int(time), int(ms)]))))
+        }
+        ("reset", events) => {
+            let events: Vec<String> = events.iter().map(|e| String::from_utf8_lossy(e).into_owned()).collect();
+            Ok(int(latency.reset(&events)))
+        }
+        ("help", []) => Ok(help_reply("LATENCY", LATENCY_HELP)),
+        ("latest" | "history", _) => Err(CommandError::WrongArity(format!("latency|{}", sub))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try LATENCY HELP.", sub))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            array, bulk, int, ok, simple,
+            tests::{run, test_backend},
+            Session,
+        },
+        resp::RespFrame,
+    };
+
+    #[test]
+    fn test_slowlog() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        backend.slowlog.set_threshold(0);
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "auth secret");
+        assert_eq!(run(&backend, &mut session, "slowlog len"), int(2));
+        let RespFrame::Array(entries) = run(&backend, &mut session, "slowlog get") else { panic!("expect: array") };
+        // newest first, the slowlog commands themselves are logged too
+        let RespFrame::Array(auth) = &entries[1] else { panic!("expect: array") };
+        assert_eq!(auth[0], int(1));
+        assert_eq!(auth[3], array([bulk("auth"), bulk("(redacted)")]));
+        assert_eq!(auth[4], bulk("internal"));
+        let RespFrame::Array(set) = &entries[2] else { panic!("expect: array") };
+        assert_eq!(set[3], array([bulk("set"), bulk("a"), bulk("1")]));
+
+        let RespFrame::Array(entries) = run(&backend, &mut session, "slowlog get 1") else { panic!("expect: array") };
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(run(&backend, &mut session, "slowlog get -2"), RespFrame::Error(_)));
+        backend.slowlog.set_threshold(-1);
+        assert_eq!(run(&backend, &mut session, "slowlog reset"), ok());
+        run(&backend, &mut session, "set a 1");
+        assert_eq!(run(&backend, &mut session, "slowlog len"), int(0));
+        assert!(matches!(run(&backend, &mut session, "slowlog help"), RespFrame::Array(help) if help.contains(&simple("LEN"))));
+    }
+
+    #[test]
+    fn test_latency() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "latency latest"), array([]));
+        backend.latency.set_threshold(1);
+        backend.latency.record("command", std::time::Duration::from_millis(20));
+        let RespFrame::Array(latest) = run(&backend, &mut session, "latency latest") else { panic!("expect: array") };
+        let RespFrame::Array(event) = &latest[0] else { panic!("expect: array") };
+        assert_eq!(event[0], bulk("command"));
+        assert_eq!(event[2..], [int(20), int(20)]);
+        let RespFrame::Array(history) = run(&backend, &mut session, "latency history command") else {
+            panic!("expect: array")
+        };
+        assert_eq!(history.len(), 1);
+        assert_eq!(run(&backend, &mut session, "latency history nope"), array([]));
+        assert_eq!(run(&backend, &mut session, "latency reset"), int(1));
+        assert_eq!(run(&backend, &mut session, "latency latest"), array([]));
+        assert!(matches!(run(&backend, &mut session, "latency help"), RespFrame::Array(help) if help.contains(&simple("LATEST"))));
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bytes::BytesMut;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::{
+    backend::snapshot::{dump_value, restore_value},
+    client::command_frame,
+    pubsub::GENERIC,
+    resp::{RespDecoder, RespEncode, RespError, RespFrame, RespLimits},
+    utils::now_ms,
+};
+
+use super::{
+    arg_i64, arg_str, bulk, eq_ignore_case, nil, ok, simple, CmdResult, CommandError, CommandSpec, Context,
+    DENYOOM, KEY1, NOSCRIPT, NO_KEYS, READONLY, UNLOCKED, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("dump", 2, READONLY, KEY1, dump),
+    CommandSpec::new("restore", -4, WRITE | DENYOOM, KEY1, restore),
+    CommandSpec::new("migrate", -6, WRITE | NOSCRIPT | UNLOCKED, NO_KEYS, migrate).with_movable_keys(migrate_keys),
+];
+
+fn dump(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(db.get(&argv[1]).map(|v| bulk(dump_value(v))).unwrap_or_else(nil))
+}
+
+// RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+fn restore(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (mut replace, mut abs_ttl, mut idle_ms, mut freq) = (false, false, None, None);
+    let mut i = 4;
+    while i < argv.len() {
+        let next = argv.get(i + 1);
+        match arg_str(&argv[i]).as_str() {
+            "replace" => replace = true,
+            "absttl" => abs_ttl = true,
+            "idletime" if next.is_some() && freq.is_none() => {
+                let secs = arg_i64(&argv[i + 1])?;
+                if secs < 0 {
+                    return Err(CommandError::Other("Invalid IDLETIME value, must be >= 0".into()));
+                }
+                idle_ms = Some(secs as u64 * 1000);
+                i += 1;
+            }
+            "freq" if next.is_some() && idle_ms.is_none() => {
+                let value = arg_i64(&argv[i + 1])?;
+                let value = u8::try_from(value)
+                    .map_err(|_| CommandError::Other("Invalid FREQ value, must be >= 0 and <= 255".into()))?;
+                freq = Some(value);
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+    let ttl = arg_i64(&argv[2])?;
+    if ttl < 0 {
+        return Err(CommandError::Other("Invalid TTL value, must be >= 0".into()));
+    }
+
+    let key = &argv[1];
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    db.expire_if_needed(key);
+    if !replace && db.contains(key) {
+        return Err(CommandError::Raw("BUSYKEY Target key name already exists.".into()));
+    }
+    let value = restore_value(&argv[3], &limits).map_err(|e| CommandError::Other(e.to_string()))?;
+    let at = match ttl {
+        0 => None,
+        ttl if abs_ttl => Some(ttl as u64),
+        ttl => Some(now_ms().saturating_add(ttl as u64)),
+    };
+    // a deadline already reached leaves no key, only the one replaced goes away
+    if at.is_some_and(|at| at <= now_ms()) {
+        let removed = db.remove(key).is_some();
+        ctx.propagate = Some(if removed { vec![vec![b"del".to_vec(), key.clone()]] } else { vec![] });
+        return Ok(ok());
+    }
+    db.set(key.clone(), value);
+    if let Some(at) = at {
+        db.set_expire(key, at);
+        // replicas get the absolute deadline
+        if !abs_ttl {
+            let mut restore = argv.to_vec();
+            restore[2] = at.to_string().into_bytes();
+            restore.push(b"ABSTTL".to_vec());
+            ctx.propagate = Some(vec![restore]);
+        }
+    }
+    db.set_access(key, idle_ms, freq);
+    Ok(ok())
+}
+
+// the single key, or the ones after KEYS when it's empty
+fn migrate_keys(argv: &[Vec<u8>]) -> Vec<usize> {
+    if argv.get(3).is_some_and(|key| !key.is_empty()) {
+        return vec![3];
+    }
+    match argv.iter().skip(6).position(|a| eq_ignore_case(a, "keys")) {
+        Some(pos) => (7 + pos..argv.len()).collect(),
+        None => vec![],
+    }
+}
+
+// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password]
+//   [AUTH2 username password] [KEYS key...]
+// The keys are dumped, and deleted afterwards, under the command and shard locks. No lock
+// is held while the RESTORE commands go out, so other clients (and a target in this same
+// process) aren't held up. A key changed meanwhile stays here and fails the command, the
+// target has the value that was sent. Like in redis this blocks the caller for up to
+// `timeout` ms per network operation.
+fn migrate(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (mut copy, mut replace, mut auth, mut keys) = (false, false, None, vec![]);
+    let mut i = 6;
+    while i < argv.len() {
+        let rest = argv.len() - i - 1;
+        match arg_str(&argv[i]).as_str() {
+            "copy" => copy = true,
+            "replace" => replace = true,
+            "auth" if rest >= 1 => {
+                auth = Some(vec![b"auth".to_vec(), argv[i + 1].clone()]);
+                i += 1;
+            }
+            "auth2" if rest >= 2 => {
+                auth = Some(vec![b"auth".to_vec(), argv[i + 1].clone(), argv[i + 2].clone()]);
+                i += 2;
+            }
+            "keys" if argv[3].is_empty() => {
+                keys = argv[i + 1..].to_vec();
+                break;
+            }
+            "keys" => {
+                return Err(CommandError::Other(
+                    "When using MIGRATE KEYS option, the key argument must be set to the empty string".into(),
+                ))
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+    if !argv[3].is_empty() {
+        keys = vec![argv[3].clone()];
+    }
+    let port = u16::try_from(arg_i64(&argv[2])?).map_err(|_| CommandError::NotInteger)?;
+    let target_db = arg_i64(&argv[4])?;
+    let timeout = match arg_i64(&argv[5])? {
+        ms if ms <= 0 => 1000,
+        ms => ms as u64,
+    };
+
+    let mut requests: Vec<Vec<Vec<u8>>> = auth.into_iter().collect();
+    requests.push(vec![b"select".to_vec(), target_db.to_string().into_bytes()]);
+    // the keys with their payload
+    let mut moved = vec![];
+    {
+        let backend = ctx.backend;
+        let _guard = backend.shared();
+        // the keys found expired here are propagated as DEL
+        let _order = backend.repl.is_enabled().then(|| backend.repl.order());
+        let mut db = ctx.write();
+        for key in keys {
+            db.expire_if_needed(&key);
+            let Some(value) = db.get(&key) else {
+                continue;
+            };
+            let ttl = db.expire_at(&key).map(|at| at.saturating_sub(now_ms()).max(1)).unwrap_or(0);
+            let payload = dump_value(value);
+            let mut restore = vec![b"restore".to_vec(), key.clone(), ttl.to_string().into_bytes(), payload.clone()];
+            if replace {
+                restore.push(b"REPLACE".to_vec());
+            }
+            requests.push(restore);
+            moved.push((key, payload));
+        }
+    }
+    if moved.is_empty() {
+        return Ok(simple("NOKEY"));
+    }
+
+    let host = arg_str(&argv[1]);
+    let limits = ctx.backend.config().resp_limits();
+    let replies = blocking_io(|| exchange(&host, port, Duration::from_millis(timeout), &limits, &requests))
+        .map_err(|e| CommandError::Raw(format!("IOERR error or timeout talking to target instance: {}", e)))?;
+    // AUTH and SELECT must succeed before any key counts as migrated
+    let (setup, restored) = replies.split_at(requests.len() - moved.len());
+    if let Some(e) = setup.iter().find_map(reply_error) {
+        return Err(CommandError::Other(format!("Target instance replied with error: {}", e)));
+    }
+    let mut error = None;
+    let mut deleted = vec![];
+    let backend = ctx.backend;
+    let _guard = backend.shared();
+    // the DEL goes to the replicas in the order of the deletes, like call() does for writes
+    let _order = backend.repl.is_enabled().then(|| backend.repl.order());
+    let mut db = ctx.write();
+    for ((key, payload), reply) in moved.into_iter().zip(restored) {
+        if let Some(e) = reply_error(reply) {
+            error = error.or(Some(format!("Target instance replied with error: {}", e)));
+        } else if copy {
+            continue;
+        } else if db.get(&key).is_some_and(|value| dump_value(value) == payload) {
+            db.remove(&key);
+            deleted.push(key);
+        } else {
+            let key = String::from_utf8_lossy(&key);
+            error = error.or(Some(format!("Key '{}' was modified during MIGRATE, it is kept here", key)));
+        }
+    }
+    drop(db);
+    for key in &deleted {
+        backend.pubsub.notify_keyspace_event(GENERIC, "del", key, ctx.session.db);
+    }
+    if !deleted.is_empty() && backend.repl.is_enabled() {
+        let mut del = vec![b"del".to_vec()];
+        del.extend(deleted);
+        backend.repl.propagate(ctx.session.db, &[del]);
+    }
+    match error {
+        Some(e) => Err(CommandError::Other(e)),
+        None => Ok(ok()),
+    }
+}
+
+// Run blocking network I/O. On a multi thread runtime the worker first hands its
+// other tasks over, so they (a target connection in this process too) keep running.
+fn blocking_io<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+fn reply_error(reply: &RespFrame) -> Option<String> {
+    match reply {
+        RespFrame::Error(e) => Some(e.as_str().to_string()),
+        _ => None,
+    }
+}
+
+// send the requests in one pipeline and read a reply for each
+fn exchange(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    limits: &RespLimits,
+    requests: &[Vec<Vec<u8>>],
+) -> Result<Vec<RespFrame>> {
+    let addr = (host, port).to_socket_addrs()?.next().ok_or_else(|| anyhow!("can't resolve {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let out: Vec<u8> = requests.iter().flat_map(|argv| command_frame(argv).encode()).collect();
+    stream.write_all(&out)?;
+
+    let mut buf = BytesMut::new();
+    let mut decoder = RespDecoder::new();
+    let mut replies = Vec::with_capacity(requests.len());
+    let mut chunk = [0; 4096];
+    while replies.len() < requests.len() {
+        match decoder.decode(&mut buf, limits) {
+            Ok(frame) => replies.push(frame),
+            Err(RespError::NotComplete) => match stream.read(&mut chunk)? {
+                0 => return Err(anyhow!("connection closed")),
+                n => buf.extend_from_slice(&chunk[..n]),
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        thread,
+    };
+
+    use bytes::BytesMut;
+    use tokio::net::TcpListener;
+
+    use crate::{
+        backend::Backend,
+        cmd::{
+            bulk, error_frame, int, nil, ok, simple,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        network,
+        resp::{RespError, RespFrame, RespLimits},
+    };
+
+    fn dump(backend: &Backend, session: &mut Session, key: &str) -> Vec<u8> {
+        let RespFrame::BulkString(payload) = run(backend, session, &format!("dump {}", key)) else {
+            panic!("expect: bulk string")
+        };
+        payload
+    }
+
+    fn restore(backend: &Backend, session: &mut Session, args: &[&[u8]]) -> RespFrame {
+        let mut argv = vec![b"restore".to_vec()];
+        argv.extend(args.iter().map(|a| a.to_vec()));
+        crate::cmd::execute_argv(backend, session, argv)
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "rpush l a b c");
+        let payload = dump(&backend, &mut session, "l");
+        assert_eq!(run(&backend, &mut session, "dump nope"), nil());
+
+        let busy = CommandError::Raw("BUSYKEY Target key name already exists.".into());
+        assert_eq!(restore(&backend, &mut session, &[b"l", b"0", &payload]), error_frame(busy));
+        assert_eq!(restore(&backend, &mut session, &[b"m", b"5000", &payload, b"IDLETIME", b"100"]), ok());
+        assert_eq!(run(&backend, &mut session, "object idletime m"), int(100));
+        assert_eq!(run(&backend, &mut session, "lrange m 0 -1"), run(&backend, &mut session, "lrange l 0 -1"));
+        assert!(matches!(run(&backend, &mut session, "pttl m"), RespFrame::Integer(ms) if ms > 4000));
+        // an absolute deadline in the past creates nothing
+        assert_eq!(restore(&backend, &mut session, &[b"l", b"1", &payload, b"REPLACE", b"ABSTTL"]), ok());
+        assert_eq!(run(&backend, &mut session, "exists l"), int(0));
+
+        let mut damaged = payload.clone();
+        damaged[2] ^= 1;
+        let wrong = CommandError::Other("DUMP payload version or checksum are wrong".into());
+        assert_eq!(restore(&backend, &mut session, &[b"x", b"0", &damaged]), error_frame(wrong));
+        assert_eq!(restore(&backend, &mut session, &[b"x", b"-1", &payload]), error_frame(CommandError::Other(
+            "Invalid TTL value, must be >= 0".into()
+        )));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_migrate() {
+        let target = test_backend();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(network::serve(listener, target.clone()));
+
+        let source = test_backend();
+        let mut session = Session::new();
+        run(&source, &mut session, "set a 1");
+        run(&source, &mut session, "hset b f v");
+        let migrate = format!("migrate 127.0.0.1 {} a 3 1000 copy", port);
+        assert_eq!(tokio::task::block_in_place(|| run(&source, &mut session, &migrate)), ok());
+        assert_eq!(run(&source, &mut session, "get a"), bulk("1"));
+
+        // without REPLACE the existing key on the target fails the migration
+        let port = port.to_string();
+        let mut argv: Vec<Vec<u8>> =
+            ["migrate", "127.0.0.1", &port, "", "3", "1000", "keys", "a", "b", "nope"].map(|s| s.into()).to_vec();
+        let reply = tokio::task::block_in_place(|| crate::cmd::execute_argv(&source, &mut session, argv.clone()));
+        assert!(matches!(reply, RespFrame::Error(e) if e.as_str().contains("BUSYKEY")));
+        assert_eq!(run(&source, &mut session, "exists a b"), int(1));
+
+        argv.insert(6, b"replace".to_vec());
+        let reply = tokio::task::block_in_place(|| crate::cmd::execute_argv(&source, &mut session, argv.clone()));
+        assert_eq!(reply, ok());
+        assert_eq!(run(&source, &mut session, "exists a b"), int(0));
+        let mut remote = Session { db: 3, ..Session::default() };
+        assert_eq!(run(&target, &mut remote, "hget b f"), bulk("v"));
+        let reply = tokio::task::block_in_place(|| crate::cmd::execute_argv(&source, &mut session, argv));
+        assert_eq!(reply, simple("NOKEY"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_migrate_to_same_instance() {
+        let backend = test_backend();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(network::serve(listener, backend.clone()));
+
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "rpush l x y");
+        // MIGRATE runs on the only worker, the RESTORE connection must still be served
+        let port = port.to_string();
+        let argv: Vec<Vec<u8>> =
+            ["migrate", "127.0.0.1", &port, "", "5", "1000", "keys", "a", "l"].map(|s| s.into()).to_vec();
+        let source = backend.clone();
+        let reply = tokio::spawn(async move { crate::cmd::execute_argv(&source, &mut session, argv) }).await.unwrap();
+        assert_eq!(reply, ok());
+
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "exists a l"), int(0));
+        let mut target = Session { db: 5, ..Session::default() };
+        assert_eq!(run(&backend, &mut target, "get a"), bulk("1"));
+        assert_eq!(run(&backend, &mut target, "llen l"), int(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_migrate_holds_no_lock_during_transfer() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a 1");
+        // before answering, the target changes the key in a transaction: it needs the
+        // exclusive command lock and the key's shard, MIGRATE must not hold either
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let source = backend.clone();
+        let target = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (mut buf, mut chunk, mut requests) = (BytesMut::new(), [0; 4096], 0);
+            while requests < 2 {
+                match RespFrame::decode_with(&mut buf, &RespLimits::default()) {
+                    Ok(_) => requests += 1,
+                    Err(RespError::NotComplete) => {
+                        let n = stream.read(&mut chunk).unwrap();
+                        buf.extend_from_slice(&chunk[..n]);
+                    }
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            let mut other = Session::new();
+            for cmd in ["multi", "set a 2", "exec"] {
+                run(&source, &mut other, cmd);
+            }
+            stream.write_all(b"+OK\r\n+OK\r\n").unwrap();
+        });
+
+        let migrate = format!("migrate 127.0.0.1 {} a 0 5000", port);
+        let reply = tokio::task::block_in_place(|| run(&backend, &mut session, &migrate));
+        target.join().unwrap();
+        let changed = CommandError::Other("Key 'a' was modified during MIGRATE, it is kept here".into());
+        assert_eq!(reply, error_frame(changed));
+        assert_eq!(run(&backend, &mut session, "get a"), bulk("2"));
+
+        run(&backend, &mut session, "multi");
+        let refused = CommandError::Other("Command not allowed inside a transaction".into());
+        assert_eq!(run(&backend, &mut session, &migrate), error_frame(refused));
+        assert!(matches!(run(&backend, &mut session, "exec"), RespFrame::Error(e) if e.as_str().starts_with("EXECABORT")));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{
+    backend::zset::ZSet,
+    resp::RespFrame,
+    utils::{geohash, parse_f64},
+};
+
+use super::{
+    arg_f64, arg_i64, arg_str, array, bulk, eq_ignore_case, int, nil, nil_array,
+    zset::{get_zset, get_zset_mut},
+    CmdResult, CommandError, CommandSpec, Context, DENYOOM, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("geoadd", -5, WRITE | DENYOOM, KEY1, geoadd),
+    CommandSpec::new("geopos", -2, READONLY, KEY1, geopos),
+    CommandSpec::new("geodist", -4, READONLY, KEY1, geodist),
+    CommandSpec::new("geohash", -2, READONLY, KEY1, geohash),
+    CommandSpec::new("geosearch", -7, READONLY, KEY1, geosearch),
+];
+
+// meters per unit
+fn unit(v: &[u8]) -> Result<f64, CommandError> {
+    match arg_str(v).as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err(CommandError::Other("unsupported unit provided. please use M, KM, FT, MI".into())),
+    }
+}
+
+fn arg_lon_lat(lon: &[u8], lat: &[u8]) -> Result<(f64, f64), CommandError> {
+    let (lon, lat) = (arg_f64(lon)?, arg_f64(lat)?);
+    if !geohash::is_valid(lon, lat) {
+        return Err(CommandError::Other(format!("invalid longitude,latitude pair {:.6},{:.6}", lon, lat)));
+    }
+    Ok((lon, lat))
+}
+
+fn position(zset: &ZSet, member: &[u8]) -> Option<(f64, f64)> {
+    zset.score(member).map(|score| geohash::decode(score as u64))
+}
+
+fn distance_frame(meters: f64, unit: f64) -> RespFrame {
+    bulk(format!("{:.4}", meters / unit))
+}
+
+fn coord_frame((lon, lat): (f64, f64)) -> RespFrame {
+    array([bulk(lon.to_string()), bulk(lat.to_string())])
+}
+
+// GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]
+fn geoadd(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut i = 2;
+    while let Some(option) = argv.get(i) {
+        if eq_ignore_case(option, "nx") {
+            nx = true;
+        } else if eq_ignore_case(option, "xx") {
+            xx = true;
+        } else if eq_ignore_case(option, "ch") {
+            ch = true;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    if nx && xx {
+        return Err(CommandError::Other("XX and NX options at the same time are not compatible".into()));
+    }
+    let triples = &argv[i..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) {
+        return Err(CommandError::Syntax);
+    }
+    let points = triples
+        .chunks(3)
+        .map(|t| arg_lon_lat(&t[0], &t[1]).map(|(lon, lat)| (geohash::encode(lon, lat) as f64, &t[2])))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut db = ctx.write();
+    let Some(zset) = get_zset_mut(&mut db, &argv[1], !xx)? else {
+        return Ok(int(0));
+    };
+    let (mut added, mut changed) = (0, 0);
+    for (score, member) in points {
+        match zset.score(member) {
+            None if !xx => {
+                zset.insert(member, score);
+                added += 1;
+            }
+            Some(old) if !nx && old != score => {
+                zset.insert(member, score);
+                changed += 1;
+            }
+            _ => {}
+        }
+    }
+    db.remove_if_empty(&argv[1]);
+    Ok(int(if ch { added + changed } else { added }))
+}
+
+// GEOPOS key [member ...]
+fn geopos(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let zset = get_zset(&db, &argv[1])?;
+    Ok(array(argv[2..].iter().map(|member| {
+        zset.and_then(|z| position(z, member)).map(coord_frame).unwrap_or_else(nil_array)
+    })))
+}
+
+// GEODIST key member1 member2 [M|KM|FT|MI]
+fn geodist(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let unit = match &argv[4..] {
+        [] => 1.0,
+        [u] => unit(u)?,
+        _ => return Err(CommandError::Syntax),
+    };
+    let db = ctx.read();
+    let Some(zset) = get_zset(&db, &argv[1])? else {
+        return Ok(nil());
+    };
+    match (position(zset, &argv[2]), position(zset, &argv[3])) {
+        (Some(a), Some(b)) => Ok(distance_frame(geohash::distance(a.0, a.1, b.0, b.1), unit)),
+        _ => Ok(nil()),
+    }
+}
+
+// GEOHASH key [member ...]
+fn geohash(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let zset = get_zset(&db, &argv[1])?;
+    Ok(array(argv[2..].iter().map(|member| {
+        zset.and_then(|z| z.score(member))
+            .map(|score| bulk(geohash::to_base32(score as u64)))
+            .unwrap_or_else(nil)
+    })))
+}
+
+enum From {
+    Member(Vec<u8>),
+    LonLat(f64, f64),
+}
+
+enum Shape {
+    // radius in meters
+    Radius(f64),
+    // width and height in meters
+    Box(f64, f64),
+}
+
+#[derive(Default)]
+struct Search {
+    from: Option<From>,
+    by: Option<(Shape, f64)>,
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+fn parse_search(argv: &[Vec<u8>]) -> Result<Search, CommandError> {
+    let mut search = Search::default();
+    let mut i = 0;
+    let arg = |i: usize| argv.get(i).ok_or(CommandError::Syntax);
+    let positive = |v: &[u8]| match parse_f64(v) {
+        Some(f) if f >= 0.0 => Ok(f),
+        Some(_) => Err(CommandError::Other("radius cannot be negative".into())),
+        None => Err(CommandError::Other("need numeric radius".into())),
+    };
+    let from_error = || CommandError::Other("exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH".into());
+    let by_error = || CommandError::Other("exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".into());
+    while i < argv.len() {
+        match arg_str(&argv[i]).as_str() {
+            "frommember" => {
+                search.from.replace(From::Member(arg(i + 1)?.clone())).map_or(Ok(()), |_| Err(from_error()))?;
+                i += 2;
+            }
+            "fromlonlat" => {
+                let (lon, lat) = arg_lon_lat(arg(i + 1)?, arg(i + 2)?)?;
+                search.from.replace(From::LonLat(lon, lat)).map_or(Ok(()), |_| Err(from_error()))?;
+                i += 3;
+            }
+            "byradius" => {
+                let (radius, unit) = (positive(arg(i + 1)?)?, unit(arg(i + 2)?)?);
+                search.by.replace((Shape::Radius(radius * unit), unit)).map_or(Ok(()), |_| Err(by_error()))?;
+                i += 3;
+            }
+            "bybox" => {
+                let (width, height, unit) = (positive(arg(i + 1)?)?, positive(arg(i + 2)?)?, unit(arg(i + 3)?)?);
+                let shape = Shape::Box(width * unit, height * unit);
+                search.by.replace((shape, unit)).map_or(Ok(()), |_| Err(by_error()))?;
+                i += 4;
+            }
+            "asc" => {
+                search.desc = Some(false);
+                i += 1;
+            }
+            "desc" => {
+                search.desc = Some(true);
+                i += 1;
+            }
+            "count" => {
+                let count = arg_i64(arg(i + 1)?)?;
+                if count <= 0 {
+                    return Err(CommandError::Other("COUNT must be > 0".into()));
+                }
+                search.count = Some(count as usize);
+                i += 2;
+                if argv.get(i).is_some_and(|a| eq_ignore_case(a, "any")) {
+                    search.any = true;
+                    i += 1;
+                }
+            }
+            "any" => return Err(CommandError::Other("the ANY argument requires COUNT argument".into())),
+            "withcoord" => {
+                search.with_coord = true;
+                i += 1;
+            }
+            "withdist" => {
+                search.with_dist = true;
+                i += 1;
+            }
+            "withhash" => {
+                search.with_hash = true;
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    if search.from.is_none() {
+        return Err(from_error());
+    }
+    if search.by.is_none() {
+        return Err(by_error());
+    }
+    Ok(search)
+}
+
+// GEOSEARCH key FROMMEMBER member|FROMLONLAT lon lat BYRADIUS radius unit|BYBOX width height unit
+//   [ASC|DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+// every member is checked, there is no geohash box pruning
+fn geosearch(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let search = parse_search(&argv[2..])?;
+    let db = ctx.read();
+    let Some(zset) = get_zset(&db, &argv[1])? else {
+        return Ok(array([]));
+    };
+    let center = match search.from {
+        Some(From::Member(ref member)) => position(zset, member)
+            .ok_or_else(|| CommandError::Other("could not decode requested zset member".into()))?,
+        Some(From::LonLat(lon, lat)) => (lon, lat),
+        None => unreachable!("checked by parse_search"),
+    };
+    let (shape, unit) = search.by.as_ref().expect("checked by parse_search");
+
+    let mut found = Vec::new();
+    for (member, score) in zset.iter() {
+        let point = geohash::decode(score as u64);
+        let distance = match *shape {
+            Shape::Radius(radius) => {
+                let d = geohash::distance(center.0, center.1, point.0, point.1);
+                (d <= radius).then_some(d)
+            }
+            Shape::Box(width, height) => geohash::distance_in_box(center, width, height, point),
+        };
+        if let Some(distance) = distance {
+            found.push((member, score, point, distance));
+            // ANY stops at the first matches instead of the closest ones
+            if search.any && Some(found.len()) == search.count {
+                break;
+            }
+        }
+    }
+    // a COUNT without ANY needs the closest members, so it sorts even without ASC
+    let desc = search.desc.or((search.count.is_some() && !search.any).then_some(false));
+    match desc {
+        Some(false) => found.sort_by(|a, b| a.3.total_cmp(&b.3)),
+        Some(true) => found.sort_by(|a, b| b.3.total_cmp(&a.3)),
+        None => {}
+    }
+    found.truncate(search.count.unwrap_or(usize::MAX));
+
+    let plain = !search.with_coord && !search.with_dist && !search.with_hash;
+    Ok(array(found.into_iter().map(|(member, score, point, distance)| {
+        if plain {
+            return bulk(member);
+        }
+        let mut item = vec![bulk(member)];
+        if search.with_dist {
+            item.push(distance_frame(distance, *unit));
+        }
+        if search.with_hash {
+            item.push(int(score as i64));
+        }
+        if search.with_coord {
+            item.push(coord_frame(point));
+        }
+        array(item)
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            array, bulk, error_frame, int, nil, nil_array,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        resp::RespFrame,
+    };
+
+    const SICILY: &str = "geoadd sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania";
+
+    #[test]
+    fn test_geoadd_geodist() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, SICILY), int(2));
+        assert_eq!(run(&backend, &mut session, "zscore sicily Palermo"), bulk("3479099956230698"));
+        assert_eq!(run(&backend, &mut session, "geodist sicily Palermo Catania"), bulk("166274.1516"));
+        assert_eq!(run(&backend, &mut session, "geodist sicily Palermo Catania km"), bulk("166.2742"));
+        assert_eq!(run(&backend, &mut session, "geodist sicily Palermo Catania mi"), bulk("103.3182"));
+        assert_eq!(run(&backend, &mut session, "geodist sicily Palermo Rome"), nil());
+        assert_eq!(
+            run(&backend, &mut session, "geodist sicily Palermo Catania yards"),
+            error_frame(CommandError::Other("unsupported unit provided. please use M, KM, FT, MI".into()))
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geohash sicily Palermo Catania Rome"),
+            array([bulk("sqc8b49rny0"), bulk("sqdtr74hyu0"), nil()])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geoadd sicily 13 86 North"),
+            error_frame(CommandError::Other("invalid longitude,latitude pair 13.000000,86.000000".into()))
+        );
+
+        let RespFrame::Array(positions) = run(&backend, &mut session, "geopos sicily Palermo Rome") else {
+            panic!("expect: array")
+        };
+        assert_eq!(positions[1], nil_array());
+        let RespFrame::Array(coord) = &positions[0] else { panic!("expect: array") };
+        let RespFrame::BulkString(lon) = &coord[0] else { panic!("expect: bulk") };
+        let lon: f64 = String::from_utf8_lossy(lon).parse().unwrap();
+        assert!((lon - 13.361389).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_geosearch() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, SICILY);
+        run(&backend, &mut session, "geoadd sicily 12.758489 38.788135 edge1 17.241510 38.788135 edge2");
+
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 byradius 200 km asc"),
+            array([bulk("Catania"), bulk("Palermo")])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 byradius 200 km desc count 1 withdist"),
+            array([array([bulk("Palermo"), bulk("190.4424")])])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 bybox 400 400 km asc count 1 withhash"),
+            array([array([bulk("Catania"), int(3479447370796909i64)])])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily frommember Palermo byradius 1 m"),
+            array([bulk("Palermo")])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 bybox 400 400 km asc"),
+            array([bulk("Catania"), bulk("Palermo"), bulk("edge2"), bulk("edge1")])
+        );
+        assert_eq!(run(&backend, &mut session, "geosearch nope fromlonlat 15 37 byradius 1 km"), array([]));
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily frommember Rome byradius 1 km"),
+            error_frame(CommandError::Other("could not decode requested zset member".into()))
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 frommember Palermo byradius 1 km"),
+            error_frame(CommandError::Other(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH".into()
+            ))
+        );
+        assert_eq!(
+            run(&backend, &mut session, "geosearch sicily fromlonlat 15 37 byradius 1 km any"),
+            error_frame(CommandError::Other("the ANY argument requires COUNT argument".into()))
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    backend::{Db, Hash, Value},
+    utils::{format_f64, parse_f64, parse_i64},
+};
+
+use super::{
+    arg_f64, arg_i64, array, bulk, bulk_array, int, nil, ok, CmdResult, CommandError, CommandSpec,
+    Context, DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("hset", -4, WRITE | DENYOOM | FAST, KEY1, hset),
+    CommandSpec::new("hmset", -4, WRITE | DENYOOM | FAST, KEY1, hmset),
+    CommandSpec::new("hsetnx", 4, WRITE | DENYOOM | FAST, KEY1, hsetnx),
+    CommandSpec::new("hget", 3, READONLY | FAST, KEY1, hget),
+    CommandSpec::new("hmget", -3, READONLY | FAST, KEY1, hmget),
+    CommandSpec::new("hdel", -3, WRITE | FAST, KEY1, hdel),
+    CommandSpec::new("hlen", 2, READONLY | FAST, KEY1, hlen),
+    CommandSpec::new("hstrlen", 3, READONLY | FAST, KEY1, hstrlen),
+    CommandSpec::new("hexists", 3, READONLY | FAST, KEY1, hexists),
+    CommandSpec::new("hgetall", 2, READONLY, KEY1, hgetall),
+    CommandSpec::new("hkeys", 2, READONLY, KEY1, hkeys),
+    CommandSpec::new("hvals", 2, READONLY, KEY1, hvals),
+    CommandSpec::new("hincrby", 4, WRITE | DENYOOM | FAST, KEY1, hincrby),
+    CommandSpec::new("hincrbyfloat", 4, WRITE | DENYOOM | FAST, KEY1, hincrbyfloat),
+];
+
+fn get_hash<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a Hash>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::Hash(h)) => Ok(Some(h)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn get_hash_mut<'a>(db: &'a mut Db, key: &[u8], create: bool) -> Result<Option<&'a mut Hash>, CommandError> {
+    if create && db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::Hash(Hash::default()));
+    }
+    match db.get_mut(key) {
+        None => Ok(None),
+        Some(Value::Hash(h)) => Ok(Some(h)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn set_fields(ctx: &mut Context, argv: &[Vec<u8>], name: &str) -> Result<usize, CommandError> {
+    if !argv.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity(name.into()));
+    }
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let hash = get_hash_mut(&mut db, &argv[1], true)?.ok_or(CommandError::NoSuchKey)?;
+    Ok(argv[2..].chunks(2).filter(|pair| hash.insert(&pair[0], &pair[1], &limits)).count())
+}
+
+fn hset(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(set_fields(ctx, argv, "hset")?))
+}
+
+fn hmset(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    set_fields(ctx, argv, "hmset")?;
+    Ok(ok())
+}
+
+fn hsetnx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let hash = get_hash_mut(&mut db, &argv[1], true)?.ok_or(CommandError::NoSuchKey)?;
+    if hash.contains_key(&argv[2]) {
+        return Ok(int(0));
+    }
+    hash.insert(&argv[2], &argv[3], &limits);
+    Ok(int(1))
+}
+
+fn hget(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(get_hash(&db, &argv[1])?
+        .and_then(|h| h.get(&argv[2]))
+        .map(bulk)
+        .unwrap_or_else(nil))
+}
+
+fn hmget(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let hash = get_hash(&db, &argv[1])?;
+    Ok(array(argv[2..].iter().map(|field| {
+        hash.and_then(|h| h.get(field)).map(bulk).unwrap_or_else(nil)
+    })))
+}
+
+fn hdel(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let Some(hash) = get_hash_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+    let removed = argv[2..].iter().filter(|f| hash.remove(f)).count();
+    db.remove_if_empty(&argv[1]);
+    Ok(int(removed))
+}
+
+fn hlen(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_hash(&db, &argv[1])?.map(|h| h.len()).unwrap_or(0)))
+}
+
+fn hstrlen(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_hash(&db, &argv[1])?.and_then(|h| h.get(&argv[2])).map(|v| v.len()).unwrap_or(0)))
+}
+
+fn hexists(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_hash(&db, &argv[1])?.map(|h| h.contains_key(&argv[2])).unwrap_or(false) as i64))
+}
+
+fn hgetall(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let Some(hash) = get_hash(&db, &argv[1])? else {
+        return Ok(array([]));
+    };
+    Ok(array(hash.iter().flat_map(|(k, v)| [bulk(k), bulk(v)])))
+}
+
+fn hkeys(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(get_hash(&db, &argv[1])?.into_iter().flat_map(|h| h.iter().map(|(k, _)| k))))
+}
+
+fn hvals(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(get_hash(&db, &argv[1])?.into_iter().flat_map(|h| h.iter().map(|(_, v)| v))))
+}
+
+fn hincrby(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let delta = arg_i64(&argv[3])?;
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let hash = get_hash_mut(&mut db, &argv[1], true)?.ok_or(CommandError::NoSuchKey)?;
+    let current = match hash.get(&argv[2]) {
+        None => 0,
+        Some(v) => parse_i64(v).ok_or_else(|| CommandError::Other("hash value is not an integer".into()))?,
+    };
+    let value = current.checked_add(delta).ok_or(CommandError::Overflow)?;
+    hash.insert(&argv[2], value.to_string().as_bytes(), &limits);
+    Ok(int(value))
+}
+
+fn hincrbyfloat(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let delta = arg_f64(&argv[3])?;
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let hash = get_hash_mut(&mut db, &argv[1], true)?.ok_or(CommandError::NoSuchKey)?;
+    let current = match hash.get(&argv[2]) {
+        None => 0.0,
+        Some(v) => parse_f64(v).ok_or_else(|| CommandError::Other("hash value is not a float".into()))?,
+    };
+    let value = current + delta;
+    if !value.is_finite() {
+        return Err(CommandError::Other("increment would produce NaN or Infinity".into()));
+    }
+    let value = format_f64(value);
+    hash.insert(&argv[2], value.as_bytes(), &limits);
+    Ok(bulk(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        array, bulk, int, nil, ok,
+        tests::{run, test_backend},
+        Session,
+    };
+
+    #[test]
+    fn test_hash_commands() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "hset h a 1 b 2"), int(2));
+        assert_eq!(run(&backend, &mut session, "hset h a 3"), int(0));
+        assert_eq!(run(&backend, &mut session, "hmset h c 4"), ok());
+        assert_eq!(run(&backend, &mut session, "hsetnx h a 9"), int(0));
+        assert_eq!(run(&backend, &mut session, "hget h a"), bulk("3"));
+        assert_eq!(run(&backend, &mut session, "hmget h a x"), array([bulk("3"), nil()]));
+        assert_eq!(run(&backend, &mut session, "hlen h"), int(3));
+        assert_eq!(run(&backend, &mut session, "hincrby h a 10"), int(13));
+        assert_eq!(run(&backend, &mut session, "hincrbyfloat h f 0.5"), bulk("0.5"));
+        assert_eq!(run(&backend, &mut session, "hexists h f"), int(1));
+        assert_eq!(run(&backend, &mut session, "hdel h a b c f x"), int(4));
+        assert_eq!(run(&backend, &mut session, "exists h"), int(0));
+    }
+}
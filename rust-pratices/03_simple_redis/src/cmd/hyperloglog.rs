@@ -0,0 +1,117 @@
+use crate::backend::{hyperloglog::HyperLogLog, Db, Value};
+
+use super::{int, ok, CmdResult, CommandError, CommandSpec, Context, ALL_KEYS, DENYOOM, FAST, KEY1, READONLY, WRITE};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("pfadd", -2, WRITE | DENYOOM | FAST, KEY1, pfadd),
+    CommandSpec::new("pfcount", -2, READONLY, ALL_KEYS, pfcount),
+    CommandSpec::new("pfmerge", -2, WRITE | DENYOOM, ALL_KEYS, pfmerge),
+];
+
+fn invalid_hll() -> CommandError {
+    CommandError::Raw("WRONGTYPE Key is not a valid HyperLogLog string value.".into())
+}
+
+// a HyperLogLog is a string in its own format, like in redis
+fn get_hll(db: &Db, key: &[u8]) -> Result<Option<HyperLogLog>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => HyperLogLog::from_bytes(s).map(Some).ok_or_else(invalid_hll),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+// PFADD key [element ...], 1 when the key was created or a register changed
+fn pfadd(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let existing = get_hll(&db, &argv[1])?;
+    let created = existing.is_none();
+    let mut hll = existing.unwrap_or_default();
+    let mut changed = false;
+    for element in &argv[2..] {
+        changed |= hll.add(element);
+    }
+    if !created && !changed {
+        return Ok(int(0));
+    }
+    db.set_keep_ttl(argv[1].clone(), Value::String(hll.to_bytes().into()));
+    Ok(int(1))
+}
+
+// PFCOUNT key [key ...], the cardinality of the union for several keys
+fn pfcount(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    if let [_, key] = argv {
+        return Ok(int(get_hll(&db, key)?.map_or(0, |hll| hll.count())));
+    }
+    let mut union = HyperLogLog::default();
+    for key in &argv[1..] {
+        if let Some(hll) = get_hll(&db, key)? {
+            union.merge(&hll);
+        }
+    }
+    Ok(int(union.count()))
+}
+
+// PFMERGE destkey [sourcekey ...], the destination is part of the union
+fn pfmerge(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let mut merged = HyperLogLog::default();
+    for key in &argv[1..] {
+        if let Some(hll) = get_hll(&db, key)? {
+            merged.merge(&hll);
+        }
+    }
+    merged.cache_count();
+    db.set_keep_ttl(argv[1].clone(), Value::String(merged.to_bytes().into()));
+    Ok(ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            error_frame, int, ok, simple,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        resp::RespFrame,
+    };
+
+    #[test]
+    fn test_pfadd_pfcount() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "pfadd h"), int(1));
+        assert_eq!(run(&backend, &mut session, "pfadd h"), int(0));
+        assert_eq!(run(&backend, &mut session, "pfcount h"), int(0));
+        assert_eq!(run(&backend, &mut session, "pfadd h a b c d e f g"), int(1));
+        assert_eq!(run(&backend, &mut session, "pfadd h a b"), int(0));
+        assert_eq!(run(&backend, &mut session, "pfcount h"), int(7));
+        assert_eq!(run(&backend, &mut session, "type h"), simple("string"));
+        assert_eq!(run(&backend, &mut session, "pfcount nope"), int(0));
+
+        run(&backend, &mut session, "set s foo");
+        assert_eq!(
+            run(&backend, &mut session, "pfadd s a"),
+            error_frame(CommandError::Raw("WRONGTYPE Key is not a valid HyperLogLog string value.".into()))
+        );
+        run(&backend, &mut session, "lpush l a");
+        assert_eq!(run(&backend, &mut session, "pfcount l"), error_frame(CommandError::WrongType));
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "pfadd a 1 2 3 4");
+        run(&backend, &mut session, "pfadd b 3 4 5 6");
+        assert_eq!(run(&backend, &mut session, "pfcount a b nope"), int(6));
+        assert_eq!(run(&backend, &mut session, "pfadd dest 7"), int(1));
+        assert_eq!(run(&backend, &mut session, "pfmerge dest a b"), ok());
+        assert_eq!(run(&backend, &mut session, "pfcount dest"), int(7));
+        assert_eq!(run(&backend, &mut session, "pfmerge empty"), ok());
+        assert_eq!(run(&backend, &mut session, "pfcount empty"), int(0));
+        assert!(matches!(run(&backend, &mut session, "get dest"), RespFrame::BulkString(b) if b.starts_with(b"HYLL")));
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::atomic::Ordering;
+
+use crate::{backend::Backend, utils::human_bytes};
+
+use super::{bulk, config::enum_name, CmdResult, CommandSpec, Context, NO_KEYS};
+
+pub const COMMANDS: &[CommandSpec] = &[CommandSpec::new("info", -1, 0, NO_KEYS, info)];
+
+// the sections of a plain INFO, commandstats has to be asked for
+const DEFAULT_SECTIONS: &[&str] =
+    &["server", "clients", "memory", "persistence", "stats", "replication", "cluster", "keyspace"];
+
+// INFO [section ...], also "default", "all" and "everything"
+fn info(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let asked: Vec<String> = argv[1..].iter().map(|s| String::from_utf8_lossy(s).to_ascii_lowercase()).collect();
+    let mut sections: Vec<&str> = Vec::new();
+    if asked.is_empty() {
+        sections.extend(DEFAULT_SECTIONS);
+    }
+    for name in &asked {
+        match name.as_str() {
+            "default" => sections.extend(DEFAULT_SECTIONS),
+            "all" | "everything" => sections.extend(DEFAULT_SECTIONS.iter().chain(&["commandstats"])),
+            name => sections.push(name),
+        }
+    }
+
+    let mut out = String::new();
+    for name in DEFAULT_SECTIONS.iter().chain(&["commandstats"]).filter(|s| sections.contains(s)) {
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        let title = name[..1].to_ascii_uppercase() + &name[1..];
+        out.push_str(&format!("# {}\r\n", title));
+        for (key, value) in section(ctx.backend, name) {
+            out.push_str(&format!("{}:{}\r\n", key, value));
+        }
+    }
+    Ok(bulk(out))
+}
+
+fn section(backend: &Backend, name: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut field = |key: &str, value: &dyn ToString| fields.push((key.to_string(), value.to_string()));
+    let config = backend.config().clone();
+    let stats = &backend.stats;
+    match name {
+        "server" => {
+            let uptime = stats.started.elapsed().as_secs();
+            field("redis_version", &env!("CARGO_PKG_VERSION"));
+            field("redis_mode", &if backend.cluster.is_some() { "cluster" } else { "standalone" });
+            field("process_id", &std::process::id());
+            field("tcp_port", &config.port);
+            field("uptime_in_seconds", &uptime);
+            field("uptime_in_days", &(uptime / 86400));
+        }
+        "clients" => {
+            field("connected_clients", &backend.connections.len());
+            field("blocked_clients", &backend.blocking.blocked_clients());
+        }
+        "memory" => {
+            let used = backend.used_memory() as u64;
+            field("used_memory", &used);
+            field("used_memory_human", &human_bytes(used));
+            field("maxmemory", &config.maxmemory);
+            field("maxmemory_human", &human_bytes(config.maxmemory));
+            field("maxmemory_policy", &enum_name(config.maxmemory_policy));
+        }
+        "persistence" => {
+            field("rdb_changes_since_last_save", &backend.dirty.load(Ordering::Relaxed));
+            field("rdb_bgsave_in_progress", &(backend.bgsave_in_progress.load(Ordering::Relaxed) as u8));
+            field("rdb_last_save_time", &backend.last_save.load(Ordering::Relaxed));
+        }
+        "stats" => {
+            field("total_connections_received", &backend.connections.total());
+            field("total_commands_processed", &stats.total_commands.load(Ordering::Relaxed));
+            field("total_net_input_bytes", &stats.net_input_bytes.load(Ordering::Relaxed));
+            field("total_net_output_bytes", &stats.net_output_bytes.load(Ordering::Relaxed));
+            field("expired_keys", &backend.expired_keys.load(Ordering::Relaxed));
+            field("evicted_keys", &backend.evicted_keys.load(Ordering::Relaxed));
+        }
+        "replication" => {
+            let repl = &backend.repl;
+            match repl.master() {
+                Some(master) => {
+                    field("role", &"slave");
+                    field("master_host", &master.host);
+                    field("master_port", &master.port);
+                    let status = if master.state.as_str() == "connected" { "up" } else { "down" };
+                    field("master_link_status", &status);
+                    field("slave_repl_offset", &master.master_offset);
+                    field("slave_read_only", &(config.replica_read_only as u8));
+                }
+                None => field("role", &"master"),
+            }
+            let replicas = repl.replicas();
+            field("connected_slaves", &replicas.len());
+            for (i, (_, ip, port, ack)) in replicas.iter().enumerate() {
+                field(&format!("slave{}", i), &format!("ip={},port={},state=online,offset={}", ip, port, ack));
+            }
+            // a replica shows the history it follows, the one its master announced
+            match repl.master_position() {
+                Some((replid, offset)) => {
+                    field("master_replid", &replid);
+                    field("master_repl_offset", &offset);
+                }
+                None => {
+                    field("master_replid", &repl.replid());
+                    field("master_repl_offset", &repl.offset());
+                }
+            }
+            let (first, len) = repl.backlog_info().unwrap_or((0, 0));
+            field("repl_backlog_active", &(repl.backlog_info().is_some() as u8));
+            field("repl_backlog_size", &config.repl_backlog_size);
+            field("repl_backlog_first_byte_offset", &first);
+            field("repl_backlog_histlen", &len);
+        }
+        "cluster" => field("cluster_enabled", &(backend.cluster.is_some() as u8)),
+        "keyspace" => {
+            for i in 0..backend.db_count() {
+                let db = backend.read(i);
+                if !db.is_empty() {
+                    field(&format!("db{}", i), &format!("keys={},expires={}", db.len(), db.expires_len()));
+                }
+            }
+        }
+        "commandstats" => {
+            for stat in stats.command_stats() {
+                let per_call = stat.usec as f64 / stat.calls as f64;
+                let value = format!(
+                    "calls={},usec={},usec_per_call={:.2},failed_calls={}",
+                    stat.calls, stat.usec, per_call, stat.failed
+                );
+                field(&format!("cmdstat_{}", stat.name), &value);
+            }
+        }
+        _ => {}
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cmd::{
+            tests::{run, test_backend},
+            Session,
+        },
+        resp::RespFrame,
+    };
+
+    fn info(backend: &Backend, session: &mut Session, line: &str) -> String {
+        let RespFrame::BulkString(text) = run(backend, session, line) else { panic!("expect: bulk") };
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "set b 2");
+        run(&backend, &mut session, "expire b 100");
+        run(&backend, &mut session, "get a");
+        run(&backend, &mut session, "incr b x");
+
+        let text = info(&backend, &mut session, "info");
+        for header in ["# Server", "# Clients", "# Memory", "# Stats", "# Replication", "# Keyspace"] {
+            assert!(text.contains(header), "missing {}", header);
+        }
+        assert!(text.contains("\r\ndb0:keys=2,expires=1\r\n"));
+        assert!(text.contains("role:master"));
+        assert!(!text.contains("# Commandstats"));
+
+        let text = info(&backend, &mut session, "info commandstats");
+        assert!(text.starts_with("# Commandstats\r\n"));
+        assert!(text.contains("cmdstat_set:calls=2,"));
+        assert!(text.contains("cmdstat_get:calls=1,"));
+        // a wrong arity is rejected before the command runs, it isn't counted
+        assert!(!text.contains("cmdstat_incr"));
+
+        let text = info(&backend, &mut session, "info stats memory");
+        assert!(text.starts_with("# Memory\r\n") && text.contains("# Stats"));
+        assert!(!text.contains("# Server"));
+    }
+
+    #[test]
+    fn test_info_maxmemory_policy() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "config set maxmemory-policy allkeys-lru");
+        assert!(info(&backend, &mut session, "info memory").contains("\r\nmaxmemory_policy:allkeys-lru\r\n"));
+        run(&backend, &mut session, "config set maxmemory-policy volatile-ttl");
+        assert!(info(&backend, &mut session, "info memory").contains("\r\nmaxmemory_policy:volatile-ttl\r\n"));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::utils::{glob_match, now_ms};
+
+use super::{
+    arg_i64, arg_usize, array, bulk, bulk_array, eq_ignore_case, int, nil, ok, simple, CmdResult,
+    CommandError, CommandSpec, Context, ALL_KEYS, FAST, KEY1, NO_KEYS, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("del", -2, WRITE, ALL_KEYS, del),
+    CommandSpec::new("unlink", -2, WRITE | FAST, ALL_KEYS, del),
+    CommandSpec::new("exists", -2, READONLY | FAST, ALL_KEYS, exists),
+    CommandSpec::new("type", 2, READONLY | FAST, KEY1, type_),
+    CommandSpec::new("keys", 2, READONLY, NO_KEYS, keys),
+    CommandSpec::new("scan", -2, READONLY, NO_KEYS, scan),
+    CommandSpec::new("randomkey", 1, READONLY, NO_KEYS, randomkey),
+    CommandSpec::new("rename", 3, WRITE, (1, 2, 1), rename),
+    CommandSpec::new("renamenx", 3, WRITE | FAST, (1, 2, 1), renamenx),
+    CommandSpec::new("expire", -3, WRITE | FAST, KEY1, expire),
+    CommandSpec::new("pexpire", -3, WRITE | FAST, KEY1, pexpire),
+    CommandSpec::new("expireat", -3, WRITE | FAST, KEY1, expireat),
+    CommandSpec::new("pexpireat", -3, WRITE | FAST, KEY1, pexpireat),
+    CommandSpec::new("ttl", 2, READONLY | FAST, KEY1, ttl),
+    CommandSpec::new("pttl", 2, READONLY | FAST, KEY1, pttl),
+    CommandSpec::new("expiretime", 2, READONLY | FAST, KEY1, expiretime),
+    CommandSpec::new("pexpiretime", 2, READONLY | FAST, KEY1, pexpiretime),
+    CommandSpec::new("persist", 2, WRITE | FAST, KEY1, persist),
+    CommandSpec::new("move", 3, WRITE | FAST, KEY1, move_),
+];
+
+fn del(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let mut count = 0;
+    for key in &argv[1..] {
+        db.expire_if_needed(key);
+        if db.remove(key).is_some() {
+            count += 1;
+        }
+    }
+    Ok(int(count))
+}
+
+fn exists(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(argv[1..].iter().filter(|key| db.contains(key)).count()))
+}
+
+fn type_(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let name = db.get(&argv[1]).map(|v| v.type_name()).unwrap_or("none");
+    Ok(simple(name))
+}
+
+fn keys(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(db.keys().filter(|k| glob_match(&argv[1], k))))
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+fn scan(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let cursor = arg_usize(&argv[1]).map_err(|_| CommandError::Other("invalid cursor".into()))?;
+    let (mut pattern, mut count, mut type_name) = (None, 10, None);
+    let mut i = 2;
+    while i < argv.len() {
+        let value = argv.get(i + 1).ok_or(CommandError::Syntax)?;
+        if eq_ignore_case(&argv[i], "match") {
+            pattern = Some(value.as_slice());
+        } else if eq_ignore_case(&argv[i], "count") {
+            count = arg_usize(value)?;
+            if count == 0 {
+                return Err(CommandError::Syntax);
+            }
+        } else if eq_ignore_case(&argv[i], "type") {
+            type_name = Some(String::from_utf8_lossy(value).to_ascii_lowercase());
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        i += 2;
+    }
+
+    let db = ctx.read();
+    let (next, keys) = db.scan(cursor, count);
+    let keys = keys.into_iter().filter(|k| {
+        pattern.map(|p| glob_match(p, k)).unwrap_or(true)
+            && type_name
+                .as_deref()
+                .map(|t| db.get(k).map(|v| v.type_name() == t).unwrap_or(false))
+                .unwrap_or(true)
+    });
+    Ok(array([bulk(next.to_string()), bulk_array(keys)]))
+}
+
+fn randomkey(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(db.random_key().map(|k| bulk(k.clone())).unwrap_or_else(nil))
+}
+
+fn do_rename(ctx: &mut Context, argv: &[Vec<u8>], nx: bool) -> Result<bool, CommandError> {
+    let (src, dst) = (&argv[1], &argv[2]);
+    let mut db = ctx.write();
+    db.expire_if_needed(src);
+    if !db.contains(src) {
+        return Err(CommandError::NoSuchKey);
+    }
+    if src == dst {
+        return Ok(!nx);
+    }
+    if nx && db.contains(dst) {
+        return Ok(false);
+    }
+    let expire_at = db.expire_at(src);
+    let value = db.remove(src).ok_or(CommandError::NoSuchKey)?;
+    db.set(dst.clone(), value);
+    if let Some(at) = expire_at {
+        db.set_expire(dst, at);
+    }
+    Ok(true)
+}
+
+fn rename(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    do_rename(ctx, argv, false)?;
+    Ok(ok())
+}
+
+fn renamenx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(do_rename(ctx, argv, true)? as i64))
+}
+
+// EXPIRE key seconds [NX | XX | GT | LT], `at` is the absolute deadline in ms
+fn do_expire(ctx: &mut Context, argv: &[Vec<u8>], at: i64) -> CmdResult {
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for opt in &argv[3..] {
+        match String::from_utf8_lossy(opt).to_ascii_lowercase().as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "gt" => gt = true,
+            "lt" => lt = true,
+            _ => return Err(CommandError::Other(format!("Unsupported option {}", String::from_utf8_lossy(opt)))),
+        }
+    }
+    if (nx && (xx || gt || lt)) || (gt && lt) {
+        return Err(CommandError::Other(
+            "NX and XX, GT or LT options at the same time are not compatible".into(),
+        ));
+    }
+
+    let key = &argv[1];
+    let mut db = ctx.write();
+    // replicas get the absolute deadline, or the DEL when it's already reached
+    ctx.propagate = Some(vec![]);
+    db.expire_if_needed(key);
+    if !db.contains(key) {
+        return Ok(int(0));
+    }
+    let current = db.expire_at(key);
+    let at = at.max(0) as u64;
+    let allowed = match current {
+        // no ttl counts as an infinite ttl for GT / LT
+        None => !xx && !gt,
+        Some(cur) => !nx && (!gt || at > cur) && (!lt || at < cur),
+    };
+    if !allowed {
+        return Ok(int(0));
+    }
+    if at <= now_ms() {
+        db.remove(key);
+        ctx.propagate = Some(vec![vec![b"del".to_vec(), key.clone()]]);
+    } else {
+        db.set_expire(key, at);
+        ctx.propagate = Some(vec![vec![b"pexpireat".to_vec(), key.clone(), at.to_string().into_bytes()]]);
+    }
+    Ok(int(1))
+}
+
+fn expire(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let secs = arg_i64(&argv[2])?;
+    let at = secs
+        .checked_mul(1000)
+        .and_then(|ms| ms.checked_add(now_ms() as i64))
+        .ok_or_else(|| CommandError::InvalidExpire("expire".into()))?;
+    do_expire(ctx, argv, at)
+}
+
+fn pexpire(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let ms = arg_i64(&argv[2])?;
+    let at = ms
+        .checked_add(now_ms() as i64)
+        .ok_or_else(|| CommandError::InvalidExpire("pexpire".into()))?;
+    do_expire(ctx, argv, at)
+}
+
+fn expireat(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let secs = arg_i64(&argv[2])?;
+    let at = secs
+        .checked_mul(1000)
+        .ok_or_else(|| CommandError::InvalidExpire("expireat".into()))?;
+    do_expire(ctx, argv, at)
+}
+
+fn pexpireat(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let at = arg_i64(&argv[2])?;
+    do_expire(ctx, argv, at)
+}
+
+// -2 when the key doesn't exist, -1 when it has no ttl
+fn ttl_ms(ctx: &mut Context, key: &[u8]) -> i64 {
+    let db = ctx.read();
+    if !db.contains(key) {
+        return -2;
+    }
+    match db.expire_at(key) {
+        None => -1,
+        Some(at) => at.saturating_sub(now_ms()) as i64,
+    }
+}
+
+fn ttl(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let ms = ttl_ms(ctx, &argv[1]);
+    Ok(int(if ms < 0 { ms } else { (ms + 500) / 1000 }))
+}
+
+fn pttl(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(ttl_ms(ctx, &argv[1])))
+}
+
+fn expire_time_ms(ctx: &mut Context, key: &[u8]) -> i64 {
+    let db = ctx.read();
+    if !db.contains(key) {
+        return -2;
+    }
+    db.expire_at(key).map(|at| at as i64).unwrap_or(-1)
+}
+
+fn expiretime(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let at = expire_time_ms(ctx, &argv[1]);
+    Ok(int(if at < 0 { at } else { at / 1000 }))
+}
+
+fn pexpiretime(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(expire_time_ms(ctx, &argv[1])))
+}
+
+fn persist(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    db.expire_if_needed(&argv[1]);
+    Ok(int(db.persist(&argv[1]) as i64))
+}
+
+// MOVE key db: move the key from the selected db to another, fails if it exists there
+fn move_(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let target = arg_i64(&argv[2]).map_err(|_| CommandError::DbIndexOutOfRange)?;
+    if target < 0 || target as usize >= ctx.backend.db_count() {
+        return Err(CommandError::DbIndexOutOfRange);
+    }
+    let (src_index, dst_index) = (ctx.session.db, target as usize);
+    if src_index == dst_index {
+        return Err(CommandError::Other("source and destination objects are the same".into()));
+    }
+
+    let key = &argv[1];
+    let (mut src, mut dst) = ctx.backend.write_pair(src_index, dst_index, ctx.shards.as_deref());
+    src.expire_if_needed(key);
+    dst.expire_if_needed(key);
+    if !src.contains(key) || dst.contains(key) {
+        return Ok(int(0));
+    }
+    let expire_at = src.expire_at(key);
+    let Some(value) = src.remove(key) else {
+        return Ok(int(0));
+    };
+    dst.set(key.clone(), value);
+    if let Some(at) = expire_at {
+        dst.set_expire(key, at);
+    }
+    Ok(int(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        bulk, error_frame, int, nil, ok, simple,
+        tests::{run, test_backend},
+        CommandError, Session,
+    };
+
+    #[test]
+    fn test_del_exists_type() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "mset a 1 b 2");
+        assert_eq!(run(&backend, &mut session, "exists a b c a"), int(3));
+        assert_eq!(run(&backend, &mut session, "type a"), simple("string"));
+        assert_eq!(run(&backend, &mut session, "del a c"), int(1));
+        assert_eq!(run(&backend, &mut session, "type a"), simple("none"));
+    }
+
+    #[test]
+    fn test_expire_ttl() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "ttl a"), int(-2));
+        run(&backend, &mut session, "set a 1");
+        assert_eq!(run(&backend, &mut session, "ttl a"), int(-1));
+        assert_eq!(run(&backend, &mut session, "expire a 100"), int(1));
+        assert_eq!(run(&backend, &mut session, "ttl a"), int(100));
+        assert_eq!(run(&backend, &mut session, "expire a 200 lt"), int(0));
+        assert_eq!(run(&backend, &mut session, "expire a 50 nx"), int(0));
+        assert_eq!(run(&backend, &mut session, "persist a"), int(1));
+        assert_eq!(run(&backend, &mut session, "ttl a"), int(-1));
+        assert_eq!(run(&backend, &mut session, "pexpire a -1"), int(1));
+        assert_eq!(run(&backend, &mut session, "get a"), nil());
+    }
+
+    #[test]
+    fn test_rename() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "rename a b"), error_frame(CommandError::NoSuchKey));
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "set c 3");
+        assert_eq!(run(&backend, &mut session, "rename a b"), ok());
+        assert_eq!(run(&backend, &mut session, "renamenx b c"), int(0));
+        assert_eq!(run(&backend, &mut session, "get b"), bulk("1"));
+    }
+
+    #[test]
+    fn test_move() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "expire a 100");
+        assert_eq!(run(&backend, &mut session, "move a 16"), error_frame(CommandError::DbIndexOutOfRange));
+        assert_eq!(run(&backend, &mut session, "move a 3"), int(1));
+        assert_eq!(run(&backend, &mut session, "move a 3"), int(0));
+        assert_eq!(run(&backend, &mut session, "get a"), nil());
+
+        run(&backend, &mut session, "select 3");
+        assert_eq!(run(&backend, &mut session, "get a"), bulk("1"));
+        assert_eq!(run(&backend, &mut session, "ttl a"), int(100));
+
+        // the destination already holds the key
+        run(&backend, &mut session, "select 0");
+        run(&backend, &mut session, "set a 2");
+        assert_eq!(run(&backend, &mut session, "move a 3"), int(0));
+    }
+
+    #[test]
+    fn test_keys_scan() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "mset user:1 a user:2 b order:1 c");
+        let crate::resp::RespFrame::Array(keys) = run(&backend, &mut session, "keys user:*") else {
+            panic!("expect: array")
+        };
+        assert_eq!(keys.len(), 2);
+        let crate::resp::RespFrame::Array(reply) = run(&backend, &mut session, "scan 0 match order:* count 100") else {
+            panic!("expect: array")
+        };
+        assert_eq!(reply[0], bulk("0"));
+        assert_eq!(reply[1], crate::cmd::bulk_array(["order:1"]));
+    }
+}
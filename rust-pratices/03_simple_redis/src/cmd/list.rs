@@ -0,0 +1,239 @@
+use crate::backend::{Db, List, Value};
+
+use super::{
+    arg_i64, arg_usize, bulk, bulk_array, int, nil, nil_array, ok, string::normalize_range,
+    CmdResult, CommandError, CommandSpec, Context, DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("lpush", -3, WRITE | DENYOOM | FAST, KEY1, lpush),
+    CommandSpec::new("rpush", -3, WRITE | DENYOOM | FAST, KEY1, rpush),
+    CommandSpec::new("lpushx", -3, WRITE | DENYOOM | FAST, KEY1, lpushx),
+    CommandSpec::new("rpushx", -3, WRITE | DENYOOM | FAST, KEY1, rpushx),
+    CommandSpec::new("lpop", -2, WRITE | FAST, KEY1, lpop),
+    CommandSpec::new("rpop", -2, WRITE | FAST, KEY1, rpop),
+    CommandSpec::new("llen", 2, READONLY | FAST, KEY1, llen),
+    CommandSpec::new("lrange", 4, READONLY, KEY1, lrange),
+    CommandSpec::new("lindex", 3, READONLY, KEY1, lindex),
+    CommandSpec::new("lset", 4, WRITE | DENYOOM, KEY1, lset),
+    CommandSpec::new("lrem", 4, WRITE, KEY1, lrem),
+    CommandSpec::new("ltrim", 4, WRITE, KEY1, ltrim),
+];
+
+fn get_list<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a List>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::List(l)) => Ok(Some(l)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn get_list_mut<'a>(db: &'a mut Db, key: &[u8], create: bool) -> Result<Option<&'a mut List>, CommandError> {
+    if create && db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::List(List::default()));
+    }
+    match db.get_mut(key) {
+        None => Ok(None),
+        Some(Value::List(l)) => Ok(Some(l)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn push(ctx: &mut Context, argv: &[Vec<u8>], left: bool, create: bool) -> CmdResult {
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let Some(list) = get_list_mut(&mut db, &argv[1], create)? else {
+        return Ok(int(0));
+    };
+    for item in &argv[2..] {
+        if left {
+            list.push_front(item.clone(), &limits);
+        } else {
+            list.push_back(item.clone(), &limits);
+        }
+    }
+    Ok(int(list.len()))
+}
+
+fn lpush(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    push(ctx, argv, true, true)
+}
+
+fn rpush(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    push(ctx, argv, false, true)
+}
+
+fn lpushx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    push(ctx, argv, true, false)
+}
+
+fn rpushx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    push(ctx, argv, false, false)
+}
+
+// LPOP key [count]
+fn pop(ctx: &mut Context, argv: &[Vec<u8>], left: bool) -> CmdResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = argv.get(2).map(|c| arg_usize(c)).transpose()?;
+
+    let mut db = ctx.write();
+    let Some(list) = get_list_mut(&mut db, &argv[1], false)? else {
+        return Ok(if count.is_some() { nil_array() } else { nil() });
+    };
+    let mut popped = Vec::new();
+    for _ in 0..count.unwrap_or(1) {
+        let item = if left { list.pop_front() } else { list.pop_back() };
+        match item {
+            Some(item) => popped.push(item),
+            None => break,
+        }
+    }
+    db.remove_if_empty(&argv[1]);
+
+    Ok(match count {
+        Some(_) => bulk_array(popped),
+        None => popped.pop().map(bulk).unwrap_or_else(nil),
+    })
+}
+
+fn lpop(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    pop(ctx, argv, true)
+}
+
+fn rpop(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    pop(ctx, argv, false)
+}
+
+fn llen(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_list(&db, &argv[1])?.map(|l| l.len()).unwrap_or(0)))
+}
+
+fn lrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (start, end) = (arg_i64(&argv[2])?, arg_i64(&argv[3])?);
+    let db = ctx.read();
+    let Some(list) = get_list(&db, &argv[1])? else {
+        return Ok(bulk_array(Vec::<Vec<u8>>::new()));
+    };
+    Ok(match normalize_range(start, end, list.len()) {
+        Some((start, end)) => bulk_array(list.iter().skip(start).take(end - start + 1)),
+        None => bulk_array(Vec::<Vec<u8>>::new()),
+    })
+}
+
+fn list_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (index >= 0 && (index as usize) < len).then_some(index as usize)
+}
+
+fn lindex(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let index = arg_i64(&argv[2])?;
+    let db = ctx.read();
+    let Some(list) = get_list(&db, &argv[1])? else {
+        return Ok(nil());
+    };
+    Ok(list_index(index, list.len()).and_then(|i| list.get(i)).map(bulk).unwrap_or_else(nil))
+}
+
+fn lset(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let index = arg_i64(&argv[2])?;
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let list = get_list_mut(&mut db, &argv[1], false)?.ok_or(CommandError::NoSuchKey)?;
+    let i = list_index(index, list.len()).ok_or_else(|| CommandError::Other("index out of range".into()))?;
+    list.set(i, argv[3].clone(), &limits);
+    Ok(ok())
+}
+
+// LREM key count element: count > 0 from head, < 0 from tail, 0 all
+fn lrem(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let count = arg_i64(&argv[2])?;
+    let element = &argv[3];
+    let mut db = ctx.write();
+    let Some(list) = get_list_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+
+    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+    let mut removed = 0;
+    if count >= 0 {
+        let mut i = 0;
+        while i < list.len() && removed < limit {
+            if list.get(i) == Some(element) {
+                list.remove(i);
+                removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+    } else {
+        let mut i = list.len();
+        while i > 0 && removed < limit {
+            i -= 1;
+            if list.get(i) == Some(element) {
+                list.remove(i);
+                removed += 1;
+            }
+        }
+    }
+    db.remove_if_empty(&argv[1]);
+    Ok(int(removed))
+}
+
+fn ltrim(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (start, end) = (arg_i64(&argv[2])?, arg_i64(&argv[3])?);
+    let mut db = ctx.write();
+    let Some(list) = get_list_mut(&mut db, &argv[1], false)? else {
+        return Ok(ok());
+    };
+    match normalize_range(start, end, list.len()) {
+        Some((start, end)) => list.trim(start, end),
+        None => list.clear(),
+    }
+    db.remove_if_empty(&argv[1]);
+    Ok(ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        bulk, bulk_array, error_frame, int, nil, ok,
+        tests::{run, test_backend},
+        CommandError, Session,
+    };
+
+    #[test]
+    fn test_push_pop() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "lpushx l a"), int(0));
+        assert_eq!(run(&backend, &mut session, "rpush l a b c"), int(3));
+        assert_eq!(run(&backend, &mut session, "lpush l z"), int(4));
+        assert_eq!(run(&backend, &mut session, "lrange l 0 -1"), bulk_array(["z", "a", "b", "c"]));
+        assert_eq!(run(&backend, &mut session, "lpop l"), bulk("z"));
+        assert_eq!(run(&backend, &mut session, "rpop l 2"), bulk_array(["c", "b"]));
+        assert_eq!(run(&backend, &mut session, "rpop l"), bulk("a"));
+        assert_eq!(run(&backend, &mut session, "exists l"), int(0));
+        assert_eq!(run(&backend, &mut session, "lpop l"), nil());
+    }
+
+    #[test]
+    fn test_lindex_lset_lrem_ltrim() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "rpush l a b a c a");
+        assert_eq!(run(&backend, &mut session, "lindex l -1"), bulk("a"));
+        assert_eq!(run(&backend, &mut session, "lset l 1 B"), ok());
+        assert_eq!(run(&backend, &mut session, "lset l 10 B"), error_frame(CommandError::Other("index out of range".into())));
+        assert_eq!(run(&backend, &mut session, "lrem l -2 a"), int(2));
+        assert_eq!(run(&backend, &mut session, "lrange l 0 -1"), bulk_array(["a", "B", "c"]));
+        assert_eq!(run(&backend, &mut session, "ltrim l 1 -1"), ok());
+        assert_eq!(run(&backend, &mut session, "lrange l 0 -1"), bulk_array(["B", "c"]));
+        assert_eq!(run(&backend, &mut session, "llen l"), int(2));
+
+        run(&backend, &mut session, "set s 1");
+        assert_eq!(run(&backend, &mut session, "lpush s a"), error_frame(CommandError::WrongType));
+    }
+}
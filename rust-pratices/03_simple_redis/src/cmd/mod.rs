@@ -0,0 +1,820 @@
+pub mod acl;
+pub mod bitmap;
+pub mod client;
+pub mod cluster;
+pub mod config;
+pub mod connection;
+pub mod diagnostics;
+pub mod dump;
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod info;
+pub mod keys;
+pub mod list;
+pub mod object;
+pub mod pubsub;
+pub mod replication;
+pub mod scripting;
+pub mod server;
+pub mod set;
+pub mod stream;
+pub mod string;
+pub mod transaction;
+pub mod zset;
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, OnceLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Instant,
+};
+
+use thiserror::Error;
+
+use crate::{
+    acl::Denied,
+    backend::{blocking::Blocked, monitor::Monitor, Backend, DbReadGuard, DbWriteGuard, UndeclaredKey},
+    pubsub::{self as events, Subscriber},
+    connections::{ClientAddr, Conn},
+    replication::ReplicaFeed,
+    resp::{Map, NullArray, NullBulkString, RespFrame, SimpleError, SimpleString},
+    tracking::{Invalidations, TrackingOptions},
+    utils::{parse_f64, parse_i64},
+};
+
+// command flags, the same meaning as in redis `COMMAND INFO`
+pub const WRITE: u32 = 1 << 0;
+pub const READONLY: u32 = 1 << 1;
+// may grow memory, rejected when out of memory
+pub const DENYOOM: u32 = 1 << 2;
+pub const ADMIN: u32 = 1 << 3;
+pub const FAST: u32 = 1 << 4;
+// executed immediately even inside MULTI
+pub const NO_MULTI: u32 = 1 << 5;
+// runs with every other command locked out, e.g. EXEC
+pub const EXCLUSIVE: u32 = 1 << 6;
+// not allowed from redis.call in a script
+pub const NOSCRIPT: u32 = 1 << 7;
+// allowed before authentication, and not subject to ACL rules
+pub const NO_AUTH: u32 = 1 << 8;
+// runs without the command lock and takes it itself around its local steps, e.g. MIGRATE
+// that waits on another server meanwhile. Not allowed in MULTI or scripts, they hold it.
+pub const UNLOCKED: u32 = 1 << 9;
+
+pub type Argv = Vec<Vec<u8>>;
+pub type CmdResult = Result<RespFrame, CommandError>;
+pub type Handler = fn(&mut Context, &[Vec<u8>]) -> CmdResult;
+// the key positions of a command whose keys can't be described by first / last / step
+pub type KeysFn = fn(&[Vec<u8>]) -> Vec<usize>;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CommandError {
+    #[error("ERR unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR DB index is out of range")]
+    DbIndexOutOfRange,
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpire(String),
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    Oom,
+    #[error("READONLY You can't write against a read only replica.")]
+    ReadOnly,
+    #[error("MOVED {0} {1}")]
+    Moved(u16, String),
+    #[error("ASK {0} {1}")]
+    Ask(u16, String),
+    #[error("CROSSSLOT Keys in request don't hash to the same slot")]
+    CrossSlot,
+    #[error("CLUSTERDOWN Hash slot not served")]
+    ClusterDown,
+    #[error("TRYAGAIN Multiple keys request during rehashing of slot")]
+    TryAgain,
+    #[error("NOSCRIPT No matching script. Please use EVAL.")]
+    NoScript,
+    #[error("NOAUTH Authentication required.")]
+    NoAuth,
+    #[error("NOPERM {0}")]
+    NoPerm(String),
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+    #[error("ERR {0}")]
+    Other(String),
+    // an error with its own prefix, e.g. EXECABORT
+    #[error("{0}")]
+    Raw(String),
+}
+
+// A command as redis describes it: `arity` counts the name too, negative means "at least".
+// first_key / last_key / step locate the keys in argv (last_key -1 is the last argument).
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i32,
+    pub flags: u32,
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+    pub handler: Handler,
+    pub movable_keys: Option<KeysFn>,
+    // flags added by a subcommand (argv[1]) on top of the command's own
+    pub subcommand_flags: &'static [(&'static str, u32)],
+}
+
+impl CommandSpec {
+    pub const fn new(
+        name: &'static str,
+        arity: i32,
+        flags: u32,
+        (first_key, last_key, step): (i32, i32, i32),
+        handler: Handler,
+    ) -> Self {
+        Self { name, arity, flags, first_key, last_key, step, handler, movable_keys: None, subcommand_flags: &[] }
+    }
+
+    pub const fn with_movable_keys(mut self, keys: KeysFn) -> Self {
+        self.movable_keys = Some(keys);
+        self
+    }
+
+    pub const fn with_subcommand_flags(mut self, flags: &'static [(&'static str, u32)]) -> Self {
+        self.subcommand_flags = flags;
+        self
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    // the same, counting the flags of the subcommand argv names
+    pub fn has_flag_for(&self, argv: &[Vec<u8>], flag: u32) -> bool {
+        let sub = argv.get(1).map(|s| s.to_ascii_lowercase());
+        let sub_flags = self.subcommand_flags.iter().filter(|(name, _)| sub.as_deref() == Some(name.as_bytes()));
+        (self.flags | sub_flags.fold(0, |acc, (_, f)| acc | f)) & flag != 0
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    // the key arguments of argv
+    pub fn keys<'a>(&self, argv: &'a [Vec<u8>]) -> Vec<&'a [u8]> {
+        if let Some(keys) = self.movable_keys {
+            return keys(argv).into_iter().filter_map(|i| argv.get(i).map(|k| k.as_slice())).collect();
+        }
+        if self.first_key <= 0 {
+            return vec![];
+        }
+        let last = if self.last_key < 0 {
+            argv.len() as i32 + self.last_key
+        } else {
+            self.last_key.min(argv.len() as i32 - 1)
+        };
+        (self.first_key..=last)
+            .step_by(self.step.max(1) as usize)
+            .filter_map(|i| argv.get(i as usize).map(|k| k.as_slice()))
+            .collect()
+    }
+}
+
+// no keys
+pub const NO_KEYS: (i32, i32, i32) = (0, 0, 0);
+// argv[1] is the only key
+pub const KEY1: (i32, i32, i32) = (1, 1, 1);
+// every argument is a key
+pub const ALL_KEYS: (i32, i32, i32) = (1, -1, 1);
+
+// every command module with the ACL category its commands belong to
+const COMMAND_GROUPS: &[(Option<&str>, &[CommandSpec])] = &[
+    (None, acl::COMMANDS),
+    (Some("bitmap"), bitmap::COMMANDS),
+    (Some("connection"), client::COMMANDS),
+    (None, cluster::COMMANDS),
+    (None, config::COMMANDS),
+    (Some("connection"), connection::COMMANDS),
+    (None, diagnostics::COMMANDS),
+    (Some("keyspace"), dump::COMMANDS),
+    (Some("geo"), geo::COMMANDS),
+    (Some("hash"), hash::COMMANDS),
+    (Some("hyperloglog"), hyperloglog::COMMANDS),
+    (None, info::COMMANDS),
+    (Some("keyspace"), keys::COMMANDS),
+    (Some("list"), list::COMMANDS),
+    (Some("keyspace"), object::COMMANDS),
+    (Some("pubsub"), pubsub::COMMANDS),
+    (None, replication::COMMANDS),
+    (Some("scripting"), scripting::COMMANDS),
+    (Some("keyspace"), server::COMMANDS),
+    (Some("set"), set::COMMANDS),
+    (Some("sortedset"), zset::COMMANDS),
+    (Some("stream"), stream::COMMANDS),
+    (Some("string"), string::COMMANDS),
+    (Some("transaction"), transaction::COMMANDS),
+];
+
+fn command_table() -> &'static HashMap<&'static str, (&'static CommandSpec, Option<&'static str>)> {
+    static TABLE: OnceLock<HashMap<&'static str, (&'static CommandSpec, Option<&'static str>)>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        COMMAND_GROUPS
+            .iter()
+            .flat_map(|(group, specs)| specs.iter().map(move |spec| (spec.name, (spec, *group))))
+            .collect()
+    })
+}
+
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+    command_table().get(name.as_str()).map(|(spec, _)| *spec)
+}
+
+pub fn all_commands() -> impl Iterator<Item = &'static CommandSpec> {
+    command_table().values().map(|(spec, _)| *spec)
+}
+
+// the ACL categories of a command: its data type or area, plus the ones its flags imply
+pub fn command_categories(spec: &CommandSpec) -> Vec<&'static str> {
+    let mut categories: Vec<&'static str> = command_table()
+        .get(spec.name)
+        .and_then(|(_, group)| *group)
+        .into_iter()
+        .collect();
+    for (flag, category) in [(WRITE, "write"), (READONLY, "read"), (ADMIN, "admin"), (ADMIN, "dangerous"), (FAST, "fast")] {
+        if spec.has_flag(flag) {
+            categories.push(category);
+        }
+    }
+    if !spec.has_flag(FAST) {
+        categories.push("slow");
+    }
+    categories
+}
+
+// State of one client connection
+#[derive(Debug, Default)]
+pub struct Session {
+    // the selected database
+    pub db: usize,
+    // commands queued after MULTI, None when not in a transaction
+    pub multi: Option<Vec<Argv>>,
+    // an error happened while queueing, EXEC must abort
+    pub multi_error: bool,
+    // set by QUIT, the connection is closed after the reply is sent
+    pub closing: bool,
+    pub addr: Option<ClientAddr>,
+    // the link to our master, its writes are allowed on a read only replica
+    pub is_master: bool,
+    // the port a replica announced with REPLCONF listening-port
+    pub listening_port: Option<u16>,
+    // set by PSYNC, the connection then only carries the replication stream
+    pub replica_feed: Option<ReplicaFeed>,
+    // set by ASKING, lets the next command run on a slot being imported
+    pub asking: bool,
+    // inside EXEC or a script whose writes are already wrapped in MULTI / EXEC
+    // in the replication stream
+    pub in_atomic: bool,
+    // the registry entry of the connection, None for internal sessions
+    pub conn: Option<Arc<Conn>>,
+    // the ACL user, None for internal sessions which aren't checked
+    pub user: Option<String>,
+    // AUTH succeeded, or the user needs no password
+    pub authenticated: bool,
+    // the channel and pattern subscriptions, from the first SUBSCRIBE on
+    pub subscriber: Option<Subscriber>,
+    // set by a blocking command that found nothing, the connection waits and
+    // runs it again; EXEC and scripts clear it, they never block
+    pub blocked: Option<Blocked>,
+    // set by MONITOR, the connection then gets every executed command
+    pub monitor: Option<Monitor>,
+    // HELLO 3 switched the connection to RESP3, it can take push messages
+    pub resp3: bool,
+    // set by CLIENT TRACKING ON
+    pub tracking: Option<TrackingOptions>,
+    // CLIENT CACHING yes / no, for the next command or transaction
+    pub caching: Option<bool>,
+    // where the client side caching invalidations for the connection arrive
+    pub invalidations: Option<Invalidations>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // with subscriptions a RESP2 connection only takes (un)subscribe commands
+    pub fn is_subscribed(&self) -> bool {
+        self.subscriber.as_ref().is_some_and(|s| s.count() > 0)
+    }
+
+    pub fn client_id(&self) -> Option<u64> {
+        self.conn.as_ref().map(|conn| conn.id)
+    }
+}
+
+pub struct Context<'a> {
+    pub backend: &'a Backend,
+    pub session: &'a mut Session,
+    // what a write sends to replicas instead of its argv, e.g. a relative
+    // expire rewritten as an absolute one so the replica ends up the same
+    pub propagate: Option<Vec<Argv>>,
+    // the shards of the command's keys, None when it has no keys and may touch any
+    pub shards: Option<Vec<usize>>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(backend: &'a Backend, session: &'a mut Session, shards: Option<Vec<usize>>) -> Self {
+        Self { backend, session, propagate: None, shards }
+    }
+
+    // the selected db with the shards of the command's keys locked
+    pub fn read(&self) -> DbReadGuard<'a> {
+        self.backend.read_shards(self.session.db, self.shards.as_deref())
+    }
+
+    pub fn write(&self) -> DbWriteGuard<'a> {
+        self.backend.write_shards(self.session.db, self.shards.as_deref())
+    }
+}
+
+// turn a request frame into argv, clients send an array of bulk strings
+pub fn frame_to_argv(frame: RespFrame) -> Result<Argv, CommandError> {
+    let RespFrame::Array(items) = frame else {
+        return Err(CommandError::Other("Protocol error: expected an array of bulk strings".into()));
+    };
+    items
+        .into_iter()
+        .map(|item| match item {
+            RespFrame::BulkString(b) => Ok(b),
+            RespFrame::SimpleString(s) => Ok(s.as_str().as_bytes().to_vec()),
+            RespFrame::Integer(n) => Ok(n.to_string().into_bytes()),
+            _ => Err(CommandError::Other("Protocol error: expected bulk strings".into())),
+        })
+        .collect()
+}
+
+pub fn execute(backend: &Backend, session: &mut Session, frame: RespFrame) -> RespFrame {
+    match frame_to_argv(frame) {
+        Ok(argv) if argv.is_empty() => RespFrame::Array(vec![]),
+        Ok(argv) => execute_argv(backend, session, argv),
+        Err(e) => error_frame(e),
+    }
+}
+
+pub fn execute_argv(backend: &Backend, session: &mut Session, argv: Argv) -> RespFrame {
+    // ASKING only holds for the command right after it
+    let asking = std::mem::take(&mut session.asking);
+    let spec = match lookup(&argv[0]) {
+        Some(spec) => spec,
+        None => {
+            session.multi_error |= session.multi.is_some();
+            // like redis, echo at most 128 bytes of the name back
+            let name = &argv[0][..argv[0].len().min(128)];
+            return error_frame(CommandError::UnknownCommand(String::from_utf8_lossy(name).into_owned()));
+        }
+    };
+    if !spec.check_arity(argv.len()) {
+        session.multi_error |= session.multi.is_some();
+        return error_frame(CommandError::WrongArity(spec.name.to_string()));
+    }
+    if let Err(e) = check_acl(backend, session, spec, &argv) {
+        session.multi_error |= session.multi.is_some();
+        return error_frame(e);
+    }
+    if session.is_subscribed() && !SUBSCRIBED_COMMANDS.contains(&spec.name) {
+        return error_frame(CommandError::Other(format!(
+            "Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context",
+            spec.name
+        )));
+    }
+
+    let guard = if spec.has_flag(EXCLUSIVE) {
+        CallGuard::Exclusive(backend.exclusive())
+    } else {
+        CallGuard::Shared(backend.shared())
+    };
+
+    // like redis, evict before every command and refuse the ones that may grow memory
+    // when that wasn't enough. EXEC is refused when it would run such a command.
+    if !backend.perform_evictions() && denies_oom(session, spec) {
+        session.multi_error |= session.multi.is_some();
+        return error_frame(CommandError::Oom);
+    }
+    if spec.has_flag(WRITE) && !session.is_master && backend.config().replica_read_only && backend.repl.is_replica() {
+        session.multi_error |= session.multi.is_some();
+        return error_frame(CommandError::ReadOnly);
+    }
+    if let Err(e) = cluster::check_redirect(backend, session, spec, &argv, asking) {
+        session.multi_error |= session.multi.is_some();
+        return error_frame(e);
+    }
+
+    if session.multi.is_some() && spec.has_flag(UNLOCKED) {
+        session.multi_error = true;
+        return error_frame(CommandError::Other("Command not allowed inside a transaction".into()));
+    }
+    if let Some(queue) = session.multi.as_mut() {
+        if !spec.has_flag(NO_MULTI) {
+            queue.push(argv);
+            return simple("QUEUED");
+        }
+    }
+    if spec.has_flag(UNLOCKED) {
+        drop(guard);
+    }
+    let reply = call(backend, session, spec, &argv);
+    // CLIENT CACHING holds for the command after it, a whole transaction included
+    if spec.name != "client" {
+        session.caching = None;
+    }
+    reply
+}
+
+// what a connection with subscriptions may run
+const SUBSCRIBED_COMMANDS: &[&str] = &["subscribe", "unsubscribe", "psubscribe", "punsubscribe", "ping", "quit"];
+
+// NOAUTH until the connection authenticates, then NOPERM for the commands and
+// keys its user may not use
+pub fn check_acl(backend: &Backend, session: &Session, spec: &CommandSpec, argv: &[Vec<u8>]) -> Result<(), CommandError> {
+    let Some(user) = session.user.as_deref() else {
+        return Ok(());
+    };
+    if spec.has_flag(NO_AUTH) {
+        return Ok(());
+    }
+    if !session.authenticated {
+        return Err(CommandError::NoAuth);
+    }
+    backend.acl.check(user, spec, argv).map_err(|denied| {
+        CommandError::NoPerm(match denied {
+            Denied::Command => format!("User {} has no permissions to run the '{}' command", user, spec.name),
+            Denied::Key => "No permissions to access a key".into(),
+            Denied::Channel => "No permissions to access a channel".into(),
+        })
+    })
+}
+
+fn denies_oom(session: &Session, spec: &CommandSpec) -> bool {
+    if spec.name == "exec" {
+        let queued = session.multi.iter().flatten();
+        return queued.filter_map(|argv| lookup(&argv[0])).any(|spec| spec.has_flag(DENYOOM));
+    }
+    spec.has_flag(DENYOOM)
+}
+
+#[allow(dead_code)]
+enum CallGuard<'a> {
+    Shared(RwLockReadGuard<'a, ()>),
+    Exclusive(RwLockWriteGuard<'a, ()>),
+}
+
+// run a command that already passed lookup and arity checks
+pub fn call(backend: &Backend, session: &mut Session, spec: &CommandSpec, argv: &[Vec<u8>]) -> RespFrame {
+    let db = session.db;
+    // a write runs and propagates under the order lock, replicas apply writes in the same order.
+    // An UNLOCKED command takes it with the command lock and propagates itself.
+    let order =
+        (spec.has_flag(WRITE) && !spec.has_flag(UNLOCKED) && backend.repl.is_enabled()).then(|| backend.repl.order());
+    // like redis, admin commands aren't shown and passwords are hidden
+    let argv_shown = match spec.name {
+        "auth" => Cow::Owned(vec![argv[0].clone(), b"(redacted)".to_vec()]),
+        _ => Cow::Borrowed(argv),
+    };
+    if !spec.has_flag_for(argv, ADMIN) {
+        backend.monitors.feed(db, &client_addr(session), &argv_shown);
+    }
+    let has_keys = spec.first_key > 0 || spec.movable_keys.is_some();
+    let shards = has_keys.then(|| backend.shards_of(&spec.keys(argv)));
+    // remembered before reading, a write right after the read can't miss it
+    if let Some(tracking) = session.tracking.as_ref().filter(|_| spec.has_flag(READONLY) && has_keys) {
+        if let Some(id) = session.client_id().filter(|_| tracking.tracks_reads(session.caching)) {
+            backend.tracking.remember(id, &spec.keys(argv));
+        }
+    }
+    let mut ctx = Context::new(backend, session, shards);
+    let start = Instant::now();
+    // a key missing from the key spec fails the command rather than being locked out of order
+    let result = panic::catch_unwind(AssertUnwindSafe(|| (spec.handler)(&mut ctx, argv))).unwrap_or_else(|e| {
+        if !e.is::<UndeclaredKey>() {
+            panic::resume_unwind(e);
+        }
+        Err(CommandError::Other(format!("'{}' used a key its key spec doesn't declare", spec.name)))
+    });
+    let elapsed = start.elapsed();
+    backend.stats.record(spec.name, elapsed, result.is_err());
+    if backend.slowlog.is_slow(elapsed) {
+        let name = ctx.session.conn.as_ref().and_then(|conn| conn.state().name.clone()).unwrap_or_default();
+        backend.slowlog.record(&argv_shown, elapsed, &client_addr(ctx.session), &name);
+    }
+    backend.latency.record(if spec.has_flag(FAST) { "fast-command" } else { "command" }, elapsed);
+    match result {
+        Ok(frame) => {
+            if spec.has_flag(WRITE) {
+                backend.add_dirty(1);
+                notify_write(backend, db, spec, argv, &frame);
+                backend.blocking.signal(db, &spec.keys(argv));
+                backend.tracking.invalidate(&spec.keys(argv), ctx.session.client_id());
+            }
+            if order.is_some() {
+                let commands = ctx.propagate.take().unwrap_or_else(|| vec![argv.to_vec()]);
+                backend.repl.propagate(db, &commands);
+            }
+            frame
+        }
+        Err(e) => error_frame(e),
+    }
+}
+
+// how MONITOR and SLOWLOG show the client
+fn client_addr(session: &Session) -> String {
+    session.addr.as_ref().map_or_else(|| "internal".into(), |addr| addr.to_string())
+}
+
+// Publish the keyspace events of a write. Most events are named after the command
+// like in redis; a 0 or nil reply means nothing changed and there is no event.
+fn notify_write(backend: &Backend, db: usize, spec: &CommandSpec, argv: &[Vec<u8>], reply: &RespFrame) {
+    let pubsub = &backend.pubsub;
+    if pubsub.notify_flags() == 0 {
+        return;
+    }
+    let sets_anyway = spec.name == "getset" || (spec.name == "set" && argv.iter().any(|a| eq_ignore_case(a, "get")));
+    match reply {
+        // the previous bit
+        RespFrame::Integer(0) if spec.name != "setbit" => return,
+        RespFrame::NullBulkString(_) | RespFrame::NullArray(_) if !sets_anyway => return,
+        _ => {}
+    }
+    let class = match command_table().get(spec.name).and_then(|(_, group)| *group) {
+        Some("string" | "bitmap" | "hyperloglog") => events::STRING,
+        Some("list") => events::LIST,
+        Some("set") => events::SET,
+        Some("hash") => events::HASH,
+        Some("sortedset" | "geo") => events::ZSET,
+        Some("stream") => events::STREAM,
+        _ => events::GENERIC,
+    };
+    let keys = spec.keys(argv);
+    let notify = |class, event, key: &[u8]| pubsub.notify_keyspace_event(class, event, key, db);
+    match spec.name {
+        "rename" | "renamenx" => {
+            notify(events::GENERIC, "rename_from", keys[0]);
+            notify(events::GENERIC, "rename_to", keys[1]);
+        }
+        "move" => {
+            notify(events::GENERIC, "move_from", keys[0]);
+            if let Some(to) = parse_i64(&argv[2]) {
+                pubsub.notify_keyspace_event(events::GENERIC, "move_to", keys[0], to as usize);
+            }
+        }
+        "smove" => {
+            notify(class, "srem", keys[0]);
+            notify(class, "sadd", keys[1]);
+        }
+        "setex" | "psetex" => {
+            notify(class, "set", keys[0]);
+            notify(events::GENERIC, "expire", keys[0]);
+        }
+        // only the destination changes
+        "sinterstore" | "sunionstore" | "sdiffstore" => notify(class, spec.name, keys[0]),
+        "xgroup" => notify(class, &format!("xgroup-{}", arg_str(&argv[1])), keys[0]),
+        "bitop" => notify(class, "set", keys[0]),
+        "bitfield" => {
+            if argv.iter().any(|a| eq_ignore_case(a, "set") || eq_ignore_case(a, "incrby")) {
+                notify(class, "setbit", keys[0]);
+            }
+        }
+        // only the groups change, redis has no events for them
+        "xreadgroup" | "xack" | "xclaim" => {}
+        // MIGRATE notifies the keys it deleted itself
+        "migrate" => {}
+        name => {
+            let event = match name {
+                "unlink" | "getdel" => "del",
+                "expire" | "pexpire" | "expireat" | "pexpireat" => "expire",
+                "setnx" | "getset" | "mset" | "msetnx" => "set",
+                "incr" | "decr" | "decrby" => "incrby",
+                "lpushx" => "lpush",
+                "rpushx" => "rpush",
+                "hmset" | "hsetnx" => "hset",
+                "pfmerge" => "pfadd",
+                "geoadd" => "zadd",
+                name => name,
+            };
+            for key in keys {
+                notify(class, event, key);
+            }
+        }
+    }
+}
+
+// the message may echo what the client sent, a CR or LF in it would end the
+// simple error early and let the rest pass as another reply
+pub fn error_frame(e: CommandError) -> RespFrame {
+    let msg = e.to_string().replace(['\r', '\n'], " ");
+    RespFrame::Error(SimpleError::new(msg))
+}
+
+// reply helpers
+
+pub fn ok() -> RespFrame {
+    simple("OK")
+}
+
+pub fn simple(v: impl Into<String>) -> RespFrame {
+    RespFrame::SimpleString(SimpleString::new(v))
+}
+
+pub fn bulk(v: impl Into<Vec<u8>>) -> RespFrame {
+    RespFrame::BulkString(v.into())
+}
+
+pub fn nil() -> RespFrame {
+    RespFrame::NullBulkString(NullBulkString)
+}
+
+pub fn nil_array() -> RespFrame {
+    RespFrame::NullArray(NullArray)
+}
+
+pub fn int(v: impl TryInto<i64>) -> RespFrame {
+    RespFrame::Integer(v.try_into().unwrap_or(i64::MAX))
+}
+
+pub fn array(items: impl IntoIterator<Item = RespFrame>) -> RespFrame {
+    RespFrame::Array(items.into_iter().collect())
+}
+
+// a map for RESP3 connections, its keys and values in a flat array for RESP2 ones
+pub fn map_reply(session: &Session, entries: impl IntoIterator<Item = (&'static str, RespFrame)>) -> RespFrame {
+    if session.resp3 {
+        let mut map = Map::new();
+        map.extend(entries.into_iter().map(|(k, v)| (k.to_string(), v)));
+        RespFrame::Map(map)
+    } else {
+        array(entries.into_iter().flat_map(|(k, v)| [bulk(k), v]))
+    }
+}
+
+pub fn bulk_array<T: AsRef<[u8]>>(items: impl IntoIterator<Item = T>) -> RespFrame {
+    array(items.into_iter().map(|v| bulk(v.as_ref())))
+}
+
+// the reply of `<COMMAND> HELP` like redis: a usage line, the subcommands, then HELP itself
+pub fn help_reply(name: &str, lines: &[&str]) -> RespFrame {
+    let usage = format!("{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:", name);
+    let help = ["HELP", "    Print this help."];
+    array(std::iter::once(usage).chain(lines.iter().chain(&help).map(|l| l.to_string())).map(simple))
+}
+
+// argument helpers
+
+pub fn arg_i64(v: &[u8]) -> Result<i64, CommandError> {
+    parse_i64(v).ok_or(CommandError::NotInteger)
+}
+
+pub fn arg_f64(v: &[u8]) -> Result<f64, CommandError> {
+    parse_f64(v).ok_or(CommandError::NotFloat)
+}
+
+pub fn arg_usize(v: &[u8]) -> Result<usize, CommandError> {
+    usize::try_from(arg_i64(v)?).map_err(|_| CommandError::NotInteger)
+}
+
+pub fn arg_str(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).to_ascii_lowercase()
+}
+
+// case-insensitive compare of an option argument, e.g. `eq_ignore_case(arg, "nx")`
+pub fn eq_ignore_case(v: &[u8], option: &str) -> bool {
+    v.eq_ignore_ascii_case(option.as_bytes())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        backend::Backend,
+        config::Config,
+        resp::{RespEncode, RespFrame},
+    };
+
+    use super::{
+        bulk, call, error_frame, execute_argv, int, lookup, nil, ok, CmdResult, CommandError, CommandSpec, Context,
+        Session, KEY1, READONLY,
+    };
+
+    // run a command line like `SET a 1` against the backend
+    pub(crate) fn run(backend: &Backend, session: &mut Session, line: &str) -> RespFrame {
+        let argv = line.split_whitespace().map(|s| s.as_bytes().to_vec()).collect();
+        execute_argv(backend, session, argv)
+    }
+
+    pub(crate) fn test_backend() -> Backend {
+        Backend::new(Config { databases: 16, dir: std::env::temp_dir(), ..Config::default() })
+    }
+
+    #[test]
+    fn test_lookup_and_arity() {
+        let spec = lookup(b"GeT").unwrap();
+        assert_eq!(spec.name, "get");
+        assert!(spec.check_arity(2));
+        assert!(!spec.check_arity(3));
+
+        let spec = lookup(b"mset").unwrap();
+        assert!(spec.check_arity(5));
+        assert!(!spec.check_arity(2));
+    }
+
+    #[test]
+    fn test_spec_keys() {
+        let argv: Vec<Vec<u8>> = ["mset", "a", "1", "b", "2"].iter().map(|s| s.as_bytes().to_vec()).collect();
+        let keys = lookup(b"mset").unwrap().keys(&argv);
+        assert_eq!(keys, vec![b"a".as_slice(), b"b".as_slice()]);
+
+        let argv: Vec<Vec<u8>> = ["del", "a", "b", "c"].iter().map(|s| s.as_bytes().to_vec()).collect();
+        assert_eq!(lookup(b"del").unwrap().keys(&argv).len(), 3);
+    }
+
+    // a command reading a key its spec doesn't declare: debug builds stop at the access,
+    // release ones fail the command instead of locking the shard out of order
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "key spec misses it"))]
+    fn test_undeclared_key() {
+        fn get_second(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+            Ok(ctx.read().get(&argv[2]).map_or_else(nil, |_| ok()))
+        }
+        const SPEC: CommandSpec = CommandSpec::new("getsecond", 3, READONLY, KEY1, get_second);
+
+        let backend = test_backend();
+        let mut session = Session::new();
+        let shards = backend.shards_of(&[b"a"]);
+        let other = (0..).map(|i| format!("k{}", i)).find(|k| backend.shards_of(&[k]) != shards).unwrap();
+        let argv = vec![b"getsecond".to_vec(), b"a".to_vec(), other.into_bytes()];
+        let undeclared = CommandError::Other("'getsecond' used a key its key spec doesn't declare".into());
+        assert_eq!(call(&backend, &mut session, &SPEC, &argv), error_frame(undeclared));
+        assert_eq!(run(&backend, &mut session, "set a 1"), ok());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(
+            run(&backend, &mut session, "nope 1"),
+            error_frame(CommandError::UnknownCommand("nope".into()))
+        );
+        assert_eq!(
+            run(&backend, &mut session, "get"),
+            error_frame(CommandError::WrongArity("get".into()))
+        );
+    }
+
+    #[test]
+    fn test_unknown_command_with_crlf() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let reply = execute_argv(&backend, &mut session, vec![b"foo\r\n+INJECTED".to_vec()]);
+        assert_eq!(reply, error_frame(CommandError::UnknownCommand("foo  +INJECTED".into())));
+        let RespFrame::Error(e) = &reply else { panic!("expected an error") };
+        assert!(!e.as_str().contains(['\r', '\n']));
+        assert_eq!(reply.encode().iter().filter(|&&b| b == b'\n').count(), 1);
+
+        let reply = execute_argv(&backend, &mut session, vec![vec![b'x'; 1000]]);
+        let RespFrame::Error(e) = &reply else { panic!("expected an error") };
+        assert_eq!(e.as_str(), format!("ERR unknown command '{}'", "x".repeat(128)));
+    }
+
+    #[test]
+    fn test_oom_rejects_denyoom_commands() {
+        let backend = Backend::new(Config { maxmemory: 100, ..Config::default() });
+        let mut session = Session::new();
+        let value = "x".repeat(50);
+        assert_eq!(run(&backend, &mut session, &format!("set a {}", value)), ok());
+        assert_eq!(run(&backend, &mut session, "set b 1"), error_frame(CommandError::Oom));
+        assert_eq!(run(&backend, &mut session, "get a"), bulk(value));
+
+        assert_eq!(run(&backend, &mut session, "multi"), ok());
+        assert_eq!(run(&backend, &mut session, "set b 1"), error_frame(CommandError::Oom));
+        assert!(matches!(run(&backend, &mut session, "exec"), RespFrame::Error(_)));
+
+        assert_eq!(run(&backend, &mut session, "del a"), int(1));
+        assert_eq!(run(&backend, &mut session, "set b 1"), ok());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{backend::value::MEMORY_SAMPLES, config::MaxmemoryPolicy};
+
+use super::{
+    arg_str, arg_usize, bulk, eq_ignore_case, help_reply, int, nil, CmdResult, CommandError, CommandSpec, Context,
+    READONLY,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("object", -2, READONLY, (2, 2, 1), object),
+    CommandSpec::new("memory", -2, READONLY, (2, 2, 1), memory),
+];
+
+const OBJECT_HELP: &[&str] = &[
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+];
+
+const MEMORY_HELP: &[&str] = &[
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+];
+
+// OBJECT ENCODING | REFCOUNT | IDLETIME | FREQ key, none of them counts as an access
+fn object(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    let key = match (sub.as_str(), &argv[2..]) {
+        ("help", []) => return Ok(help_reply("OBJECT", OBJECT_HELP)),
+        ("encoding" | "refcount" | "idletime" | "freq", [key]) => key,
+        ("encoding" | "refcount" | "idletime" | "freq", _) => {
+            return Err(CommandError::WrongArity(format!("object|{}", sub)))
+        }
+        _ => return Err(CommandError::Other(format!("unknown subcommand '{}'. Try OBJECT HELP.", sub))),
+    };
+    let lfu = matches!(ctx.backend.config().maxmemory_policy, MaxmemoryPolicy::AllkeysLfu | MaxmemoryPolicy::VolatileLfu);
+    let db = ctx.read();
+    let Some((value, idle_ms, freq)) = db.peek(key) else {
+        return Ok(nil());
+    };
+    match sub.as_str() {
+        "encoding" => Ok(bulk(value.encoding())),
+        // values are never shared between keys
+        "refcount" => Ok(int(1)),
+        "idletime" if lfu => Err(CommandError::Other("An LFU maxmemory policy is selected, idle time not tracked.".into())),
+        "idletime" => Ok(int(idle_ms / 1000)),
+        "freq" if !lfu => Err(CommandError::Other(
+            "An LFU maxmemory policy is not selected, access frequency not tracked.".into(),
+        )),
+        _ => Ok(int(freq)),
+    }
+}
+
+// MEMORY USAGE key [SAMPLES count], SAMPLES 0 looks at every element
+fn memory(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    if sub == "help" && argv.len() == 2 {
+        return Ok(help_reply("MEMORY", MEMORY_HELP));
+    }
+    if sub != "usage" {
+        return Err(CommandError::Other(format!("unknown subcommand '{}'. Try MEMORY HELP.", sub)));
+    }
+    let samples = match &argv[2..] {
+        [_] => MEMORY_SAMPLES,
+        [_, option, count] if eq_ignore_case(option, "samples") => arg_usize(count)?,
+        [_, ..] => return Err(CommandError::Syntax),
+        [] => return Err(CommandError::WrongArity("memory|usage".into())),
+    };
+    let db = ctx.read();
+    Ok(db.key_memory(&argv[2], samples).map(int).unwrap_or_else(nil))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::shard::LFU_INIT_VAL,
+        cmd::{
+            bulk, error_frame, int, nil, simple,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        config::MaxmemoryPolicy,
+        resp::RespFrame,
+    };
+
+    #[test]
+    fn test_object_encoding() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let cases = [
+            ("set s 123", "s", "int"),
+            ("append s abc", "s", "embstr"),
+            ("hset h f v", "h", "listpack"),
+            ("sadd i 1 2 3", "i", "intset"),
+            ("sadd i x", "i", "listpack"),
+            ("rpush l a b", "l", "listpack"),
+        ];
+        for (cmd, key, expected) in cases {
+            run(&backend, &mut session, cmd);
+            assert_eq!(run(&backend, &mut session, &format!("object encoding {}", key)), bulk(expected));
+        }
+        run(&backend, &mut session, "config set hash-max-listpack-entries 2");
+        run(&backend, &mut session, "hset h a 1 b 2");
+        assert_eq!(run(&backend, &mut session, "object encoding h"), bulk("hashtable"));
+        assert_eq!(run(&backend, &mut session, "object encoding nope"), nil());
+        assert_eq!(run(&backend, &mut session, "object refcount h"), int(1));
+    }
+
+    #[test]
+    fn test_object_access_and_memory() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "hset h f v");
+        assert_eq!(run(&backend, &mut session, "object idletime h"), int(0));
+        assert!(matches!(run(&backend, &mut session, "object freq h"), RespFrame::Error(_)));
+        backend.update_config(|c| c.maxmemory_policy = MaxmemoryPolicy::AllkeysLfu);
+        let freq = run(&backend, &mut session, "object freq h");
+        assert!(matches!(freq, RespFrame::Integer(n) if n >= LFU_INIT_VAL as i64));
+
+        let RespFrame::Integer(small) = run(&backend, &mut session, "memory usage h") else { panic!("expect: integer") };
+        run(&backend, &mut session, "config set hash-max-listpack-entries 0");
+        run(&backend, &mut session, "hset h g w");
+        let RespFrame::Integer(table) = run(&backend, &mut session, "memory usage h samples 0") else {
+            panic!("expect: integer")
+        };
+        assert!(small < table);
+        assert_eq!(run(&backend, &mut session, "memory usage nope"), nil());
+        assert_eq!(run(&backend, &mut session, "memory usage h count 1"), error_frame(CommandError::Syntax));
+    }
+
+    #[test]
+    fn test_object_memory_help() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let cases = [("object help", "OBJECT", "ENCODING <key>"), ("memory help", "MEMORY", "USAGE <key> [SAMPLES <count>]")];
+        for (cmd, name, line) in cases {
+            let RespFrame::Array(help) = run(&backend, &mut session, cmd) else { panic!("expect: array") };
+            let usage = format!("{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:", name);
+            assert_eq!(help.first(), Some(&simple(usage)));
+            assert!(help.contains(&simple(line)) && help.contains(&simple("HELP")));
+        }
+        assert!(matches!(run(&backend, &mut session, "object nope k"), RespFrame::Error(e) if e.as_str().contains("Try OBJECT HELP")));
+        assert!(matches!(run(&backend, &mut session, "memory nope"), RespFrame::Error(e) if e.as_str().contains("Try MEMORY HELP")));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::pubsub::Subscriber;
+
+use super::{
+    array, bulk, bulk_array, help_reply, int, nil, CmdResult, CommandError, CommandSpec, Context, FAST, NOSCRIPT, NO_KEYS,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("subscribe", -2, NOSCRIPT, NO_KEYS, subscribe),
+    CommandSpec::new("unsubscribe", -1, NOSCRIPT, NO_KEYS, unsubscribe),
+    CommandSpec::new("psubscribe", -2, NOSCRIPT, NO_KEYS, psubscribe),
+    CommandSpec::new("punsubscribe", -1, NOSCRIPT, NO_KEYS, punsubscribe),
+    CommandSpec::new("publish", 3, FAST, NO_KEYS, publish),
+    CommandSpec::new("pubsub", -2, 0, NO_KEYS, pubsub),
+];
+
+fn subscriber<'a>(ctx: &'a mut Context) -> &'a mut Subscriber {
+    let backend = ctx.backend;
+    ctx.session.subscriber.get_or_insert_with(|| Subscriber::new(backend))
+}
+
+// the confirmations are queued, the reply is the first one
+fn first_confirmation(subscriber: &mut Subscriber) -> CmdResult {
+    Ok(subscriber.try_recv().unwrap_or_else(nil))
+}
+
+fn subscribe(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let subscriber = subscriber(ctx);
+    for channel in &argv[1..] {
+        subscriber.subscribe(channel);
+    }
+    first_confirmation(subscriber)
+}
+
+fn psubscribe(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let subscriber = subscriber(ctx);
+    for pattern in &argv[1..] {
+        subscriber.psubscribe(pattern);
+    }
+    first_confirmation(subscriber)
+}
+
+// without arguments, from everything subscribed to
+fn unsubscribe(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let subscriber = subscriber(ctx);
+    let channels = if argv.len() > 1 { argv[1..].to_vec() } else { subscriber.channels().cloned().collect() };
+    if channels.is_empty() {
+        subscriber.confirm_none("unsubscribe");
+    }
+    for channel in &channels {
+        subscriber.unsubscribe(channel);
+    }
+    first_confirmation(subscriber)
+}
+
+fn punsubscribe(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let subscriber = subscriber(ctx);
+    let patterns = if argv.len() > 1 { argv[1..].to_vec() } else { subscriber.patterns().cloned().collect() };
+    if patterns.is_empty() {
+        subscriber.confirm_none("punsubscribe");
+    }
+    for pattern in &patterns {
+        subscriber.punsubscribe(pattern);
+    }
+    first_confirmation(subscriber)
+}
+
+fn publish(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(ctx.backend.pubsub.publish(&argv[1], &argv[2])))
+}
+
+const PUBSUB_HELP: &[&str] = &[
+    "CHANNELS [<pattern>]",
+    "    Return the currently active channels matching a <pattern> (default: '*').",
+    "NUMPAT",
+    "    Return number of subscriptions to patterns.",
+    "NUMSUB [<channel> ...]",
+    "    Return the number of subscribers for the specified channels, excluding",
+    "    pattern subscriptions(default: no channels).",
+];
+
+// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT
+fn pubsub(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = String::from_utf8_lossy(&argv[1]).to_ascii_lowercase();
+    let pubsub = &ctx.backend.pubsub;
+    match (sub.as_str(), &argv[2..]) {
+        ("channels", []) => Ok(bulk_array(pubsub.active_channels(None))),
+        ("channels", [pattern]) => Ok(bulk_array(pubsub.active_channels(Some(pattern)))),
+        ("numsub", channels) => Ok(array(
+            channels.iter().flat_map(|c| [bulk(c.clone()), int(pubsub.num_subscribers(c))]),
+        )),
+        ("numpat", []) => Ok(int(pubsub.num_patterns())),
+        ("help", []) => Ok(help_reply("PUBSUB", PUBSUB_HELP)),
+        ("channels" | "numpat", _) => Err(CommandError::WrongArity(format!("pubsub|{}", sub))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try PUBSUB HELP.", sub))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{array, bulk, error_frame, int, nil, ok, simple, tests::run, CommandError, Session},
+        backend::Backend,
+        resp::RespFrame,
+    };
+
+    fn next(session: &mut Session) -> Option<RespFrame> {
+        session.subscriber.as_mut().and_then(|s| s.try_recv())
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let backend = Backend::default();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "subscribe a b"), array([bulk("subscribe"), bulk("a"), int(1)]));
+        assert_eq!(next(&mut session), Some(array([bulk("subscribe"), bulk("b"), int(2)])));
+        assert_eq!(run(&backend, &mut session, "psubscribe x*"), array([bulk("psubscribe"), bulk("x*"), int(3)]));
+        assert_eq!(
+            run(&backend, &mut session, "get a"),
+            error_frame(CommandError::Other(
+                "Can't execute 'get': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context".into()
+            ))
+        );
+        assert_eq!(run(&backend, &mut session, "ping"), array([bulk("pong"), bulk("")]));
+
+        let mut publisher = Session::new();
+        assert_eq!(run(&backend, &mut publisher, "publish a hello"), int(1));
+        assert_eq!(run(&backend, &mut publisher, "publish xy hi"), int(1));
+        assert_eq!(run(&backend, &mut publisher, "pubsub channels"), array([bulk("a"), bulk("b")]));
+        assert_eq!(run(&backend, &mut publisher, "pubsub numsub a c"), array([bulk("a"), int(1), bulk("c"), int(0)]));
+        assert_eq!(run(&backend, &mut publisher, "pubsub numpat"), int(1));
+        assert!(matches!(run(&backend, &mut publisher, "pubsub help"), RespFrame::Array(help) if help.contains(&simple("NUMPAT"))));
+        assert_eq!(next(&mut session), Some(array([bulk("message"), bulk("a"), bulk("hello")])));
+        assert_eq!(next(&mut session), Some(array([bulk("pmessage"), bulk("x*"), bulk("xy"), bulk("hi")])));
+
+        assert_eq!(run(&backend, &mut session, "unsubscribe"), array([bulk("unsubscribe"), bulk("a"), int(2)]));
+        assert_eq!(next(&mut session), Some(array([bulk("unsubscribe"), bulk("b"), int(1)])));
+        assert_eq!(run(&backend, &mut session, "punsubscribe"), array([bulk("punsubscribe"), bulk("x*"), int(0)]));
+        assert_eq!(run(&backend, &mut session, "unsubscribe"), array([bulk("unsubscribe"), nil(), int(0)]));
+        assert_eq!(run(&backend, &mut session, "ping"), simple("PONG"));
+        assert_eq!(run(&backend, &mut publisher, "publish a hello"), int(0));
+    }
+
+    #[test]
+    fn test_keyspace_notifications() {
+        let backend = Backend::default();
+        let mut admin = Session::new();
+        assert_eq!(run(&backend, &mut admin, "config set notify-keyspace-events KEA"), ok());
+        assert_eq!(run(&backend, &mut admin, "config get notify-keyspace-events"), array([bulk("notify-keyspace-events"), bulk("AKE")]));
+
+        let mut session = Session::new();
+        run(&backend, &mut session, "psubscribe __key*__:*");
+        run(&backend, &mut admin, "set k 1");
+        assert_eq!(next(&mut session), Some(array([bulk("pmessage"), bulk("__key*__:*"), bulk("__keyspace@0__:k"), bulk("set")])));
+        assert_eq!(next(&mut session), Some(array([bulk("pmessage"), bulk("__key*__:*"), bulk("__keyevent@0__:set"), bulk("k")])));
+        // nothing deleted, no event
+        run(&backend, &mut admin, "del nope");
+        assert_eq!(next(&mut session), None);
+        run(&backend, &mut admin, "rename k j");
+        let events: Vec<_> = std::iter::from_fn(|| next(&mut session)).collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], array([bulk("pmessage"), bulk("__key*__:*"), bulk("__keyspace@0__:k"), bulk("rename_from")]));
+
+        // expirations and only them
+        assert_eq!(run(&backend, &mut admin, "config set notify-keyspace-events Ex"), ok());
+        run(&backend, &mut admin, "pexpire j 1");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(backend.active_expire(20), 1);
+        assert_eq!(next(&mut session), Some(array([bulk("pmessage"), bulk("__key*__:*"), bulk("__keyevent@0__:expired"), bulk("j")])));
+        assert_eq!(next(&mut session), None);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    backend::snapshot,
+    replication::{replica, LinkState, SyncKind},
+    resp::{RespEncode, RespFrame},
+};
+
+use super::{
+    arg_i64, array, bulk, eq_ignore_case, int, ok, simple, CmdResult, CommandError, CommandSpec,
+    Context, ADMIN, EXCLUSIVE, NOSCRIPT, NO_KEYS, NO_MULTI,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("replicaof", 3, ADMIN | NO_MULTI | NOSCRIPT, NO_KEYS, replicaof),
+    CommandSpec::new("slaveof", 3, ADMIN | NO_MULTI | NOSCRIPT, NO_KEYS, replicaof),
+    CommandSpec::new("role", 1, NO_MULTI, NO_KEYS, role),
+    CommandSpec::new("replconf", -1, ADMIN | NO_MULTI | NOSCRIPT, NO_KEYS, replconf),
+    CommandSpec::new("psync", 3, ADMIN | NO_MULTI | EXCLUSIVE | NOSCRIPT, NO_KEYS, psync),
+];
+
+// REPLICAOF host port | NO ONE
+fn replicaof(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if eq_ignore_case(&argv[1], "no") && eq_ignore_case(&argv[2], "one") {
+        ctx.backend.repl.clear_master();
+        return Ok(ok());
+    }
+    let port = arg_i64(&argv[2])
+        .ok()
+        .and_then(|p| u16::try_from(p).ok())
+        .ok_or_else(|| CommandError::Other("Invalid master port".into()))?;
+    let host = String::from_utf8_lossy(&argv[1]).into_owned();
+    if let Some(master) = ctx.backend.repl.master() {
+        if master.host == host && master.port == port {
+            return Ok(simple("OK Already connected to specified master"));
+        }
+    }
+    replica::start(ctx.backend, host, port).map_err(|e| CommandError::Other(e.to_string()))?;
+    Ok(ok())
+}
+
+fn role(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    let repl = &ctx.backend.repl;
+    Ok(match repl.master() {
+        Some(master) => array([
+            bulk("slave"),
+            bulk(master.host),
+            int(master.port),
+            bulk(master.state.as_str()),
+            int(master.master_offset),
+        ]),
+        None => array([
+            bulk("master"),
+            int(repl.offset()),
+            array(repl.replicas().into_iter().map(|(_, ip, port, ack)| {
+                array([bulk(ip), bulk(port.to_string()), bulk(ack.to_string())])
+            })),
+        ]),
+    })
+}
+
+// REPLCONF listening-port <port> | capa <capability>..., sent by a replica before PSYNC
+fn replconf(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() % 2 != 1 {
+        return Err(CommandError::Syntax);
+    }
+    for pair in argv[1..].chunks(2) {
+        if eq_ignore_case(&pair[0], "listening-port") {
+            let port = arg_i64(&pair[1])?;
+            ctx.session.listening_port = Some(u16::try_from(port).map_err(|_| CommandError::NotInteger)?);
+        } else if !eq_ignore_case(&pair[0], "capa") {
+            return Err(CommandError::Other(format!(
+                "Unrecognized REPLCONF option: {}",
+                String::from_utf8_lossy(&pair[0])
+            )));
+        }
+    }
+    Ok(ok())
+}
+
+// PSYNC replid offset, "? -1" asks for a full sync. It runs with every other command
+// locked out, so the snapshot and the stream offset describe the same point.
+fn psync(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let backend = ctx.backend;
+    if let Some(master) = backend.repl.master() {
+        if master.state != LinkState::Connected {
+            return Err(CommandError::Raw("NOMASTERLINK Can't SYNC while not connected with my master".into()));
+        }
+    }
+    let offset = arg_i64(&argv[2])?;
+    let replid = String::from_utf8_lossy(&argv[1]).into_owned();
+    let requested = (replid != "?" && offset > 0).then_some((replid.as_str(), offset as u64));
+
+    let ip = ctx.session.addr.as_ref().and_then(|a| a.ip()).map(|ip| ip.to_string()).unwrap_or_default();
+    let port = ctx.session.listening_port.unwrap_or(0);
+    let (kind, feed) = backend.repl.attach_replica(ip, port, requested, || {
+        let dbs = backend.read_all();
+        RespFrame::BulkString(snapshot::dump(&dbs)).encode()
+    });
+    ctx.session.replica_feed = Some(feed);
+    Ok(match kind {
+        SyncKind::Full { replid, offset } => simple(format!("FULLRESYNC {} {}", replid, offset)),
+        SyncKind::Partial { replid } => simple(format!("CONTINUE {}", replid)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            array, bulk, int,
+            tests::{run, test_backend},
+            Session,
+        },
+        resp::RespFrame,
+    };
+
+    // PSYNC replid offset from a new connection, with the reply and what the link sends first
+    fn psync(backend: &crate::backend::Backend, replid: &str, offset: i64) -> (String, Vec<u8>) {
+        let mut session = Session::new();
+        let RespFrame::SimpleString(reply) = run(backend, &mut session, &format!("psync {} {}", replid, offset)) else {
+            panic!("expect: simple string")
+        };
+        (reply.as_str().to_string(), session.replica_feed.unwrap().initial)
+    }
+
+    #[test]
+    fn test_psync_partial_resync_from_backlog() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let (reply, _) = psync(&backend, "?", -1);
+        let replid = backend.repl.replid();
+        assert_eq!(reply, format!("FULLRESYNC {} 0", replid));
+
+        run(&backend, &mut session, "set a 1");
+        let offset = backend.repl.offset();
+        run(&backend, &mut session, "incr a");
+        // what the replica hasn't applied yet comes from the backlog
+        let (reply, tail) = psync(&backend, &replid, offset as i64 + 1);
+        assert_eq!(reply, format!("CONTINUE {}", replid));
+        assert_eq!(tail, b"*2\r\n$4\r\nincr\r\n$1\r\na\r\n");
+        let (reply, tail) = psync(&backend, &replid, backend.repl.offset() as i64 + 1);
+        assert_eq!(reply, format!("CONTINUE {}", replid));
+        assert!(tail.is_empty());
+
+        // another history or an offset the backlog doesn't have needs a full sync
+        assert!(psync(&backend, "0000", offset as i64 + 1).0.starts_with("FULLRESYNC"));
+        assert!(psync(&backend, &replid, backend.repl.offset() as i64 + 100).0.starts_with("FULLRESYNC"));
+    }
+
+    #[test]
+    fn test_role() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "role"), array([bulk("master"), int(0), array([])]));
+
+        let (_, feed) = backend.repl.attach_replica("10.0.0.2".into(), 6380, None, Vec::new);
+        run(&backend, &mut session, "set a 1");
+        backend.repl.ack_replica(feed.id, 7);
+        let offset = backend.repl.offset();
+        assert_eq!(
+            run(&backend, &mut session, "role"),
+            array([bulk("master"), int(offset), array([array([bulk("10.0.0.2"), bulk("6380"), bulk("7")])])])
+        );
+
+        backend.repl.set_master("127.0.0.1".into(), 6390);
+        backend.repl.set_master_position("f".repeat(40), 25);
+        assert_eq!(
+            run(&backend, &mut session, "role"),
+            array([bulk("slave"), bulk("127.0.0.1"), int(6390), bulk("connect"), int(25)])
+        );
+        backend.repl.clear_master();
+        assert!(matches!(run(&backend, &mut session, "role"), RespFrame::Array(role) if role[0] == bulk("master")));
+    }
+}
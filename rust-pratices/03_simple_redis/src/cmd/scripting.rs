@@ -0,0 +1,185 @@
+use crate::scripting;
+
+use super::{
+    arg_i64, array, bulk, int, ok, CmdResult, CommandError, CommandSpec, Context, EXCLUSIVE,
+    NOSCRIPT, NO_KEYS,
+};
+
+// a script runs with every other command locked out, like EXEC
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("eval", -3, EXCLUSIVE | NOSCRIPT, NO_KEYS, eval).with_movable_keys(script_keys),
+    CommandSpec::new("evalsha", -3, EXCLUSIVE | NOSCRIPT, NO_KEYS, evalsha).with_movable_keys(script_keys),
+    CommandSpec::new("script", -2, NOSCRIPT, NO_KEYS, script),
+];
+
+// EVAL script numkeys key [key ...] arg [arg ...]
+fn script_keys(argv: &[Vec<u8>]) -> Vec<usize> {
+    let numkeys = argv.get(2).and_then(|n| arg_i64(n).ok()).unwrap_or(0).max(0) as usize;
+    (3..argv.len().min(3 + numkeys)).collect()
+}
+
+fn numkeys(argv: &[Vec<u8>]) -> Result<usize, CommandError> {
+    let numkeys = arg_i64(&argv[2])?;
+    if numkeys < 0 {
+        return Err(CommandError::Other("Number of keys can't be negative".into()));
+    }
+    if numkeys as usize > argv.len() - 3 {
+        return Err(CommandError::Other("Number of keys can't be greater than number of args".into()));
+    }
+    Ok(numkeys as usize)
+}
+
+fn eval(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (keys, args) = argv[3..].split_at(numkeys(argv)?);
+    ctx.backend.scripts.load(&argv[1]);
+    scripting::eval(ctx.backend, ctx.session, &argv[1], keys, args)
+}
+
+fn evalsha(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (keys, args) = argv[3..].split_at(numkeys(argv)?);
+    let sha = String::from_utf8_lossy(&argv[1]);
+    let body = ctx.backend.scripts.get(&sha).ok_or(CommandError::NoScript)?;
+    scripting::eval(ctx.backend, ctx.session, &body, keys, args)
+}
+
+// SCRIPT LOAD body | EXISTS sha [sha ...] | FLUSH [ASYNC|SYNC]
+fn script(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = String::from_utf8_lossy(&argv[1]).to_ascii_lowercase();
+    let scripts = &ctx.backend.scripts;
+    match (sub.as_str(), &argv[2..]) {
+        ("load", [body]) => Ok(bulk(scripts.load(body))),
+        ("exists", shas) if !shas.is_empty() => {
+            Ok(array(shas.iter().map(|sha| int(scripts.contains(&String::from_utf8_lossy(sha)) as i64))))
+        }
+        ("flush", [] | [_]) => {
+            scripts.flush();
+            Ok(ok())
+        }
+        ("load" | "exists" | "flush", _) => Err(CommandError::WrongArity(format!("script|{}", sub))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'", sub))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            array, bulk, error_frame, execute_argv, int, nil, ok, simple,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        resp::{RespEncode, RespFrame, SimpleError},
+        scripting::sha1_hex,
+    };
+
+    // run EVAL with the script as one argument, `args` split on whitespace
+    fn eval(backend: &crate::backend::Backend, session: &mut Session, script: &str, args: &str) -> RespFrame {
+        let mut argv = vec![b"eval".to_vec(), script.as_bytes().to_vec()];
+        argv.extend(args.split_whitespace().map(|s| s.as_bytes().to_vec()));
+        execute_argv(backend, session, argv)
+    }
+
+    #[test]
+    fn test_eval() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(eval(&backend, &mut session, "return 1 + 1", "0"), int(2));
+        assert_eq!(
+            eval(&backend, &mut session, "return {KEYS[1], ARGV[1], ARGV[2]}", "1 k a b"),
+            array([bulk("k"), bulk("a"), bulk("b")])
+        );
+        assert_eq!(eval(&backend, &mut session, "return redis.call('set', KEYS[1], ARGV[1])", "1 a 5"), ok());
+        assert_eq!(eval(&backend, &mut session, "return redis.call('incrby', 'a', 2) * 10", "0"), int(70));
+        assert_eq!(eval(&backend, &mut session, "return redis.call('get', 'nope')", "0"), nil());
+        assert_eq!(eval(&backend, &mut session, "return redis.status_reply('FINE')", "0"), simple("FINE"));
+
+        // errors of redis.call stop the script, redis.pcall hands them back
+        run(&backend, &mut session, "rpush l x");
+        assert_eq!(
+            eval(&backend, &mut session, "redis.call('incr', 'l') return 1", "0"),
+            error_frame(CommandError::WrongType)
+        );
+        assert_eq!(
+            eval(&backend, &mut session, "local r = redis.pcall('incr', 'l') return r['err']", "0"),
+            bulk(CommandError::WrongType.to_string())
+        );
+        assert_eq!(
+            eval(&backend, &mut session, "return redis.call('multi')", "0"),
+            RespFrame::Error(SimpleError::new("ERR This Redis command is not allowed from script"))
+        );
+        assert!(matches!(eval(&backend, &mut session, "return (", "0"), RespFrame::Error(_)));
+        assert_eq!(
+            eval(&backend, &mut session, "return 1", "2 a"),
+            error_frame(CommandError::Other("Number of keys can't be greater than number of args".into()))
+        );
+
+        // a SELECT in the script stays in the script
+        assert_eq!(eval(&backend, &mut session, "redis.call('select', 3) return redis.call('set', 'b', 1)", "0"), ok());
+        assert_eq!(run(&backend, &mut session, "exists b"), int(0));
+    }
+
+    #[test]
+    fn test_script_error_is_one_line() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "rpush l x");
+        for script in [
+            "redis.call('incr', 'l') return 1",
+            "redis.call('nope') return 1",
+            "local ok, e = pcall(redis.call, 'incr', 'l') error(e)",
+            "error('boom')",
+        ] {
+            let reply = eval(&backend, &mut session, script, "0");
+            let RespFrame::Error(e) = &reply else { panic!("expected an error from {}", script) };
+            assert!(!e.as_str().contains(['\r', '\n']) && !e.as_str().contains("stack traceback"), "{:?}", e);
+            assert_eq!(reply.encode().iter().filter(|&&b| b == b'\n').count(), 1);
+        }
+        assert_eq!(
+            eval(&backend, &mut session, "error('boom')", "0"),
+            error_frame(CommandError::Other("Error running script: [string \"user_script\"]:1: boom".into()))
+        );
+    }
+
+    #[test]
+    fn test_script_writes_replicate_as_transaction() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let (_, mut feed) = backend.repl.attach_replica("127.0.0.1".into(), 6380, None, Vec::new);
+        eval(&backend, &mut session, "redis.call('set', 'a', 1) redis.call('get', 'a') return redis.call('del', 'a')", "0");
+        let mut stream = Vec::new();
+        while let Ok(data) = feed.rx.try_recv() {
+            stream.extend_from_slice(&data);
+        }
+        let stream = String::from_utf8(stream).unwrap();
+        let commands: Vec<&str> = ["select", "multi", "set", "del", "exec"].into_iter().filter(|c| stream.contains(c)).collect();
+        assert_eq!(commands.len(), 5);
+        assert!(!stream.contains("get") && !stream.contains("eval"));
+        assert!(stream.find("multi") < stream.find("set") && stream.find("del") < stream.find("exec"));
+    }
+
+    #[test]
+    fn test_script_cache() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let body = "return ARGV[1]";
+        let sha = sha1_hex(body.as_bytes());
+        let evalsha = |session: &mut Session, sha: &str| {
+            let argv = ["evalsha", sha, "0", "hi"].iter().map(|s| s.as_bytes().to_vec()).collect();
+            execute_argv(&backend, session, argv)
+        };
+        assert_eq!(evalsha(&mut session, &sha), error_frame(CommandError::NoScript));
+        let load = vec![b"script".to_vec(), b"load".to_vec(), body.as_bytes().to_vec()];
+        assert_eq!(execute_argv(&backend, &mut session, load), bulk(sha.clone()));
+        assert_eq!(evalsha(&mut session, &sha), bulk("hi"));
+        assert_eq!(
+            run(&backend, &mut session, &format!("script exists {} 0000", sha)),
+            array([int(1), int(0)])
+        );
+        assert_eq!(run(&backend, &mut session, "script flush"), ok());
+        assert_eq!(evalsha(&mut session, &sha), error_frame(CommandError::NoScript));
+
+        // EVAL caches the script as well
+        eval(&backend, &mut session, body, "0 x");
+        assert_eq!(evalsha(&mut session, &sha.to_uppercase()), bulk("hi"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::atomic::Ordering;
+
+use crate::{
+    backend::snapshot,
+    shutdown::SaveMode,
+    utils::now_ms,
+};
+
+use super::{
+    arg_i64, bulk, eq_ignore_case, int, ok, simple, CmdResult, CommandError, CommandSpec, Context,
+    ADMIN, FAST, NOSCRIPT, NO_KEYS, NO_MULTI, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("dbsize", 1, READONLY | FAST, NO_KEYS, dbsize),
+    CommandSpec::new("flushdb", -1, WRITE, NO_KEYS, flushdb),
+    CommandSpec::new("flushall", -1, WRITE, NO_KEYS, flushall),
+    CommandSpec::new("swapdb", 3, WRITE | FAST, NO_KEYS, swapdb),
+    CommandSpec::new("save", 1, ADMIN, NO_KEYS, save),
+    CommandSpec::new("bgsave", -1, ADMIN, NO_KEYS, bgsave),
+    CommandSpec::new("lastsave", 1, FAST, NO_KEYS, lastsave),
+    CommandSpec::new("time", 1, FAST, NO_KEYS, time),
+    CommandSpec::new("shutdown", -1, ADMIN | NOSCRIPT | NO_MULTI, NO_KEYS, shutdown),
+];
+
+fn dbsize(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(ctx.read().len()))
+}
+
+// FLUSHDB / FLUSHALL [ASYNC | SYNC], both are done synchronously
+fn check_flush_args(argv: &[Vec<u8>]) -> Result<(), CommandError> {
+    match argv.get(1) {
+        None => Ok(()),
+        Some(mode) if argv.len() == 2 && (eq_ignore_case(mode, "async") || eq_ignore_case(mode, "sync")) => Ok(()),
+        Some(_) => Err(CommandError::Syntax),
+    }
+}
+
+fn flushdb(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    check_flush_args(argv)?;
+    ctx.write().clear();
+    ctx.backend.tracking.invalidate_all();
+    Ok(ok())
+}
+
+fn flushall(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    check_flush_args(argv)?;
+    ctx.backend.flush_all();
+    Ok(ok())
+}
+
+fn db_index(ctx: &Context, v: &[u8]) -> Result<usize, CommandError> {
+    let index = arg_i64(v).map_err(|_| CommandError::Other("invalid first DB index".into()))?;
+    if index < 0 || index as usize >= ctx.backend.db_count() {
+        return Err(CommandError::DbIndexOutOfRange);
+    }
+    Ok(index as usize)
+}
+
+// SWAPDB index1 index2: every client connected to index1 sees the data of index2 at once
+fn swapdb(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let a = db_index(ctx, &argv[1])?;
+    let b = db_index(ctx, &argv[2])?;
+    ctx.backend.swap_db(a, b);
+    Ok(ok())
+}
+
+fn save(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    if ctx.backend.bgsave_in_progress.load(Ordering::Acquire) {
+        return Err(CommandError::Other("Background save already in progress".into()));
+    }
+    snapshot::save(ctx.backend).map_err(|e| CommandError::Other(e.to_string()))?;
+    Ok(ok())
+}
+
+fn bgsave(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() > 2 || (argv.len() == 2 && !eq_ignore_case(&argv[1], "schedule")) {
+        return Err(CommandError::Syntax);
+    }
+    snapshot::bgsave(ctx.backend).map_err(|e| CommandError::Other(e.to_string()))?;
+    Ok(simple("Background saving started"))
+}
+
+fn lastsave(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    Ok(int(ctx.backend.last_save.load(Ordering::Relaxed)))
+}
+
+fn time(_ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    let now = now_ms();
+    Ok(super::array([bulk((now / 1000).to_string()), bulk(((now % 1000) * 1000).to_string())]))
+}
+
+// SHUTDOWN [NOSAVE | SAVE]: the server stops once the connections finished the
+// commands they read, the snapshot is written last
+fn shutdown(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mode = match &argv[1..] {
+        [] => SaveMode::Default,
+        [mode] if eq_ignore_case(mode, "nosave") => SaveMode::NoSave,
+        [mode] if eq_ignore_case(mode, "save") => SaveMode::Save,
+        _ => return Err(CommandError::Syntax),
+    };
+    ctx.backend.shutdown.request(mode);
+    ctx.session.closing = true;
+    Ok(ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        bulk, error_frame, int, ok,
+        tests::{run, test_backend},
+        CommandError, Session,
+    };
+
+    #[test]
+    fn test_dbsize_flushdb() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "mset a 1 b 2");
+        run(&backend, &mut session, "select 1");
+        run(&backend, &mut session, "set c 3");
+        assert_eq!(run(&backend, &mut session, "dbsize"), int(1));
+        assert_eq!(run(&backend, &mut session, "flushdb"), ok());
+        assert_eq!(run(&backend, &mut session, "dbsize"), int(0));
+
+        run(&backend, &mut session, "select 0");
+        assert_eq!(run(&backend, &mut session, "dbsize"), int(2));
+        assert_eq!(run(&backend, &mut session, "flushall"), ok());
+        assert_eq!(run(&backend, &mut session, "dbsize"), int(0));
+    }
+
+    #[test]
+    fn test_swapdb() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        let mut other = Session::new();
+        run(&backend, &mut session, "set a zero");
+        run(&backend, &mut other, "select 1");
+        run(&backend, &mut other, "set a one");
+
+        assert_eq!(run(&backend, &mut session, "swapdb 0 1"), ok());
+        assert_eq!(run(&backend, &mut session, "get a"), bulk("one"));
+        assert_eq!(run(&backend, &mut other, "get a"), bulk("zero"));
+        assert_eq!(run(&backend, &mut session, "swapdb 0 16"), error_frame(CommandError::DbIndexOutOfRange));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "shutdown now"), error_frame(CommandError::Syntax));
+        assert!(!backend.shutdown.is_requested() && !session.closing);
+        assert_eq!(run(&backend, &mut session, "shutdown nosave"), ok());
+        assert!(backend.shutdown.is_requested() && session.closing);
+    }
+}
@@ -0,0 +1,248 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use rand::seq::IteratorRandom;
+
+use crate::backend::{Db, Set, Value};
+
+use super::{
+    arg_i64, bulk, bulk_array, int, nil, CmdResult, CommandError, CommandSpec, Context, ALL_KEYS,
+    DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("sadd", -3, WRITE | DENYOOM | FAST, KEY1, sadd),
+    CommandSpec::new("srem", -3, WRITE | FAST, KEY1, srem),
+    CommandSpec::new("smembers", 2, READONLY, KEY1, smembers),
+    CommandSpec::new("sismember", 3, READONLY | FAST, KEY1, sismember),
+    CommandSpec::new("smismember", -3, READONLY | FAST, KEY1, smismember),
+    CommandSpec::new("scard", 2, READONLY | FAST, KEY1, scard),
+    CommandSpec::new("spop", -2, WRITE | FAST, KEY1, spop),
+    CommandSpec::new("srandmember", -2, READONLY, KEY1, srandmember),
+    CommandSpec::new("smove", 4, WRITE | FAST, (1, 2, 1), smove),
+    CommandSpec::new("sinter", -2, READONLY, ALL_KEYS, sinter),
+    CommandSpec::new("sunion", -2, READONLY, ALL_KEYS, sunion),
+    CommandSpec::new("sdiff", -2, READONLY, ALL_KEYS, sdiff),
+    CommandSpec::new("sinterstore", -3, WRITE | DENYOOM, ALL_KEYS, sinterstore),
+    CommandSpec::new("sunionstore", -3, WRITE | DENYOOM, ALL_KEYS, sunionstore),
+    CommandSpec::new("sdiffstore", -3, WRITE | DENYOOM, ALL_KEYS, sdiffstore),
+];
+
+fn get_set<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a Set>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::Set(s)) => Ok(Some(s)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn get_set_mut<'a>(db: &'a mut Db, key: &[u8], create: bool) -> Result<Option<&'a mut Set>, CommandError> {
+    if create && db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::Set(Set::default()));
+    }
+    match db.get_mut(key) {
+        None => Ok(None),
+        Some(Value::Set(s)) => Ok(Some(s)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn sadd(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let set = get_set_mut(&mut db, &argv[1], true)?.ok_or(CommandError::NoSuchKey)?;
+    Ok(int(argv[2..].iter().filter(|m| set.insert(m, &limits)).count()))
+}
+
+fn srem(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let Some(set) = get_set_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+    let removed = argv[2..].iter().filter(|m| set.remove(m)).count();
+    db.remove_if_empty(&argv[1]);
+    Ok(int(removed))
+}
+
+fn smembers(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(get_set(&db, &argv[1])?.into_iter().flat_map(Set::iter)))
+}
+
+fn sismember(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_set(&db, &argv[1])?.map(|s| s.contains(&argv[2])).unwrap_or(false) as i64))
+}
+
+fn smismember(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let set = get_set(&db, &argv[1])?;
+    Ok(super::array(
+        argv[2..].iter().map(|m| int(set.map(|s| s.contains(m)).unwrap_or(false) as i64)),
+    ))
+}
+
+fn scard(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_set(&db, &argv[1])?.map(|s| s.len()).unwrap_or(0)))
+}
+
+// SPOP key [count]
+fn spop(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = argv.get(2).map(|c| super::arg_usize(c)).transpose()?;
+    let mut db = ctx.write();
+    let Some(set) = get_set_mut(&mut db, &argv[1], false)? else {
+        return Ok(if count.is_some() { bulk_array(Vec::<Vec<u8>>::new()) } else { nil() });
+    };
+    let picked: Vec<Vec<u8>> =
+        set.iter().map(Cow::into_owned).choose_multiple(&mut rand::thread_rng(), count.unwrap_or(1));
+    for m in &picked {
+        set.remove(m);
+    }
+    db.remove_if_empty(&argv[1]);
+    // the members are picked at random, replicas remove the same ones
+    let mut srem = vec![b"srem".to_vec(), argv[1].clone()];
+    srem.extend(picked.iter().cloned());
+    ctx.propagate = Some(if picked.is_empty() { vec![] } else { vec![srem] });
+    Ok(match count {
+        Some(_) => bulk_array(picked),
+        None => picked.into_iter().next().map(bulk).unwrap_or_else(nil),
+    })
+}
+
+// SRANDMEMBER key [count], a negative count allows repeated members
+fn srandmember(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = argv.get(2).map(|c| arg_i64(c)).transpose()?;
+    let db = ctx.read();
+    let Some(set) = get_set(&db, &argv[1])? else {
+        return Ok(if count.is_some() { bulk_array(Vec::<Vec<u8>>::new()) } else { nil() });
+    };
+    let mut rng = rand::thread_rng();
+    Ok(match count {
+        None => set.iter().choose(&mut rng).map(|m| bulk(m.into_owned())).unwrap_or_else(nil),
+        Some(n) if n >= 0 => bulk_array(set.iter().choose_multiple(&mut rng, n as usize)),
+        Some(n) => {
+            let members: Vec<Cow<'_, [u8]>> = set.iter().collect();
+            bulk_array((0..n.unsigned_abs()).filter_map(|_| members.iter().choose(&mut rng)))
+        }
+    })
+}
+
+fn smove(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (src, dst, member) = (&argv[1], &argv[2], &argv[3]);
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    // type check both sides before changing anything
+    get_set(&db, dst)?;
+    let Some(src_set) = get_set_mut(&mut db, src, false)? else {
+        return Ok(int(0));
+    };
+    if !src_set.remove(member) {
+        return Ok(int(0));
+    }
+    db.remove_if_empty(src);
+    let dst_set = get_set_mut(&mut db, dst, true)?.ok_or(CommandError::NoSuchKey)?;
+    dst_set.insert(member, &limits);
+    Ok(int(1))
+}
+
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+fn compute(db: &Db, keys: &[Vec<u8>], op: SetOp) -> Result<HashSet<Vec<u8>>, CommandError> {
+    let sets = keys.iter().map(|k| get_set(db, k)).collect::<Result<Vec<_>, _>>()?;
+    let empty = Set::default();
+    let sets: Vec<&Set> = sets.into_iter().map(|s| s.unwrap_or(&empty)).collect();
+    let (first, rest) = sets.split_first().expect("arity checked");
+    let members: Vec<Cow<'_, [u8]>> = match op {
+        SetOp::Inter => first.iter().filter(|m| rest.iter().all(|s| s.contains(m))).collect(),
+        SetOp::Union => sets.iter().flat_map(|s| s.iter()).collect(),
+        SetOp::Diff => first.iter().filter(|m| !rest.iter().any(|s| s.contains(m))).collect(),
+    };
+    Ok(members.into_iter().map(Cow::into_owned).collect())
+}
+
+fn sinter(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(compute(&db, &argv[1..], SetOp::Inter)?))
+}
+
+fn sunion(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(compute(&db, &argv[1..], SetOp::Union)?))
+}
+
+fn sdiff(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(bulk_array(compute(&db, &argv[1..], SetOp::Diff)?))
+}
+
+fn store(ctx: &mut Context, argv: &[Vec<u8>], op: SetOp) -> CmdResult {
+    let limits = ctx.backend.encoding_limits();
+    let mut db = ctx.write();
+    let result = compute(&db, &argv[2..], op)?;
+    let len = result.len();
+    if result.is_empty() {
+        db.remove(&argv[1]);
+    } else {
+        db.set(argv[1].clone(), Value::Set(Set::from_members(result, &limits)));
+    }
+    Ok(int(len))
+}
+
+fn sinterstore(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    store(ctx, argv, SetOp::Inter)
+}
+
+fn sunionstore(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    store(ctx, argv, SetOp::Union)
+}
+
+fn sdiffstore(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    store(ctx, argv, SetOp::Diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::{
+            bulk_array, int,
+            tests::{run, test_backend},
+            Session,
+        },
+        resp::RespFrame,
+    };
+
+    fn sorted(frame: RespFrame) -> Vec<RespFrame> {
+        let RespFrame::Array(mut items) = frame else { panic!("expect: array") };
+        items.sort_by_key(|f| format!("{:?}", f));
+        items
+    }
+
+    #[test]
+    fn test_set_commands() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "sadd a 1 2 3 3"), int(3));
+        assert_eq!(run(&backend, &mut session, "sadd b 2 3 4"), int(3));
+        assert_eq!(run(&backend, &mut session, "sismember a 1"), int(1));
+        assert_eq!(run(&backend, &mut session, "scard a"), int(3));
+        let RespFrame::Array(inter) = bulk_array(["2", "3"]) else { unreachable!() };
+        assert_eq!(sorted(run(&backend, &mut session, "sinter a b")), inter);
+        assert_eq!(run(&backend, &mut session, "sunionstore u a b"), int(4));
+        assert_eq!(run(&backend, &mut session, "sdiffstore d a b"), int(1));
+        assert_eq!(run(&backend, &mut session, "smove a b 1"), int(1));
+        assert_eq!(run(&backend, &mut session, "srem a 2 3"), int(2));
+        assert_eq!(run(&backend, &mut session, "exists a"), int(0));
+        assert_eq!(sorted(run(&backend, &mut session, "spop b 10")).len(), 4);
+        assert_eq!(run(&backend, &mut session, "exists b"), int(0));
+    }
+}
@@ -0,0 +1,755 @@
+use std::time::Duration;
+
+use crate::{
+    backend::{
+        blocking::Blocked,
+        stream::{Claim, Fields, Stream, StreamId, Trim},
+        Db, Value,
+    },
+    utils::now_ms,
+};
+
+use super::{
+    arg_i64, arg_str, array, bulk, bulk_array, eq_ignore_case, help_reply, int, nil, nil_array, ok, CmdResult, CommandError,
+    CommandSpec, Context, RespFrame, DENYOOM, FAST, KEY1, NO_KEYS, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("xadd", -5, WRITE | DENYOOM | FAST, KEY1, xadd),
+    CommandSpec::new("xlen", 2, READONLY | FAST, KEY1, xlen),
+    CommandSpec::new("xrange", -4, READONLY, KEY1, xrange),
+    CommandSpec::new("xrevrange", -4, READONLY, KEY1, xrevrange),
+    CommandSpec::new("xdel", -3, WRITE | FAST, KEY1, xdel),
+    CommandSpec::new("xtrim", -4, WRITE, KEY1, xtrim),
+    CommandSpec::new("xread", -4, READONLY, NO_KEYS, xread).with_movable_keys(xread_keys),
+    CommandSpec::new("xgroup", -2, WRITE, (2, 2, 1), xgroup),
+    CommandSpec::new("xreadgroup", -7, WRITE, NO_KEYS, xreadgroup).with_movable_keys(xreadgroup_keys),
+    CommandSpec::new("xack", -4, WRITE | FAST, KEY1, xack),
+    CommandSpec::new("xpending", -3, READONLY, KEY1, xpending),
+    CommandSpec::new("xclaim", -6, WRITE | FAST, KEY1, xclaim),
+];
+
+fn get_stream<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a Stream>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::Stream(s)) => Ok(Some(s)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn get_stream_mut<'a>(db: &'a mut Db, key: &[u8], create: bool) -> Result<Option<&'a mut Stream>, CommandError> {
+    if create && db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::Stream(Box::default()));
+    }
+    match db.get_mut(key) {
+        None => Ok(None),
+        Some(Value::Stream(s)) => Ok(Some(s)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn invalid_id() -> CommandError {
+    CommandError::Other("Invalid stream ID specified as stream command argument".into())
+}
+
+fn arg_id(v: &[u8], default_seq: u64) -> Result<StreamId, CommandError> {
+    StreamId::parse(v, default_seq).ok_or_else(invalid_id)
+}
+
+// a bound of XRANGE: `-`, `+`, an ID or `(`ID for an exclusive one. A missing
+// sequence number takes in the whole millisecond. None when nothing can be in range.
+fn range_bound(v: &[u8], start: bool) -> Result<Option<StreamId>, CommandError> {
+    let default_seq = if start { 0 } else { u64::MAX };
+    match v {
+        b"-" => Ok(Some(StreamId::MIN)),
+        b"+" => Ok(Some(StreamId::MAX)),
+        [b'(', id @ ..] => {
+            let id = arg_id(id, default_seq)?;
+            Ok(if start { id.next() } else { id.prev() })
+        }
+        id => arg_id(id, default_seq).map(Some),
+    }
+}
+
+fn nogroup(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Raw(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+fn id_frame(id: StreamId) -> RespFrame {
+    bulk(id.to_string())
+}
+
+fn entry_frame(id: StreamId, fields: Fields) -> RespFrame {
+    array([id_frame(id), bulk_array(fields)])
+}
+
+fn entries_frame(entries: Vec<(StreamId, Fields)>) -> RespFrame {
+    array(entries.into_iter().map(|(id, fields)| entry_frame(id, fields)))
+}
+
+// MAXLEN|MINID [=|~] threshold [LIMIT count] at argv[i], returns the index after it.
+// `~` trims exactly too, so replicas end up with the same entries.
+fn parse_trim(argv: &[Vec<u8>], mut i: usize) -> Result<(Trim, usize), CommandError> {
+    let maxlen = eq_ignore_case(&argv[i], "maxlen");
+    i += 1;
+    let approx = argv.get(i).is_some_and(|a| a == b"~");
+    if argv.get(i).is_some_and(|a| a == b"~" || a == b"=") {
+        i += 1;
+    }
+    let threshold = argv.get(i).ok_or(CommandError::Syntax)?;
+    let trim = if maxlen {
+        let max = arg_i64(threshold)?;
+        Trim::MaxLen(usize::try_from(max).map_err(|_| CommandError::Other("The MAXLEN argument must be >= 0.".into()))?)
+    } else {
+        Trim::MinId(arg_id(threshold, 0)?)
+    };
+    i += 1;
+    if argv.get(i).is_some_and(|a| eq_ignore_case(a, "limit")) {
+        if !approx {
+            return Err(CommandError::Other("syntax error, LIMIT cannot be used without the special ~ option".into()));
+        }
+        arg_i64(argv.get(i + 1).ok_or(CommandError::Syntax)?)?;
+        i += 2;
+    }
+    Ok((trim, i))
+}
+
+// the ID argument of XADD
+enum NewId {
+    Auto,
+    // `ms-*`, the next sequence number of that millisecond
+    Seq(u64),
+    Exact(StreamId),
+}
+
+// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]
+fn xadd(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut i = 2;
+    let mut mkstream = true;
+    let mut trim = None;
+    loop {
+        match argv.get(i) {
+            Some(a) if eq_ignore_case(a, "nomkstream") => {
+                mkstream = false;
+                i += 1;
+            }
+            Some(a) if eq_ignore_case(a, "maxlen") || eq_ignore_case(a, "minid") => {
+                let (t, next) = parse_trim(argv, i)?;
+                trim = Some(t);
+                i = next;
+            }
+            _ => break,
+        }
+    }
+    let fields = argv.get(i + 1..).unwrap_or_default();
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("xadd".into()));
+    }
+    let new_id = match argv[i].as_slice() {
+        b"*" => NewId::Auto,
+        id => match id.strip_suffix(b"-*") {
+            Some(ms) => NewId::Seq(String::from_utf8_lossy(ms).parse().map_err(|_| invalid_id())?),
+            None => NewId::Exact(arg_id(id, 0)?),
+        },
+    };
+    if matches!(new_id, NewId::Exact(StreamId::MIN)) {
+        return Err(CommandError::Other("The ID specified in XADD must be greater than 0-0".into()));
+    }
+
+    let mut db = ctx.write();
+    let Some(stream) = get_stream_mut(&mut db, &argv[1], mkstream)? else {
+        return Ok(nil());
+    };
+    let last = stream.last_id();
+    let id = match new_id {
+        NewId::Auto => stream.next_id(now_ms()).ok_or_else(|| {
+            CommandError::Other("The stream has exhausted the last possible ID, unable to add more items".into())
+        })?,
+        NewId::Seq(ms) if ms == last.ms => last.next().filter(|id| id.ms == ms).unwrap_or(last),
+        NewId::Seq(ms) => StreamId::new(ms, 0),
+        NewId::Exact(id) => id,
+    };
+    if !stream.add(id, fields.to_vec()) {
+        return Err(CommandError::Other(
+            "The ID specified in XADD is equal or smaller than the target stream top item".into(),
+        ));
+    }
+    if let Some(trim) = trim {
+        stream.trim(trim);
+    }
+    // replicas get the ID that was picked
+    let mut propagated = argv.to_vec();
+    propagated[i] = id.to_string().into_bytes();
+    ctx.propagate = Some(vec![propagated]);
+    Ok(id_frame(id))
+}
+
+fn xlen(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_stream(&db, &argv[1])?.map_or(0, |s| s.len())))
+}
+
+// XRANGE key start end [COUNT count], XREVRANGE takes end before start
+fn range(ctx: &mut Context, argv: &[Vec<u8>], rev: bool) -> CmdResult {
+    let (start, end) = if rev { (&argv[3], &argv[2]) } else { (&argv[2], &argv[3]) };
+    let (start, end) = (range_bound(start, true)?, range_bound(end, false)?);
+    let count = match &argv[4..] {
+        [] => None,
+        [option, n] if eq_ignore_case(option, "count") => Some(arg_i64(n)?.max(0) as usize),
+        _ => return Err(CommandError::Syntax),
+    };
+    let db = ctx.read();
+    let (Some(stream), Some(start), Some(end)) = (get_stream(&db, &argv[1])?, start, end) else {
+        return Ok(array([]));
+    };
+    Ok(entries_frame(stream.range(start, end, count, rev)))
+}
+
+fn xrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    range(ctx, argv, false)
+}
+
+fn xrevrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    range(ctx, argv, true)
+}
+
+// XDEL key id [id ...]
+fn xdel(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let ids = argv[2..].iter().map(|id| arg_id(id, 0)).collect::<Result<Vec<_>, _>>()?;
+    let mut db = ctx.write();
+    let Some(stream) = get_stream_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+    Ok(int(ids.into_iter().filter(|id| stream.delete(*id)).count()))
+}
+
+// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+fn xtrim(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if !eq_ignore_case(&argv[2], "maxlen") && !eq_ignore_case(&argv[2], "minid") {
+        return Err(CommandError::Syntax);
+    }
+    let (trim, next) = parse_trim(argv, 2)?;
+    if next != argv.len() {
+        return Err(CommandError::Syntax);
+    }
+    let mut db = ctx.write();
+    let Some(stream) = get_stream_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+    Ok(int(stream.trim(trim)))
+}
+
+// the keys after STREAMS: the first half of what follows it
+fn streams_keys(argv: &[Vec<u8>], from: usize) -> Vec<usize> {
+    let Some(at) = argv.iter().skip(from).position(|a| eq_ignore_case(a, "streams")) else {
+        return vec![];
+    };
+    let first = from + at + 1;
+    (first..first + (argv.len() - first) / 2).collect()
+}
+
+fn xread_keys(argv: &[Vec<u8>]) -> Vec<usize> {
+    streams_keys(argv, 1)
+}
+
+// GROUP group consumer come first
+fn xreadgroup_keys(argv: &[Vec<u8>]) -> Vec<usize> {
+    streams_keys(argv, 4)
+}
+
+// the options of XREAD / XREADGROUP
+struct ReadArgs {
+    count: Option<usize>,
+    // Some(None) blocks forever
+    block: Option<Option<Duration>>,
+    no_ack: bool,
+    // where the keys start, the IDs follow them
+    streams: usize,
+    n: usize,
+}
+
+// [COUNT count] [BLOCK ms] [NOACK] STREAMS key [key ...] id [id ...] from argv[from]
+fn parse_read(argv: &[Vec<u8>], from: usize, group: bool) -> Result<ReadArgs, CommandError> {
+    let mut args = ReadArgs { count: None, block: None, no_ack: false, streams: 0, n: 0 };
+    let mut i = from;
+    while i < argv.len() {
+        let option = &argv[i];
+        if eq_ignore_case(option, "streams") {
+            args.streams = i + 1;
+            break;
+        } else if eq_ignore_case(option, "count") && i + 1 < argv.len() {
+            let count = arg_i64(&argv[i + 1])?;
+            args.count = (count > 0).then_some(count as usize);
+            i += 2;
+        } else if eq_ignore_case(option, "block") && i + 1 < argv.len() {
+            let ms = arg_i64(&argv[i + 1])?;
+            if ms < 0 {
+                return Err(CommandError::Other("timeout is negative".into()));
+            }
+            args.block = Some((ms > 0).then(|| Duration::from_millis(ms as u64)));
+            i += 2;
+        } else if eq_ignore_case(option, "noack") && group {
+            args.no_ack = true;
+            i += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    let rest = argv.len().saturating_sub(args.streams);
+    if args.streams == 0 || rest == 0 || !rest.is_multiple_of(2) {
+        let name = if group { "xreadgroup" } else { "xread" };
+        let id = if group { ">" } else { "$" };
+        return Err(CommandError::Other(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            name, id
+        )));
+    }
+    args.n = rest / 2;
+    Ok(args)
+}
+
+impl ReadArgs {
+    fn keys<'a>(&self, argv: &'a [Vec<u8>]) -> &'a [Vec<u8>] {
+        &argv[self.streams..self.streams + self.n]
+    }
+
+    fn ids<'a>(&self, argv: &'a [Vec<u8>]) -> &'a [Vec<u8>] {
+        &argv[self.streams + self.n..]
+    }
+}
+
+// XREAD [COUNT count] [BLOCK ms] STREAMS key [key ...] id [id ...]
+fn xread(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let args = parse_read(argv, 1, false)?;
+    let keys = args.keys(argv);
+    let db = ctx.read();
+    let mut after = Vec::with_capacity(args.n);
+    for (key, id) in keys.iter().zip(args.ids(argv)) {
+        after.push(match id.as_slice() {
+            b"$" => get_stream(&db, key)?.map_or(StreamId::MIN, |s| s.last_id()),
+            id => arg_id(id, 0)?,
+        });
+    }
+
+    let mut replies = Vec::new();
+    for (key, after) in keys.iter().zip(&after) {
+        let Some(stream) = get_stream(&db, key)? else {
+            continue;
+        };
+        let entries = stream.read_after(*after, args.count);
+        if !entries.is_empty() {
+            replies.push(array([bulk(key.clone()), entries_frame(entries)]));
+        }
+    }
+    if !replies.is_empty() {
+        return Ok(array(replies));
+    }
+    if let Some(timeout) = args.block {
+        // `$` is what was last when the command came, it mustn't move on retries
+        let mut retry = argv.to_vec();
+        for (i, id) in after.iter().enumerate() {
+            retry[args.streams + args.n + i] = id.to_string().into_bytes();
+        }
+        ctx.session.blocked = Some(Blocked { keys: keys.to_vec(), timeout, argv: retry });
+    }
+    Ok(nil_array())
+}
+
+// XREADGROUP GROUP group consumer [COUNT count] [BLOCK ms] [NOACK] STREAMS key [key ...] id [id ...]
+// `>` reads what the group never got, an ID the consumer's pending entries after it
+fn xreadgroup(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if !eq_ignore_case(&argv[1], "group") {
+        return Err(CommandError::Syntax);
+    }
+    let (group, consumer) = (&argv[2], &argv[3]);
+    let args = parse_read(argv, 4, true)?;
+    let keys = args.keys(argv);
+    let after = args
+        .ids(argv)
+        .iter()
+        .map(|id| if id == b">" { Ok(None) } else { arg_id(id, 0).map(Some) })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut db = ctx.write();
+    for key in keys {
+        if get_stream(&db, key)?.and_then(|s| s.group(group)).is_none() {
+            return Err(CommandError::Raw(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(group)
+            )));
+        }
+    }
+    let now = now_ms();
+    let mut replies = Vec::new();
+    for (key, after) in keys.iter().zip(&after) {
+        let Some(stream) = get_stream_mut(&mut db, key, false)? else {
+            continue;
+        };
+        let entries = stream.read_group(group, consumer, *after, args.count, args.no_ack, now).unwrap_or_default();
+        // a history read replies for its key even when there is nothing
+        if after.is_some() || !entries.is_empty() {
+            let entries = entries.into_iter().map(|(id, fields)| match fields {
+                Some(fields) => entry_frame(id, fields),
+                None => array([id_frame(id), nil_array()]),
+            });
+            replies.push(array([bulk(key.clone()), array(entries)]));
+        }
+    }
+    if !replies.is_empty() {
+        return Ok(array(replies));
+    }
+    if let Some(timeout) = args.block {
+        ctx.session.blocked = Some(Blocked { keys: keys.to_vec(), timeout, argv: argv.to_vec() });
+    }
+    Ok(nil_array())
+}
+
+const XGROUP_HELP: &[&str] = &[
+    "CREATE <key> <groupname> <id|$> [option]",
+    "    Create a new consumer group. Options are:",
+    "    * MKSTREAM",
+    "      Create the empty stream if it does not exist.",
+    "    * ENTRIESREAD entries_read",
+    "      Set the group's entries_read counter (internal use).",
+    "CREATECONSUMER <key> <groupname> <consumer>",
+    "    Create a new consumer in the specified group.",
+    "DELCONSUMER <key> <groupname> <consumer>",
+    "    Remove the specified consumer.",
+    "DESTROY <key> <groupname>",
+    "    Remove the specified group.",
+    "SETID <key> <groupname> <id|$> [ENTRIESREAD entries_read]",
+    "    Set the current group ID and entries_read counter.",
+];
+
+// XGROUP CREATE key group id|$ [MKSTREAM] [ENTRIESREAD n] | SETID key group id|$ [ENTRIESREAD n]
+//      | DESTROY key group | CREATECONSUMER key group consumer | DELCONSUMER key group consumer
+fn xgroup(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let sub = arg_str(&argv[1]);
+    let args = &argv[2..];
+    if sub == "help" && args.is_empty() {
+        return Ok(help_reply("XGROUP", XGROUP_HELP));
+    }
+    let (key, group) = match args {
+        [key, group, ..] => (key, group),
+        _ => return xgroup_unknown(&sub),
+    };
+    let nogroup = || {
+        CommandError::Raw(format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        ))
+    };
+    // `$` is the last ID of the stream, resolved under the lock
+    let group_id = |id: &[u8]| if id == b"$" { Ok(None) } else { arg_id(id, 0).map(Some) };
+    let mut mkstream = false;
+    let id = match (sub.as_str(), &args[2..]) {
+        ("create" | "setid", [id, options @ ..]) => {
+            let mut i = 0;
+            while i < options.len() {
+                if eq_ignore_case(&options[i], "mkstream") && sub == "create" {
+                    mkstream = true;
+                    i += 1;
+                } else if eq_ignore_case(&options[i], "entriesread") && i + 1 < options.len() {
+                    arg_i64(&options[i + 1])?;
+                    i += 2;
+                } else {
+                    return Err(CommandError::Syntax);
+                }
+            }
+            group_id(id)?
+        }
+        ("destroy", []) | ("createconsumer" | "delconsumer", [_]) => None,
+        ("create" | "setid" | "destroy" | "createconsumer" | "delconsumer", _) => {
+            return Err(CommandError::WrongArity(format!("xgroup|{}", sub)))
+        }
+        _ => return xgroup_unknown(&sub),
+    };
+
+    let mut db = ctx.write();
+    let Some(stream) = get_stream_mut(&mut db, key, mkstream)? else {
+        return Err(CommandError::Other(
+            "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.".into(),
+        ));
+    };
+    let id = id.unwrap_or(stream.last_id());
+    let now = now_ms();
+    match sub.as_str() {
+        "create" if !stream.create_group(group, id) => {
+            Err(CommandError::Raw("BUSYGROUP Consumer Group name already exists".into()))
+        }
+        "create" => Ok(ok()),
+        "setid" => {
+            stream.group_mut(group).ok_or_else(nogroup)?.last_delivered = id;
+            Ok(ok())
+        }
+        "destroy" => Ok(int(stream.destroy_group(group) as i64)),
+        "createconsumer" => Ok(int(stream.group_mut(group).ok_or_else(nogroup)?.touch_consumer(&args[2], now) as i64)),
+        _ => Ok(int(stream.group_mut(group).ok_or_else(nogroup)?.delete_consumer(&args[2]))),
+    }
+}
+
+fn xgroup_unknown(sub: &str) -> CmdResult {
+    Err(CommandError::Other(format!("unknown subcommand '{}'. Try XGROUP HELP.", sub)))
+}
+
+// XACK key group id [id ...]
+fn xack(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let ids = argv[3..].iter().map(|id| arg_id(id, 0)).collect::<Result<Vec<_>, _>>()?;
+    let mut db = ctx.write();
+    let group = get_stream_mut(&mut db, &argv[1], false)?.and_then(|s| s.group_mut(&argv[2]));
+    let Some(group) = group else {
+        return Ok(int(0));
+    };
+    Ok(int(ids.into_iter().filter(|id| group.ack(*id)).count()))
+}
+
+// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+fn xpending(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let group = get_stream(&db, &argv[1])?.and_then(|s| s.group(&argv[2]));
+    let group = group.ok_or_else(|| nogroup(&argv[1], &argv[2]))?;
+
+    // the summary: how many, the smallest and greatest ID, how many per consumer
+    if argv.len() == 3 {
+        let (Some((first, _)), Some((last, _))) = (group.pending.first_key_value(), group.pending.last_key_value())
+        else {
+            return Ok(array([int(0), nil(), nil(), nil_array()]));
+        };
+        let mut per_consumer = std::collections::BTreeMap::<&[u8], usize>::new();
+        for pending in group.pending.values() {
+            *per_consumer.entry(&pending.consumer).or_default() += 1;
+        }
+        let consumers = per_consumer.into_iter().map(|(name, n)| array([bulk(name), bulk(n.to_string())]));
+        return Ok(array([int(group.pending.len()), id_frame(*first), id_frame(*last), array(consumers)]));
+    }
+
+    let (min_idle, rest) = match &argv[3..] {
+        [option, idle, rest @ ..] if eq_ignore_case(option, "idle") => (arg_i64(idle)?.max(0) as u64, rest),
+        rest => (0, rest),
+    };
+    let (start, end, count, consumer) = match rest {
+        [start, end, count] => (start, end, count, None),
+        [start, end, count, consumer] => (start, end, count, Some(consumer)),
+        _ => return Err(CommandError::Syntax),
+    };
+    let (start, end) = (range_bound(start, true)?, range_bound(end, false)?);
+    let count = arg_i64(count)?.max(0) as usize;
+    let (Some(start), Some(end)) = (start, end) else {
+        return Ok(array([]));
+    };
+    if start > end {
+        return Ok(array([]));
+    }
+    let now = now_ms();
+    let entries = group
+        .pending
+        .range(start..=end)
+        .filter(|(_, p)| consumer.is_none_or(|c| p.consumer == *c))
+        .map(|(id, p)| (id, p, now.saturating_sub(p.delivered_at)))
+        .filter(|(_, _, idle)| *idle >= min_idle)
+        .take(count)
+        .map(|(id, p, idle)| array([id_frame(*id), bulk(p.consumer.clone()), int(idle), int(p.deliveries)]));
+    Ok(array(entries))
+}
+
+// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-ms]
+//        [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID id]
+fn xclaim(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut claim = Claim { min_idle: arg_i64(&argv[4])?.max(0) as u64, ..Claim::default() };
+    let mut i = 5;
+    let mut ids = Vec::new();
+    while let Some(id) = argv.get(i).and_then(|id| StreamId::parse(id, 0)) {
+        ids.push(id);
+        i += 1;
+    }
+    if ids.is_empty() {
+        return Err(invalid_id());
+    }
+    let now = now_ms();
+    let mut last_id = None;
+    while i < argv.len() {
+        let option = arg_str(&argv[i]);
+        let value = argv.get(i + 1);
+        match (option.as_str(), value) {
+            ("force", _) => claim.force = true,
+            ("justid", _) => claim.just_id = true,
+            ("idle", Some(ms)) => claim.delivered_at = Some(now.saturating_sub(arg_i64(ms)?.max(0) as u64)),
+            ("time", Some(ms)) => claim.delivered_at = Some(arg_i64(ms)?.max(0) as u64),
+            ("retrycount", Some(n)) => claim.deliveries = Some(arg_i64(n)?.max(0) as u64),
+            ("lastid", Some(id)) => last_id = Some(arg_id(id, 0)?),
+            _ => {
+                return Err(CommandError::Other(format!(
+                    "Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&argv[i])
+                )))
+            }
+        }
+        i += if matches!(option.as_str(), "force" | "justid") { 1 } else { 2 };
+    }
+
+    let mut db = ctx.write();
+    let stream = get_stream_mut(&mut db, &argv[1], false)?.filter(|s| s.group(&argv[2]).is_some());
+    let stream = stream.ok_or_else(|| nogroup(&argv[1], &argv[2]))?;
+    if let (Some(last_id), Some(group)) = (last_id, stream.group_mut(&argv[2])) {
+        group.last_delivered = group.last_delivered.max(last_id);
+    }
+    let claimed = stream.claim(&argv[2], &argv[3], &ids, &claim, now).unwrap_or_default();
+    if claim.just_id {
+        return Ok(array(claimed.into_iter().map(|(id, _)| id_frame(id))));
+    }
+    Ok(entries_frame(claimed))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Value,
+        cmd::{array, bulk, bulk_array, error_frame, int, nil, nil_array, ok, simple, tests::run, CommandError, Session},
+        resp::RespFrame,
+    };
+
+    use super::super::tests::test_backend;
+
+    fn entry(id: &str, fields: &[&str]) -> RespFrame {
+        array([bulk(id), bulk_array(fields)])
+    }
+
+    #[test]
+    fn test_xadd_xrange() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "xadd s 1-1 a 1"), bulk("1-1"));
+        assert_eq!(run(&backend, &mut session, "xadd s 1-* a 2"), bulk("1-2"));
+        assert_eq!(run(&backend, &mut session, "xadd s 3 a 3 b 4"), bulk("3-0"));
+        assert_eq!(
+            run(&backend, &mut session, "xadd s 2-0 a 5"),
+            error_frame(CommandError::Other(
+                "The ID specified in XADD is equal or smaller than the target stream top item".into()
+            ))
+        );
+        assert!(matches!(run(&backend, &mut session, "xadd s 0-0 a 1"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut session, "xadd s 4 a"), error_frame(CommandError::WrongArity("xadd".into())));
+        assert_eq!(run(&backend, &mut session, "xadd nope nomkstream * a 1"), nil());
+        assert_eq!(run(&backend, &mut session, "xlen s"), int(3));
+        assert_eq!(run(&backend, &mut session, "type s"), simple("stream"));
+
+        assert_eq!(
+            run(&backend, &mut session, "xrange s - 1"),
+            array([entry("1-1", &["a", "1"]), entry("1-2", &["a", "2"])])
+        );
+        assert_eq!(run(&backend, &mut session, "xrange s (1-1 3 count 1"), array([entry("1-2", &["a", "2"])]));
+        assert_eq!(run(&backend, &mut session, "xrevrange s 3 - count 1"), array([entry("3-0", &["a", "3", "b", "4"])]));
+        assert_eq!(run(&backend, &mut session, "xrange s 5 4"), array([]));
+
+        // the oldest entries go, the stream stays even when empty
+        assert_eq!(run(&backend, &mut session, "xadd s maxlen 2 5 a 7"), bulk("5-0"));
+        assert_eq!(run(&backend, &mut session, "xlen s"), int(2));
+        assert_eq!(run(&backend, &mut session, "xtrim s minid ~ 4 limit 10"), int(1));
+        assert_eq!(run(&backend, &mut session, "xdel s 9-9"), int(0));
+        assert_eq!(run(&backend, &mut session, "xtrim s maxlen = 0"), int(1));
+        assert_eq!(run(&backend, &mut session, "exists s"), int(1));
+        assert!(matches!(run(&backend, &mut session, "xadd s 5 a 1"), RespFrame::Error(_)));
+        let RespFrame::BulkString(auto) = run(&backend, &mut session, "xadd s * a 6") else { panic!() };
+        assert!(String::from_utf8(auto).unwrap().ends_with("-0"));
+    }
+
+    #[test]
+    fn test_xread() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "xadd a 1 f 1");
+        run(&backend, &mut session, "xadd b 2 f 2");
+        assert_eq!(
+            run(&backend, &mut session, "xread count 1 streams a b 0 0"),
+            array([
+                array([bulk("a"), array([entry("1-0", &["f", "1"])])]),
+                array([bulk("b"), array([entry("2-0", &["f", "2"])])]),
+            ])
+        );
+        assert_eq!(run(&backend, &mut session, "xread streams a b $ $"), nil_array());
+        assert!(session.blocked.is_none());
+        assert!(matches!(run(&backend, &mut session, "xread streams a b 0"), RespFrame::Error(_)));
+
+        // nothing new: the command is to be run again with `$` pinned
+        assert_eq!(run(&backend, &mut session, "xread block 0 streams a c $ $"), nil_array());
+        let blocked = session.blocked.take().unwrap();
+        assert_eq!(blocked.keys, vec![b"a".to_vec(), b"c".to_vec()]);
+        assert_eq!(blocked.timeout, None);
+        assert_eq!(blocked.argv[6..], [b"1-0".to_vec(), b"0-0".to_vec()]);
+
+        // EXEC never blocks
+        run(&backend, &mut session, "multi");
+        run(&backend, &mut session, "xread block 10 streams a $");
+        assert_eq!(run(&backend, &mut session, "exec"), array([nil_array()]));
+        assert!(session.blocked.is_none());
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert!(matches!(run(&backend, &mut session, "xgroup create s g $"), RespFrame::Error(_)));
+        assert_eq!(run(&backend, &mut session, "xgroup create s g $ mkstream"), ok());
+        assert_eq!(
+            run(&backend, &mut session, "xgroup create s g $"),
+            error_frame(CommandError::Raw("BUSYGROUP Consumer Group name already exists".into()))
+        );
+        run(&backend, &mut session, "xadd s 1 f 1");
+        run(&backend, &mut session, "xadd s 2 f 2");
+
+        assert_eq!(
+            run(&backend, &mut session, "xreadgroup group g alice count 1 streams s >"),
+            array([array([bulk("s"), array([entry("1-0", &["f", "1"])])])])
+        );
+        assert_eq!(
+            run(&backend, &mut session, "xreadgroup group g bob streams s >"),
+            array([array([bulk("s"), array([entry("2-0", &["f", "2"])])])])
+        );
+        assert_eq!(run(&backend, &mut session, "xreadgroup group g bob block 5 streams s >"), nil_array());
+        assert!(session.blocked.take().is_some());
+        assert!(matches!(run(&backend, &mut session, "xreadgroup group nope bob streams s >"), RespFrame::Error(_)));
+
+        let RespFrame::Array(summary) = run(&backend, &mut session, "xpending s g") else { panic!() };
+        assert_eq!(summary[..3], [int(2), bulk("1-0"), bulk("2-0")]);
+        assert_eq!(
+            summary[3],
+            array([array([bulk("alice"), bulk("1")]), array([bulk("bob"), bulk("1")])])
+        );
+        let RespFrame::Array(details) = run(&backend, &mut session, "xpending s g - + 10 bob") else { panic!() };
+        assert_eq!(details.len(), 1);
+
+        // bob's entry is idle enough for alice right away
+        assert_eq!(run(&backend, &mut session, "xclaim s g alice 0 2-0 justid"), array([bulk("2-0")]));
+        assert_eq!(
+            run(&backend, &mut session, "xreadgroup group g alice streams s 0"),
+            array([array([bulk("s"), array([entry("1-0", &["f", "1"]), entry("2-0", &["f", "2"])])])])
+        );
+        assert_eq!(run(&backend, &mut session, "xclaim s g bob 3600000 1-0"), array([]));
+        assert_eq!(run(&backend, &mut session, "xack s g 1-0 2-0 3-0"), int(2));
+        assert_eq!(run(&backend, &mut session, "xpending s g"), array([int(0), nil(), nil(), nil_array()]));
+
+        assert_eq!(run(&backend, &mut session, "xgroup createconsumer s g carol"), int(1));
+        assert_eq!(run(&backend, &mut session, "xgroup delconsumer s g carol"), int(0));
+        assert_eq!(run(&backend, &mut session, "xgroup setid s g 0"), ok());
+        assert_eq!(
+            run(&backend, &mut session, "xreadgroup group g carol noack streams s >"),
+            array([array([bulk("s"), array([entry("1-0", &["f", "1"]), entry("2-0", &["f", "2"])])])])
+        );
+        assert_eq!(run(&backend, &mut session, "xpending s g"), array([int(0), nil(), nil(), nil_array()]));
+        assert_eq!(run(&backend, &mut session, "xgroup destroy s g"), int(1));
+        assert_eq!(run(&backend, &mut session, "xgroup destroy s g"), int(0));
+        let RespFrame::Array(help) = run(&backend, &mut session, "xgroup help") else { panic!("expect: array") };
+        assert_eq!(help.first(), Some(&simple("XGROUP <subcommand> [<arg> [value] [opt] ...]. Subcommands are:")));
+        assert!(help.contains(&simple("DESTROY <key> <groupname>")));
+        assert!(matches!(run(&backend, &mut session, "xgroup nope s g"), RespFrame::Error(e) if e.as_str().contains("Try XGROUP HELP")));
+
+        let db = backend.read(0);
+        assert!(matches!(db.get(b"s"), Some(Value::Stream(s)) if s.groups().count() == 0));
+    }
+}
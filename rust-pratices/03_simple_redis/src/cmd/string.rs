@@ -0,0 +1,365 @@
+use crate::{
+    backend::{Db, Str, Value},
+    utils::{format_f64, now_ms, parse_f64, parse_i64},
+};
+
+use super::{
+    arg_f64, arg_i64, bulk, eq_ignore_case, int, nil, ok, array, CmdResult, CommandError,
+    CommandSpec, Context, ALL_KEYS, DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("get", 2, READONLY | FAST, KEY1, get),
+    CommandSpec::new("set", -3, WRITE | DENYOOM, KEY1, set),
+    CommandSpec::new("setnx", 3, WRITE | DENYOOM | FAST, KEY1, setnx),
+    CommandSpec::new("setex", 4, WRITE | DENYOOM, KEY1, setex),
+    CommandSpec::new("psetex", 4, WRITE | DENYOOM, KEY1, psetex),
+    CommandSpec::new("getset", 3, WRITE | DENYOOM | FAST, KEY1, getset),
+    CommandSpec::new("getdel", 2, WRITE | FAST, KEY1, getdel),
+    CommandSpec::new("mget", -2, READONLY | FAST, ALL_KEYS, mget),
+    CommandSpec::new("mset", -3, WRITE | DENYOOM, (1, -1, 2), mset),
+    CommandSpec::new("msetnx", -3, WRITE | DENYOOM, (1, -1, 2), msetnx),
+    CommandSpec::new("incr", 2, WRITE | DENYOOM | FAST, KEY1, incr),
+    CommandSpec::new("decr", 2, WRITE | DENYOOM | FAST, KEY1, decr),
+    CommandSpec::new("incrby", 3, WRITE | DENYOOM | FAST, KEY1, incrby),
+    CommandSpec::new("decrby", 3, WRITE | DENYOOM | FAST, KEY1, decrby),
+    CommandSpec::new("incrbyfloat", 3, WRITE | DENYOOM | FAST, KEY1, incrbyfloat),
+    CommandSpec::new("append", 3, WRITE | DENYOOM, KEY1, append),
+    CommandSpec::new("strlen", 2, READONLY | FAST, KEY1, strlen),
+    CommandSpec::new("getrange", 4, READONLY, KEY1, getrange),
+    CommandSpec::new("setrange", 4, WRITE | DENYOOM, KEY1, setrange),
+];
+
+pub(crate) fn get_string<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a Str>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn get(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(get_string(&db, &argv[1])?.map(|s| bulk(s.clone())).unwrap_or_else(nil))
+}
+
+// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+fn set(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+    let mut expire_at: Option<u64> = None;
+
+    let mut i = 3;
+    while i < argv.len() {
+        let opt = &argv[i];
+        if eq_ignore_case(opt, "nx") && !xx {
+            nx = true;
+        } else if eq_ignore_case(opt, "xx") && !nx {
+            xx = true;
+        } else if eq_ignore_case(opt, "get") {
+            get = true;
+        } else if eq_ignore_case(opt, "keepttl") && expire_at.is_none() {
+            keep_ttl = true;
+        } else if ["ex", "px", "exat", "pxat"].iter().any(|o| eq_ignore_case(opt, o))
+            && expire_at.is_none()
+            && !keep_ttl
+        {
+            let v = argv.get(i + 1).ok_or(CommandError::Syntax)?;
+            let v = arg_i64(v)?;
+            if v <= 0 {
+                return Err(CommandError::InvalidExpire("set".into()));
+            }
+            let at = match String::from_utf8_lossy(opt).to_ascii_lowercase().as_str() {
+                "ex" => now_ms() as i64 + v.saturating_mul(1000),
+                "px" => now_ms() as i64 + v,
+                "exat" => v.saturating_mul(1000),
+                _ => v,
+            };
+            expire_at = Some(at.max(0) as u64);
+            i += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        i += 1;
+    }
+
+    let key = &argv[1];
+    let mut db = ctx.write();
+    let old = if get { get_string(&db, key)?.cloned() } else { None };
+    let exists = db.contains(key);
+    if (nx && exists) || (xx && !exists) {
+        ctx.propagate = Some(vec![]);
+        return Ok(if get { old.map(bulk).unwrap_or_else(nil) } else { nil() });
+    }
+
+    let value = Value::String(argv[2].clone().into());
+    if keep_ttl {
+        db.set_keep_ttl(key.clone(), value);
+    } else {
+        db.set(key.clone(), value);
+    }
+    if let Some(at) = expire_at {
+        db.set_expire(key, at);
+        ctx.propagate = Some(vec![set_pxat(key, &argv[2], at)]);
+    }
+    Ok(if get { old.map(bulk).unwrap_or_else(nil) } else { ok() })
+}
+
+fn setnx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    if db.contains(&argv[1]) {
+        return Ok(int(0));
+    }
+    db.set(argv[1].clone(), Value::String(argv[2].clone().into()));
+    Ok(int(1))
+}
+
+fn set_with_ttl(ctx: &mut Context, argv: &[Vec<u8>], unit_ms: i64, name: &str) -> CmdResult {
+    let ttl = arg_i64(&argv[2])?;
+    if ttl <= 0 {
+        return Err(CommandError::InvalidExpire(name.into()));
+    }
+    let at = now_ms() + ttl.saturating_mul(unit_ms) as u64;
+    let mut db = ctx.write();
+    db.set(argv[1].clone(), Value::String(argv[3].clone().into()));
+    db.set_expire(&argv[1], at);
+    ctx.propagate = Some(vec![set_pxat(&argv[1], &argv[3], at)]);
+    Ok(ok())
+}
+
+// a SET with a relative TTL reaches replicas with the absolute deadline
+fn set_pxat(key: &[u8], value: &[u8], at: u64) -> Vec<Vec<u8>> {
+    vec![b"set".to_vec(), key.to_vec(), value.to_vec(), b"pxat".to_vec(), at.to_string().into_bytes()]
+}
+
+fn setex(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    set_with_ttl(ctx, argv, 1000, "setex")
+}
+
+fn psetex(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    set_with_ttl(ctx, argv, 1, "psetex")
+}
+
+fn getset(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let old = get_string(&db, &argv[1])?.cloned();
+    db.set(argv[1].clone(), Value::String(argv[2].clone().into()));
+    Ok(old.map(bulk).unwrap_or_else(nil))
+}
+
+fn getdel(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let old = get_string(&db, &argv[1])?.cloned();
+    if old.is_some() {
+        db.remove(&argv[1]);
+    }
+    Ok(old.map(bulk).unwrap_or_else(nil))
+}
+
+fn mget(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(array(argv[1..].iter().map(|key| match db.get(key) {
+        Some(Value::String(s)) => bulk(s),
+        _ => nil(),
+    })))
+}
+
+fn mset(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() % 2 != 1 {
+        return Err(CommandError::WrongArity("mset".into()));
+    }
+    let mut db = ctx.write();
+    for pair in argv[1..].chunks(2) {
+        db.set(pair[0].clone(), Value::String(pair[1].clone().into()));
+    }
+    Ok(ok())
+}
+
+fn msetnx(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    if argv.len() % 2 != 1 {
+        return Err(CommandError::WrongArity("msetnx".into()));
+    }
+    let mut db = ctx.write();
+    if argv[1..].chunks(2).any(|pair| db.contains(&pair[0])) {
+        return Ok(int(0));
+    }
+    for pair in argv[1..].chunks(2) {
+        db.set(pair[0].clone(), Value::String(pair[1].clone().into()));
+    }
+    Ok(int(1))
+}
+
+pub(crate) fn incr_by(db: &mut Db, key: &[u8], delta: i64) -> Result<i64, CommandError> {
+    let current = match get_string(db, key)? {
+        None => 0,
+        Some(s) => parse_i64(s).ok_or(CommandError::NotInteger)?,
+    };
+    let value = current.checked_add(delta).ok_or(CommandError::Overflow)?;
+    db.set_keep_ttl(key.to_vec(), Value::String(Str::from_int(value)));
+    Ok(value)
+}
+
+fn incr(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    Ok(int(incr_by(&mut db, &argv[1], 1)?))
+}
+
+fn decr(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    Ok(int(incr_by(&mut db, &argv[1], -1)?))
+}
+
+fn incrby(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let delta = arg_i64(&argv[2])?;
+    let mut db = ctx.write();
+    Ok(int(incr_by(&mut db, &argv[1], delta)?))
+}
+
+fn decrby(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let delta = arg_i64(&argv[2])?.checked_neg().ok_or(CommandError::Overflow)?;
+    let mut db = ctx.write();
+    Ok(int(incr_by(&mut db, &argv[1], delta)?))
+}
+
+fn incrbyfloat(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let delta = arg_f64(&argv[2])?;
+    let mut db = ctx.write();
+    let current = match get_string(&db, &argv[1])? {
+        None => 0.0,
+        Some(s) => parse_f64(s).ok_or(CommandError::NotFloat)?,
+    };
+    let value = current + delta;
+    if !value.is_finite() {
+        return Err(CommandError::Other("increment would produce NaN or Infinity".into()));
+    }
+    let value = format_f64(value);
+    db.set_keep_ttl(argv[1].clone(), Value::String(value.clone().into_bytes().into()));
+    Ok(bulk(value))
+}
+
+fn append(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let mut value = get_string(&db, &argv[1])?.map(|s| s.to_vec()).unwrap_or_default();
+    value.extend_from_slice(&argv[2]);
+    let len = value.len();
+    db.set_keep_ttl(argv[1].clone(), Value::String(value.into()));
+    Ok(int(len))
+}
+
+fn strlen(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_string(&db, &argv[1])?.map(|s| s.len()).unwrap_or(0)))
+}
+
+// turn redis style inclusive [start, end] with negative indexes into a rust range
+pub(crate) fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+fn getrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (start, end) = (arg_i64(&argv[2])?, arg_i64(&argv[3])?);
+    let db = ctx.read();
+    let Some(s) = get_string(&db, &argv[1])? else {
+        return Ok(bulk(""));
+    };
+    Ok(match normalize_range(start, end, s.len()) {
+        Some((start, end)) => bulk(&s[start..=end]),
+        None => bulk(""),
+    })
+}
+
+// redis `proto-max-bulk-len`, a string can't grow past it
+pub(crate) const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+fn setrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let offset = arg_i64(&argv[2])?;
+    if offset < 0 {
+        return Err(CommandError::Other("offset is out of range".into()));
+    }
+    let offset = offset as usize;
+    let patch = &argv[3];
+    if offset + patch.len() > MAX_STRING_LEN {
+        return Err(CommandError::Other("string exceeds maximum allowed size (proto-max-bulk-len)".into()));
+    }
+
+    let mut db = ctx.write();
+    let mut value = get_string(&db, &argv[1])?.map(|s| s.to_vec()).unwrap_or_default();
+    if patch.is_empty() {
+        return Ok(int(value.len()));
+    }
+    if value.len() < offset + patch.len() {
+        value.resize(offset + patch.len(), 0);
+    }
+    value[offset..offset + patch.len()].copy_from_slice(patch);
+    let len = value.len();
+    db.set_keep_ttl(argv[1].clone(), Value::String(value.into()));
+    Ok(int(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        bulk, int, nil, ok,
+        tests::{run, test_backend},
+        CommandError, Session, error_frame,
+    };
+
+    #[test]
+    fn test_get_set() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "get a"), nil());
+        assert_eq!(run(&backend, &mut session, "set a hello"), ok());
+        assert_eq!(run(&backend, &mut session, "get a"), bulk("hello"));
+        assert_eq!(run(&backend, &mut session, "set a world nx"), nil());
+        assert_eq!(run(&backend, &mut session, "set a world xx get"), bulk("hello"));
+        assert_eq!(run(&backend, &mut session, "set b 1 xx"), nil());
+        assert_eq!(run(&backend, &mut session, "set a 1 ex 0"), error_frame(CommandError::InvalidExpire("set".into())));
+        assert_eq!(run(&backend, &mut session, "set a 1 nx xx"), error_frame(CommandError::Syntax));
+    }
+
+    #[test]
+    fn test_incr() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "incr n"), int(1));
+        assert_eq!(run(&backend, &mut session, "incrby n 10"), int(11));
+        assert_eq!(run(&backend, &mut session, "decrby n 12"), int(-1));
+        assert_eq!(run(&backend, &mut session, "incrbyfloat n 1.5"), bulk("0.5"));
+        assert_eq!(run(&backend, &mut session, "incr n"), error_frame(CommandError::NotInteger));
+        run(&backend, &mut session, "set big 9223372036854775807");
+        assert_eq!(run(&backend, &mut session, "incr big"), error_frame(CommandError::Overflow));
+    }
+
+    #[test]
+    fn test_append_and_ranges() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "append s hello"), int(5));
+        assert_eq!(run(&backend, &mut session, "append s _world"), int(11));
+        assert_eq!(run(&backend, &mut session, "getrange s 0 4"), bulk("hello"));
+        assert_eq!(run(&backend, &mut session, "getrange s -5 -1"), bulk("world"));
+        assert_eq!(run(&backend, &mut session, "setrange s 6 W"), int(11));
+        assert_eq!(run(&backend, &mut session, "get s"), bulk("hello_World"));
+        assert_eq!(run(&backend, &mut session, "setrange p 2 x"), int(3));
+        assert_eq!(run(&backend, &mut session, "get p"), bulk(b"\0\0x".to_vec()));
+    }
+
+    #[test]
+    fn test_mset_mget() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "mset a 1 b 2"), ok());
+        assert_eq!(
+            run(&backend, &mut session, "mget a b c"),
+            crate::cmd::array([bulk("1"), bulk("2"), nil()])
+        );
+        assert_eq!(run(&backend, &mut session, "msetnx c 3 a 1"), int(0));
+        assert_eq!(run(&backend, &mut session, "msetnx c 3 d 4"), int(1));
+    }
+}
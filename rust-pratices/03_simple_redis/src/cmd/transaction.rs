@@ -0,0 +1,110 @@
+use super::{
+    call, lookup, nil_array, ok, CmdResult, CommandError, CommandSpec, Context, EXCLUSIVE, FAST,
+    NOSCRIPT, NO_KEYS, NO_MULTI, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("multi", 1, FAST | NO_MULTI | NOSCRIPT, NO_KEYS, multi),
+    CommandSpec::new("exec", 1, NO_MULTI | EXCLUSIVE | NOSCRIPT, NO_KEYS, exec),
+    CommandSpec::new("discard", 1, FAST | NO_MULTI | NOSCRIPT, NO_KEYS, discard),
+];
+
+fn multi(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    if ctx.session.multi.is_some() {
+        return Err(CommandError::Other("MULTI calls can not be nested".into()));
+    }
+    ctx.session.multi = Some(Vec::new());
+    ctx.session.multi_error = false;
+    Ok(ok())
+}
+
+// EXEC runs while holding the command lock exclusively, so no other client
+// can observe the transaction half applied
+fn exec(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    let Some(queue) = ctx.session.multi.take() else {
+        return Err(CommandError::Other("EXEC without MULTI".into()));
+    };
+    if std::mem::take(&mut ctx.session.multi_error) {
+        return Err(CommandError::Raw(
+            "EXECABORT Transaction discarded because of previous errors.".into(),
+        ));
+    }
+    if queue.is_empty() {
+        return Ok(super::array([]));
+    }
+
+    // replicas get the writes wrapped in MULTI / EXEC too
+    let repl = &ctx.backend.repl;
+    let writes = queue.iter().filter_map(|argv| lookup(&argv[0])).any(|spec| spec.has_flag(WRITE));
+    if writes {
+        repl.propagate(ctx.session.db, &[vec![b"multi".to_vec()]]);
+        ctx.session.in_atomic = true;
+    }
+    let replies = queue
+        .into_iter()
+        .map(|argv| match lookup(&argv[0]) {
+            Some(spec) => {
+                let reply = call(ctx.backend, ctx.session, spec, &argv);
+                ctx.session.blocked = None;
+                reply
+            }
+            None => nil_array(),
+        })
+        .collect::<Vec<_>>();
+    if writes {
+        ctx.session.in_atomic = false;
+        repl.propagate(ctx.session.db, &[vec![b"exec".to_vec()]]);
+    }
+    Ok(super::array(replies))
+}
+
+fn discard(ctx: &mut Context, _argv: &[Vec<u8>]) -> CmdResult {
+    if ctx.session.multi.take().is_none() {
+        return Err(CommandError::Other("DISCARD without MULTI".into()));
+    }
+    ctx.session.multi_error = false;
+    Ok(ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        array, bulk, error_frame, int, ok, simple,
+        tests::{run, test_backend},
+        CommandError, Session,
+    };
+
+    #[test]
+    fn test_multi_exec() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "multi"), ok());
+        assert_eq!(run(&backend, &mut session, "set a 1"), simple("QUEUED"));
+        assert_eq!(run(&backend, &mut session, "incr a"), simple("QUEUED"));
+        assert_eq!(run(&backend, &mut session, "get a"), simple("QUEUED"));
+        assert_eq!(run(&backend, &mut session, "exec"), array([ok(), int(2), bulk("2")]));
+        assert_eq!(
+            run(&backend, &mut session, "exec"),
+            error_frame(CommandError::Other("EXEC without MULTI".into()))
+        );
+    }
+
+    #[test]
+    fn test_multi_abort() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        run(&backend, &mut session, "multi");
+        run(&backend, &mut session, "set a 1");
+        run(&backend, &mut session, "nope");
+        assert_eq!(
+            run(&backend, &mut session, "exec"),
+            error_frame(CommandError::Raw("EXECABORT Transaction discarded because of previous errors.".into()))
+        );
+        assert_eq!(run(&backend, &mut session, "exists a"), int(0));
+
+        run(&backend, &mut session, "multi");
+        run(&backend, &mut session, "set a 1");
+        assert_eq!(run(&backend, &mut session, "discard"), ok());
+        assert_eq!(run(&backend, &mut session, "exists a"), int(0));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    backend::{zset::ZSet, Db, Value},
+    utils::format_f64,
+};
+
+use super::{
+    arg_f64, arg_i64, array, bulk, eq_ignore_case, int, nil, string::normalize_range, CmdResult, CommandError,
+    CommandSpec, Context, DENYOOM, FAST, KEY1, READONLY, WRITE,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("zadd", -4, WRITE | DENYOOM | FAST, KEY1, zadd),
+    CommandSpec::new("zrem", -3, WRITE | FAST, KEY1, zrem),
+    CommandSpec::new("zscore", 3, READONLY | FAST, KEY1, zscore),
+    CommandSpec::new("zcard", 2, READONLY | FAST, KEY1, zcard),
+    CommandSpec::new("zrank", 3, READONLY | FAST, KEY1, zrank),
+    CommandSpec::new("zrange", -4, READONLY, KEY1, zrange),
+];
+
+pub(crate) fn get_zset<'a>(db: &'a Db, key: &[u8]) -> Result<Option<&'a ZSet>, CommandError> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(Value::ZSet(z)) => Ok(Some(z)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub(crate) fn get_zset_mut<'a>(db: &'a mut Db, key: &[u8], create: bool) -> Result<Option<&'a mut ZSet>, CommandError> {
+    if create && db.get_mut(key).is_none() {
+        db.set(key.to_vec(), Value::ZSet(ZSet::default()));
+    }
+    match db.get_mut(key) {
+        None => Ok(None),
+        Some(Value::ZSet(z)) => Ok(Some(z)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+// ZADD key [NX|XX] [CH] score member [score member ...]
+fn zadd(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut i = 2;
+    while let Some(option) = argv.get(i) {
+        if eq_ignore_case(option, "nx") {
+            nx = true;
+        } else if eq_ignore_case(option, "xx") {
+            xx = true;
+        } else if eq_ignore_case(option, "ch") {
+            ch = true;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    if nx && xx {
+        return Err(CommandError::Other("XX and NX options at the same time are not compatible".into()));
+    }
+    let pairs = &argv[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    let pairs = pairs
+        .chunks(2)
+        .map(|pair| arg_f64(&pair[0]).map(|score| (score, &pair[1])))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut db = ctx.write();
+    let Some(zset) = get_zset_mut(&mut db, &argv[1], !xx)? else {
+        return Ok(int(0));
+    };
+    let (mut added, mut changed) = (0, 0);
+    for (score, member) in pairs {
+        match zset.score(member) {
+            None if !xx => {
+                zset.insert(member, score);
+                added += 1;
+            }
+            Some(old) if !nx && old != score => {
+                zset.insert(member, score);
+                changed += 1;
+            }
+            _ => {}
+        }
+    }
+    db.remove_if_empty(&argv[1]);
+    Ok(int(if ch { added + changed } else { added }))
+}
+
+fn zrem(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let mut db = ctx.write();
+    let Some(zset) = get_zset_mut(&mut db, &argv[1], false)? else {
+        return Ok(int(0));
+    };
+    let removed = argv[2..].iter().filter(|member| zset.remove(member)).count();
+    db.remove_if_empty(&argv[1]);
+    Ok(int(removed))
+}
+
+fn zscore(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let score = get_zset(&db, &argv[1])?.and_then(|z| z.score(&argv[2]));
+    Ok(score.map(|s| bulk(format_f64(s))).unwrap_or_else(nil))
+}
+
+fn zcard(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    Ok(int(get_zset(&db, &argv[1])?.map_or(0, |z| z.len())))
+}
+
+fn zrank(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let db = ctx.read();
+    let rank = get_zset(&db, &argv[1])?.and_then(|z| z.rank(&argv[2]));
+    Ok(rank.map(int).unwrap_or_else(nil))
+}
+
+// ZRANGE key start stop [WITHSCORES], by rank
+fn zrange(ctx: &mut Context, argv: &[Vec<u8>]) -> CmdResult {
+    let (start, end) = (arg_i64(&argv[2])?, arg_i64(&argv[3])?);
+    let with_scores = match &argv[4..] {
+        [] => false,
+        [option] if eq_ignore_case(option, "withscores") => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    let db = ctx.read();
+    let Some(zset) = get_zset(&db, &argv[1])? else {
+        return Ok(array([]));
+    };
+    let Some((start, end)) = normalize_range(start, end, zset.len()) else {
+        return Ok(array([]));
+    };
+    let members = zset.iter().skip(start).take(end - start + 1);
+    Ok(array(members.flat_map(|(member, score)| {
+        let score = with_scores.then(|| bulk(format_f64(score)));
+        std::iter::once(bulk(member)).chain(score)
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::{
+        array, bulk, int, nil,
+        tests::{run, test_backend},
+        Session,
+    };
+
+    #[test]
+    fn test_zadd_zrange() {
+        let backend = test_backend();
+        let mut session = Session::new();
+        assert_eq!(run(&backend, &mut session, "zadd z 1 a 2 b 1.5 c"), int(3));
+        assert_eq!(run(&backend, &mut session, "zadd z xx ch 3 a 1 d"), int(1));
+        assert_eq!(run(&backend, &mut session, "zadd z nx 9 a 0 d"), int(1));
+        assert_eq!(run(&backend, &mut session, "zscore z a"), bulk("3"));
+        assert_eq!(run(&backend, &mut session, "zscore z c"), bulk("1.5"));
+        assert_eq!(run(&backend, &mut session, "zscore z nope"), nil());
+        assert_eq!(run(&backend, &mut session, "zrank z a"), int(3));
+        assert_eq!(run(&backend, &mut session, "zcard z"), int(4));
+        assert_eq!(
+            run(&backend, &mut session, "zrange z 0 1 withscores"),
+            array([bulk("d"), bulk("0"), bulk("c"), bulk("1.5")])
+        );
+        assert_eq!(run(&backend, &mut session, "zrange z -2 -1"), array([bulk("b"), bulk("a")]));
+        assert_eq!(run(&backend, &mut session, "zrem z a b c d e"), int(4));
+        assert_eq!(run(&backend, &mut session, "exists z"), int(0));
+        assert_eq!(run(&backend, &mut session, "zadd z xx 1 a"), int(0));
+        assert_eq!(run(&backend, &mut session, "exists z"), int(0));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{ffi::OsString, fmt, fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser, ValueEnum};
+
+use crate::{backend::EncodingLimits, pubsub::parse_events, resp::RespLimits, tls::TlsAuthClients, utils::parse_memory};
+
+// which keys may be evicted when maxmemory is reached, the same names as redis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MaxmemoryPolicy {
+    Noeviction,
+    AllkeysLru,
+    VolatileLru,
+    AllkeysLfu,
+    VolatileLfu,
+    AllkeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+// how much is logged, the same names as redis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+}
+
+// the `save` points: snapshot after `seconds` when at least `changes` writes happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveRules(pub Vec<(u64, u64)>);
+
+impl fmt::Display for SaveRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self.0.iter().map(|(seconds, changes)| format!("{} {}", seconds, changes)).collect();
+        write!(f, "{}", pairs.join(" "))
+    }
+}
+
+pub fn parse_save(s: &str) -> Result<SaveRules, String> {
+    let numbers = s
+        .split_whitespace()
+        .map(|n| n.parse::<u64>().map_err(|_| format!("invalid save point: {}", s)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() % 2 != 0 {
+        return Err(format!("save needs pairs of seconds and changes: {}", s));
+    }
+    Ok(SaveRules(numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect()))
+}
+
+// Every option can also be set in a redis.conf style file given as the first
+// argument, a line per option: `maxmemory 100mb`. The command line wins.
+#[derive(Debug, Clone, Parser)]
+#[command(name = "simple-redis", version, about = "A simple redis server", args_override_self = true)]
+pub struct Config {
+    /// config file, one `option value` per line
+    pub config_file: Option<PathBuf>,
+
+    #[arg(long, default_value = "0.0.0.0")]
+    pub bind: String,
+
+    #[arg(long, default_value_t = 6379)]
+    pub port: u16,
+
+    /// also listen on this Unix socket
+    #[arg(long)]
+    pub unixsocket: Option<PathBuf>,
+
+    /// permissions of the Unix socket file, octal like 700
+    #[arg(long, value_parser = parse_socket_perm)]
+    pub unixsocketperm: Option<u32>,
+
+    /// number of logical databases, selected per connection with SELECT
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub databases: u32,
+
+    /// locked shards every database is split into, commands on keys of different shards run in parallel
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub keyspace_shards: u32,
+
+    /// directory of the snapshot file
+    #[arg(long, default_value = ".")]
+    pub dir: PathBuf,
+
+    #[arg(long, default_value = "dump.srdb")]
+    pub dbfilename: String,
+
+    /// memory limit of the keyspace, e.g. 100mb or 1gb, 0 means no limit
+    #[arg(long, default_value = "0", value_parser = parse_memory)]
+    pub maxmemory: u64,
+
+    /// how keys are evicted once maxmemory is reached
+    #[arg(long, value_enum, default_value_t = MaxmemoryPolicy::Noeviction)]
+    pub maxmemory_policy: MaxmemoryPolicy,
+
+    /// keys sampled per eviction, more is closer to exact LRU/LFU but slower
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub maxmemory_samples: u32,
+
+    /// snapshot after SECONDS when at least CHANGES writes happened, e.g. "3600 1 300 100", empty disables it
+    #[arg(long, default_value = "", value_parser = parse_save)]
+    pub save: SaveRules,
+
+    #[arg(long, value_enum, default_value_t = LogLevel::Notice)]
+    pub loglevel: LogLevel,
+
+    /// start as a replica of this master
+    #[arg(long, num_args = 2, value_names = ["HOST", "PORT"])]
+    pub replicaof: Option<Vec<String>>,
+
+    /// size of the replication backlog kept for partial resyncs
+    #[arg(long, default_value = "1mb", value_parser = parse_memory)]
+    pub repl_backlog_size: u64,
+
+    /// bytes of the replication stream a replica may have unsent before it's disconnected, 0 for no limit
+    #[arg(long, default_value = "256mb", value_parser = parse_memory)]
+    pub replica_output_buffer_limit: u64,
+
+    /// reject writes from clients while being a replica
+    #[arg(long, default_value = "yes", value_parser = clap::builder::BoolishValueParser::new())]
+    pub replica_read_only: bool,
+
+    /// run in cluster mode, with the nodes and slot layout kept in this file (relative to dir)
+    #[arg(long)]
+    pub cluster_config_file: Option<PathBuf>,
+
+    /// password of the default user, AUTH is required when set
+    #[arg(long)]
+    pub requirepass: Option<String>,
+
+    /// password used to authenticate with the master
+    #[arg(long)]
+    pub masterauth: Option<String>,
+
+    /// user used to authenticate with the master, default when not set
+    #[arg(long)]
+    pub masteruser: Option<String>,
+
+    /// keyspace events published, e.g. Ex for expirations, see CONFIG SET
+    #[arg(long, default_value = "", value_parser = parse_notify_events)]
+    pub notify_keyspace_events: u32,
+
+    /// commands taking at least this many microseconds go to the SLOWLOG, -1 disables it
+    #[arg(long, default_value_t = 10000, allow_negative_numbers = true)]
+    pub slowlog_log_slower_than: i64,
+
+    /// entries kept in the SLOWLOG
+    #[arg(long, default_value_t = 128)]
+    pub slowlog_max_len: u32,
+
+    /// events taking at least this many milliseconds are kept by LATENCY, 0 disables it
+    #[arg(long, default_value_t = 0)]
+    pub latency_monitor_threshold: u64,
+
+    /// hashes with more fields than this leave the listpack encoding
+    #[arg(long, default_value_t = 128)]
+    pub hash_max_listpack_entries: u64,
+
+    /// hashes with a longer field or value leave the listpack encoding
+    #[arg(long, default_value_t = 64)]
+    pub hash_max_listpack_value: u64,
+
+    /// sets of integers with more members than this leave the intset encoding
+    #[arg(long, default_value_t = 512)]
+    pub set_max_intset_entries: u64,
+
+    /// sets with more members than this leave the listpack encoding
+    #[arg(long, default_value_t = 128)]
+    pub set_max_listpack_entries: u64,
+
+    /// sets with a longer member leave the listpack encoding
+    #[arg(long, default_value_t = 64)]
+    pub set_max_listpack_value: u64,
+
+    /// lists longer than this stay listpacks, -1 to -5 limit the bytes to 4kb..64kb instead
+    #[arg(long, default_value_t = -2, allow_negative_numbers = true, value_parser = clap::value_parser!(i64).range(-5..))]
+    pub list_max_listpack_size: i64,
+
+    /// max size of a single bulk string a client may send, e.g. 512mb
+    #[arg(long, default_value = "512mb", value_parser = parse_memory)]
+    pub proto_max_bulk_len: u64,
+
+    /// max bytes buffered for a client while its command isn't complete, the client is closed beyond it
+    #[arg(long, default_value = "1gb", value_parser = parse_memory)]
+    pub client_query_buffer_limit: u64,
+
+    /// also accept TLS connections on this port
+    #[arg(long)]
+    pub tls_port: Option<u16>,
+
+    /// certificate of the server, PEM
+    #[arg(long)]
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// private key of the server certificate, PEM
+    #[arg(long)]
+    pub tls_key_file: Option<PathBuf>,
+
+    /// CA that client and master certificates are verified with, PEM
+    #[arg(long)]
+    pub tls_ca_cert_file: Option<PathBuf>,
+
+    /// whether TLS clients must present a certificate signed by the CA
+    #[arg(long, value_enum, default_value_t = TlsAuthClients::Yes)]
+    pub tls_auth_clients: TlsAuthClients,
+
+    /// connect to the master over TLS
+    #[arg(long, default_value = "no", value_parser = clap::builder::BoolishValueParser::new())]
+    pub tls_replication: bool,
+}
+
+// The config file as command line arguments. `save` may be given on several
+// lines, like in redis, the points add up.
+fn file_args(text: &str) -> Result<Vec<String>> {
+    let options: Vec<String> =
+        Config::command().get_arguments().filter_map(|arg| arg.get_long().map(String::from)).collect();
+    let mut args = Vec::new();
+    let mut save: Option<Vec<String>> = None;
+    for (n, line) in text.lines().enumerate() {
+        let words = split_line(line).with_context(|| format!("config file line {}: unbalanced quotes", n + 1))?;
+        let Some((name, values)) = words.split_first() else {
+            continue;
+        };
+        let name = name.to_ascii_lowercase();
+        if !options.contains(&name) || values.is_empty() {
+            bail!("config file line {}: bad directive or wrong number of arguments '{}'", n + 1, line.trim());
+        }
+        if name == "save" {
+            save.get_or_insert_with(Vec::new).extend(values.iter().cloned());
+            continue;
+        }
+        args.push(format!("--{}", name));
+        args.extend(values.iter().cloned());
+    }
+    if let Some(points) = save {
+        args.push("--save".into());
+        args.push(points.join(" "));
+    }
+    Ok(args)
+}
+
+// split a config line into words, with "double" or 'single' quoted ones and # comments
+fn split_line(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '#' && words.is_empty() {
+            break;
+        }
+        let mut word = String::new();
+        if c == '"' || c == '\'' {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    q if q == c => break,
+                    '\\' if c == '"' => word.push(chars.next()?),
+                    other => word.push(other),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+        }
+        words.push(word);
+    }
+    Some(words)
+}
+
+// a value written back to the config file, quoted when it would be split
+pub fn quote_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        return value.into();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_socket_perm(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8).ok().filter(|perm| *perm <= 0o777).ok_or_else(|| format!("invalid permissions: {}", s))
+}
+
+fn parse_notify_events(s: &str) -> Result<u32, String> {
+    parse_events(s).ok_or_else(|| format!("invalid keyspace events: {}", s))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::parse_from(["simple-redis"])
+    }
+}
+
+impl Config {
+    // the command line, on top of the config file when one is given
+    pub fn load() -> Result<Self> {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let config = Config::parse_from(&args);
+        let Some(path) = config.config_file.as_ref() else {
+            return Ok(config);
+        };
+        let text = fs::read_to_string(path).with_context(|| format!("can't read config file {}", path.display()))?;
+        let mut merged = vec![args[0].clone()];
+        merged.extend(file_args(&text)?.into_iter().map(OsString::from));
+        merged.extend(args[1..].iter().cloned());
+        Ok(Config::try_parse_from(merged)?)
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    pub fn tls_addr(&self) -> Option<String> {
+        self.tls_port.map(|port| format!("{}:{}", self.bind, port))
+    }
+
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    pub fn cluster_config_path(&self) -> Option<PathBuf> {
+        self.cluster_config_file.as_ref().map(|f| self.dir.join(f))
+    }
+
+    pub fn master_addr(&self) -> anyhow::Result<Option<(String, u16)>> {
+        match self.replicaof.as_deref() {
+            Some([host, port]) => Ok(Some((host.clone(), port.parse()?))),
+            Some(_) => anyhow::bail!("replicaof needs a host and a port"),
+            None => Ok(None),
+        }
+    }
+
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        EncodingLimits {
+            hash_max_listpack_entries: self.hash_max_listpack_entries as usize,
+            hash_max_listpack_value: self.hash_max_listpack_value as usize,
+            set_max_intset_entries: self.set_max_intset_entries as usize,
+            set_max_listpack_entries: self.set_max_listpack_entries as usize,
+            set_max_listpack_value: self.set_max_listpack_value as usize,
+            list_max_listpack_size: self.list_max_listpack_size,
+        }
+    }
+
+    // the limits applied to what clients send
+    pub fn resp_limits(&self) -> RespLimits {
+        RespLimits::new()
+            .max_bulk_len(self.proto_max_bulk_len as usize)
+            .max_pending_buf(self.client_query_buffer_limit as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_args, quote_value, split_line};
+
+    #[test]
+    fn test_file_args() {
+        let text = "# comment\n\nport 7000\nMaxmemory 100mb\nsave 3600 1\nsave 60 1000\nrequirepass \"a b\\\"c\"\n";
+        let args = file_args(text).unwrap();
+        assert_eq!(
+            args,
+            ["--port", "7000", "--maxmemory", "100mb", "--requirepass", "a b\"c", "--save", "3600 1 60 1000"]
+        );
+        assert!(file_args("nope 1").is_err());
+        assert!(file_args("port").is_err());
+        assert!(file_args("port \"7000").is_err());
+        assert_eq!(split_line("save ''"), Some(vec!["save".to_string(), String::new()]));
+        assert_eq!(quote_value("a b\"c"), "\"a b\\\"c\"");
+        assert_eq!(quote_value(""), "\"\"");
+    }
+
+    #[test]
+    fn test_parse_file_with_overrides() {
+        let mut args = vec!["simple-redis".to_string()];
+        args.extend(file_args("port 7000\nloglevel warning\nslowlog-log-slower-than -1\nunixsocketperm 770\n").unwrap());
+        args.extend(["--port".to_string(), "7001".to_string()]);
+        let config = <super::Config as clap::Parser>::try_parse_from(args).unwrap();
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.loglevel, super::LogLevel::Warning);
+        assert_eq!(config.slowlog_log_slower_than, -1);
+        assert_eq!(config.unixsocketperm, Some(0o770));
+        assert!(super::parse_socket_perm("800").is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+// The live client connections, for CLIENT LIST / KILL / PAUSE
+#[derive(Debug)]
+pub struct Connections {
+    conns: Mutex<BTreeMap<u64, Arc<Conn>>>,
+    next_id: AtomicU64,
+    pause: Mutex<Option<Pause>>,
+    // woken by CLIENT UNPAUSE
+    unpaused: Notify,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+    // only write commands are held back with CLIENT PAUSE .. WRITE
+    writes_only: bool,
+}
+
+// where a connection comes from or arrives at, a Unix socket shows as `path:0` like in redis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAddr {
+    Tcp(SocketAddr),
+    Unix(String),
+}
+
+impl ClientAddr {
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            ClientAddr::Tcp(addr) => Some(addr.ip()),
+            ClientAddr::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for ClientAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ClientAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientAddr::Tcp(addr) => write!(f, "{}", addr),
+            ClientAddr::Unix(path) => write!(f, "{}:0", path),
+        }
+    }
+}
+
+// one connection, shared between its task and the registry
+#[derive(Debug)]
+pub struct Conn {
+    pub id: u64,
+    pub addr: ClientAddr,
+    pub laddr: Option<ClientAddr>,
+    created: Instant,
+    state: Mutex<ConnState>,
+    killed: AtomicBool,
+    kill: Notify,
+}
+
+// what the connection task reports after each command
+#[derive(Debug, Clone)]
+pub struct ConnState {
+    pub name: Option<String>,
+    pub user: String,
+    pub db: usize,
+    // the last command run, lowercase
+    pub cmd: String,
+    pub last_interaction: Instant,
+    // bytes read and not parsed yet
+    pub qbuf: usize,
+    // bytes of replies not written yet
+    pub omem: usize,
+    // commands queued in MULTI, None outside of a transaction
+    pub multi: Option<usize>,
+    pub flags: ConnFlags,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnFlags {
+    #[default]
+    Normal,
+    // the link to our master
+    Master,
+    // a replica fed by PSYNC
+    Replica,
+}
+
+// the CLIENT KILL filters, all given ones must match
+#[derive(Debug, Default)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub user: Option<String>,
+    // don't kill the connection asking
+    pub skip: Option<u64>,
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self {
+            conns: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            pause: Mutex::new(None),
+            unpaused: Notify::new(),
+        }
+    }
+}
+
+impl Connections {
+    fn conns(&self) -> MutexGuard<'_, BTreeMap<u64, Arc<Conn>>> {
+        self.conns.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn register(&self, addr: ClientAddr, laddr: Option<ClientAddr>) -> Arc<Conn> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let conn = Arc::new(Conn {
+            id,
+            addr,
+            laddr,
+            created: now,
+            state: Mutex::new(ConnState {
+                name: None,
+                user: "default".into(),
+                db: 0,
+                cmd: "NULL".into(),
+                last_interaction: now,
+                qbuf: 0,
+                omem: 0,
+                multi: None,
+                flags: ConnFlags::Normal,
+            }),
+            killed: AtomicBool::new(false),
+            kill: Notify::new(),
+        });
+        self.conns().insert(id, conn.clone());
+        conn
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.conns().remove(&id);
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Conn>> {
+        self.conns().get(&id).cloned()
+    }
+
+    // every connection, ordered by id
+    pub fn list(&self) -> Vec<Arc<Conn>> {
+        self.conns().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.conns().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conns().is_empty()
+    }
+
+    // connections accepted since the start
+    pub fn total(&self) -> u64 {
+        self.next_id.load(Ordering::Relaxed) - 1
+    }
+
+    // close the matching connections, returns how many
+    pub fn kill(&self, filter: &KillFilter) -> usize {
+        let matched: Vec<Arc<Conn>> = self
+            .list()
+            .into_iter()
+            .filter(|conn| {
+                let state = conn.state();
+                filter.id.is_none_or(|id| conn.id == id)
+                    && filter.addr.as_ref().is_none_or(|a| conn.addr.to_string() == *a)
+                    && filter.laddr.as_ref().is_none_or(|a| conn.laddr.as_ref().is_some_and(|l| l.to_string() == *a))
+                    && filter.user.as_ref().is_none_or(|u| state.user == *u)
+                    && filter.skip.is_none_or(|id| conn.id != id)
+            })
+            .collect();
+        for conn in &matched {
+            conn.kill();
+        }
+        matched.len()
+    }
+
+    pub fn pause(&self, timeout: Duration, writes_only: bool) {
+        let until = Instant::now() + timeout;
+        let mut pause = self.pause.lock().unwrap_or_else(|e| e.into_inner());
+        // a pause in progress is only extended, like redis
+        *pause = Some(match *pause {
+            Some(p) if p.until > until => Pause { until: p.until, writes_only: p.writes_only && writes_only },
+            _ => Pause { until, writes_only },
+        });
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.unpaused.notify_waiters();
+    }
+
+    // when a command (a write one or not) has to wait for a pause to end
+    fn paused_until(&self, write: bool) -> Option<Instant> {
+        let mut pause = self.pause.lock().unwrap_or_else(|e| e.into_inner());
+        match *pause {
+            Some(p) if p.until <= Instant::now() => {
+                *pause = None;
+                None
+            }
+            Some(p) if write || !p.writes_only => Some(p.until),
+            _ => None,
+        }
+    }
+
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            let unpaused = self.unpaused.notified();
+            let Some(until) = self.paused_until(write) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(until.into()) => {}
+                _ = unpaused => {}
+            }
+        }
+    }
+}
+
+impl Conn {
+    pub fn state(&self) -> MutexGuard<'_, ConnState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Release);
+        // the permit is kept when the task isn't waiting right now
+        self.kill.notify_one();
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    pub async fn killed(&self) {
+        self.kill.notified().await
+    }
+
+    // one line of CLIENT LIST
+    pub fn describe(&self) -> String {
+        let state = self.state();
+        let now = Instant::now();
+        let flags = match (state.flags, state.multi.is_some()) {
+            (ConnFlags::Master, _) => "M",
+            (ConnFlags::Replica, _) => "S",
+            (ConnFlags::Normal, true) => "x",
+            (ConnFlags::Normal, false) => "N",
+        };
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} multi={} qbuf={} omem={} cmd={} user={}",
+            self.id,
+            self.addr,
+            self.laddr.as_ref().map(|a| a.to_string()).unwrap_or_default(),
+            state.name.as_deref().unwrap_or(""),
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(state.last_interaction).as_secs(),
+            flags,
+            state.db,
+            state.multi.map(|n| n as i64).unwrap_or(-1),
+            state.qbuf,
+            state.omem,
+            state.cmd,
+            state.user,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{ClientAddr, Connections, KillFilter};
+
+    #[test]
+    fn test_register_and_kill() {
+        let conns = Connections::default();
+        let a = conns.register(ClientAddr::Tcp("127.0.0.1:5001".parse().unwrap()), None);
+        let b = conns.register(ClientAddr::Tcp("127.0.0.1:5002".parse().unwrap()), None);
+        assert_eq!(b.id, a.id + 1);
+        assert!(a.describe().starts_with(&format!("id={} addr=127.0.0.1:5001 laddr= name= age=0", a.id)));
+
+        let filter = KillFilter { addr: Some("127.0.0.1:5002".into()), ..KillFilter::default() };
+        assert_eq!(conns.kill(&filter), 1);
+        assert!(b.is_killed() && !a.is_killed());
+        let filter = KillFilter { user: Some("default".into()), skip: Some(a.id), ..KillFilter::default() };
+        assert_eq!(conns.kill(&filter), 1);
+
+        conns.unregister(b.id);
+        assert_eq!(conns.len(), 1);
+
+        let path = ClientAddr::Unix("/tmp/redis.sock".into());
+        let c = conns.register(path.clone(), Some(path));
+        assert!(c.describe().contains(" addr=/tmp/redis.sock:0 laddr=/tmp/redis.sock:0 "));
+    }
+
+    #[tokio::test]
+    async fn test_pause() {
+        let conns = Connections::default();
+        conns.pause(Duration::from_millis(50), true);
+        // reads go on during a write pause
+        tokio::time::timeout(Duration::from_millis(10), conns.wait_unpaused(false)).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(10), conns.wait_unpaused(true)).await.is_err());
+        tokio::time::timeout(Duration::from_millis(100), conns.wait_unpaused(true)).await.unwrap();
+
+        let conns = Arc::new(conns);
+        conns.pause(Duration::from_secs(60), false);
+        let waiting = tokio::spawn({
+            let conns = conns.clone();
+            async move { conns.wait_unpaused(false).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        conns.unpause();
+        tokio::time::timeout(Duration::from_millis(100), waiting).await.unwrap().unwrap();
+    }
+}
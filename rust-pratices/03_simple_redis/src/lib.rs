@@ -0,0 +1,22 @@
+// The simple-redis server as a library: the network server of the binary, and
+// `Store` to run the same command engine in process.
+pub mod acl;
+pub mod backend;
+pub mod client;
+pub mod cluster;
+pub mod cmd;
+pub mod config;
+pub mod connections;
+pub mod logging;
+pub mod network;
+pub mod pubsub;
+pub mod replication;
+pub mod resp;
+pub mod scripting;
+pub mod shutdown;
+pub mod store;
+pub mod tls;
+pub mod tracking;
+pub mod utils;
+
+pub use store::{Store, StoreError};
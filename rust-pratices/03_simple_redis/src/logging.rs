@@ -0,0 +1,34 @@
+use std::sync::OnceLock;
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::config::LogLevel;
+
+// lets CONFIG SET loglevel change the filter of the running subscriber
+static RELOAD: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+fn filter(level: LogLevel) -> EnvFilter {
+    let level = match level {
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Verbose | LogLevel::Notice => LevelFilter::INFO,
+        LogLevel::Warning => LevelFilter::WARN,
+    };
+    EnvFilter::builder().with_default_directive(level.into()).parse_lossy("")
+}
+
+// RUST_LOG still wins at startup when it's set
+pub fn init(level: LogLevel) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| filter(level));
+    let (layer, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry().with(layer).with(tracing_subscriber::fmt::layer()).init();
+    let _ = RELOAD.set(handle);
+}
+
+pub fn set_level(level: LogLevel) -> Result<(), String> {
+    match RELOAD.get() {
+        Some(handle) => handle.reload(filter(level)).map_err(|e| e.to_string()),
+        // without a subscriber, in tests
+        None => Ok(()),
+    }
+}
@@ -1,8 +1,90 @@
-use std::{fs, os, path};
+use std::time::Duration;
 
-mod resp;
+use anyhow::Result;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
 
-fn main() {
-    let e = invalid_frame_length!(10);
-    println!("{e}");
+use simple_redis::{
+    backend::{snapshot, Backend},
+    cluster::Cluster,
+    config::Config,
+    logging, network, replication, shutdown, tls,
+};
+
+// how often expired keys are sampled, and how many per db each time
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_SAMPLES: usize = 20;
+// how often the save points are checked
+const SAVE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::load()?;
+    logging::init(config.loglevel);
+
+    let backend = match config.cluster_config_path() {
+        Some(path) => {
+            // the address other nodes and clients are redirected to
+            let host = if config.bind == "0.0.0.0" { "127.0.0.1" } else { config.bind.as_str() };
+            let cluster = Cluster::load(&path, host, config.port)?;
+            cluster.save(&path)?;
+            Backend::with_cluster(config, cluster)
+        }
+        None => Backend::new(config),
+    };
+    snapshot::load(&backend)?;
+    let master_addr = backend.config().master_addr()?;
+    if let Some((host, port)) = master_addr {
+        replication::replica::start(&backend, host, port)?;
+    }
+
+    let expire_backend = backend.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            expire_backend.active_expire(ACTIVE_EXPIRE_SAMPLES);
+        }
+    });
+
+    let save_backend = backend.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAVE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if snapshot::save_due(&save_backend) {
+                if let Err(e) = snapshot::bgsave(&save_backend) {
+                    warn!("Background save failed to start: {}", e);
+                }
+            }
+        }
+    });
+
+    // the listeners are set up once, from the startup settings
+    let config = backend.config().clone();
+    if let (Some(acceptor), Some(addr)) = (tls::acceptor(&config)?, config.tls_addr()) {
+        let listener = TcpListener::bind(&addr).await?;
+        info!("Simple-Redis: listening for TLS on: {}", addr);
+        tokio::spawn(network::serve_tls(listener, acceptor, backend.clone()));
+    }
+
+    if let Some(path) = &config.unixsocket {
+        let listener = network::bind_unix(path, config.unixsocketperm)?;
+        info!("Simple-Redis: listening on unix socket: {}", path.display());
+        tokio::spawn(network::serve_unix(listener, path.display().to_string(), backend.clone()));
+    }
+
+    let addr = config.addr();
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Simple-Redis: listening on: {}", addr);
+    let signal_backend = backend.clone();
+    tokio::spawn(async move {
+        if let Err(e) = shutdown::on_signals(signal_backend).await {
+            warn!("Can't watch for signals: {}", e);
+        }
+    });
+    // returns once SHUTDOWN or a signal stopped the listeners
+    network::serve(listener, backend.clone()).await?;
+    let mode = backend.shutdown.requested().await;
+    shutdown::finish(&backend, mode).await
 }
@@ -0,0 +1,303 @@
+use std::{
+    fs::{self, Permissions},
+    future::Future,
+    io::ErrorKind,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, UnixListener},
+};
+use tokio_rustls::{TlsAcceptor, TlsStream};
+use tracing::{info, warn};
+
+use crate::{
+    acl::DEFAULT_USER,
+    backend::{blocking::Blocked, Backend},
+    cmd::{self, CommandError, Session, WRITE},
+    connections::{ClientAddr, Conn, ConnFlags},
+    replication,
+    resp::{RespDecoder, RespEncode, RespError, RespFrame},
+    tls::Stream,
+    tracking::Invalidations,
+};
+
+const BUF_SIZE: usize = 4096;
+
+// accept connections until shutdown, each one served by its own task
+pub async fn serve(listener: TcpListener, backend: Backend) -> Result<()> {
+    loop {
+        let (stream, raddr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = backend.shutdown.requested() => return Ok(()),
+        };
+        info!("Accepted connection from: {}", raddr);
+
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stream_handler(stream.into(), raddr.into(), backend).await {
+                warn!("Error process connection with: addr={}, e={}", raddr, e);
+            }
+        });
+    }
+}
+
+// the same for the TLS port, the handshake happens in the connection's task
+pub async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, backend: Backend) -> Result<()> {
+    loop {
+        let (stream, raddr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = backend.shutdown.requested() => return Ok(()),
+        };
+        info!("Accepted TLS connection from: {}", raddr);
+
+        let backend = backend.clone();
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => Stream::Tls(Box::new(TlsStream::Server(stream))),
+                Err(e) => {
+                    warn!("TLS handshake with {} failed: {}", raddr, e);
+                    return;
+                }
+            };
+            if let Err(e) = stream_handler(stream, raddr.into(), backend).await {
+                warn!("Error process connection with: addr={}, e={}", raddr, e);
+            }
+        });
+    }
+}
+
+// A stale socket file from an earlier run is replaced, like redis does
+pub fn bind_unix(path: &Path, perm: Option<u32>) -> Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("can't bind unix socket {}", path.display()))?;
+    if let Some(perm) = perm {
+        fs::set_permissions(path, Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+// the same for the unix socket, its clients show the socket path as their address
+pub async fn serve_unix(listener: UnixListener, path: String, backend: Backend) -> Result<()> {
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = backend.shutdown.requested() => return Ok(()),
+        };
+        let raddr = ClientAddr::Unix(path.clone());
+        info!("Accepted connection from: {}", raddr);
+
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stream_handler(Stream::Unix(stream), raddr.clone(), backend).await {
+                warn!("Error process connection with: addr={}, e={}", raddr, e);
+            }
+        });
+    }
+}
+
+// Serve one client: decode every complete frame in the buffer (pipelining),
+// execute it and write the replies back in one go. On shutdown the commands
+// already read still run before the connection is closed.
+pub async fn stream_handler(mut stream: Stream, raddr: ClientAddr, backend: Backend) -> Result<()> {
+    let mut buf = BytesMut::with_capacity(BUF_SIZE);
+    let mut decoder = RespDecoder::new();
+    let conn = backend.connections.register(raddr.clone(), stream.local_addr());
+    let _registered = Registered(backend.clone(), conn.id);
+    let mut session = Session {
+        addr: Some(raddr.clone()),
+        conn: Some(conn.clone()),
+        user: Some(DEFAULT_USER.into()),
+        authenticated: !backend.acl.default_needs_auth(),
+        invalidations: Some(Invalidations::new(&backend, conn.id)),
+        ..Session::default()
+    };
+
+    loop {
+        let n = tokio::select! {
+            n = stream.read_buf(&mut buf) => n?,
+            _ = conn.killed() => 0,
+            _ = backend.shutdown.requested() => {
+                stream.shutdown().await?;
+                return Ok(());
+            }
+            Some(message) = next_message(&mut session) => {
+                let out = message.encode();
+                stream.write_all(&out).await?;
+                backend.stats.add_net_output(out.len());
+                continue;
+            }
+        };
+        if n == 0 {
+            info!("Connection closed: {}", raddr);
+            return Ok(());
+        }
+        backend.stats.add_net_input(n);
+        // read on every round so CONFIG SET applies to the connections already open
+        let limits = backend.config().resp_limits();
+        if buf.len() > limits.max_pending_buf {
+            warn!("Closing client {} that reached max query buffer length: {}", raddr, buf.len());
+            let reply = cmd::error_frame(CommandError::Other("Protocol error: query buffer limit reached".into()));
+            stream.write_all(&reply.encode()).await?;
+            return Err(RespError::BufferOverflow(buf.len()).into());
+        }
+
+        let mut out = Vec::with_capacity(BUF_SIZE);
+        loop {
+            match decoder.decode(&mut buf, &limits) {
+                Ok(frame) => {
+                    let name = command_name(&frame);
+                    if let Some(spec) = name.as_deref().and_then(cmd::lookup) {
+                        // CLIENT itself isn't paused, it's how a pause is lifted
+                        if spec.name != "client" {
+                            backend.connections.wait_unpaused(spec.has_flag(WRITE)).await;
+                        }
+                    }
+                    let mut reply = cmd::execute(&backend, &mut session, frame);
+                    if let Some(blocked) = session.blocked.take() {
+                        // the replies so far go out now, this one waits for its keys
+                        stream.write_all(&out).await?;
+                        backend.stats.add_net_output(out.len());
+                        out.clear();
+                        reply = block(&backend, &mut session, &conn, blocked).await;
+                    }
+                    out.extend_from_slice(&reply.encode());
+                    // what was queued meanwhile goes right after the reply
+                    if let Some(subscriber) = session.subscriber.as_mut() {
+                        while let Some(message) = subscriber.try_recv() {
+                            out.extend_from_slice(&message.encode());
+                        }
+                    }
+                    while let Some(invalidation) = session.invalidations.as_mut().and_then(|i| i.try_recv()) {
+                        if let Some(message) = invalidation.frame(&session) {
+                            out.extend_from_slice(&message.encode());
+                        }
+                    }
+                    report(&conn, &session, name, buf.len(), out.len());
+                    if session.closing || session.replica_feed.is_some() || conn.is_killed() {
+                        break;
+                    }
+                }
+                Err(RespError::NotComplete) => break,
+                Err(e) => {
+                    // the stream can't be resynchronized after a bad frame, reply and close
+                    warn!("Protocol error from {}: {}", raddr, e);
+                    let reply = cmd::error_frame(CommandError::Other(format!("Protocol error: {}", e)));
+                    out.extend_from_slice(&reply.encode());
+                    stream.write_all(&out).await?;
+                    return Err(e.into());
+                }
+            }
+        }
+
+        stream.write_all(&out).await?;
+        backend.stats.add_net_output(out.len());
+        conn.state().omem = 0;
+        if session.closing || conn.is_killed() {
+            return Ok(());
+        }
+        if backend.shutdown.is_requested() {
+            stream.shutdown().await?;
+            return Ok(());
+        }
+        if let Some(feed) = session.replica_feed.take() {
+            info!("Replica {} attached: {}", feed.id, raddr);
+            return replication::master::feed_replica(stream, buf, feed, backend).await;
+        }
+    }
+}
+
+// drops the connection from the registry however the handler returns
+struct Registered(Backend, u64);
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.0.connections.unregister(self.1);
+    }
+}
+
+// Wait for writes to the keys of a blocked command and run it again, until it
+// has something to reply or times out
+async fn block(backend: &Backend, session: &mut Session, conn: &Conn, blocked: Blocked) -> RespFrame {
+    let waiter = backend.blocking.watch(session.db, &blocked.keys);
+    let timeout = async {
+        match blocked.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timeout);
+    loop {
+        // once more right away, a write may have come before watching
+        let reply = cmd::execute_argv(backend, session, blocked.argv.clone());
+        if session.blocked.take().is_none() || conn.is_killed() {
+            return reply;
+        }
+        tokio::select! {
+            _ = waiter.woken() => {}
+            _ = &mut timeout => return cmd::nil_array(),
+            _ = conn.killed() => return cmd::nil_array(),
+            _ = backend.shutdown.requested() => return cmd::nil_array(),
+        }
+    }
+}
+
+// the next pub/sub message, MONITOR line or invalidation, never ready without any
+async fn next_message(session: &mut Session) -> Option<RespFrame> {
+    loop {
+        let invalidation = tokio::select! {
+            message = or_pending(session.subscriber.as_mut().map(|s| s.recv())) => return message,
+            line = or_pending(session.monitor.as_mut().map(|m| m.recv())) => return line,
+            invalidation = or_pending(session.invalidations.as_mut().map(|i| i.recv())) => invalidation?,
+        };
+        // the ones the connection can't take are dropped
+        if let Some(message) = invalidation.frame(session) {
+            return Some(message);
+        }
+    }
+}
+
+async fn or_pending<T>(recv: Option<impl Future<Output = Option<T>>>) -> Option<T> {
+    match recv {
+        Some(recv) => recv.await,
+        None => std::future::pending().await,
+    }
+}
+
+fn command_name(frame: &RespFrame) -> Option<Vec<u8>> {
+    match frame {
+        RespFrame::Array(items) => match items.first()? {
+            RespFrame::BulkString(name) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// what CLIENT LIST shows about the connection after a command
+fn report(conn: &Conn, session: &Session, name: Option<Vec<u8>>, qbuf: usize, omem: usize) {
+    let mut state = conn.state();
+    if let Some(name) = name {
+        state.cmd = String::from_utf8_lossy(&name).into_owned();
+    }
+    state.db = session.db;
+    state.last_interaction = std::time::Instant::now();
+    state.qbuf = qbuf;
+    state.omem = omem;
+    state.multi = session.multi.as_ref().map(|q| q.len());
+    state.flags = if session.replica_feed.is_some() {
+        ConnFlags::Replica
+    } else if session.is_master {
+        ConnFlags::Master
+    } else {
+        ConnFlags::Normal
+    };
+}
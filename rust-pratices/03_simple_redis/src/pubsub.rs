@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    backend::Backend,
+    cmd::{array, bulk, int},
+    resp::RespFrame,
+    utils::glob_match,
+};
+
+// keyspace event classes, the letters of notify-keyspace-events
+pub const KEYSPACE: u32 = 1 << 0; // K
+pub const KEYEVENT: u32 = 1 << 1; // E
+pub const GENERIC: u32 = 1 << 2; // g
+pub const STRING: u32 = 1 << 3; // $
+pub const LIST: u32 = 1 << 4; // l
+pub const SET: u32 = 1 << 5; // s
+pub const HASH: u32 = 1 << 6; // h
+pub const ZSET: u32 = 1 << 7; // z
+pub const EXPIRED: u32 = 1 << 8; // x
+pub const EVICTED: u32 = 1 << 9; // e
+pub const STREAM: u32 = 1 << 10; // t
+pub const KEY_MISS: u32 = 1 << 11; // m
+pub const NEW: u32 = 1 << 12; // n
+
+const CLASSES: &[(char, u32)] = &[
+    ('g', GENERIC),
+    ('$', STRING),
+    ('l', LIST),
+    ('s', SET),
+    ('h', HASH),
+    ('z', ZSET),
+    ('x', EXPIRED),
+    ('e', EVICTED),
+    ('t', STREAM),
+    ('m', KEY_MISS),
+    ('n', NEW),
+];
+
+// `A` is every class but m and n, like redis
+const ALL: u32 = GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM;
+
+// parse a notify-keyspace-events value like `Ex` or `KA`
+pub fn parse_events(s: &str) -> Option<u32> {
+    let mut flags = 0;
+    for c in s.chars() {
+        flags |= match c {
+            'K' => KEYSPACE,
+            'E' => KEYEVENT,
+            'A' => ALL,
+            c => CLASSES.iter().find(|(l, _)| *l == c)?.1,
+        };
+    }
+    Some(flags)
+}
+
+pub fn format_events(flags: u32) -> String {
+    let mut out = String::new();
+    if flags & ALL == ALL {
+        out.push('A');
+    }
+    for (letter, class) in CLASSES {
+        if flags & class != 0 && (flags & ALL != ALL || ALL & class == 0) {
+            out.push(*letter);
+        }
+    }
+    if flags & KEYSPACE != 0 {
+        out.push('K');
+    }
+    if flags & KEYEVENT != 0 {
+        out.push('E');
+    }
+    out
+}
+
+type Subscribers = HashMap<Vec<u8>, HashMap<u64, UnboundedSender<RespFrame>>>;
+
+// Channel and pattern subscriptions of every connection. Messages go to each
+// subscriber's queue, its connection task writes them out.
+#[derive(Debug)]
+pub struct PubSub {
+    channels: Mutex<Subscribers>,
+    patterns: Mutex<Subscribers>,
+    next_id: AtomicU64,
+    // the notify-keyspace-events classes
+    notify: AtomicU32,
+}
+
+impl PubSub {
+    pub fn new(notify: u32) -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+            patterns: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            notify: AtomicU32::new(notify),
+        }
+    }
+
+    fn channels(&self) -> MutexGuard<'_, Subscribers> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn patterns(&self) -> MutexGuard<'_, Subscribers> {
+        self.patterns.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // send a message to the channel's subscribers, returns how many got it
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> usize {
+        let mut receivers = 0;
+        if let Some(subscribers) = self.channels().get(channel) {
+            let frame = array([bulk("message"), bulk(channel), bulk(message)]);
+            receivers += subscribers.values().filter(|tx| tx.send(frame.clone()).is_ok()).count();
+        }
+        for (pattern, subscribers) in self.patterns().iter() {
+            if glob_match(pattern, channel) {
+                let frame = array([bulk("pmessage"), bulk(pattern.clone()), bulk(channel), bulk(message)]);
+                receivers += subscribers.values().filter(|tx| tx.send(frame.clone()).is_ok()).count();
+            }
+        }
+        receivers
+    }
+
+    // channels with at least one subscriber, matching the pattern when given
+    pub fn active_channels(&self, pattern: Option<&[u8]>) -> Vec<Vec<u8>> {
+        let channels = self.channels();
+        let mut names: Vec<Vec<u8>> = channels.keys().filter(|c| pattern.is_none_or(|p| glob_match(p, c))).cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn num_subscribers(&self, channel: &[u8]) -> usize {
+        self.channels().get(channel).map_or(0, |s| s.len())
+    }
+
+    // number of distinct patterns subscribed to
+    pub fn num_patterns(&self) -> usize {
+        self.patterns().len()
+    }
+
+    pub fn notify_flags(&self) -> u32 {
+        self.notify.load(Ordering::Relaxed)
+    }
+
+    pub fn set_notify_flags(&self, flags: u32) {
+        self.notify.store(flags, Ordering::Relaxed);
+    }
+
+    // publish a keyspace event when its class is enabled: the event name on
+    // __keyspace@<db>__:<key> and the key on __keyevent@<db>__:<event>
+    pub fn notify_keyspace_event(&self, class: u32, event: &str, key: &[u8], db: usize) {
+        let flags = self.notify_flags();
+        if flags & class == 0 {
+            return;
+        }
+        if flags & KEYSPACE != 0 {
+            let mut channel = format!("__keyspace@{}__:", db).into_bytes();
+            channel.extend_from_slice(key);
+            self.publish(&channel, event.as_bytes());
+        }
+        if flags & KEYEVENT != 0 {
+            self.publish(format!("__keyevent@{}__:{}", db, event).as_bytes(), key);
+        }
+    }
+}
+
+// The subscriptions of one connection with its message queue. Dropping it
+// unsubscribes from everything.
+#[derive(Debug)]
+pub struct Subscriber {
+    backend: Backend,
+    id: u64,
+    tx: UnboundedSender<RespFrame>,
+    rx: UnboundedReceiver<RespFrame>,
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+}
+
+impl Subscriber {
+    pub fn new(backend: &Backend) -> Self {
+        let (tx, rx) = unbounded_channel();
+        let id = backend.pubsub.next_id.fetch_add(1, Ordering::Relaxed);
+        Self { backend: backend.clone(), id, tx, rx, channels: BTreeSet::new(), patterns: BTreeSet::new() }
+    }
+
+    // subscriptions of both kinds, the count in (un)subscribe replies
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.channels.iter()
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.patterns.iter()
+    }
+
+    // The confirmations are queued like messages: a subscription's confirmation is
+    // queued before it can receive anything, an unsubscription's after it can't
+    // anymore. The command replies with the head of the queue.
+    fn confirm(&self, kind: &str, name: &[u8]) {
+        let _ = self.tx.send(array([bulk(kind), bulk(name), int(self.count())]));
+    }
+
+    pub fn subscribe(&mut self, channel: &[u8]) {
+        let added = self.channels.insert(channel.to_vec());
+        self.confirm("subscribe", channel);
+        if added {
+            self.backend.pubsub.channels().entry(channel.to_vec()).or_default().insert(self.id, self.tx.clone());
+        }
+    }
+
+    pub fn unsubscribe(&mut self, channel: &[u8]) {
+        if self.channels.remove(channel) {
+            remove(&mut self.backend.pubsub.channels(), channel, self.id);
+        }
+        self.confirm("unsubscribe", channel);
+    }
+
+    pub fn psubscribe(&mut self, pattern: &[u8]) {
+        let added = self.patterns.insert(pattern.to_vec());
+        self.confirm("psubscribe", pattern);
+        if added {
+            self.backend.pubsub.patterns().entry(pattern.to_vec()).or_default().insert(self.id, self.tx.clone());
+        }
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &[u8]) {
+        if self.patterns.remove(pattern) {
+            remove(&mut self.backend.pubsub.patterns(), pattern, self.id);
+        }
+        self.confirm("punsubscribe", pattern);
+    }
+
+    // the (p)unsubscribe confirmation when there was nothing to unsubscribe from
+    pub fn confirm_none(&self, kind: &str) {
+        let _ = self.tx.send(array([bulk(kind), crate::cmd::nil(), int(self.count())]));
+    }
+
+    pub fn try_recv(&mut self) -> Option<RespFrame> {
+        self.rx.try_recv().ok()
+    }
+
+    pub async fn recv(&mut self) -> Option<RespFrame> {
+        self.rx.recv().await
+    }
+}
+
+fn remove(subscribers: &mut Subscribers, name: &[u8], id: u64) {
+    if let Some(ids) = subscribers.get_mut(name) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            subscribers.remove(name);
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let pubsub = &self.backend.pubsub;
+        for channel in &self.channels {
+            remove(&mut pubsub.channels(), channel, self.id);
+        }
+        for pattern in &self.patterns {
+            remove(&mut pubsub.patterns(), pattern, self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend,
+        cmd::{array, bulk, int},
+    };
+
+    use super::{format_events, parse_events, Subscriber, EXPIRED, GENERIC, KEYEVENT, KEYSPACE};
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(parse_events("Ex"), Some(KEYEVENT | EXPIRED));
+        assert_eq!(parse_events(""), Some(0));
+        assert_eq!(parse_events("Kq"), None);
+        assert_eq!(format_events(parse_events("KEA").unwrap()), "AKE");
+        assert_eq!(format_events(parse_events("gxK").unwrap()), "gxK");
+        assert_eq!(format_events(parse_events("AKEnm").unwrap()), "AmnKE");
+    }
+
+    #[test]
+    fn test_publish() {
+        let backend = Backend::default();
+        let mut a = Subscriber::new(&backend);
+        let mut b = Subscriber::new(&backend);
+        a.subscribe(b"news");
+        b.psubscribe(b"n*");
+        assert_eq!(a.try_recv(), Some(array([bulk("subscribe"), bulk("news"), int(1)])));
+        assert_eq!(b.try_recv(), Some(array([bulk("psubscribe"), bulk("n*"), int(1)])));
+        assert_eq!(backend.pubsub.publish(b"news", b"hi"), 2);
+        assert_eq!(a.try_recv(), Some(array([bulk("message"), bulk("news"), bulk("hi")])));
+        assert_eq!(b.try_recv(), Some(array([bulk("pmessage"), bulk("n*"), bulk("news"), bulk("hi")])));
+        assert_eq!(backend.pubsub.active_channels(None), vec![b"news".to_vec()]);
+
+        drop(a);
+        assert_eq!(backend.pubsub.num_subscribers(b"news"), 0);
+        assert_eq!(backend.pubsub.publish(b"news", b"again"), 1);
+
+        backend.pubsub.set_notify_flags(KEYSPACE | KEYEVENT | GENERIC);
+        let mut c = Subscriber::new(&backend);
+        c.subscribe(b"__keyevent@0__:del");
+        c.subscribe(b"__keyspace@0__:k");
+        c.try_recv();
+        c.try_recv();
+        backend.pubsub.notify_keyspace_event(GENERIC, "del", b"k", 0);
+        assert_eq!(c.try_recv(), Some(array([bulk("message"), bulk("__keyspace@0__:k"), bulk("del")])));
+        assert_eq!(c.try_recv(), Some(array([bulk("message"), bulk("__keyevent@0__:del"), bulk("k")])));
+        backend.pubsub.notify_keyspace_event(EXPIRED, "expired", b"k", 0);
+        assert_eq!(c.try_recv(), None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+// The tail of the replication stream, kept so that a replica reconnecting after a
+// short break can continue from its offset instead of doing a full sync.
+// Offsets count bytes of the stream since the replication id was created,
+// `offset` is the offset of the last byte written.
+#[derive(Debug)]
+pub struct Backlog {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    offset: u64,
+}
+
+impl Backlog {
+    pub fn new(capacity: usize, offset: u64) -> Self {
+        Self { buf: VecDeque::with_capacity(capacity.min(1 << 20)), capacity: capacity.max(1), offset }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // offset of the first byte still held
+    pub fn first_offset(&self) -> u64 {
+        self.offset + 1 - self.buf.len() as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.offset += data.len() as u64;
+        let data = if data.len() > self.capacity { &data[data.len() - self.capacity..] } else { data };
+        let overflow = (self.buf.len() + data.len()).saturating_sub(self.capacity);
+        self.buf.drain(..overflow);
+        self.buf.extend(data);
+    }
+
+    // the stream starting at byte `from`, None when it's no longer (or not yet) held
+    pub fn range_from(&self, from: u64) -> Option<Vec<u8>> {
+        if from < self.first_offset() || from > self.offset + 1 {
+            return None;
+        }
+        let skip = (from - self.first_offset()) as usize;
+        Some(self.buf.iter().skip(skip).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backlog;
+
+    #[test]
+    fn test_backlog_range() {
+        let mut backlog = Backlog::new(8, 0);
+        assert_eq!(backlog.range_from(1), Some(vec![]));
+
+        backlog.push(b"hello");
+        assert_eq!(backlog.offset(), 5);
+        assert_eq!(backlog.range_from(1), Some(b"hello".to_vec()));
+        assert_eq!(backlog.range_from(4), Some(b"lo".to_vec()));
+        assert_eq!(backlog.range_from(6), Some(vec![]));
+        assert_eq!(backlog.range_from(7), None);
+
+        backlog.push(b"world");
+        assert_eq!(backlog.len(), 8);
+        assert_eq!(backlog.first_offset(), 3);
+        assert_eq!(backlog.range_from(2), None);
+        assert_eq!(backlog.range_from(3), Some(b"lloworld".to_vec()));
+
+        backlog.push(b"0123456789");
+        assert_eq!(backlog.range_from(13), Some(b"23456789".to_vec()));
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::Ordering;
+
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::{
+    backend::Backend,
+    cmd::{eq_ignore_case, frame_to_argv},
+    resp::{RespDecoder, RespError, RespLimits},
+    tls::Stream,
+    utils::parse_i64,
+};
+
+use super::ReplicaFeed;
+
+// Serve a connection that turned into a replica link with PSYNC: send the sync data,
+// then forward the replication stream. The replica only sends REPLCONF ACK back.
+pub async fn feed_replica(mut stream: Stream, mut buf: BytesMut, feed: ReplicaFeed, backend: Backend) -> Result<()> {
+    let ReplicaFeed { id, initial, mut rx, pending, dropped } = feed;
+    let limits = RespLimits::default();
+    let mut decoder = RespDecoder::new();
+    let link = async {
+        stream.write_all(&initial).await?;
+        loop {
+            loop {
+                let frame = match decoder.decode(&mut buf, &limits) {
+                    Ok(frame) => frame,
+                    Err(RespError::NotComplete) => break,
+                    Err(e) => return Err(e.into()),
+                };
+                if let Ok(argv) = frame_to_argv(frame) {
+                    if argv.len() == 3 && eq_ignore_case(&argv[0], "replconf") && eq_ignore_case(&argv[1], "ack") {
+                        if let Some(offset) = parse_i64(&argv[2]) {
+                            backend.repl.ack_replica(id, offset.max(0) as u64);
+                        }
+                    }
+                }
+            }
+            tokio::select! {
+                data = rx.recv() => match data {
+                    Some(data) => {
+                        stream.write_all(&data).await?;
+                        pending.fetch_sub(data.len(), Ordering::Relaxed);
+                    }
+                    // dropped by the master, the replica has to sync again
+                    None => return Ok(()),
+                },
+                n = stream.read_buf(&mut buf) => {
+                    if n? == 0 {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+    // a replica that stopped reading is left at once, not after what is queued for it
+    let result: Result<()> = tokio::select! {
+        result = link => result,
+        _ = dropped => {
+            warn!("Replica {} dropped by the master", id);
+            Ok(())
+        }
+    };
+    backend.repl.detach_replica(id);
+    info!("Replica {} disconnected", id);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::cmd::{
+        tests::{run, test_backend},
+        Session,
+    };
+
+    use super::feed_replica;
+
+    #[tokio::test]
+    async fn test_feed_replica() {
+        let backend = test_backend();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut replica = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (_, feed) = backend.repl.attach_replica("127.0.0.1".into(), 6380, None, || b"snapshot".to_vec());
+        let link = tokio::spawn(feed_replica(stream.into(), BytesMut::new(), feed, backend.clone()));
+
+        // the sync data first, then the writes
+        let mut received = vec![0; 8];
+        replica.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, b"snapshot");
+        run(&backend, &mut Session::new(), "set a 1");
+        let expected = b"*2\r\n$6\r\nselect\r\n$1\r\n0\r\n*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let mut received = vec![0; expected.len()];
+        replica.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        replica.write_all(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n$2\r\n41\r\n").await.unwrap();
+        for _ in 0..100 {
+            if backend.repl.replicas()[0].3 == 41 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(backend.repl.replicas()[0].3, 41);
+
+        // the replica going away ends the link
+        drop(replica);
+        link.await.unwrap().unwrap();
+        assert!(backend.repl.replicas().is_empty());
+    }
+
+    #[test]
+    fn test_expired_keys_propagated_as_del() {
+        let backend = test_backend();
+        let (_, mut feed) = backend.repl.attach_replica("127.0.0.1".into(), 6380, None, Vec::new);
+        let mut session = Session::new();
+        run(&backend, &mut session, "set a 1 px 1");
+        run(&backend, &mut session, "set b 1 px 1");
+        std::thread::sleep(Duration::from_millis(5));
+        while feed.rx.try_recv().is_ok() {}
+
+        // the lazy path, before the write that found the key expired
+        run(&backend, &mut session, "append a 2");
+        let mut stream = Vec::new();
+        while let Ok(data) = feed.rx.try_recv() {
+            stream.extend_from_slice(&data);
+        }
+        assert_eq!(stream, b"*2\r\n$3\r\ndel\r\n$1\r\na\r\n*3\r\n$6\r\nappend\r\n$1\r\na\r\n$1\r\n2\r\n");
+
+        // the active expire cycle
+        assert_eq!(backend.active_expire(20), 1);
+        assert_eq!(&feed.rx.try_recv().unwrap()[..], b"*2\r\n$3\r\ndel\r\n$1\r\nb\r\n");
+    }
+}
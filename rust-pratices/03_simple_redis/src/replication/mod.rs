@@ -0,0 +1,420 @@
+pub mod backlog;
+pub mod master;
+pub mod replica;
+
+pub use backlog::Backlog;
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+use bytes::Bytes;
+use rand::Rng;
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::AbortHandle,
+};
+use tracing::warn;
+
+use crate::{client::command_frame, cmd::Argv, resp::RespEncode};
+
+// Replication state of the server. As a master every write command is appended to
+// the replication stream: the backlog and every attached replica get the same bytes.
+// As a replica `master` describes the link to the server we copy from.
+#[derive(Debug)]
+pub struct Replication {
+    state: Mutex<ReplState>,
+    // turned on when the first replica attaches, from then on writes are propagated
+    enabled: AtomicBool,
+    // held by a write command while it runs and propagates, so the stream has the
+    // writes in the order they were applied
+    order: Mutex<()>,
+    // mirrors `master.is_some()`, checked by every write
+    replica: AtomicBool,
+    next_replica_id: AtomicU64,
+    // bytes of the stream a replica may have unsent before it is disconnected, 0 for no limit
+    output_limit: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct ReplState {
+    replid: String,
+    // offset of the last byte of the stream
+    offset: u64,
+    backlog: Option<Backlog>,
+    backlog_size: usize,
+    // the db the stream currently writes to, a SELECT is emitted when it changes
+    stream_db: Option<usize>,
+    replicas: Vec<ReplicaLink>,
+    master: Option<MasterLink>,
+}
+
+// a replica attached to this server
+#[derive(Debug)]
+pub struct ReplicaLink {
+    pub id: u64,
+    pub ip: String,
+    pub listening_port: u16,
+    // the offset the replica acknowledged with REPLCONF ACK
+    pub ack_offset: u64,
+    tx: UnboundedSender<Bytes>,
+    // bytes sent to the link task and not written to the replica yet
+    pending: Arc<AtomicUsize>,
+    // never sent, dropping the link tells the link task to stop at once
+    _dropped: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Connect,
+    Connecting,
+    Sync,
+    Connected,
+}
+
+impl LinkState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkState::Connect => "connect",
+            LinkState::Connecting => "connecting",
+            LinkState::Sync => "sync",
+            LinkState::Connected => "connected",
+        }
+    }
+}
+
+// the master this server replicates from
+#[derive(Debug, Clone)]
+pub struct MasterLink {
+    pub host: String,
+    pub port: u16,
+    pub state: LinkState,
+    // id and offset of the master stream applied so far, used to ask for a partial resync
+    pub master_replid: Option<String>,
+    pub master_offset: u64,
+    task: Option<AbortHandle>,
+}
+
+// What PSYNC hands to the connection that turns into a replica link: the bytes to
+// send first (a snapshot or the backlog tail) and then everything propagated.
+#[derive(Debug)]
+pub struct ReplicaFeed {
+    pub id: u64,
+    pub initial: Vec<u8>,
+    pub rx: UnboundedReceiver<Bytes>,
+    // to subtract what was written from, see `ReplicaLink::pending`
+    pub pending: Arc<AtomicUsize>,
+    // fires when the master drops the link, e.g. past the output buffer limit
+    pub dropped: oneshot::Receiver<()>,
+}
+
+// the reply of PSYNC
+#[derive(Debug, PartialEq)]
+pub enum SyncKind {
+    Full { replid: String, offset: u64 },
+    Partial { replid: String },
+}
+
+pub fn new_replid() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40).map(|_| format!("{:x}", rng.gen_range(0..16u8))).collect()
+}
+
+impl Replication {
+    pub fn new(backlog_size: usize, output_limit: usize) -> Self {
+        Self {
+            state: Mutex::new(ReplState {
+                replid: new_replid(),
+                offset: 0,
+                backlog: None,
+                backlog_size,
+                stream_db: None,
+                replicas: Vec::new(),
+                master: None,
+            }),
+            enabled: AtomicBool::new(false),
+            order: Mutex::new(()),
+            replica: AtomicBool::new(false),
+            next_replica_id: AtomicU64::new(1),
+            output_limit: AtomicUsize::new(output_limit),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReplState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub fn order(&self) -> MutexGuard<'_, ()> {
+        self.order.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn output_limit(&self) -> usize {
+        self.output_limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_output_limit(&self, limit: usize) {
+        self.output_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn replid(&self) -> String {
+        self.state().replid.clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.state().offset
+    }
+
+    // (first offset, length) of the backlog when there is one
+    pub fn backlog_info(&self) -> Option<(u64, usize)> {
+        self.state().backlog.as_ref().map(|b| (b.first_offset(), b.len()))
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::Acquire)
+    }
+
+    pub fn master(&self) -> Option<MasterLink> {
+        self.state().master.clone()
+    }
+
+    // (id, ip, listening port, acknowledged offset) of each attached replica
+    pub fn replicas(&self) -> Vec<(u64, String, u16, u64)> {
+        let state = self.state();
+        state.replicas.iter().map(|r| (r.id, r.ip.clone(), r.listening_port, r.ack_offset)).collect()
+    }
+
+    // append commands run against `db` to the stream
+    pub fn propagate(&self, db: usize, commands: &[Argv]) {
+        if !self.is_enabled() || commands.is_empty() {
+            return;
+        }
+        let mut state = self.state();
+        let mut data = Vec::new();
+        if state.stream_db != Some(db) {
+            data.extend_from_slice(&command_frame(&[b"select".to_vec(), db.to_string().into_bytes()]).encode());
+            state.stream_db = Some(db);
+        }
+        for argv in commands {
+            data.extend_from_slice(&command_frame(argv).encode());
+        }
+        state.offset += data.len() as u64;
+        if let Some(backlog) = state.backlog.as_mut() {
+            backlog.push(&data);
+        }
+        let data = Bytes::from(data);
+        let limit = self.output_limit();
+        // a replica whose link task is gone or that can't keep up is dropped here
+        state.replicas.retain(|r| {
+            let pending = r.pending.fetch_add(data.len(), Ordering::Relaxed) + data.len();
+            if limit > 0 && pending > limit {
+                warn!("Replica {} has {} bytes of the stream unsent, over the output buffer limit, disconnecting", r.id, pending);
+                return false;
+            }
+            r.tx.send(data.clone()).is_ok()
+        });
+    }
+
+    // Register a replica asking for the stream after `requested` (replid, offset).
+    // The caller must hold the command lock exclusively, so that `snapshot` and the
+    // stream offset describe the same point.
+    pub fn attach_replica(
+        &self,
+        ip: String,
+        listening_port: u16,
+        requested: Option<(&str, u64)>,
+        snapshot: impl FnOnce() -> Vec<u8>,
+    ) -> (SyncKind, ReplicaFeed) {
+        let mut state = self.state();
+        if state.backlog.is_none() {
+            state.backlog = Some(Backlog::new(state.backlog_size, state.offset));
+            self.enabled.store(true, Ordering::Release);
+        }
+
+        let partial = requested.and_then(|(replid, from)| {
+            if replid != state.replid {
+                return None;
+            }
+            state.backlog.as_ref()?.range_from(from)
+        });
+        let (kind, initial) = match partial {
+            Some(tail) => (SyncKind::Partial { replid: state.replid.clone() }, tail),
+            None => {
+                // the replica starts from db 0, make the stream select explicitly again
+                state.stream_db = None;
+                let kind = SyncKind::Full { replid: state.replid.clone(), offset: state.offset };
+                (kind, snapshot())
+            }
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (dropped_tx, dropped) = oneshot::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let id = self.next_replica_id.fetch_add(1, Ordering::Relaxed);
+        state.replicas.push(ReplicaLink {
+            id,
+            ip,
+            listening_port,
+            ack_offset: 0,
+            tx,
+            pending: pending.clone(),
+            _dropped: dropped_tx,
+        });
+        (kind, ReplicaFeed { id, initial, rx, pending, dropped })
+    }
+
+    pub fn detach_replica(&self, id: u64) {
+        self.state().replicas.retain(|r| r.id != id);
+    }
+
+    pub fn ack_replica(&self, id: u64, offset: u64) {
+        if let Some(r) = self.state().replicas.iter_mut().find(|r| r.id == id) {
+            r.ack_offset = offset;
+        }
+    }
+
+    // replicate from host:port from now on, the previous link task is stopped
+    pub fn set_master(&self, host: String, port: u16) {
+        let mut state = self.state();
+        if let Some(old) = state.master.take().and_then(|m| m.task) {
+            old.abort();
+        }
+        state.master = Some(MasterLink {
+            host,
+            port,
+            state: LinkState::Connect,
+            master_replid: None,
+            master_offset: 0,
+            task: None,
+        });
+        self.replica.store(true, Ordering::Release);
+    }
+
+    pub fn set_master_task(&self, task: AbortHandle) {
+        match self.state().master.as_mut() {
+            Some(master) => master.task = Some(task),
+            None => task.abort(),
+        }
+    }
+
+    // REPLICAOF NO ONE: stop the link and keep the data, as a master with a new history
+    pub fn clear_master(&self) {
+        let mut state = self.state();
+        if let Some(master) = state.master.take() {
+            if let Some(task) = master.task {
+                task.abort();
+            }
+            state.replid = new_replid();
+        }
+        self.replica.store(false, Ordering::Release);
+    }
+
+    pub fn set_link_state(&self, link: LinkState) {
+        if let Some(master) = self.state().master.as_mut() {
+            master.state = link;
+        }
+    }
+
+    // the (replid, offset) the link has applied, for PSYNC
+    pub fn master_position(&self) -> Option<(String, u64)> {
+        let state = self.state();
+        let master = state.master.as_ref()?;
+        Some((master.master_replid.clone()?, master.master_offset))
+    }
+
+    pub fn set_master_position(&self, replid: String, offset: u64) {
+        if let Some(master) = self.state().master.as_mut() {
+            master.master_replid = Some(replid);
+            master.master_offset = offset;
+        }
+    }
+
+    pub fn add_master_offset(&self, n: u64) {
+        if let Some(master) = self.state().master.as_mut() {
+            master.master_offset += n;
+        }
+    }
+
+    // After a full sync the keyspace was replaced, our own replicas can't continue
+    // from our stream anymore: start a new history and drop them so they resync.
+    pub fn reset_history(&self) {
+        let mut state = self.state();
+        state.replid = new_replid();
+        state.stream_db = None;
+        state.replicas.clear();
+        if state.backlog.is_some() {
+            state.backlog = Some(Backlog::new(state.backlog_size, state.offset));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use tokio::sync::oneshot::error::TryRecvError;
+
+    use super::{Replication, SyncKind};
+
+    fn argv(line: &str) -> Vec<Vec<u8>> {
+        line.split_whitespace().map(|s| s.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_propagate_and_partial_sync() {
+        let repl = Replication::new(1024, 0);
+        // nothing is recorded before a replica attaches
+        repl.propagate(0, &[argv("set a 1")]);
+        assert_eq!(repl.offset(), 0);
+
+        let (kind, mut feed) = repl.attach_replica("127.0.0.1".into(), 6380, None, || b"snapshot".to_vec());
+        let replid = repl.replid();
+        assert_eq!(kind, SyncKind::Full { replid: replid.clone(), offset: 0 });
+        assert_eq!(feed.initial, b"snapshot");
+
+        repl.propagate(2, &[argv("set a 1")]);
+        let sent = feed.rx.try_recv().unwrap();
+        assert!(sent.starts_with(b"*2\r\n$6\r\nselect\r\n$1\r\n2\r\n*3\r\n$3\r\nset"));
+        assert_eq!(repl.offset(), sent.len() as u64);
+
+        repl.propagate(2, &[argv("del a")]);
+        let second = feed.rx.try_recv().unwrap();
+        assert_eq!(&second[..], b"*2\r\n$3\r\ndel\r\n$1\r\na\r\n");
+
+        // a replica that applied the first write continues with the second one
+        let from = sent.len() as u64 + 1;
+        let (kind, feed) = repl.attach_replica("127.0.0.1".into(), 6381, Some((&replid, from)), Vec::new);
+        assert_eq!(kind, SyncKind::Partial { replid: replid.clone() });
+        assert_eq!(feed.initial, second.to_vec());
+
+        let (kind, _) = repl.attach_replica("127.0.0.1".into(), 6382, Some(("other", from)), Vec::new);
+        assert!(matches!(kind, SyncKind::Full { .. }));
+        assert_eq!(repl.replicas().len(), 3);
+    }
+
+    #[test]
+    fn test_output_buffer_limit() {
+        let repl = Replication::new(1024, 64);
+        let (_, mut feed) = repl.attach_replica("127.0.0.1".into(), 6380, None, Vec::new);
+        repl.propagate(0, &[argv("set a 1")]);
+        let sent = feed.rx.try_recv().unwrap();
+        assert_eq!(feed.pending.load(Ordering::Relaxed), sent.len());
+
+        // what the link task wrote doesn't count anymore
+        feed.pending.fetch_sub(sent.len(), Ordering::Relaxed);
+        repl.propagate(0, &[argv("set b 2")]);
+        assert_eq!(repl.replicas().len(), 1);
+
+        // a replica that doesn't read falls behind and is disconnected
+        repl.propagate(0, &[argv("set c 3"), argv("set d 4")]);
+        assert!(repl.replicas().is_empty());
+        assert_eq!(feed.dropped.try_recv(), Err(TryRecvError::Closed));
+    }
+}
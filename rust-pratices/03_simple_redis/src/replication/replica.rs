@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{snapshot, Backend},
+    client::{command_frame, Client},
+    cmd::{self, eq_ignore_case, frame_to_argv, Session},
+    resp::{RespDecoder, RespEncode, RespError, RespFrame, RespLimits},
+    tls::{self, Stream},
+};
+
+use super::LinkState;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// how often the applied offset is acknowledged to the master
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Start replicating from host:port in a background task, replacing any previous link.
+pub fn start(backend: &Backend, host: String, port: u16) -> Result<()> {
+    let handle = tokio::runtime::Handle::try_current().map_err(|_| anyhow!("replication needs a running runtime"))?;
+    backend.repl.set_master(host.clone(), port);
+    let task = handle.spawn(run(backend.clone(), host, port));
+    backend.repl.set_master_task(task.abort_handle());
+    Ok(())
+}
+
+fn master_session() -> Session {
+    Session { is_master: true, ..Session::default() }
+}
+
+async fn run(backend: Backend, host: String, port: u16) {
+    // the link session outlives a reconnect: after a partial resync the stream
+    // goes on in the db it selected before
+    let mut session = master_session();
+    loop {
+        backend.repl.set_link_state(LinkState::Connecting);
+        if let Err(e) = sync_with_master(&backend, &host, port, &mut session).await {
+            warn!("Replication with master {}:{} failed: {}", host, port, e);
+        }
+        backend.repl.set_link_state(LinkState::Connect);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn sync_with_master(backend: &Backend, host: &str, port: u16, session: &mut Session) -> Result<()> {
+    // the snapshot is as large as the master's keyspace
+    let limits = RespLimits::new().max_bulk_len(usize::MAX).max_pending_buf(usize::MAX);
+    // the settings are read once, CONFIG SET may change them meanwhile
+    let config = backend.config().clone();
+    let client = if config.tls_replication {
+        Client::connect_tls((host, port), &tls::replication_connector(&config)?, host).await?
+    } else {
+        Client::connect((host, port)).await?
+    };
+    let mut client = client.with_limits(limits);
+
+    if let Some(password) = &config.masterauth {
+        let mut args = vec!["auth".to_string()];
+        args.extend(config.masteruser.clone());
+        args.push(password.clone());
+        if let RespFrame::Error(e) = client.command(&args).await? {
+            bail!("AUTH refused by master: {}", e.as_str());
+        }
+    }
+    for args in [
+        vec!["ping".to_string()],
+        vec!["replconf".into(), "listening-port".into(), config.port.to_string()],
+        vec!["replconf".into(), "capa".into(), "psync2".into()],
+    ] {
+        if let RespFrame::Error(e) = client.command(&args).await? {
+            bail!("{} refused by master: {}", args.join(" "), e.as_str());
+        }
+    }
+
+    // ask to continue after what we applied, "? -1" asks for a full sync
+    let (replid, from) = match backend.repl.master_position() {
+        Some((replid, offset)) => (replid, (offset + 1).to_string()),
+        None => ("?".into(), "-1".into()),
+    };
+    let reply = match client.command(&["psync", replid.as_str(), from.as_str()]).await? {
+        RespFrame::SimpleString(s) => s.as_str().to_string(),
+        other => bail!("unexpected PSYNC reply: {:?}", other),
+    };
+    let mut parts = reply.split_whitespace();
+    match parts.next() {
+        Some("FULLRESYNC") => {
+            let (Some(replid), Some(offset)) = (parts.next(), parts.next().and_then(|o| o.parse::<u64>().ok())) else {
+                bail!("invalid FULLRESYNC reply: {}", reply);
+            };
+            backend.repl.set_link_state(LinkState::Sync);
+            let RespFrame::BulkString(data) = client.read_frame().await? else {
+                bail!("expected the snapshot from master");
+            };
+            // the position is set with the keys, a client never sees the data without it
+            let keys = {
+                let _exclusive = backend.exclusive();
+                let keys = snapshot::restore(backend, &data)?;
+                backend.repl.reset_history();
+                backend.repl.set_master_position(replid.to_string(), offset);
+                keys
+            };
+            *session = master_session();
+            info!("Full resync with master {}:{} done, {} keys loaded", host, port, keys);
+        }
+        Some("CONTINUE") => {
+            // the master may go on under another id, e.g. after it was promoted
+            if let Some((replid, (_, offset))) = parts.next().zip(backend.repl.master_position()) {
+                backend.repl.set_master_position(replid.to_string(), offset);
+            }
+            info!("Partial resync with master {}:{} accepted", host, port);
+        }
+        _ => bail!("unexpected PSYNC reply: {}", reply),
+    }
+
+    backend.repl.set_link_state(LinkState::Connected);
+    let (stream, buf) = client.into_parts();
+    apply_stream(backend, stream, buf, session, &limits).await
+}
+
+// apply the master's write stream, counting the bytes of every command in the offset
+async fn apply_stream(
+    backend: &Backend,
+    mut stream: Stream,
+    mut buf: BytesMut,
+    session: &mut Session,
+    limits: &RespLimits,
+) -> Result<()> {
+    let mut ack = tokio::time::interval(ACK_INTERVAL);
+    let mut decoder = RespDecoder::new();
+    loop {
+        loop {
+            let before = buf.len();
+            let frame = match decoder.decode(&mut buf, limits) {
+                Ok(frame) => frame,
+                Err(RespError::NotComplete) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let argv = frame_to_argv(frame)?;
+            if argv.len() == 3 && eq_ignore_case(&argv[0], "replconf") && eq_ignore_case(&argv[1], "getack") {
+                send_ack(backend, &mut stream).await?;
+            } else if !argv.is_empty() {
+                // replies to the master are not sent
+                cmd::execute_argv(backend, session, argv);
+            }
+            backend.repl.add_master_offset((before - buf.len()) as u64);
+        }
+
+        tokio::select! {
+            n = stream.read_buf(&mut buf) => {
+                if n? == 0 {
+                    bail!("connection closed by master");
+                }
+            }
+            _ = ack.tick() => send_ack(backend, &mut stream).await?,
+        }
+    }
+}
+
+async fn send_ack(backend: &Backend, stream: &mut Stream) -> Result<()> {
+    let offset = backend.repl.master_position().map(|(_, offset)| offset).unwrap_or(0);
+    let frame = command_frame(&["replconf".to_string(), "ack".into(), offset.to_string()]);
+    stream.write_all(&frame.encode()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use crate::{
+        backend::Backend,
+        cmd::{
+            bulk, error_frame, int, nil, ok,
+            tests::{run, test_backend},
+            CommandError, Session,
+        },
+        network,
+        resp::RespFrame,
+    };
+
+    use super::master_session;
+
+    async fn start_server() -> (Backend, u16) {
+        let backend = test_backend();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(network::serve(listener, backend.clone()));
+        (backend, port)
+    }
+
+    async fn wait_for(backend: &Backend, db: usize, key: &str, value: &str) {
+        let mut session = Session { db, ..Session::default() };
+        for _ in 0..100 {
+            if run(backend, &mut session, &format!("get {}", key)) == bulk(value) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("replica never got {}={}", key, value);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_full_sync_and_stream() {
+        let (master, port) = start_server().await;
+        let mut session = Session::new();
+        run(&master, &mut session, "set a 1");
+        run(&master, &mut session, "select 2");
+        run(&master, &mut session, "set b 2");
+
+        let replica = test_backend();
+        let mut client = Session::new();
+        assert_eq!(run(&replica, &mut client, &format!("replicaof 127.0.0.1 {}", port)), ok());
+        wait_for(&replica, 0, "a", "1").await;
+        wait_for(&replica, 2, "b", "2").await;
+        // the replica follows the master's history, its INFO shows the master's id
+        let RespFrame::BulkString(info) = run(&replica, &mut client, "info replication") else { panic!("expect: bulk") };
+        let info = String::from_utf8(info).unwrap();
+        assert!(info.contains(&format!("\r\nmaster_replid:{}\r\n", master.repl.replid())), "{}", info);
+
+        // writes after the sync come through the stream, in the db they were made in
+        run(&master, &mut session, "incr b");
+        run(&master, &mut session, "setex c 100 x");
+        run(&master, &mut session, "select 0");
+        run(&master, &mut session, "multi");
+        run(&master, &mut session, "incr a");
+        run(&master, &mut session, "exec");
+        wait_for(&replica, 0, "a", "2").await;
+        wait_for(&replica, 2, "b", "3").await;
+        assert_eq!(replica.read(2).expire_at(b"c"), master.read(2).expire_at(b"c"));
+
+        assert_eq!(run(&replica, &mut client, "set a 10"), error_frame(CommandError::ReadOnly));
+        let RespFrame::Array(role) = run(&master, &mut session, "role") else { panic!("expect: array") };
+        assert_eq!(role[0], bulk("master"));
+
+        assert_eq!(run(&replica, &mut client, "replicaof no one"), ok());
+        assert_eq!(run(&replica, &mut client, "set a 10"), ok());
+    }
+
+    #[test]
+    fn test_replica_keeps_expired_keys() {
+        let replica = test_backend();
+        replica.repl.set_master("127.0.0.1".into(), 6379);
+        let mut master = master_session();
+        run(&replica, &mut master, "set a 1 px 1");
+        std::thread::sleep(Duration::from_millis(5));
+
+        // clients see the key expired, it stays until the master deletes it
+        let mut client = Session::new();
+        assert_eq!(run(&replica, &mut client, "get a"), nil());
+        assert_eq!(replica.active_expire(20), 0);
+        assert_eq!(replica.read(0).len(), 1);
+        // the master's stream still finds it, e.g. a PERSIST the master ran in time
+        assert_eq!(run(&replica, &mut master, "persist a"), int(1));
+        assert_eq!(run(&replica, &mut client, "get a"), bulk("1"));
+        run(&replica, &mut master, "pexpire a 1");
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(run(&replica, &mut master, "del a"), int(1));
+        assert_eq!(replica.read(0).len(), 0);
+    }
+}
@@ -0,0 +1,365 @@
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+};
+
+use super::{RespError, RespFrame};
+
+impl de::Error for RespError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        RespError::Serde(msg.to_string())
+    }
+}
+
+// Deserialize a RespFrame back into any `T: Deserialize`, the reverse of `to_frame`
+pub fn from_frame<T: DeserializeOwned>(frame: RespFrame) -> Result<T, RespError> {
+    T::deserialize(FrameDeserializer::new(frame))
+}
+
+pub struct FrameDeserializer {
+    frame: RespFrame,
+}
+
+impl FrameDeserializer {
+    pub fn new(frame: RespFrame) -> Self {
+        Self { frame }
+    }
+
+    fn into_string(self) -> Result<String, RespError> {
+        match self.frame {
+            RespFrame::SimpleString(s) => Ok(s.0),
+            RespFrame::Error(e) => Ok(e.0),
+            RespFrame::BulkString(b) => String::from_utf8(b)
+                .map_err(|e| RespError::Serde(format!("expect: utf8 string, got: {}", e))),
+            other => Err(RespError::Serde(format!("expect: string, got: {:?}", other))),
+        }
+    }
+}
+
+// Redis replies numbers as bulk strings quite often (e.g. HGET), so accept both
+macro_rules! deserialize_number {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+            match self.frame {
+                RespFrame::BulkString(_) | RespFrame::SimpleString(_) => {
+                    let s = self.into_string()?;
+                    let v = s
+                        .parse::<$ty>()
+                        .map_err(|e| RespError::Serde(format!("parse {:?} err: {}", s, e)))?;
+                    visitor.$visit(v)
+                }
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for FrameDeserializer {
+    type Error = RespError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        match self.frame {
+            RespFrame::SimpleString(s) => visitor.visit_string(s.0),
+            RespFrame::Error(e) => Err(RespError::Serde(format!("got error frame: {}", e.0))),
+            RespFrame::Integer(n) => visitor.visit_i64(n),
+            RespFrame::BulkString(b) => match String::from_utf8(b) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            RespFrame::NullBulkString(_) | RespFrame::NullArray(_) | RespFrame::Null(_) => {
+                visitor.visit_unit()
+            }
+            RespFrame::Array(items) | RespFrame::Push(items) => visitor.visit_seq(SeqAccess::new(items)),
+            RespFrame::Set(set) => visitor.visit_seq(SeqAccess::new(set.0.into_iter().collect())),
+            RespFrame::Boolean(b) => visitor.visit_bool(b),
+            RespFrame::Double(d) => visitor.visit_f64(d.0),
+            RespFrame::Map(map) => visitor.visit_map(MapAccess::new(map.0)),
+        }
+    }
+
+    deserialize_number!(deserialize_i8, visit_i8, i8);
+    deserialize_number!(deserialize_i16, visit_i16, i16);
+    deserialize_number!(deserialize_i32, visit_i32, i32);
+    deserialize_number!(deserialize_i64, visit_i64, i64);
+    deserialize_number!(deserialize_u8, visit_u8, u8);
+    deserialize_number!(deserialize_u16, visit_u16, u16);
+    deserialize_number!(deserialize_u32, visit_u32, u32);
+    deserialize_number!(deserialize_u64, visit_u64, u64);
+    deserialize_number!(deserialize_f32, visit_f32, f32);
+    deserialize_number!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        match self.frame {
+            RespFrame::Integer(n) => visitor.visit_string(n.to_string()),
+            RespFrame::Double(d) => visitor.visit_string(d.0.to_string()),
+            _ => visitor.visit_string(self.into_string()?),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        match self.frame {
+            RespFrame::BulkString(b) => visitor.visit_byte_buf(b),
+            RespFrame::SimpleString(s) => visitor.visit_byte_buf(s.0.into_bytes()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RespError> {
+        match self.frame {
+            RespFrame::NullBulkString(_) | RespFrame::NullArray(_) | RespFrame::Null(_) => {
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RespError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RespError> {
+        match self.frame {
+            RespFrame::Map(map) => {
+                if map.len() != 1 {
+                    return Err(RespError::Serde(format!(
+                        "expect: map with a single variant entry, got {} entries",
+                        map.len()
+                    )));
+                }
+                let (variant, value) = map.0.into_iter().next().unwrap();
+                visitor.visit_enum(EnumAccess { variant, value: Some(value) })
+            }
+            _ => visitor.visit_enum(EnumAccess { variant: self.into_string()?, value: None }),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool char unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccess {
+    iter: std::vec::IntoIter<RespFrame>,
+}
+
+impl SeqAccess {
+    fn new(items: Vec<RespFrame>) -> Self {
+        Self { iter: items.into_iter() }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = RespError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, RespError> {
+        match self.iter.next() {
+            Some(frame) => seed.deserialize(FrameDeserializer::new(frame)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapAccess {
+    iter: std::collections::hash_map::IntoIter<String, RespFrame>,
+    value: Option<RespFrame>,
+}
+
+impl MapAccess {
+    fn new(map: std::collections::HashMap<String, RespFrame>) -> Self {
+        Self { iter: map.into_iter(), value: None }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = RespError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, RespError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, RespError> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| RespError::Serde("next_value called before next_key".into()))?;
+        seed.deserialize(FrameDeserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<RespFrame>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = RespError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess), RespError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<RespFrame>,
+}
+
+impl VariantAccess {
+    fn take(self) -> Result<FrameDeserializer, RespError> {
+        self.value
+            .map(FrameDeserializer::new)
+            .ok_or_else(|| RespError::Serde("expect: variant with data".into()))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = RespError;
+
+    fn unit_variant(self) -> Result<(), RespError> {
+        match self.value {
+            None => Ok(()),
+            Some(frame) => de::Deserialize::deserialize(FrameDeserializer::new(frame)),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, RespError> {
+        seed.deserialize(self.take()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, RespError> {
+        de::Deserializer::deserialize_seq(self.take()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RespError> {
+        de::Deserializer::deserialize_map(self.take()?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::resp::{from_frame, to_frame, Map, RespFrame, RespNull};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u32,
+        score: f64,
+        admin: bool,
+        nickname: Option<String>,
+        tags: Vec<String>,
+        extra: HashMap<String, i64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Ping,
+        Login(String),
+        Move { x: i32, y: i32 },
+        Pair(u8, u8),
+    }
+
+    #[test]
+    fn test_struct_roundtrip() {
+        let user = User {
+            name: "guan".into(),
+            age: 18,
+            score: 99.5,
+            admin: true,
+            nickname: Some("xin".into()),
+            tags: vec!["a".into(), "b".into()],
+            extra: HashMap::from([("level".into(), 3)]),
+        };
+        let frame = to_frame(&user).unwrap();
+        let back: User = from_frame(frame).unwrap();
+        assert_eq!(back, user);
+    }
+
+    #[test]
+    fn test_enum_roundtrip() {
+        for event in [
+            Event::Ping,
+            Event::Login("guan".into()),
+            Event::Move { x: 1, y: -2 },
+            Event::Pair(3, 4),
+        ] {
+            let frame = to_frame(&event).unwrap();
+            let back: Event = from_frame(frame).unwrap();
+            assert_eq!(back, event);
+        }
+    }
+
+    #[test]
+    fn test_number_from_bulk_string() {
+        let value: i64 = from_frame(RespFrame::BulkString(b"-42".to_vec())).unwrap();
+        assert_eq!(value, -42);
+
+        let value: f64 = from_frame(RespFrame::BulkString(b"1.5".to_vec())).unwrap();
+        assert_eq!(value, 1.5);
+    }
+
+    #[test]
+    fn test_option_from_null() {
+        let value: Option<String> = from_frame(RespFrame::Null(RespNull)).unwrap();
+        assert_eq!(value, None);
+
+        let mut map = Map::new();
+        map.insert("name".into(), RespFrame::Integer(1));
+        let err = from_frame::<User>(RespFrame::Map(map));
+        assert!(err.is_err());
+    }
+}
@@ -1,24 +1,246 @@
-use std::{collections::HashMap, io::BufRead, str::FromStr};
+use std::collections::HashMap;
 
 use crate::invalid_frame;
 
 use super::{
-    Double, Map, NullArray, NullBulkString, RespDecode, RespError, RespFrame, RespNull, Set,
-    SimpleError, SimpleString,
+    Double, Map, NullArray, NullBulkString, RespDecode, RespError, RespFrame, RespLimits, RespNull,
+    Set, SimpleError, SimpleString,
 };
 use bytes::{Buf, BytesMut};
 
 impl RespDecode for RespFrame {
     fn decode(buf: BytesMut) -> Result<Self, RespError> {
-        let mut iter = buf.iter().peekable();
-        match iter.peek() {
-            Some(b'+') => {
-                SimpleString::decode(buf[1..].into()).map(|val| RespFrame::SimpleString(val))
+        let limits = RespLimits::default();
+        let mut parser = FrameParser::new(&buf, &limits);
+        parser.parse_frame(0)
+    }
+}
+
+impl RespFrame {
+    // Decode one frame from the front of a buffer holding it whole, e.g. a snapshot.
+    // A connection reading a frame piece by piece uses a RespDecoder.
+    pub fn decode_with(buf: &mut BytesMut, limits: &RespLimits) -> Result<RespFrame, RespError> {
+        RespDecoder::new().decode(buf, limits)
+    }
+}
+
+// Decodes the frames of a connection buffer. When a frame isn't complete yet the elements
+// of its outermost aggregate parsed so far are kept, so a large command arriving in many
+// reads is parsed once and not again from its first byte on every read. Between two
+// calls the buffer may only grow.
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    progress: Option<Progress>,
+}
+
+// the complete elements of the outermost aggregate and where the next one starts
+#[derive(Debug)]
+struct Progress {
+    pos: usize,
+    frames: Vec<RespFrame>,
+}
+
+impl RespDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Decode one frame from the front of the buffer and advance past it.
+    // NotComplete means more bytes are needed; any other error is fatal for the connection.
+    pub fn decode(&mut self, buf: &mut BytesMut, limits: &RespLimits) -> Result<RespFrame, RespError> {
+        let mut parser = FrameParser::new(buf, limits);
+        parser.progress = self.progress.take();
+        match parser.parse_frame(0) {
+            Ok(frame) => {
+                let consumed = parser.pos;
+                buf.advance(consumed);
+                Ok(frame)
+            }
+            Err(RespError::NotComplete) if buf.len() > limits.max_pending_buf => {
+                Err(RespError::BufferOverflow(buf.len()))
+            }
+            Err(RespError::NotComplete) => {
+                self.progress = parser.progress;
+                Err(RespError::NotComplete)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// A cursor over a buffer that may hold a partial frame. Lengths from headers are
+// validated against the limits before they are used to slice or allocate.
+struct FrameParser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    limits: &'a RespLimits,
+    // resumed by the outermost aggregate, saved again when the buffer runs out
+    progress: Option<Progress>,
+}
+
+impl<'a> FrameParser<'a> {
+    fn new(buf: &'a [u8], limits: &'a RespLimits) -> Self {
+        Self { buf, pos: 0, limits, progress: None }
+    }
+
+    // read until the next \r\n, the returned line doesn't contain the splitter
+    fn read_line(&mut self) -> Result<&'a [u8], RespError> {
+        let rest = &self.buf[self.pos..];
+        let end = rest
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(RespError::NotComplete)?;
+        self.pos += end + 2;
+        Ok(&rest[..end])
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, RespError> {
+        let line = self.read_line()?;
+        std::str::from_utf8(line).map_err(|e| invalid_frame!("not utf8: {}", e))
+    }
+
+    // parse the <length> of a header, -1 is returned as None
+    fn read_len(&mut self) -> Result<Option<usize>, RespError> {
+        let s = self.read_str()?;
+        let len = s.parse::<i64>().map_err(|e| invalid_frame!("parse length {:?} err: {}", s, e))?;
+        match len {
+            -1 => Ok(None),
+            n if n < 0 => Err(RespError::InvalidFrameLength(n as isize)),
+            n => usize::try_from(n).map(Some).map_err(|_| RespError::InvalidFrameLength(isize::MAX)),
+        }
+    }
+
+    fn read_aggregate_len(&mut self, depth: usize) -> Result<Option<usize>, RespError> {
+        if depth + 1 > self.limits.max_nesting_depth {
+            return Err(RespError::NestingTooDeep(depth + 1));
+        }
+        let len = self.read_len()?;
+        if let Some(n) = len {
+            if n > self.limits.max_array_len {
+                return Err(RespError::ArrayTooLarge(n));
+            }
+        }
+        Ok(len)
+    }
+
+    fn parse_frame(&mut self, depth: usize) -> Result<RespFrame, RespError> {
+        let symbol = *self.buf.get(self.pos).ok_or(RespError::NotComplete)?;
+        self.pos += 1;
+        match symbol {
+            b'+' => Ok(RespFrame::SimpleString(SimpleString(self.read_str()?.to_string()))),
+            b'-' => Ok(RespFrame::Error(SimpleError(self.read_str()?.to_string()))),
+            b':' => {
+                let s = self.read_str()?;
+                let n = s.parse::<i64>().map_err(|e| invalid_frame!("parse i64 {:?} err: {}", s, e))?;
+                Ok(RespFrame::Integer(n))
+            }
+            b'$' => match self.read_len()? {
+                None => Ok(RespFrame::NullBulkString(NullBulkString)),
+                Some(len) => self.read_bulk(len).map(RespFrame::BulkString),
+            },
+            b'*' => match self.read_aggregate_len(depth)? {
+                None => Ok(RespFrame::NullArray(NullArray)),
+                Some(len) => self.read_frames(len, depth).map(RespFrame::Array),
+            },
+            b'_' => {
+                let line = self.read_line()?;
+                if !line.is_empty() {
+                    return Err(invalid_frame!("expect: RespNull(_\\r\\n)"));
+                }
+                Ok(RespFrame::Null(RespNull))
+            }
+            b'#' => match self.read_line()? {
+                b"t" => Ok(RespFrame::Boolean(true)),
+                b"f" => Ok(RespFrame::Boolean(false)),
+                _ => Err(invalid_frame!("expect: bool(t|f)")),
+            },
+            b',' => {
+                let s = self.read_str()?;
+                Ok(RespFrame::Double(Double(parse_double(s)?)))
+            }
+            b'%' => {
+                let len = self.read_aggregate_len(depth)?.ok_or(RespError::InvalidFrameLength(-1))?;
+                let mut map = HashMap::with_capacity(len.min(1024));
+                let mut frames = self.read_frames(len.saturating_mul(2), depth)?.into_iter();
+                while let (Some(key), Some(value)) = (frames.next(), frames.next()) {
+                    let key = match key {
+                        RespFrame::SimpleString(s) => s.0,
+                        RespFrame::BulkString(b) => {
+                            String::from_utf8(b).map_err(|e| invalid_frame!("map key not utf8: {}", e))?
+                        }
+                        _ => return Err(invalid_frame!("map key must be a string")),
+                    };
+                    map.insert(key, value);
+                }
+                Ok(RespFrame::Map(Map(map)))
             }
-            Some(b'-') => SimpleError::decode(buf[1..].into()).map(|val| RespFrame::Error(val)),
-            _ => todo!(),
+            b'~' => {
+                let len = self.read_aggregate_len(depth)?.ok_or(RespError::InvalidFrameLength(-1))?;
+                let frames = self.read_frames(len, depth)?;
+                Ok(RespFrame::Set(Set(frames.into_iter().collect())))
+            }
+            b'>' => {
+                let len = self.read_aggregate_len(depth)?.ok_or(RespError::InvalidFrameLength(-1))?;
+                self.read_frames(len, depth).map(RespFrame::Push)
+            }
+            _ => Err(invalid_frame!("not support type: {}", symbol)),
         }
     }
+
+    fn read_bulk(&mut self, len: usize) -> Result<Vec<u8>, RespError> {
+        if len > self.limits.max_bulk_len {
+            return Err(RespError::BulkTooLarge(len));
+        }
+        let rest = &self.buf[self.pos..];
+        if rest.len() < len.saturating_add(2) {
+            return Err(RespError::NotComplete);
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(invalid_frame!("bulk string is not terminated by \\r\\n"));
+        }
+        self.pos += len + 2;
+        Ok(rest[..len].to_vec())
+    }
+
+    // don't trust the announced length for pre-allocation, a peer could lie
+    fn read_frames(&mut self, len: usize, depth: usize) -> Result<Vec<RespFrame>, RespError> {
+        let resumed = if depth == 0 { self.progress.take() } else { None };
+        let mut frames = match resumed {
+            Some(progress) => {
+                self.pos = progress.pos;
+                progress.frames
+            }
+            None => Vec::with_capacity(len.min(1024)),
+        };
+        while frames.len() < len {
+            let start = self.pos;
+            match self.parse_frame(depth + 1) {
+                Ok(frame) => frames.push(frame),
+                Err(RespError::NotComplete) if depth == 0 => {
+                    self.progress = Some(Progress { pos: start, frames });
+                    return Err(RespError::NotComplete);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(frames)
+    }
+}
+
+// decode a whole buffer as one frame of the expected type
+fn decode_as<T>(
+    buf: &BytesMut,
+    prefix: &[u8],
+    error_msg: &str,
+    pick: impl FnOnce(RespFrame) -> Option<T>,
+) -> Result<T, RespError> {
+    validate_len_of_buf(buf)?;
+    validate_starts_with(buf, prefix, error_msg)?;
+
+    let limits = RespLimits::default();
+    let mut parser = FrameParser::new(buf, &limits);
+    let frame = parser.parse_frame(0)?;
+    pick(frame).ok_or_else(|| RespError::InvalidFrameType(error_msg.to_string()))
 }
 
 fn lookup_pos_before_end(buf: &BytesMut) -> Result<usize, RespError> {
@@ -33,22 +255,22 @@ fn lookup_pos_before_end(buf: &BytesMut) -> Result<usize, RespError> {
     if end == 0 {
         return Err(RespError::NotComplete);
     }
-    return Ok(end);
+    Ok(end)
 }
 
-fn lookup_pos_at_first(buf: &BytesMut) -> Result<usize, RespError> {
-    let mut end = 0;
-    for i in 1..buf.len()-1 {
-        if buf[i] == b'\r' && buf[i + 1] == b'\n' {
-            end = i;
-            break;
-        }
+// a RESP3 double: a decimal number, or inf / -inf / nan. Anything else,
+// including the spellings `str::parse` also takes like "infinity", is invalid.
+fn parse_double(s: &str) -> Result<f64, RespError> {
+    match s {
+        "inf" | "+inf" => Ok(f64::INFINITY),
+        "-inf" => Ok(f64::NEG_INFINITY),
+        "nan" => Ok(f64::NAN),
+        s => s
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| invalid_frame!("expect: double, got {:?}", s)),
     }
-
-    if end == 0 {
-        return Err(RespError::NotComplete);
-    }
-    return Ok(end)
 }
 
 fn validate_len_of_buf(buf: &BytesMut) -> Result<(), RespError> {
@@ -117,19 +339,10 @@ impl RespDecode for i64 {
 // note: A bulk string represents a single binary string. 
 impl RespDecode for Vec<u8> {
     fn decode(buf: BytesMut) -> Result<Self, RespError> {
-        validate_len_of_buf(&buf)?;
-        validate_starts_with(&buf, b"$", "expect: Vec<u8>($)")?;
-
-        // the left element of first '\r\n' is total of elements
-        let num_splitter_index = lookup_pos_at_first(&buf)
-            .map_err(|e| RespError::InvalidFrame(format!("lack of the first splitter: {}", e.to_string())))?;
-        let length = String::from_utf8(buf[1..num_splitter_index].to_vec())
-            .map(|val| val.parse::<usize>())
-            .map_err(|e| RespError::InvalidFrame(format!("parse length err: {}", e)))?;
-        let length = length.map_err(|e| RespError::InvalidFrame(format!("parse length err: {}", e)))?;
-
-        let start = num_splitter_index+2;
-        return Ok(buf[start..start+length].to_vec());
+        decode_as(&buf, b"$", "expect: Vec<u8>($)", |frame| match frame {
+            RespFrame::BulkString(data) => Some(data),
+            _ => None,
+        })
     }
 }
 
@@ -151,7 +364,10 @@ impl RespDecode for NullBulkString {
 // *<number-of-elements>\r\n<element-1>...<element-n>
 impl RespDecode for Vec<RespFrame> {
     fn decode(buf: BytesMut) -> Result<Self, RespError> {
-        todo!()
+        decode_as(&buf, b"*", "expect: Vec<RespFrame>(*)", |frame| match frame {
+            RespFrame::Array(frames) => Some(frames),
+            _ => None,
+        })
     }
 }
 
@@ -207,40 +423,26 @@ impl RespDecode for Double {
 
         let end = lookup_pos_before_end(&buf)?;
         let s: &str = &String::from_utf8_lossy(&buf[1..end]);
-        match FromStr::from_str(s) {
-            Err(_) => Ok(Double(f64::NAN)),
-            Ok(value) => Ok(Double(value)),
-        }
+        Ok(Double(parse_double(s)?))
     }
 }
 
 
 impl RespDecode for Map {
     fn decode(buf: BytesMut) -> Result<Self, RespError> {
-        validate_len_of_buf(&buf)?;
-        validate_starts_with(&buf, b"%", "expect: Map(%)")?;
-
-        // find number of k-v pairs
-        let splitter_start_pos = lookup_pos_at_first(&buf)
-            .map_err(|_| invalid_frame!("lack of the first splitter"))?;
-        let num = String::from_utf8(buf[1..splitter_start_pos].to_vec())
-            .map_err(|e| invalid_frame!("extract number of entries err: {}", e))?;
-        let num = num.parse::<usize>().map_err(|e| invalid_frame!("parse to usize err: {}", e))?;
-
-        // parse entries
-        let map = HashMap::with_capacity(num);
-        
-
-        Ok(Map(map))
+        decode_as(&buf, b"%", "expect: Map(%)", |frame| match frame {
+            RespFrame::Map(map) => Some(map),
+            _ => None,
+        })
     }
 }
 
 impl RespDecode for Set {
     fn decode(buf: BytesMut) -> Result<Self, RespError> {
-        validate_len_of_buf(&buf)?;
-        validate_starts_with(&buf, b"~", "expect: Set(~)")?;
-
-        todo!()
+        decode_as(&buf, b"~", "expect: Set(~)", |frame| match frame {
+            RespFrame::Set(set) => Some(set),
+            _ => None,
+        })
     }
 }
 
@@ -248,7 +450,10 @@ impl RespDecode for Set {
 mod tests {
     use bytes::BytesMut;
 
-    use crate::resp::{Double, NullArray, NullBulkString, RespDecode, RespEncode, RespNull, SimpleString};
+    use crate::resp::{
+        Double, Map, NullArray, NullBulkString, RespDecode, RespDecoder, RespEncode, RespError,
+        RespFrame, RespLimits, RespNull, Set, SimpleString,
+    };
 
     #[test]
     fn test_string_decode() {
@@ -321,10 +526,20 @@ mod tests {
         let value = Double::decode(bytes).unwrap();
         assert_eq!(value, Double(-31.415_f64));
 
-        let bytes = BytesMut::from(",abcder\r\n");
+        let bytes = BytesMut::from(",nan\r\n");
         let value = Double::decode(bytes).unwrap();
         assert!(f64::is_nan(value.0));
 
+        for garbage in [",abcder\r\n", ",NaN\r\n", ",infinity\r\n", ",1e400\r\n", ",\r\n"] {
+            assert!(matches!(Double::decode(BytesMut::from(garbage)), Err(RespError::InvalidFrame(_))), "{}", garbage);
+            let mut buf = BytesMut::from(garbage);
+            assert!(matches!(RespFrame::decode_with(&mut buf, &Default::default()), Err(RespError::InvalidFrame(_))));
+        }
+
+        let bytes = BytesMut::from(",inf\r\n");
+        let value = Double::decode(bytes).unwrap();
+        assert_eq!(value.0, f64::INFINITY);
+
         let bytes = BytesMut::from(",+inf\r\n");
         let value = Double::decode(bytes).unwrap();
         assert!(f64::is_sign_positive(value.0) && f64::is_infinite(value.0));
@@ -333,4 +548,130 @@ mod tests {
         let value = Double::decode(bytes).unwrap();
         assert!(f64::is_sign_negative(value.0) && f64::is_infinite(value.0));
     }
+
+    #[test]
+    fn test_array_decode() {
+        let bytes = BytesMut::from("*2\r\n$3\r\nget\r\n*2\r\n:1\r\n#t\r\n");
+        let frames = Vec::<RespFrame>::decode(bytes).unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                RespFrame::BulkString(b"get".to_vec()),
+                RespFrame::Array(vec![RespFrame::Integer(1), RespFrame::Boolean(true)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_and_set_decode() {
+        let bytes = BytesMut::from("%2\r\n+a\r\n:1\r\n$1\r\nb\r\n,1.5\r\n");
+        let map = Map::decode(bytes).unwrap();
+        assert_eq!(map.get("a"), Some(&RespFrame::Integer(1)));
+        assert_eq!(map.get("b"), Some(&RespFrame::Double(Double(1.5))));
+
+        let bytes = BytesMut::from("~2\r\n+a\r\n,2.5\r\n");
+        let set = Set::decode(bytes).unwrap();
+        assert!(set.contains(&RespFrame::SimpleString("a".into())));
+        assert!(set.contains(&RespFrame::Double(Double(2.5))));
+    }
+
+    #[test]
+    fn test_decode_with_advances_buf() {
+        let limits = RespLimits::default();
+        let mut buf = BytesMut::from("+OK\r\n:5\r\n$5\r\nhel");
+        assert_eq!(
+            RespFrame::decode_with(&mut buf, &limits).unwrap(),
+            RespFrame::SimpleString("OK".into())
+        );
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits).unwrap(), RespFrame::Integer(5));
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::NotComplete));
+
+        buf.extend_from_slice(b"lo\r\n");
+        assert_eq!(
+            RespFrame::decode_with(&mut buf, &limits).unwrap(),
+            RespFrame::BulkString(b"hello".to_vec())
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decoder_keeps_progress() {
+        let limits = RespLimits::default();
+        let full = b"*3\r\n$3\r\nset\r\n%1\r\n+k\r\n:1\r\n$5\r\nhello\r\n+OK\r\n";
+        let mut decoder = RespDecoder::new();
+        let mut buf = BytesMut::from(&full[..20]);
+        assert_eq!(decoder.decode(&mut buf, &limits), Err(RespError::NotComplete));
+        // the complete elements are kept, the map is parsed again from its header
+        let progress = decoder.progress.as_ref().unwrap();
+        assert_eq!((progress.pos, progress.frames.len()), (13, 1));
+
+        // fed byte by byte the frames come out the same
+        let mut frames = vec![];
+        for &byte in &full[20..] {
+            buf.extend_from_slice(&[byte]);
+            match decoder.decode(&mut buf, &limits) {
+                Ok(frame) => frames.push(frame),
+                Err(e) => assert_eq!(e, RespError::NotComplete),
+            }
+        }
+        let mut whole = BytesMut::from(&full[..]);
+        let expected = RespFrame::decode_with(&mut whole, &limits).unwrap();
+        assert_eq!(frames, vec![expected, RespFrame::SimpleString("OK".into())]);
+        assert!(buf.is_empty() && decoder.progress.is_none());
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let limits = RespLimits::new()
+            .max_bulk_len(16)
+            .max_array_len(4)
+            .max_nesting_depth(2)
+            .max_pending_buf(32);
+
+        let mut buf = BytesMut::from("$9999999999\r\n");
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::BulkTooLarge(9999999999)));
+
+        let mut buf = BytesMut::from("*5\r\n");
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::ArrayTooLarge(5)));
+
+        let mut buf = BytesMut::from("*1\r\n*1\r\n*1\r\n:1\r\n");
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::NestingTooDeep(3)));
+
+        let mut buf = BytesMut::from("+".repeat(40).as_str());
+        assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::BufferOverflow(40)));
+
+        let mut buf = BytesMut::from("$-5\r\n");
+        assert!(RespFrame::decode_with(&mut buf, &limits).unwrap_err().is_fatal());
+    }
+
+    #[test]
+    fn test_bulk_string_out_of_bounds() {
+        // the announced length is larger than the data, must not panic
+        let bytes = BytesMut::from("$12\r\nhello\r\n");
+        assert_eq!(Vec::<u8>::decode(bytes), Err(RespError::NotComplete));
+
+        let bytes = BytesMut::from("$2\r\nhello\r\n");
+        assert!(Vec::<u8>::decode(bytes).is_err());
+    }
+
+    #[test]
+    fn test_truncated_frames_never_panic() {
+        let full = b"*3\r\n$3\r\nset\r\n%1\r\n+k\r\n~2\r\n,1.5\r\n_\r\n#f\r\n";
+        let limits = RespLimits::default();
+        for end in 0..full.len() {
+            let mut buf = BytesMut::from(&full[..end]);
+            assert_eq!(RespFrame::decode_with(&mut buf, &limits), Err(RespError::NotComplete));
+        }
+        let mut buf = BytesMut::from(&full[..]);
+        assert!(RespFrame::decode_with(&mut buf, &limits).is_ok());
+    }
+
+    #[test]
+    fn test_push_decode() {
+        let mut buf = BytesMut::from(">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n");
+        let frame = RespFrame::decode_with(&mut buf, &RespLimits::default()).unwrap();
+        let keys = RespFrame::Array(vec![RespFrame::BulkString(b"k".to_vec())]);
+        assert_eq!(frame, RespFrame::Push(vec![RespFrame::BulkString(b"invalidate".to_vec()), keys]));
+        assert_eq!(frame.encode(), b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n");
+    }
 }
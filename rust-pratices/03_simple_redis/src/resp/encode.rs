@@ -25,6 +25,12 @@ impl RespEncode for RespFrame {
             RespFrame::Double(data) => data.encode(),
             RespFrame::Map(data) => data.encode(),
             RespFrame::Set(data) => data.encode(),
+            // Push: ><number-of-elements>\r\n<element-1>...<element-n>, laid out like an array
+            RespFrame::Push(data) => {
+                let mut buf = data.encode();
+                buf[0] = b'>';
+                buf
+            }
         }
     }
 }
@@ -46,8 +52,7 @@ impl RespEncode for SimpleError {
 // Integers: :[<+|->]<value>\r\n
 impl RespEncode for i64 {
     fn encode(self) -> Vec<u8> {
-        let sign = if self > 0 { "+" } else { "-" };
-        format!(":{}{}\r\n", sign, self).into()
+        format!(":{}\r\n", self).into()
     }
 }
 
@@ -80,6 +85,7 @@ impl RespEncode for Vec<RespFrame> {
         let mut buf = Vec::with_capacity(BUF_LEN);
         buf.extend_from_slice(b"*");
         buf.extend_from_slice(self.len().to_string().as_bytes());
+        buf.extend_from_slice(b"\r\n");
         for frame in self {
             buf.extend_from_slice(frame.encode().as_ref());
         }
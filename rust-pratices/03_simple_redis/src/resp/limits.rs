@@ -0,0 +1,51 @@
+// Limits applied while decoding frames that come from an untrusted peer.
+// Every length announced in a frame header is checked before any memory is reserved for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespLimits {
+    // max bytes of a single bulk string ($<len>), like redis `proto-max-bulk-len`
+    pub max_bulk_len: usize,
+    // max elements of a single aggregate (*<n>, %<n>, ~<n>)
+    pub max_array_len: usize,
+    // max nesting of aggregates, a flat array has depth 1
+    pub max_nesting_depth: usize,
+    // max bytes buffered for one connection while waiting for a complete frame,
+    // like redis `client-query-buffer-limit`
+    pub max_pending_buf: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: 1024 * 1024,
+            max_nesting_depth: 128,
+            max_pending_buf: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl RespLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_bulk_len(mut self, v: usize) -> Self {
+        self.max_bulk_len = v;
+        self
+    }
+
+    pub fn max_array_len(mut self, v: usize) -> Self {
+        self.max_array_len = v;
+        self
+    }
+
+    pub fn max_nesting_depth(mut self, v: usize) -> Self {
+        self.max_nesting_depth = v;
+        self
+    }
+
+    pub fn max_pending_buf(mut self, v: usize) -> Self {
+        self.max_pending_buf = v;
+        self
+    }
+}
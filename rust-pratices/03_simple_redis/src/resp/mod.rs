@@ -1,5 +1,13 @@
+pub mod de;
 pub mod decode;
 pub mod encode;
+pub mod limits;
+pub mod ser;
+
+pub use de::from_frame;
+pub use decode::RespDecoder;
+pub use limits::RespLimits;
+pub use ser::to_frame;
 
 use bytes::BytesMut;
 use std::{
@@ -26,13 +34,31 @@ pub enum RespError {
     InvalidFrameLength(isize),
     #[error("Frame is not complete")]
     NotComplete,
+    #[error("Serde: {0}")]
+    Serde(String),
+    #[error("Bulk string too large: {0}")]
+    BulkTooLarge(usize),
+    #[error("Aggregate too large: {0}")]
+    ArrayTooLarge(usize),
+    #[error("Nesting too deep: {0}")]
+    NestingTooDeep(usize),
+    #[error("Pending buffer too large: {0}")]
+    BufferOverflow(usize),
+}
+
+impl RespError {
+    // every error but NotComplete means the stream can't be trusted any more,
+    // the connection should be closed
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, RespError::NotComplete)
+    }
 }
 
 #[macro_export]
 macro_rules! invalid_frame {
     ($($arg:tt)*) => {
         {
-            let err = crate::resp::RespError::InvalidFrame(format!($($arg)*));
+            let err = $crate::resp::RespError::InvalidFrame(format!($($arg)*));
             err
         }
     };
@@ -42,7 +68,7 @@ macro_rules! invalid_frame {
 macro_rules! invalid_frame_type {
     ($($arg:tt)*) => {
         {
-            let err = crate::resp::RespError::InvalidFrameType(format!($($arg)*));
+            let err = $crate::resp::RespError::InvalidFrameType(format!($($arg)*));
             err
         }
     };
@@ -52,7 +78,7 @@ macro_rules! invalid_frame_type {
 macro_rules! invalid_frame_length {
     ($arg:tt) => {
         {
-            let err = crate::resp::RespError::InvalidFrameLength($arg);
+            let err = $crate::resp::RespError::InvalidFrameLength($arg);
             err
         }
     };
@@ -69,7 +95,7 @@ mod tests {
     }
 }
 
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
 pub enum RespFrame {
     SimpleString(SimpleString),
     Error(SimpleError),
@@ -83,44 +109,11 @@ pub enum RespFrame {
     Double(Double),
     Map(Map),
     Set(Set),
+    // RESP3 out of band data, e.g. client side caching invalidations
+    Push(Vec<RespFrame>),
 }
 
-impl RespFrame {
-    fn from_bytes(buf: BytesMut) -> Result<Self, RespError> {
-        if buf.len() < 1 {
-            return Err(invalid_frame!("expect: need valid buf"));
-        }
-
-        let first_symbol = buf[0];
-        match first_symbol {
-            b'+' => SimpleString::decode(buf).map(|val| Self::SimpleString(val)),
-            b'-' => SimpleError::decode(buf).map(|val| Self::Error(val)),
-            b':' => Double::decode(buf).map(|val| Self::Double(val)),
-            b'$' => {
-                if buf == "$-1\r\n" {
-                    Ok(Self::NullBulkString(NullBulkString::default()))
-                } else {
-                    Vec::<u8>::decode(buf).map(|val| Self::BulkString(val))
-                }
-            },
-            b'*' => {
-                if buf == "*-1\r\n" {
-                    Ok(Self::NullArray(NullArray::default()))
-                } else {
-                    Vec::<RespFrame>::decode(buf).map(|val| Self::Array(val))
-                }
-            },
-            b'_' => RespNull::decode(buf).map(|val| Self::Null(val)),
-            b'#' => bool::decode(buf).map(|val| Self::Boolean(val)),
-            b',' => Double::decode(buf).map(|val| Self::Double(val)),
-            b'%' => Map::decode(buf).map(|val| Self::Map(val)),
-            b'~' => Set::decode(buf).map(|val| Self::Set(val)),
-            _ => Err(invalid_frame!("not support type: {}", first_symbol))
-        }
-    }
-}
-
-#[derive(Eq, Hash, PartialEq, Debug)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
 pub struct SimpleString(String);
 
 impl SimpleString {
@@ -129,25 +122,41 @@ impl SimpleString {
     }
 }
 
+impl SimpleString {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl From<&str> for SimpleString {
     fn from(value: &str) -> Self {
         SimpleString::new(value)
     }
 }
 
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
 pub struct SimpleError(String);
 
-#[derive(Eq, Hash, PartialEq, Default, Debug)]
+impl SimpleError {
+    pub fn new(v: impl Into<String>) -> Self {
+        Self(v.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Default, Debug, Clone)]
 pub struct RespNull;
 
-#[derive(Eq, Hash, PartialEq, Default, Debug)]
+#[derive(Eq, Hash, PartialEq, Default, Debug, Clone)]
 pub struct NullArray;
 
-#[derive(Eq, Hash, PartialEq, Default, Debug)]
+#[derive(Eq, Hash, PartialEq, Default, Debug, Clone)]
 pub struct NullBulkString;
 
-#[derive(PartialEq, PartialOrd, Debug)]
+#[derive(PartialEq, PartialOrd, Debug, Clone)]
 pub struct Double(f64);
 
 impl Deref for Double {
@@ -162,11 +171,11 @@ impl std::cmp::Eq for Double {}
 
 impl std::hash::Hash for Double {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        todo!()
+        self.0.to_bits().hash(state);
     }
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
 pub struct Map(HashMap<String, RespFrame>);
 
 impl Map {
@@ -189,17 +198,18 @@ impl DerefMut for Map {
     }
 }
 
+// entries have no order, only the length is stable across equal maps
 impl std::hash::Hash for Map {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        todo!()
+        self.0.len().hash(state);
     }
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
 pub struct Set(HashSet<RespFrame>);
 
 impl Set {
-    fn new() -> Set {
+    pub fn new() -> Set {
         Set(HashSet::new())
     }
 }
@@ -220,6 +230,6 @@ impl DerefMut for Set {
 
 impl std::hash::Hash for Set {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        todo!()
+        self.0.len().hash(state);
     }
 }
@@ -0,0 +1,386 @@
+use serde::{ser, Serialize};
+
+use super::{Double, Map, RespError, RespFrame, RespNull};
+
+impl ser::Error for RespError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        RespError::Serde(msg.to_string())
+    }
+}
+
+// Serialize any `T: Serialize` into a RespFrame:
+//   struct / map -> Map, seq / tuple -> Array, None / unit -> Null,
+//   bool -> Boolean, float -> Double, integer -> Integer, string / bytes -> BulkString
+// enum variants with data are wrapped in a single-entry Map: { variant: data }
+pub fn to_frame<T: Serialize + ?Sized>(value: &T) -> Result<RespFrame, RespError> {
+    value.serialize(FrameSerializer)
+}
+
+pub struct FrameSerializer;
+
+pub struct SerializeVec {
+    items: Vec<RespFrame>,
+    variant: Option<&'static str>,
+}
+
+pub struct SerializeMap {
+    map: Map,
+    next_key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+fn wrap_variant(variant: Option<&'static str>, frame: RespFrame) -> RespFrame {
+    match variant {
+        None => frame,
+        Some(name) => {
+            let mut map = Map::new();
+            map.insert(name.to_string(), frame);
+            RespFrame::Map(map)
+        }
+    }
+}
+
+fn frame_to_key(frame: RespFrame) -> Result<String, RespError> {
+    match frame {
+        RespFrame::SimpleString(s) => Ok(s.0),
+        RespFrame::BulkString(b) => {
+            String::from_utf8(b).map_err(|e| RespError::Serde(format!("map key is not utf8: {}", e)))
+        }
+        RespFrame::Integer(n) => Ok(n.to_string()),
+        RespFrame::Boolean(b) => Ok(b.to_string()),
+        other => Err(RespError::Serde(format!("map key must be a string, got: {:?}", other))),
+    }
+}
+
+impl ser::Serializer for FrameSerializer {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVec;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<RespFrame, RespError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<RespFrame, RespError> {
+        let v = i64::try_from(v).map_err(|_| RespError::Serde(format!("{} overflows i64", v)))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<RespFrame, RespError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::Double(Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<RespFrame, RespError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::BulkString(v.as_bytes().to_vec()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::BulkString(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::Null(RespNull))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<RespFrame, RespError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<RespFrame, RespError> {
+        Ok(RespFrame::Null(RespNull))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<RespFrame, RespError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<RespFrame, RespError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<RespFrame, RespError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<RespFrame, RespError> {
+        Ok(wrap_variant(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, RespError> {
+        Ok(SerializeVec { items: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, RespError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, RespError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, RespError> {
+        Ok(SerializeVec { items: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, RespError> {
+        Ok(SerializeMap { map: Map::new(), next_key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, RespError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap, RespError> {
+        Ok(SerializeMap { map: Map::new(), next_key: None, variant: Some(variant) })
+    }
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RespError> {
+        self.items.push(value.serialize(FrameSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        Ok(wrap_variant(self.variant, RespFrame::Array(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RespError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RespError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVec {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RespError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RespError> {
+        self.next_key = Some(frame_to_key(key.serialize(FrameSerializer)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RespError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| RespError::Serde("serialize_value called before serialize_key".into()))?;
+        self.map.insert(key, value.serialize(FrameSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        Ok(wrap_variant(self.variant, RespFrame::Map(self.map)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RespError> {
+        self.map.insert(key.to_string(), value.serialize(FrameSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = RespFrame;
+    type Error = RespError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RespError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<RespFrame, RespError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::resp::{to_frame, Double, RespFrame, RespNull};
+
+    #[derive(Serialize)]
+    struct User {
+        name: String,
+        age: u32,
+        score: f64,
+        admin: bool,
+        nickname: Option<String>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_struct_to_frame() {
+        let user = User {
+            name: "guan".into(),
+            age: 18,
+            score: 99.5,
+            admin: false,
+            nickname: None,
+            tags: vec!["a".into(), "b".into()],
+        };
+
+        let frame = to_frame(&user).unwrap();
+        let RespFrame::Map(map) = frame else { panic!("expect: Map") };
+        assert_eq!(map["name"], RespFrame::BulkString(b"guan".to_vec()));
+        assert_eq!(map["age"], RespFrame::Integer(18));
+        assert_eq!(map["score"], RespFrame::Double(Double(99.5)));
+        assert_eq!(map["admin"], RespFrame::Boolean(false));
+        assert_eq!(map["nickname"], RespFrame::Null(RespNull));
+        assert_eq!(
+            map["tags"],
+            RespFrame::Array(vec![
+                RespFrame::BulkString(b"a".to_vec()),
+                RespFrame::BulkString(b"b".to_vec()),
+            ])
+        );
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+    }
+
+    #[test]
+    fn test_enum_to_frame() {
+        assert_eq!(to_frame(&Shape::Empty).unwrap(), RespFrame::BulkString(b"Empty".to_vec()));
+
+        let RespFrame::Map(map) = to_frame(&Shape::Circle(1.5)).unwrap() else {
+            panic!("expect: Map")
+        };
+        assert_eq!(map["Circle"], RespFrame::Double(Double(1.5)));
+    }
+
+    #[test]
+    fn test_u64_overflow() {
+        assert!(to_frame(&u64::MAX).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use mlua::{Lua, Table, Value};
+
+use crate::{
+    cmd::{array, bulk, int, nil, simple},
+    resp::{RespFrame, SimpleError},
+};
+
+// RESP -> Lua, the same mapping as redis: nil replies become false, status and error
+// replies become tables with a single `ok` / `err` field.
+pub fn frame_to_lua<'lua>(lua: &'lua Lua, frame: &RespFrame) -> mlua::Result<Value<'lua>> {
+    Ok(match frame {
+        RespFrame::Integer(n) => Value::Integer(*n),
+        RespFrame::BulkString(b) => Value::String(lua.create_string(b)?),
+        RespFrame::SimpleString(s) => Value::Table(single_field(lua, "ok", s.as_str())?),
+        RespFrame::Error(e) => Value::Table(single_field(lua, "err", e.as_str())?),
+        RespFrame::NullBulkString(_) | RespFrame::NullArray(_) | RespFrame::Null(_) => Value::Boolean(false),
+        RespFrame::Boolean(b) => Value::Boolean(*b),
+        RespFrame::Double(d) => Value::Number(**d),
+        RespFrame::Array(items) | RespFrame::Push(items) => sequence(lua, items.iter())?,
+        RespFrame::Set(items) => sequence(lua, items.iter())?,
+        // a map is flattened to key, value, key, value...
+        RespFrame::Map(map) => {
+            let table = lua.create_table()?;
+            for (key, value) in map.iter() {
+                table.raw_push(lua.create_string(key)?)?;
+                table.raw_push(frame_to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+fn single_field<'lua>(lua: &'lua Lua, name: &str, value: &str) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.raw_set(name, value)?;
+    Ok(table)
+}
+
+fn sequence<'lua, 'a>(lua: &'lua Lua, items: impl Iterator<Item = &'a RespFrame>) -> mlua::Result<Value<'lua>> {
+    let table = lua.create_table()?;
+    for item in items {
+        table.raw_push(frame_to_lua(lua, item)?)?;
+    }
+    Ok(Value::Table(table))
+}
+
+// Lua -> RESP: numbers are truncated to integers, true is 1, false and nil are nil,
+// a table is an `ok` / `err` reply or an array up to its first nil
+pub fn lua_to_frame(value: &Value) -> RespFrame {
+    match value {
+        Value::Integer(n) => int(*n),
+        Value::Number(n) => int(*n as i64),
+        Value::String(s) => bulk(s.as_bytes()),
+        Value::Boolean(true) => int(1),
+        Value::Table(table) => {
+            // like redis, CR / LF become spaces so the reply stays one line
+            if let Ok(mlua::Value::String(err)) = table.raw_get::<_, Value>("err") {
+                return RespFrame::Error(SimpleError::new(err.to_string_lossy().replace(['\r', '\n'], " ")));
+            }
+            if let Ok(mlua::Value::String(ok)) = table.raw_get::<_, Value>("ok") {
+                return simple(ok.to_string_lossy().replace(['\r', '\n'], " "));
+            }
+            let items = (1..).map_while(|i| match table.raw_get::<_, Value>(i) {
+                Ok(Value::Nil) | Err(_) => None,
+                Ok(item) => Some(lua_to_frame(&item)),
+            });
+            array(items)
+        }
+        _ => nil(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{Lua, Value};
+
+    use super::{frame_to_lua, lua_to_frame};
+    use crate::{
+        cmd::{array, bulk, int, nil, simple},
+        resp::{RespFrame, SimpleError},
+    };
+
+    #[test]
+    fn test_frame_round_trip() {
+        let lua = Lua::new();
+        for frame in [
+            int(42),
+            bulk("hello"),
+            simple("OK"),
+            RespFrame::Error(SimpleError::new("ERR boom")),
+            array([int(1), bulk("a"), array([bulk("b")])]),
+        ] {
+            let value = frame_to_lua(&lua, &frame).unwrap();
+            assert_eq!(lua_to_frame(&value), frame);
+        }
+        assert_eq!(frame_to_lua(&lua, &nil()).unwrap(), Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_lua_values() {
+        let lua = Lua::new();
+        let value: Value = lua.load("return {1, 2.9, 'x', true, false, nil, 7}").eval().unwrap();
+        // the array stops at the first nil, false is a nil reply
+        assert_eq!(lua_to_frame(&value), array([int(1), int(2), bulk("x"), int(1), nil()]));
+        assert_eq!(lua_to_frame(&Value::Nil), nil());
+    }
+}
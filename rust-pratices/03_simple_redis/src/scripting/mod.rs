@@ -0,0 +1,205 @@
+pub mod convert;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use mlua::{Lua, LuaOptions, MultiValue, StdLib, Value};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    backend::Backend,
+    cmd::{call, check_acl, error_frame, lookup, CommandError, Session, DENYOOM, NOSCRIPT, WRITE},
+    resp::RespFrame,
+};
+
+use convert::{frame_to_lua, lua_to_frame};
+
+pub fn sha1_hex(body: &[u8]) -> String {
+    hex::encode(Sha1::digest(body))
+}
+
+// Script bodies by their SHA1, filled by EVAL and SCRIPT LOAD, used by EVALSHA
+#[derive(Debug, Default)]
+pub struct ScriptCache(Mutex<HashMap<String, Vec<u8>>>);
+
+impl ScriptCache {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn load(&self, body: &[u8]) -> String {
+        let sha = sha1_hex(body);
+        self.lock().entry(sha.clone()).or_insert_with(|| body.to_vec());
+        sha
+    }
+
+    pub fn get(&self, sha: &str) -> Option<Vec<u8>> {
+        self.lock().get(&sha.to_ascii_lowercase()).cloned()
+    }
+
+    pub fn contains(&self, sha: &str) -> bool {
+        self.lock().contains_key(&sha.to_ascii_lowercase())
+    }
+
+    pub fn flush(&self) {
+        self.lock().clear();
+    }
+}
+
+// What the script's commands share: where they run, and whether the replication
+// stream got the MULTI that wraps the script's writes.
+struct ScriptState<'a> {
+    backend: &'a Backend,
+    session: &'a mut Session,
+    wrapped: bool,
+}
+
+impl ScriptState<'_> {
+    // run one redis.call / redis.pcall, the command layer checks minus locking:
+    // the script already holds the command lock exclusively
+    fn call(&mut self, argv: Vec<Vec<u8>>) -> RespFrame {
+        let Some(name) = argv.first() else {
+            return error_frame(CommandError::Other("Please specify at least one argument for this redis lib call".into()));
+        };
+        let Some(spec) = lookup(name) else {
+            return error_frame(CommandError::Other("Unknown Redis command called from script".into()));
+        };
+        if !spec.check_arity(argv.len()) {
+            return error_frame(CommandError::Other("Wrong number of args calling Redis command from script".into()));
+        }
+        if spec.has_flag_for(&argv, NOSCRIPT) {
+            return error_frame(CommandError::Other("This Redis command is not allowed from script".into()));
+        }
+        let backend = self.backend;
+        if let Err(e) = check_acl(backend, self.session, spec, &argv) {
+            return error_frame(e);
+        }
+        if spec.has_flag(DENYOOM) && !backend.perform_evictions() {
+            return error_frame(CommandError::Oom);
+        }
+        if spec.has_flag(WRITE) && !self.session.is_master && backend.config().replica_read_only && backend.repl.is_replica() {
+            return error_frame(CommandError::ReadOnly);
+        }
+        // like EXEC, replicas apply the writes of a script as one transaction
+        if spec.has_flag(WRITE) && backend.repl.is_enabled() && !self.session.in_atomic {
+            backend.repl.propagate(self.session.db, &[vec![b"multi".to_vec()]]);
+            self.session.in_atomic = true;
+            self.wrapped = true;
+        }
+        let reply = call(backend, self.session, spec, &argv);
+        self.session.blocked = None;
+        reply
+    }
+}
+
+fn lua_argv(args: MultiValue) -> mlua::Result<Vec<Vec<u8>>> {
+    args.into_iter()
+        .map(|arg| match arg {
+            Value::String(s) => Ok(s.as_bytes().to_vec()),
+            Value::Integer(n) => Ok(n.to_string().into_bytes()),
+            Value::Number(n) => Ok(crate::utils::format_f64(n).into_bytes()),
+            _ => Err(mlua::Error::RuntimeError(
+                "Lua redis lib command arguments must be strings or integers".into(),
+            )),
+        })
+        .collect()
+}
+
+// the message of the error the script stopped with, without the callback wrapping
+// and the traceback: only its first line fits in an error reply
+fn error_message(e: &mlua::Error) -> String {
+    let msg = match e {
+        mlua::Error::CallbackError { cause, .. } => return error_message(cause),
+        mlua::Error::RuntimeError(msg) => msg.clone(),
+        e => e.to_string(),
+    };
+    let msg = msg.split("stack traceback:").next().unwrap_or_default();
+    msg.lines().next().unwrap_or_default().trim_end().to_string()
+}
+
+// Run a script body with KEYS and ARGV set. Each run gets a fresh interpreter with
+// only the table, string and math libraries, so scripts can't keep state around.
+pub fn eval(
+    backend: &Backend,
+    session: &mut Session,
+    body: &[u8],
+    keys: &[Vec<u8>],
+    args: &[Vec<u8>],
+) -> Result<RespFrame, CommandError> {
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::new())
+        .map_err(|e| CommandError::Other(e.to_string()))?;
+    // SELECT inside a script doesn't change the caller's db
+    let db = session.db;
+    let state = RefCell::new(ScriptState { backend, session, wrapped: false });
+
+    let result = lua.scope(|scope| {
+        let globals = lua.globals();
+        globals.set("KEYS", keys.iter().map(|k| lua.create_string(k)).collect::<mlua::Result<Vec<_>>>()?)?;
+        globals.set("ARGV", args.iter().map(|a| lua.create_string(a)).collect::<mlua::Result<Vec<_>>>()?)?;
+
+        let redis = lua.create_table()?;
+        // redis.call raises the error reply, redis.pcall returns it as {err = ...}
+        redis.set(
+            "call",
+            scope.create_function(|lua, args: MultiValue| {
+                let reply = state.borrow_mut().call(lua_argv(args)?);
+                match reply {
+                    RespFrame::Error(e) => Err(mlua::Error::RuntimeError(e.as_str().to_string())),
+                    reply => frame_to_lua(lua, &reply),
+                }
+            })?,
+        )?;
+        redis.set(
+            "pcall",
+            scope.create_function(|lua, args: MultiValue| {
+                let reply = match lua_argv(args) {
+                    Ok(argv) => state.borrow_mut().call(argv),
+                    Err(e) => error_frame(CommandError::Other(error_message(&e))),
+                };
+                frame_to_lua(lua, &reply)
+            })?,
+        )?;
+        redis.set(
+            "error_reply",
+            lua.create_function(|lua, msg: String| {
+                let table = lua.create_table()?;
+                table.set("err", msg)?;
+                Ok(table)
+            })?,
+        )?;
+        redis.set(
+            "status_reply",
+            lua.create_function(|lua, msg: String| {
+                let table = lua.create_table()?;
+                table.set("ok", msg)?;
+                Ok(table)
+            })?,
+        )?;
+        redis.set("sha1hex", lua.create_function(|_, body: mlua::String| Ok(sha1_hex(body.as_bytes())))?)?;
+        globals.set("redis", redis)?;
+
+        let value: Value = lua.load(body).set_name("user_script").eval()?;
+        Ok(lua_to_frame(&value))
+    });
+
+    let state = state.into_inner();
+    if state.wrapped {
+        state.session.in_atomic = false;
+        backend.repl.propagate(state.session.db, &[vec![b"exec".to_vec()]]);
+    }
+    state.session.db = db;
+
+    result.map_err(|e| {
+        let msg = error_message(&e);
+        // an error reply raised by redis.call keeps its own prefix, e.g. WRONGTYPE
+        let prefixed = msg.split(' ').next().is_some_and(|w| w.len() > 1 && w.bytes().all(|b| b.is_ascii_uppercase()));
+        if prefixed {
+            CommandError::Raw(msg)
+        } else {
+            CommandError::Other(format!("Error running script: {}", msg))
+        }
+    })
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{snapshot, Backend},
+    connections::ConnFlags,
+};
+
+// how long the connections get to finish the commands they already read
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const DRAIN_POLL: Duration = Duration::from_millis(10);
+
+// whether the last snapshot is written on the way out, by default only with save points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMode {
+    Default,
+    Save,
+    NoSave,
+}
+
+// Requested by SHUTDOWN, SIGTERM or SIGINT. The listeners and the connections
+// watch it and stop on their own.
+#[derive(Debug)]
+pub struct Shutdown(watch::Sender<Option<SaveMode>>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(watch::Sender::new(None))
+    }
+}
+
+impl Shutdown {
+    // the first request wins, a later SHUTDOWN SAVE doesn't change a NOSAVE one
+    pub fn request(&self, mode: SaveMode) {
+        self.0.send_if_modified(|requested| {
+            if requested.is_some() {
+                return false;
+            }
+            *requested = Some(mode);
+            true
+        });
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    // resolves once a shutdown is requested, with its mode
+    pub async fn requested(&self) -> SaveMode {
+        let mut rx = self.0.subscribe();
+        // the sender lives as long as self, the wait can't fail
+        let mode = rx.wait_for(Option::is_some).await.map(|mode| *mode).ok().flatten();
+        mode.unwrap_or(SaveMode::Default)
+    }
+}
+
+// SIGTERM and SIGINT stop the server like SHUTDOWN does
+pub async fn on_signals(backend: Backend) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => info!("Received SIGTERM scheduling shutdown..."),
+        interrupted = tokio::signal::ctrl_c() => {
+            interrupted?;
+            info!("Received SIGINT scheduling shutdown...");
+        }
+    }
+    backend.shutdown.request(SaveMode::Default);
+    Ok(())
+}
+
+// Once the listeners stopped: wait for the connections to finish what they read,
+// then write the last snapshot. Replicas stay attached until the very end, they
+// get the writes made meanwhile.
+pub async fn finish(backend: &Backend, mode: SaveMode) -> Result<()> {
+    let config = backend.config().clone();
+    if let Some(path) = &config.unixsocket {
+        if let Err(e) = fs::remove_file(path) {
+            warn!("Can't remove the unix socket {}: {}", path.display(), e);
+        }
+    }
+
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    let draining = || backend.connections.list().iter().any(|conn| conn.state().flags != ConnFlags::Replica);
+    while draining() {
+        if Instant::now() >= deadline {
+            warn!("Connections still open after {:?}, closing them", DRAIN_TIMEOUT);
+            break;
+        }
+        tokio::time::sleep(DRAIN_POLL).await;
+    }
+
+    let save = match mode {
+        SaveMode::Save => true,
+        SaveMode::NoSave => false,
+        SaveMode::Default => !config.save.0.is_empty(),
+    };
+    if save {
+        while backend.bgsave_in_progress.load(Ordering::Acquire) {
+            tokio::time::sleep(DRAIN_POLL).await;
+        }
+        info!("Saving the final snapshot before exiting.");
+        snapshot::save(backend)?;
+    }
+    info!("Simple-Redis is now ready to exit, bye bye...");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{SaveMode, Shutdown};
+
+    #[tokio::test]
+    async fn test_request() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.is_requested());
+        assert!(tokio::time::timeout(Duration::from_millis(10), shutdown.requested()).await.is_err());
+        shutdown.request(SaveMode::NoSave);
+        shutdown.request(SaveMode::Save);
+        assert!(shutdown.is_requested());
+        let mode = tokio::time::timeout(Duration::from_millis(10), shutdown.requested()).await.unwrap();
+        assert_eq!(mode, SaveMode::NoSave);
+    }
+}
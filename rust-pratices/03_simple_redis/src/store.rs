@@ -0,0 +1,294 @@
+use std::{collections::HashMap, time::Duration};
+
+use thiserror::Error;
+
+use crate::{
+    backend::Backend,
+    cmd::{execute_argv, Argv, Session},
+    config::Config,
+    resp::RespFrame,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StoreError {
+    // the error reply of the command, e.g. WRONGTYPE ...
+    #[error("{0}")]
+    Command(String),
+    #[error("unexpected reply: {0:?}")]
+    UnexpectedReply(RespFrame),
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+// The command engine in process, without networking. Commands run like they do
+// for a client of the server: same types, expiration, transactions and scripts.
+// A Store is one client session, `session` opens another one on the same data.
+// Blocking commands never wait, they time out at once like inside MULTI.
+#[derive(Debug)]
+pub struct Store {
+    backend: Backend,
+    // an internal session, ACL rules don't apply to it
+    session: Session,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn argv<T: AsRef<[u8]>>(parts: impl IntoIterator<Item = T>) -> Argv {
+    parts.into_iter().map(|p| p.as_ref().to_vec()).collect()
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Self::with_backend(Backend::new(config))
+    }
+
+    // a client of an existing backend, e.g. the one a server in the same process serves
+    pub fn with_backend(backend: Backend) -> Self {
+        Self { backend, session: Session::new() }
+    }
+
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    // another client of the same data, with its own selected db and MULTI state
+    pub fn session(&self) -> Store {
+        Self::with_backend(self.backend.clone())
+    }
+
+    // run one command, the first argument is its name
+    pub fn execute<T: AsRef<[u8]>>(&mut self, args: &[T]) -> RespFrame {
+        if args.is_empty() {
+            return RespFrame::Array(vec![]);
+        }
+        let reply = execute_argv(&self.backend, &mut self.session, argv(args));
+        self.session.blocked = None;
+        reply
+    }
+
+    // run a command and turn its reply into T, errors replies become StoreError::Command
+    fn call<T, A: AsRef<[u8]>>(&mut self, args: &[A], parse: impl FnOnce(RespFrame) -> Result<T, RespFrame>) -> StoreResult<T> {
+        match self.execute(args) {
+            RespFrame::Error(e) => Err(StoreError::Command(e.as_str().to_string())),
+            reply => parse(reply).map_err(StoreError::UnexpectedReply),
+        }
+    }
+
+    pub fn select(&mut self, db: usize) -> StoreResult<()> {
+        let db = db.to_string();
+        self.call(&[b"select", db.as_bytes()], status)
+    }
+
+    pub fn get(&mut self, key: impl AsRef<[u8]>) -> StoreResult<Option<Vec<u8>>> {
+        self.call(&[b"get", key.as_ref()], optional_bulk)
+    }
+
+    pub fn set(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> StoreResult<()> {
+        self.call(&[b"set", key.as_ref(), value.as_ref()], status)
+    }
+
+    // SET with PX, the key expires after `ttl`
+    pub fn set_ex(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>, ttl: Duration) -> StoreResult<()> {
+        let ms = ttl.as_millis().to_string();
+        self.call(&[b"set", key.as_ref(), value.as_ref(), b"px", ms.as_bytes()], status)
+    }
+
+    pub fn del<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> StoreResult<i64> {
+        let args = argv([b"del".as_ref()].into_iter().chain(keys.iter().map(|k| k.as_ref())));
+        self.call(&args, integer)
+    }
+
+    pub fn exists(&mut self, key: impl AsRef<[u8]>) -> StoreResult<bool> {
+        self.call(&[b"exists", key.as_ref()], integer).map(|n| n > 0)
+    }
+
+    // false when the key doesn't exist
+    pub fn expire(&mut self, key: impl AsRef<[u8]>, ttl: Duration) -> StoreResult<bool> {
+        let ms = ttl.as_millis().to_string();
+        self.call(&[b"pexpire", key.as_ref(), ms.as_bytes()], integer).map(|n| n == 1)
+    }
+
+    // the time left, None when the key doesn't exist or doesn't expire
+    pub fn ttl(&mut self, key: impl AsRef<[u8]>) -> StoreResult<Option<Duration>> {
+        let ms = self.call(&[b"pttl", key.as_ref()], integer)?;
+        Ok((ms >= 0).then(|| Duration::from_millis(ms as u64)))
+    }
+
+    pub fn incr_by(&mut self, key: impl AsRef<[u8]>, by: i64) -> StoreResult<i64> {
+        let by = by.to_string();
+        self.call(&[b"incrby", key.as_ref(), by.as_bytes()], integer)
+    }
+
+    // the length of the list after the push
+    pub fn rpush<V: AsRef<[u8]>>(&mut self, key: impl AsRef<[u8]>, values: &[V]) -> StoreResult<i64> {
+        let args = argv([b"rpush".as_ref(), key.as_ref()].into_iter().chain(values.iter().map(|v| v.as_ref())));
+        self.call(&args, integer)
+    }
+
+    pub fn lpush<V: AsRef<[u8]>>(&mut self, key: impl AsRef<[u8]>, values: &[V]) -> StoreResult<i64> {
+        let args = argv([b"lpush".as_ref(), key.as_ref()].into_iter().chain(values.iter().map(|v| v.as_ref())));
+        self.call(&args, integer)
+    }
+
+    pub fn lrange(&mut self, key: impl AsRef<[u8]>, start: i64, stop: i64) -> StoreResult<Vec<Vec<u8>>> {
+        let (start, stop) = (start.to_string(), stop.to_string());
+        self.call(&[b"lrange", key.as_ref(), start.as_bytes(), stop.as_bytes()], bulks)
+    }
+
+    // true when the field is new
+    pub fn hset(&mut self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> StoreResult<bool> {
+        self.call(&[b"hset", key.as_ref(), field.as_ref(), value.as_ref()], integer).map(|n| n == 1)
+    }
+
+    pub fn hget(&mut self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>) -> StoreResult<Option<Vec<u8>>> {
+        self.call(&[b"hget", key.as_ref(), field.as_ref()], optional_bulk)
+    }
+
+    pub fn hgetall(&mut self, key: impl AsRef<[u8]>) -> StoreResult<HashMap<Vec<u8>, Vec<u8>>> {
+        let items = self.call(&[b"hgetall", key.as_ref()], bulks)?;
+        Ok(items.chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect())
+    }
+
+    // the number of members added
+    pub fn sadd<M: AsRef<[u8]>>(&mut self, key: impl AsRef<[u8]>, members: &[M]) -> StoreResult<i64> {
+        let args = argv([b"sadd".as_ref(), key.as_ref()].into_iter().chain(members.iter().map(|m| m.as_ref())));
+        self.call(&args, integer)
+    }
+
+    pub fn smembers(&mut self, key: impl AsRef<[u8]>) -> StoreResult<Vec<Vec<u8>>> {
+        self.call(&[b"smembers", key.as_ref()], bulks)
+    }
+
+    // true when the member is new
+    pub fn zadd(&mut self, key: impl AsRef<[u8]>, score: f64, member: impl AsRef<[u8]>) -> StoreResult<bool> {
+        let score = score.to_string();
+        self.call(&[b"zadd", key.as_ref(), score.as_bytes(), member.as_ref()], integer).map(|n| n == 1)
+    }
+
+    pub fn zscore(&mut self, key: impl AsRef<[u8]>, member: impl AsRef<[u8]>) -> StoreResult<Option<f64>> {
+        let score = self.call(&[b"zscore", key.as_ref(), member.as_ref()], optional_bulk)?;
+        Ok(score.and_then(|s| crate::utils::parse_f64(&s)))
+    }
+
+    // commands run with `execute` are queued until `exec`
+    pub fn multi(&mut self) -> StoreResult<()> {
+        self.call(&["multi"], status)
+    }
+
+    // the replies of the queued commands, run with no other command in between
+    pub fn exec(&mut self) -> StoreResult<Vec<RespFrame>> {
+        self.call(&["exec"], |reply| match reply {
+            RespFrame::Array(replies) => Ok(replies),
+            reply => Err(reply),
+        })
+    }
+
+    pub fn discard(&mut self) -> StoreResult<()> {
+        self.call(&["discard"], status)
+    }
+
+    pub fn flushall(&mut self) -> StoreResult<()> {
+        self.call(&["flushall"], status)
+    }
+}
+
+fn status(reply: RespFrame) -> Result<(), RespFrame> {
+    match reply {
+        RespFrame::SimpleString(_) => Ok(()),
+        reply => Err(reply),
+    }
+}
+
+fn integer(reply: RespFrame) -> Result<i64, RespFrame> {
+    match reply {
+        RespFrame::Integer(n) => Ok(n),
+        reply => Err(reply),
+    }
+}
+
+fn optional_bulk(reply: RespFrame) -> Result<Option<Vec<u8>>, RespFrame> {
+    match reply {
+        RespFrame::BulkString(b) => Ok(Some(b)),
+        RespFrame::NullBulkString(_) => Ok(None),
+        reply => Err(reply),
+    }
+}
+
+fn bulks(reply: RespFrame) -> Result<Vec<Vec<u8>>, RespFrame> {
+    let RespFrame::Array(items) = reply else { return Err(reply) };
+    if !items.iter().all(|item| matches!(item, RespFrame::BulkString(_))) {
+        return Err(RespFrame::Array(items));
+    }
+    Ok(items.into_iter().filter_map(|item| if let RespFrame::BulkString(b) = item { Some(b) } else { None }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        cmd::{bulk, int, nil_array},
+        resp::RespFrame,
+    };
+
+    use super::{Store, StoreError};
+
+    #[test]
+    fn test_store_commands() {
+        let mut store = Store::new();
+        store.set("a", "1").unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.incr_by("a", 41).unwrap(), 42);
+        assert_eq!(store.execute(&[b"get".as_ref(), b"a"]), bulk("42"));
+        assert_eq!(store.rpush("l", &["x", "y"]).unwrap(), 2);
+        assert_eq!(store.lrange("l", 0, -1).unwrap(), vec![b"x".to_vec(), b"y".to_vec()]);
+        assert!(store.hset("h", "f", "v").unwrap());
+        assert_eq!(store.hgetall("h").unwrap().get(b"f".as_ref()), Some(&b"v".to_vec()));
+        assert!(store.zadd("z", 1.5, "m").unwrap());
+        assert_eq!(store.zscore("z", "m").unwrap(), Some(1.5));
+        assert_eq!(
+            store.get("l"),
+            Err(StoreError::Command("WRONGTYPE Operation against a key holding the wrong kind of value".into()))
+        );
+
+        store.set_ex("t", "v", Duration::from_millis(20)).unwrap();
+        assert!(store.ttl("t").unwrap().is_some());
+        assert_eq!(store.ttl("a").unwrap(), None);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(store.get("t").unwrap(), None);
+
+        // blocking commands don't wait
+        assert_eq!(store.execute(&["xread", "block", "0", "streams", "s", "$"]), nil_array());
+        assert_eq!(store.del(&["a", "l", "missing"]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_store_transaction() {
+        let mut store = Store::new();
+        let mut other = store.session();
+        store.multi().unwrap();
+        store.execute(&["set", "a", "1"]);
+        store.execute(&["incr", "a"]);
+        assert_eq!(other.get("a").unwrap(), None);
+        assert_eq!(store.exec().unwrap(), vec![crate::cmd::ok(), int(2)]);
+
+        // a command that can't be queued aborts the transaction
+        store.multi().unwrap();
+        assert!(matches!(store.execute(&["nosuch"]), RespFrame::Error(_)));
+        store.execute(&["set", "a", "3"]);
+        assert!(matches!(store.exec(), Err(StoreError::Command(e)) if e.starts_with("EXECABORT")));
+        assert_eq!(other.get("a").unwrap(), Some(b"2".to_vec()));
+
+        // sessions select their own db
+        other.select(1).unwrap();
+        assert!(!other.exists("a").unwrap());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use clap::ValueEnum;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpStream, UnixStream},
+};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+        server::WebPkiClientVerifier,
+        ClientConfig, RootCertStore, ServerConfig,
+    },
+    TlsAcceptor, TlsConnector, TlsStream,
+};
+
+use crate::{config::Config, connections::ClientAddr};
+
+// whether clients of the TLS port must present a certificate, the same values as redis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TlsAuthClients {
+    Yes,
+    No,
+    Optional,
+}
+
+// A connection, plain, over TLS or a Unix socket, so that all of them serve the same way
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn local_addr(&self) -> Option<ClientAddr> {
+        match self {
+            Stream::Tcp(stream) => stream.local_addr().ok().map(ClientAddr::Tcp),
+            Stream::Tls(stream) => stream.get_ref().0.local_addr().ok().map(ClientAddr::Tcp),
+            Stream::Unix(stream) => {
+                let addr = stream.local_addr().ok()?;
+                Some(ClientAddr::Unix(addr.as_pathname()?.display().to_string()))
+            }
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("can't open certificate file {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        bail!("no certificate in {}", path.display());
+    }
+    Ok(certs)
+}
+
+pub fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("can't open key file {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))?.ok_or_else(|| anyhow!("no private key in {}", path.display()))
+}
+
+fn root_store(ca_file: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_file)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+// The TLS side of the server: its certificate, and the CA client certificates must
+// be signed by unless tls-auth-clients is no.
+pub fn server_config(
+    cert_file: &Path,
+    key_file: &Path,
+    ca_file: Option<&Path>,
+    auth_clients: TlsAuthClients,
+) -> Result<ServerConfig> {
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+    let builder = match (auth_clients, ca_file) {
+        (TlsAuthClients::No, _) => builder.with_no_client_auth(),
+        (_, None) => bail!("tls-auth-clients needs tls-ca-cert-file to verify client certificates"),
+        (auth, Some(ca_file)) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(root_store(ca_file)?), provider);
+            let verifier = if auth == TlsAuthClients::Optional { verifier.allow_unauthenticated() } else { verifier };
+            builder.with_client_cert_verifier(verifier.build()?)
+        }
+    };
+    Ok(builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?)?)
+}
+
+// The client side: servers are trusted when signed by the CA, the client presents
+// its own certificate when one is given.
+pub fn client_config(ca_file: &Path, identity: Option<(&Path, &Path)>) -> Result<ClientConfig> {
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(root_store(ca_file)?);
+    Ok(match identity {
+        Some((cert_file, key_file)) => builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?,
+        None => builder.with_no_client_auth(),
+    })
+}
+
+// the acceptor of the TLS port, None when it isn't enabled
+pub fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>> {
+    if config.tls_port.is_none() {
+        return Ok(None);
+    }
+    let (Some(cert_file), Some(key_file)) = (&config.tls_cert_file, &config.tls_key_file) else {
+        bail!("tls-port needs tls-cert-file and tls-key-file");
+    };
+    let server = server_config(cert_file, key_file, config.tls_ca_cert_file.as_deref(), config.tls_auth_clients)?;
+    Ok(Some(TlsAcceptor::from(Arc::new(server))))
+}
+
+// how a replica connects to its master with tls-replication: trusting the same CA,
+// and presenting the server's own certificate
+pub fn replication_connector(config: &Config) -> Result<TlsConnector> {
+    let ca_file = config.tls_ca_cert_file.as_deref().ok_or_else(|| anyhow!("tls-replication needs tls-ca-cert-file"))?;
+    let identity = config.tls_cert_file.as_deref().zip(config.tls_key_file.as_deref());
+    Ok(TlsConnector::from(Arc::new(client_config(ca_file, identity)?)))
+}
+
+pub async fn connect_tls(stream: TcpStream, connector: &TlsConnector, server_name: &str) -> Result<Stream> {
+    let name = ServerName::try_from(server_name.to_string())?;
+    let stream = connector.connect(name, stream).await?;
+    Ok(Stream::Tls(Box::new(TlsStream::Client(stream))))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    use super::{client_config, connect_tls, server_config, TlsAuthClients};
+
+    // a CA, a server certificate for localhost and a client certificate, all written
+    // as PEM files in a fresh temporary directory
+    pub(crate) struct TestPki {
+        pub dir: PathBuf,
+    }
+
+    impl TestPki {
+        pub(crate) fn generate(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("simple-redis-tls-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(vec![]).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            std::fs::write(dir.join("ca.crt"), ca.pem()).unwrap();
+
+            for (file, names) in [("server", vec!["localhost".to_string()]), ("client", vec!["client".to_string()])] {
+                let key = KeyPair::generate().unwrap();
+                let cert = CertificateParams::new(names).unwrap().signed_by(&key, &ca, &ca_key).unwrap();
+                std::fs::write(dir.join(format!("{}.crt", file)), cert.pem()).unwrap();
+                std::fs::write(dir.join(format!("{}.key", file)), key.serialize_pem()).unwrap();
+            }
+            Self { dir }
+        }
+
+        pub(crate) fn path(&self, file: &str) -> PathBuf {
+            self.dir.join(file)
+        }
+    }
+
+    impl Drop for TestPki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // echo one message back over TLS, returns whether the exchange worked
+    async fn exchange(acceptor: TlsAcceptor, connector: TlsConnector) -> bool {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                let mut buf = [0; 4];
+                if stream.read_exact(&mut buf).await.is_ok() {
+                    let _ = stream.write_all(&buf).await;
+                }
+            }
+        });
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let Ok(mut stream) = connect_tls(stream, &connector, "localhost").await else {
+            return false;
+        };
+        let mut buf = [0; 4];
+        stream.write_all(b"ping").await.is_ok() && stream.read_exact(&mut buf).await.is_ok() && &buf == b"ping"
+    }
+
+    #[tokio::test]
+    async fn test_client_auth() {
+        let pki = TestPki::generate("auth");
+        let (ca, cert, key) = (pki.path("ca.crt"), pki.path("server.crt"), pki.path("server.key"));
+        let server = |auth| TlsAcceptor::from(Arc::new(server_config(&cert, &key, Some(&ca), auth).unwrap()));
+        let with_cert = TlsConnector::from(Arc::new(
+            client_config(&ca, Some((&pki.path("client.crt"), &pki.path("client.key")))).unwrap(),
+        ));
+        let anonymous = TlsConnector::from(Arc::new(client_config(&ca, None).unwrap()));
+
+        assert!(exchange(server(TlsAuthClients::Yes), with_cert.clone()).await);
+        assert!(!exchange(server(TlsAuthClients::Yes), anonymous.clone()).await);
+        assert!(exchange(server(TlsAuthClients::Optional), anonymous.clone()).await);
+        assert!(exchange(server(TlsAuthClients::No), anonymous).await);
+
+        assert!(server_config(&cert, &key, None, TlsAuthClients::Yes).is_err());
+    }
+}
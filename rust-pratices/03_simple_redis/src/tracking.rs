@@ -0,0 +1,254 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    backend::Backend,
+    cmd::{array, bulk, bulk_array, nil, Session},
+    resp::{RespFrame, RespNull},
+};
+
+// the channel a RESP2 connection subscribes to when other connections redirect to it
+pub const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+// How a connection asked for tracking with CLIENT TRACKING ON.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    // the connection that gets the invalidations instead of this one
+    pub redirect: Option<u64>,
+    // invalidate every key under the prefixes, whether it was read or not
+    pub bcast: bool,
+    pub prefixes: Vec<Vec<u8>>,
+    // only the reads after CLIENT CACHING yes are tracked
+    pub optin: bool,
+    // every read is tracked but the ones after CLIENT CACHING no
+    pub optout: bool,
+    // no invalidations for the keys the connection changes itself
+    pub noloop: bool,
+}
+
+impl TrackingOptions {
+    // whether the next read is remembered, `caching` is what CLIENT CACHING said
+    pub fn tracks_reads(&self, caching: Option<bool>) -> bool {
+        if self.bcast {
+            false
+        } else if self.optin {
+            caching == Some(true)
+        } else if self.optout {
+            caching != Some(false)
+        } else {
+            true
+        }
+    }
+
+    fn matches(&self, key: &[u8]) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p))
+    }
+}
+
+// What a connection is told: these keys changed, None when every key did (a flush).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalidation {
+    pub keys: Option<Vec<Vec<u8>>>,
+    // sent on behalf of another connection
+    pub redirected: bool,
+}
+
+impl Invalidation {
+    // A RESP3 connection gets its own invalidations as push messages, a redirect target
+    // gets them as messages of __redis__:invalidate and only while it's subscribed to it
+    pub fn frame(self, session: &Session) -> Option<RespFrame> {
+        let keys = self.keys.map(bulk_array);
+        if self.redirected {
+            let subscribed = session.subscriber.as_ref().is_some_and(|s| s.channels().any(|c| c == INVALIDATE_CHANNEL));
+            subscribed.then(|| array([bulk("message"), bulk(INVALIDATE_CHANNEL), keys.unwrap_or_else(nil)]))
+        } else {
+            let keys = keys.unwrap_or(RespFrame::Null(RespNull));
+            session.resp3.then(|| RespFrame::Push(vec![bulk("invalidate"), keys]))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    // the connections that read each key since it last changed
+    keys: HashMap<Vec<u8>, HashSet<u64>>,
+    // the connections with tracking on
+    clients: HashMap<u64, TrackingOptions>,
+    // the invalidation queue of every connection
+    queues: HashMap<u64, UnboundedSender<Invalidation>>,
+}
+
+impl Table {
+    fn send(&self, id: u64, keys: Option<Vec<Vec<u8>>>) {
+        let Some(options) = self.clients.get(&id) else { return };
+        let to = options.redirect.unwrap_or(id);
+        if let Some(tx) = self.queues.get(&to) {
+            let _ = tx.send(Invalidation { keys, redirected: options.redirect.is_some() });
+        }
+    }
+}
+
+// Client side caching: which connection read which key, and the queues their
+// invalidations go to. Keys aren't told apart by db, like in redis.
+#[derive(Debug, Default)]
+pub struct Tracking {
+    table: Mutex<Table>,
+    // connections with tracking on, writes skip the table while there are none
+    clients: AtomicUsize,
+}
+
+impl Tracking {
+    fn table(&self) -> MutexGuard<'_, Table> {
+        self.table.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_connected(&self, id: u64) -> bool {
+        self.table().queues.contains_key(&id)
+    }
+
+    pub fn enable(&self, id: u64, options: TrackingOptions) {
+        let mut table = self.table();
+        table.clients.insert(id, options);
+        self.clients.store(table.clients.len(), Ordering::Relaxed);
+    }
+
+    // the keys the connection read stay in the table until they change, they're
+    // skipped from then on
+    pub fn disable(&self, id: u64) {
+        let mut table = self.table();
+        table.clients.remove(&id);
+        self.clients.store(table.clients.len(), Ordering::Relaxed);
+    }
+
+    pub fn remember<K: AsRef<[u8]>>(&self, id: u64, keys: &[K]) {
+        let mut table = self.table();
+        for key in keys {
+            table.keys.entry(key.as_ref().to_vec()).or_default().insert(id);
+        }
+    }
+
+    // tell the connections that read the keys, or broadcast a prefix of them, that
+    // they changed. `by` is the connection that changed them, for NOLOOP.
+    pub fn invalidate<K: AsRef<[u8]>>(&self, keys: &[K], by: Option<u64>) {
+        if self.clients.load(Ordering::Relaxed) == 0 || keys.is_empty() {
+            return;
+        }
+        let mut table = self.table();
+        let mut targets: HashMap<u64, Vec<Vec<u8>>> = HashMap::new();
+        for key in keys.iter().map(|k| k.as_ref()) {
+            let readers = table.keys.remove(key).unwrap_or_default();
+            let bcast = table.clients.iter().filter(|(_, o)| o.bcast && o.matches(key)).map(|(id, _)| *id);
+            for id in readers.into_iter().chain(bcast.collect::<Vec<_>>()) {
+                let changed = targets.entry(id).or_default();
+                if !changed.iter().any(|k| k == key) {
+                    changed.push(key.to_vec());
+                }
+            }
+        }
+        for (id, keys) in targets {
+            let noloop = table.clients.get(&id).is_some_and(|o| o.noloop);
+            if !(noloop && by == Some(id)) {
+                table.send(id, Some(keys));
+            }
+        }
+    }
+
+    // every key changed: FLUSHDB, FLUSHALL, SWAPDB
+    pub fn invalidate_all(&self) {
+        if self.clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut table = self.table();
+        table.keys.clear();
+        for id in table.clients.keys() {
+            table.send(*id, None);
+        }
+    }
+}
+
+// The invalidation queue of a connection, registered while it's alive. Dropping it
+// turns tracking off.
+#[derive(Debug)]
+pub struct Invalidations {
+    backend: Backend,
+    id: u64,
+    rx: UnboundedReceiver<Invalidation>,
+}
+
+impl Invalidations {
+    pub fn new(backend: &Backend, id: u64) -> Self {
+        let (tx, rx) = unbounded_channel();
+        backend.tracking.table().queues.insert(id, tx);
+        Self { backend: backend.clone(), id, rx }
+    }
+
+    pub fn try_recv(&mut self) -> Option<Invalidation> {
+        self.rx.try_recv().ok()
+    }
+
+    pub async fn recv(&mut self) -> Option<Invalidation> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Invalidations {
+    fn drop(&mut self) {
+        let tracking = &self.backend.tracking;
+        tracking.table().queues.remove(&self.id);
+        tracking.disable(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::Backend;
+
+    use super::{Invalidation, Invalidations, TrackingOptions};
+
+    fn changed(keys: &[&str], redirected: bool) -> Option<Invalidation> {
+        let keys = keys.iter().map(|k| k.as_bytes().to_vec()).collect();
+        Some(Invalidation { keys: Some(keys), redirected })
+    }
+
+    #[test]
+    fn test_tracking_table() {
+        let backend = Backend::default();
+        let tracking = &backend.tracking;
+        let mut a = Invalidations::new(&backend, 1);
+        let mut b = Invalidations::new(&backend, 2);
+        let mut c = Invalidations::new(&backend, 3);
+        tracking.enable(1, TrackingOptions::default());
+        tracking.enable(2, TrackingOptions { bcast: true, prefixes: vec![b"user:".to_vec()], ..Default::default() });
+        tracking.enable(4, TrackingOptions { redirect: Some(3), noloop: true, ..Default::default() });
+
+        tracking.remember(1, &["k", "user:1"]);
+        tracking.remember(4, &["k"]);
+        tracking.invalidate(&["k", "user:1", "k"], Some(4));
+        assert_eq!(a.try_recv(), changed(&["k", "user:1"], false));
+        assert_eq!(b.try_recv(), changed(&["user:1"], false));
+        // 4 changed k itself
+        assert_eq!(c.try_recv(), None);
+
+        // an invalidation is sent once, until the key is read again
+        tracking.invalidate(&["k", "user:1"], None);
+        assert_eq!(a.try_recv(), None);
+        assert_eq!(b.try_recv(), changed(&["user:1"], false));
+
+        tracking.remember(4, &["j"]);
+        tracking.invalidate(&["j"], None);
+        assert_eq!(c.try_recv(), changed(&["j"], true));
+
+        drop(a);
+        tracking.invalidate_all();
+        assert_eq!(b.try_recv(), Some(Invalidation { keys: None, redirected: false }));
+        assert_eq!(c.try_recv(), Some(Invalidation { keys: None, redirected: true }));
+        assert_eq!(c.try_recv(), None);
+    }
+}
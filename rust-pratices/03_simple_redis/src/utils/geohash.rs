@@ -0,0 +1,106 @@
+// The 52-bit geohash redis keeps as a sorted set score: 26 bits of latitude in
+// the even bits and 26 bits of longitude in the odd ones. Latitudes are limited
+// to what Web Mercator can show.
+
+pub const LON_MIN: f64 = -180.0;
+pub const LON_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.05112878;
+pub const LAT_MAX: f64 = 85.05112878;
+
+const STEP: u32 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub fn is_valid(lon: f64, lat: f64) -> bool {
+    (LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+// spread the low 32 bits of x over the even bits
+fn spread(x: u64) -> u64 {
+    (0..32).fold(0, |acc, i| acc | ((x >> i) & 1) << (2 * i))
+}
+
+fn squash(x: u64) -> u64 {
+    (0..32).fold(0, |acc, i| acc | ((x >> (2 * i)) & 1) << i)
+}
+
+fn encode_in(lon: f64, lat: f64, lat_range: (f64, f64)) -> u64 {
+    let scale = (1u64 << STEP) as f64;
+    let lat_offset = ((lat - lat_range.0) / (lat_range.1 - lat_range.0) * scale) as u64;
+    let lon_offset = ((lon - LON_MIN) / (LON_MAX - LON_MIN) * scale) as u64;
+    spread(lat_offset) | spread(lon_offset) << 1
+}
+
+pub fn encode(lon: f64, lat: f64) -> u64 {
+    encode_in(lon, lat, (LAT_MIN, LAT_MAX))
+}
+
+// the center of the hash's cell as (longitude, latitude)
+pub fn decode(bits: u64) -> (f64, f64) {
+    let scale = (1u64 << STEP) as f64;
+    let (lat_cell, lon_cell) = (squash(bits) as f64, squash(bits >> 1) as f64);
+    let lat_min = LAT_MIN + lat_cell / scale * (LAT_MAX - LAT_MIN);
+    let lat_max = LAT_MIN + (lat_cell + 1.0) / scale * (LAT_MAX - LAT_MIN);
+    let lon_min = LON_MIN + lon_cell / scale * (LON_MAX - LON_MIN);
+    let lon_max = LON_MIN + (lon_cell + 1.0) / scale * (LON_MAX - LON_MIN);
+    let lon = ((lon_min + lon_max) / 2.0).clamp(LON_MIN, LON_MAX);
+    let lat = ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX);
+    (lon, lat)
+}
+
+// the standard 11 character geohash, re-encoded with latitudes in [-90, 90]
+pub fn to_base32(bits: u64) -> String {
+    let (lon, lat) = decode(bits);
+    let bits = encode_in(lon, lat, (-90.0, 90.0));
+    (0..11)
+        .map(|i| {
+            // 52 bits only fill 10 characters, the last one is always 0
+            let index = if i == 10 { 0 } else { (bits >> (52 - (i + 1) * 5)) & 0x1f };
+            BASE32[index as usize] as char
+        })
+        .collect()
+}
+
+// the great-circle distance in meters (haversine)
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+// the distance from the center when the point is inside the width x height box
+// around it, all in meters
+pub fn distance_in_box(center: (f64, f64), width: f64, height: f64, point: (f64, f64)) -> Option<f64> {
+    let lat_distance = EARTH_RADIUS_IN_METERS * (point.1.to_radians() - center.1.to_radians()).abs();
+    if lat_distance > height / 2.0 {
+        return None;
+    }
+    let lon_distance = distance(point.0, point.1, center.0, point.1);
+    if lon_distance > width / 2.0 {
+        return None;
+    }
+    Some(distance(center.0, center.1, point.0, point.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, distance, encode, to_base32};
+
+    #[test]
+    fn test_geohash() {
+        // Palermo, from the redis GEOADD documentation
+        let bits = encode(13.361389, 38.115556);
+        assert_eq!(bits, 3479099956230698);
+        let (lon, lat) = decode(bits);
+        assert!((lon - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+        assert_eq!(to_base32(bits), "sqc8b49rny0");
+        assert_eq!(to_base32(encode(15.087269, 37.502669)), "sqdtr74hyu0");
+    }
+
+    #[test]
+    fn test_distance() {
+        let d = distance(13.361389, 38.115556, 15.087269, 37.502669);
+        assert!((d - 166274.1516).abs() < 1.0, "{}", d);
+    }
+}
@@ -0,0 +1,144 @@
+// Glob-style pattern matching on bytes, the same rules as redis `stringmatchlen`:
+//   *      any sequence (also empty)
+//   ?      any single byte
+//   [abc]  one of the bytes, [^abc] none of them, [a-z] a range
+//   \x     escape, matches x literally
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    glob_match_impl(pattern, text, false)
+}
+
+pub fn glob_match_nocase(pattern: &[u8], text: &[u8]) -> bool {
+    glob_match_impl(pattern, text, true)
+}
+
+fn eq_byte(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+fn glob_match_impl(pattern: &[u8], text: &[u8], nocase: bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // position to retry from when a `*` has to swallow one more byte
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    star = Some((p, t));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, next)) = match_class(pattern, p, text[t], nocase) {
+                        if matched {
+                            p = next;
+                            t += 1;
+                            continue;
+                        }
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if eq_byte(pattern[p + 1], text[t], nocase) {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if eq_byte(c, text[t], nocase) {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        match star {
+            Some((sp, st)) => {
+                p = sp;
+                t = st + 1;
+                star = Some((sp, st + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// match `c` against the class starting at pattern[start] == '[',
+// returns (matched, index after the closing ']')
+fn match_class(pattern: &[u8], start: usize, c: u8, nocase: bool) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            matched |= eq_byte(pattern[i + 1], c, nocase);
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (mut lo, mut hi) = (pattern[i], pattern[i + 2]);
+            if lo > hi {
+                std::mem::swap(&mut lo, &mut hi);
+            }
+            let in_range = |x: u8| x >= lo && x <= hi;
+            matched |= in_range(c) || (nocase && (in_range(c.to_ascii_lowercase()) || in_range(c.to_ascii_uppercase())));
+            i += 3;
+        } else {
+            matched |= eq_byte(pattern[i], c, nocase);
+            i += 1;
+        }
+    }
+
+    if i >= pattern.len() {
+        // unterminated class, redis treats it as the end of pattern
+        return None;
+    }
+    Some((matched != negate, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, glob_match_nocase};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"user:*:name", b"user:1000:name"));
+        assert!(!glob_match(b"user:*:name", b"user:1000:age"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(glob_match(b"", b""));
+        assert!(!glob_match(b"", b"a"));
+    }
+
+    #[test]
+    fn test_glob_match_nocase() {
+        assert!(glob_match_nocase(b"MAX*", b"maxmemory"));
+        assert!(!glob_match(b"MAX*", b"maxmemory"));
+    }
+}
@@ -0,0 +1,129 @@
+pub mod crc64;
+pub mod geohash;
+pub mod glob;
+
+pub use crc64::crc64;
+pub use glob::{glob_match, glob_match_nocase};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// unix time in milliseconds
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub fn parse_i64(v: &[u8]) -> Option<i64> {
+    std::str::from_utf8(v).ok()?.parse::<i64>().ok()
+}
+
+// the integer `v` is the exact decimal form of, without a plus sign, spaces or
+// leading zeros, so that it converts back to the same bytes
+pub fn parse_canonical_i64(v: &[u8]) -> Option<i64> {
+    if v.is_empty() || v.len() > 20 {
+        return None;
+    }
+    let n = parse_i64(v)?;
+    (n.to_string().as_bytes() == v).then_some(n)
+}
+
+pub fn parse_f64(v: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(v).ok()?;
+    let f = match s.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        _ => s.parse::<f64>().ok()?,
+    };
+    if f.is_nan() {
+        return None;
+    }
+    Some(f)
+}
+
+// format a float the way redis replies it: integers without fraction, no trailing zeros
+pub fn format_f64(v: f64) -> String {
+    if v.is_infinite() {
+        return if v > 0.0 { "inf".into() } else { "-inf".into() };
+    }
+    if v.fract() == 0.0 && v.abs() < 1e17 {
+        return format!("{}", v as i64);
+    }
+    format!("{}", v)
+}
+
+// a memory amount the way redis config reads it: 1k = 1000 bytes, 1kb = 1024 bytes,
+// the same for m/mb and g/gb
+pub fn parse_memory(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let split = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+    let (num, unit) = lower.split_at(split);
+    let num: u64 = num.parse().map_err(|_| format!("invalid memory amount: {}", s))?;
+    let unit: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => return Err(format!("invalid memory unit: {}", s)),
+    };
+    num.checked_mul(unit).ok_or_else(|| format!("memory amount too large: {}", s))
+}
+
+// bytes as INFO shows them in the *_human fields: 1.50K, 12.00M
+pub fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if n < 1024 {
+        return format!("{}B", n);
+    }
+    let mut value = n as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_f64, human_bytes, parse_canonical_i64, parse_memory};
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(0), "0B");
+        assert_eq!(human_bytes(1536), "1.50K");
+        assert_eq!(human_bytes(3 << 30), "3.00G");
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("0"), Ok(0));
+        assert_eq!(parse_memory("100"), Ok(100));
+        assert_eq!(parse_memory("1k"), Ok(1000));
+        assert_eq!(parse_memory("1KB"), Ok(1024));
+        assert_eq!(parse_memory("2mb"), Ok(2 << 20));
+        assert_eq!(parse_memory("1gb"), Ok(1 << 30));
+        assert!(parse_memory("1tb").is_err());
+        assert!(parse_memory("mb").is_err());
+    }
+
+    #[test]
+    fn test_format_f64() {
+        assert_eq!(format_f64(3.0), "3");
+        assert_eq!(format_f64(0.5), "0.5");
+        assert_eq!(format_f64(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn test_parse_canonical_i64() {
+        assert_eq!(parse_canonical_i64(b"-42"), Some(-42));
+        assert_eq!(parse_canonical_i64(b"-9223372036854775808"), Some(i64::MIN));
+        for v in [&b"+1"[..], b"01", b" 1", b"-0", b"", b"1.0"] {
+            assert_eq!(parse_canonical_i64(v), None);
+        }
+    }
+}